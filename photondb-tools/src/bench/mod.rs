@@ -116,10 +116,22 @@ pub(crate) struct Args {
     #[arg(long, default_value_t = 8192)]
     page_size: u64,
 
+    /// Number of recent delta pages kept uncompacted on consolidation.
+    #[arg(long, default_value_t = 0)]
+    consolidate_keep_recent_deltas: usize,
+
+    /// Cache delta pages as cold on point lookups.
+    #[arg(long, default_value_t = false)]
+    cold_cache_delta_pages: bool,
+
     /// Size for read page cache.
     #[arg(long, default_value_t = 134217728)]
     cache_size: u64,
 
+    /// The number of page cache shards, zero means deciding by the cache size.
+    #[arg(long, default_value_t = 0)]
+    cache_shards: usize,
+
     /// Disable space reclamation
     #[clap(long, default_value_t = false)]
     disable_space_reclaiming: bool,
@@ -132,6 +144,10 @@ pub(crate) struct Args {
     #[clap(long, default_value_t = 10 << 30)]
     space_used_high: u64,
 
+    /// Target file size for compaction.
+    #[clap(long, default_value_t = 64 << 20)]
+    file_base_size: usize,
+
     /// The maxmum number of write buffers.
     #[arg(long, default_value_t = 4)]
     max_write_buffers: usize,
@@ -212,8 +228,15 @@ impl fmt::Display for StoreType {
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum RandomDistType {
+    /// All keys are equally likely.
     Uniform,
+    /// The smallest keys are the hottest.
     Zipf,
+    /// Keys are visited in order, and wrap around after the last one.
+    Sequential,
+    /// The largest keys, which are inserted last by a sequential fill, are
+    /// the hottest.
+    Latest,
 }
 
 impl fmt::Display for RandomDistType {
@@ -221,6 +244,8 @@ impl fmt::Display for RandomDistType {
         f.write_str(match self {
             RandomDistType::Uniform => "uniform",
             RandomDistType::Zipf => "zipf",
+            RandomDistType::Sequential => "sequential",
+            RandomDistType::Latest => "latest",
         })
     }
 }
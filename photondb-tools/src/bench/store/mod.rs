@@ -12,9 +12,9 @@ use super::*;
 pub(crate) trait Store<E>: std::fmt::Debug + Clone + Sync + Send + 'static {
     async fn open_table(config: Arc<Args>, env: &E) -> Self;
 
-    async fn put(&self, key: &[u8], lsn: u64, value: &[u8]) -> Result<()>;
+    async fn put(&self, key: &[u8], value: &[u8]) -> Result<()>;
 
-    async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>>;
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
 
     async fn flush(&self);
 
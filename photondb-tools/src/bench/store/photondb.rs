@@ -2,7 +2,8 @@ use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use photondb::{
-    env::Env, raw::Table, ChecksumType, Compression, FlushOptions, TableOptions, TableStats,
+    env::Env, raw::Table, ChecksumType, Compression, FlushOptions, OpenMode, TableOptions,
+    TableStats,
 };
 
 use super::Store;
@@ -23,16 +24,29 @@ where
             std::fs::remove_dir_all(&config.db).unwrap();
         }
         let mut options = TableOptions::default();
+        options.open_mode = if config.use_existing_db == 1 {
+            OpenMode::OpenExisting
+        } else {
+            OpenMode::CreateNew
+        };
         options.page_store.prepopulate_cache_on_flush = false;
         options.page_store.cache_strict_capacity_limit = true;
         options.page_store.cache_estimated_entry_charge = 4840;
         options.page_store.cache_capacity = config.cache_size as usize;
+        options.page_store.cache_shards = config.cache_shards;
         options.page_store.max_write_buffers = config.max_write_buffers;
         options.page_store.write_buffer_capacity = config.write_buffer_size as u32;
         options.page_store.disable_space_reclaiming = config.disable_space_reclaiming;
         options.page_store.max_space_amplification_percent = config.max_space_amplification_percent;
         options.page_store.space_used_high = config.space_used_high;
+        options.page_store.file_base_size = config.file_base_size;
         options.page_size = config.page_size as usize;
+        options.consolidate_keep_recent_deltas = config.consolidate_keep_recent_deltas;
+        options.cold_cache_delta_pages = config.cold_cache_delta_pages;
+        options.slow_op_threshold = Some(Duration::from_millis(200));
+        // Keys are overwritten by the workloads, so each write needs a larger
+        // LSN.
+        options.auto_lsn = true;
         options.page_store.page_checksum_type = if config.verify_checksum == 1 {
             ChecksumType::CRC32
         } else {
@@ -51,25 +65,13 @@ where
         Self { table }
     }
 
-    async fn put(&self, key: &[u8], lsn: u64, value: &[u8]) -> Result<()> {
-        photondb::perf::reset_perf_ctx();
-        self.table.put(key, lsn, value).await.expect("put fail");
-        photondb::perf::with(|ctx| {
-            if ctx.total > Duration::from_millis(300) {
-                log::info!("slow PUT: {ctx:?}");
-            }
-        });
+    async fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.table.put_simple(key, value).await.expect("put fail");
         Ok(())
     }
 
-    async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>> {
-        photondb::perf::reset_perf_ctx();
-        let r = self.table.get(key, lsn).await.expect("get fail");
-        photondb::perf::with(|ctx| {
-            if ctx.total > Duration::from_millis(200) {
-                log::info!("slow GET: {ctx:?}");
-            }
-        });
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let r = self.table.get_simple(key).await.expect("get fail");
         Ok(r)
     }
 
@@ -49,6 +49,8 @@ impl KeyGenerator {
         let rand_dist: Box<dyn RandDist + Send> = match key_dist {
             RandomDistType::Uniform => Box::new(UniformDist::new(rng, key_nums)),
             RandomDistType::Zipf => Box::new(ZipfDist::new(rng, key_nums, 0.99)),
+            RandomDistType::Sequential => Box::new(SequentialDist::new(key_nums)),
+            RandomDistType::Latest => Box::new(LatestDist::new(rng, key_nums, 0.99)),
         };
         let state = Some(match mode {
             GenMode::Random => KeyGeneratorState::Random { rand_dist },
@@ -246,6 +248,45 @@ impl RandDist for ZipfDist {
     }
 }
 
+struct SequentialDist {
+    next: u64,
+    key_nums: u64,
+}
+
+impl SequentialDist {
+    fn new(key_nums: u64) -> Self {
+        Self { next: 0, key_nums }
+    }
+}
+
+impl RandDist for SequentialDist {
+    fn next(&mut self) -> u64 {
+        let val = self.next;
+        self.next = (self.next + 1) % self.key_nums.max(1);
+        val
+    }
+}
+
+// Ref https://github.com/brianfrankcooper/YCSB/blob/cd1589ce6f5abf96e17aa8ab80c78a4348fdf29a/core/src/main/java/site/ycsb/generator/SkewedLatestGenerator.java
+struct LatestDist {
+    zipf: ZipfDist,
+    key_nums: u64,
+}
+
+impl LatestDist {
+    fn new(rng: SmallRng, key_nums: u64, theta: f64) -> Self {
+        let zipf = ZipfDist::new(rng, key_nums, theta);
+        Self { zipf, key_nums }
+    }
+}
+
+impl RandDist for LatestDist {
+    fn next(&mut self) -> u64 {
+        let max = self.key_nums.saturating_sub(1);
+        max - self.zipf.next().min(max)
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct Stats<S: Store<E>, E: Env> {
     tid: u32,
@@ -585,6 +626,24 @@ mod tests {
         }
         println!("uniform:");
         dump(&mut s);
+
+        let mut d3 = SequentialDist::new(3);
+        s.clear();
+        for _ in 0..5 {
+            s.push(d3.next())
+        }
+        assert_eq!(s, vec![0, 1, 2, 0, 1]);
+
+        let rng = SmallRng::seed_from_u64(seed);
+        let mut d4 = LatestDist::new(rng, 100, 0.99);
+        s.clear();
+        for _ in 0..10000 {
+            s.push(d4.next())
+        }
+        assert!(s.iter().all(|&v| v < 100));
+        assert!(s.iter().filter(|&&v| v >= 90).count() > s.len() / 2);
+        println!("latest:");
+        dump(&mut s);
     }
 
     fn dump(s: &mut [u64]) {
@@ -252,7 +252,7 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
             let mut key = vec![0u8; ctx.config.key_size as usize];
             key_gen.generate_key(&mut key);
             let value = value_gen.generate_value();
-            table.put(&key, 0, value).await.unwrap();
+            table.put(&key, value).await.unwrap();
 
             photonio::task::yield_now().await;
 
@@ -288,7 +288,7 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
             let mut key = vec![0u8; ctx.config.key_size as usize];
             key_gen.generate_key(&mut key);
             reads += 1;
-            if let Some(v) = table.get(&key, 0).await.expect("get key fail") {
+            if let Some(v) = table.get(&key).await.expect("get key fail") {
                 founds += 1;
                 let bytes = key.len() + v.len() + std::mem::size_of::<u64>();
                 ctx.stats
@@ -333,14 +333,14 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
             let mut key = vec![0u8; ctx.config.key_size as usize];
             key_gen.generate_key(&mut key);
 
-            if let Some(ov) = table.get(&key, 0).await.expect("read of update fail") {
+            if let Some(ov) = table.get(&key).await.expect("read of update fail") {
                 founds += 1;
                 bytes += key.len() + ov.len() + std::mem::size_of::<u64>();
             }
 
             updates += 1;
             let value = val_gen.generate_value();
-            table.put(&key, 0, value).await.expect("put of update fail");
+            table.put(&key, value).await.expect("put of update fail");
 
             photonio::task::yield_now().await;
 
@@ -387,7 +387,7 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
             key_gen.generate_key(&mut key);
 
             if read_weight > 0 {
-                if let Some(v) = table.get(&key, 0).await.expect("get key fail") {
+                if let Some(v) = table.get(&key).await.expect("get key fail") {
                     founds += 1;
                     let bytes = key.len() + v.len() + std::mem::size_of::<u64>();
                     ctx.stats
@@ -407,7 +407,7 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
             if write_weight > 0 {
                 let value = val_gen.generate_value();
 
-                table.put(&key, 0, value).await.unwrap();
+                table.put(&key, value).await.unwrap();
 
                 photonio::task::yield_now().await;
 
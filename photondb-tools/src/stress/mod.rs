@@ -230,6 +230,8 @@ pub(crate) async fn run(args: Args) -> Result<()> {
     let env = env::Photon;
     let mut options = TableOptions::default();
     options.page_store.space_used_high = args.space_used_high;
+    // Keys are overwritten by the tasks, so each write needs a larger LSN.
+    options.auto_lsn = true;
     let table = env
         .spawn_background(Table::open(args.db.clone(), options))
         .await?;
@@ -303,7 +305,7 @@ async fn write_task(job: Arc<Job>, seed: u64) {
         key[2] = job.key_prefix[2].load(Ordering::Relaxed);
         fill_bytes(&mut rng, &mut key[3..]);
         fill_bytes(&mut rng, value.as_mut_slice());
-        if let Err(err) = job.table.put(&key, 0, &value).await {
+        if let Err(err) = job.table.put_simple(&key, &value).await {
             error!("Write to DB: {err:?}");
             std::process::abort();
         }
@@ -352,7 +354,7 @@ async fn read_task(job: Arc<Job>, seed: u64) {
             Ok(None) => {
                 let mut value = vec![0u8; job.args.value_size];
                 fill_bytes(&mut rng, value.as_mut_slice());
-                if let Err(err) = job.table.put(&key, 0, &value).await {
+                if let Err(err) = job.table.put_simple(&key, &value).await {
                     error!("Write to DB: {err:?}");
                     std::process::abort();
                 }
@@ -24,5 +24,10 @@ fn main() -> Result<()> {
             println!("{:?} {:?}", k, v);
         }
     }
+    // Iterate the tree entry by entry.
+    for entry in guard.scan() {
+        let (k, v) = entry?;
+        println!("{:?} {:?}", k, v);
+    }
     Ok(())
 }
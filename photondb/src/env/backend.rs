@@ -0,0 +1,305 @@
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind, Result},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use super::*;
+
+/// Provides storage for immutable objects, like the page files of a page
+/// store kept in an object storage service.
+///
+/// Unlike [`Env`], this doesn't model files that are appended to. An object is
+/// written as a whole by [`Self::put_object`] and becomes visible atomically,
+/// and it is never modified afterwards, which matches the page files written
+/// by flushes and compactions. Reads fetch ranges of objects.
+///
+/// Returns errors with [`ErrorKind::NotFound`] for objects that don't exist.
+#[async_trait]
+pub trait PageStoreBackend: Send + Sync + 'static {
+    /// Writes the object with the name, replacing any object with the same
+    /// name.
+    async fn put_object(&self, name: &str, data: Vec<u8>) -> Result<()>;
+
+    /// Reads `len` bytes from the object at `offset`.
+    ///
+    /// Returns an error with [`ErrorKind::UnexpectedEof`] if the range is out
+    /// of the object.
+    async fn get_object_range(&self, name: &str, offset: u64, len: usize) -> Result<Vec<u8>>;
+
+    /// Returns the size of the object.
+    async fn object_size(&self, name: &str) -> Result<u64>;
+
+    /// Returns the names of the objects that start with the prefix, in
+    /// ascending order.
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Deletes the object. Deleting an object that doesn't exist succeeds.
+    async fn delete_object(&self, name: &str) -> Result<()>;
+}
+
+/// An implementation of [`PageStoreBackend`] that keeps objects in memory.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    objects: Arc<Mutex<BTreeMap<String, Arc<[u8]>>>>,
+}
+
+impl MemoryBackend {
+    /// Creates an empty backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, name: &str) -> Result<Arc<[u8]>> {
+        self.objects
+            .lock()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ErrorKind::NotFound.into())
+    }
+}
+
+#[async_trait]
+impl PageStoreBackend for MemoryBackend {
+    async fn put_object(&self, name: &str, data: Vec<u8>) -> Result<()> {
+        self.objects.lock().insert(name.to_owned(), data.into());
+        Ok(())
+    }
+
+    async fn get_object_range(&self, name: &str, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let object = self.get(name)?;
+        let start = usize::try_from(offset).map_err(|_| Error::from(ErrorKind::UnexpectedEof))?;
+        match start.checked_add(len) {
+            Some(end) if end <= object.len() => Ok(object[start..end].to_vec()),
+            _ => Err(ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    async fn object_size(&self, name: &str) -> Result<u64> {
+        Ok(self.get(name)?.len() as u64)
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<String>> {
+        let objects = self.objects.lock();
+        Ok(objects
+            .range(prefix.to_owned()..)
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_object(&self, name: &str) -> Result<()> {
+        self.objects.lock().remove(name);
+        Ok(())
+    }
+}
+
+/// An implementation of [`PageStoreBackend`] that keeps objects as files in a
+/// local directory.
+///
+/// Objects are written to a temporary file first, which is synced and then
+/// renamed to the name of the object, so that a partially written object is
+/// never visible. Object names must be valid file names.
+#[derive(Clone)]
+pub struct LocalBackend<E: Env> {
+    env: E,
+    dir: PathBuf,
+}
+
+/// The subdirectory of a [`LocalBackend`] that holds the objects being
+/// written.
+const LOCAL_TMP_DIR: &str = "tmp";
+
+impl<E: Env> LocalBackend<E> {
+    /// Opens a backend that keeps objects in the directory, which is created
+    /// if it doesn't exist.
+    pub async fn open<P: AsRef<Path>>(env: E, dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        env.create_dir_all(dir.join(LOCAL_TMP_DIR)).await?;
+        Ok(Self { env, dir })
+    }
+}
+
+#[async_trait]
+impl<E: Env> PageStoreBackend for LocalBackend<E> {
+    async fn put_object(&self, name: &str, data: Vec<u8>) -> Result<()> {
+        let tmp = self.dir.join(LOCAL_TMP_DIR).join(name);
+        let mut writer = self.env.open_sequential_writer(&tmp).await?;
+        writer.write_all(&data).await?;
+        writer.sync_all().await?;
+        drop(writer);
+        self.env.rename(&tmp, self.dir.join(name)).await?;
+        self.env.open_dir(&self.dir).await?.sync_all().await
+    }
+
+    async fn get_object_range(&self, name: &str, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let reader = self.env.open_positional_reader(self.dir.join(name)).await?;
+        let mut buf = vec![0; len];
+        reader.read_exact_at(&mut buf, offset).await?;
+        Ok(buf)
+    }
+
+    async fn object_size(&self, name: &str) -> Result<u64> {
+        Ok(self.env.metadata(self.dir.join(name)).await?.len)
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in self.env.read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if name.starts_with(prefix) {
+                    names.push(name.to_owned());
+                }
+            }
+        }
+        names.sort_unstable();
+        Ok(names)
+    }
+
+    async fn delete_object(&self, name: &str) -> Result<()> {
+        match self.env.remove_file(self.dir.join(name)).await {
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// An implementation of [`PageStoreBackend`] that caches the objects of a
+/// remote backend in a local one.
+///
+/// Writes go to the remote backend first and then to the cache. Reads are
+/// served by the cache, and an object missing from the cache is fetched from
+/// the remote backend as a whole, which is only correct because objects are
+/// never modified. Listing always asks the remote backend, which is the
+/// source of truth.
+pub struct CachedBackend<R, C> {
+    remote: R,
+    cache: C,
+}
+
+impl<R: PageStoreBackend, C: PageStoreBackend> CachedBackend<R, C> {
+    /// Creates a backend that caches the objects of `remote` in `cache`.
+    pub fn new(remote: R, cache: C) -> Self {
+        Self { remote, cache }
+    }
+
+    /// Returns the remote backend.
+    pub fn remote(&self) -> &R {
+        &self.remote
+    }
+
+    /// Returns the cache backend.
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    /// Fetches the object from the remote backend into the cache if it is not
+    /// cached.
+    async fn fill(&self, name: &str) -> Result<()> {
+        match self.cache.object_size(name).await {
+            Ok(_) => return Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let size = self.remote.object_size(name).await?;
+        let size = usize::try_from(size).map_err(|_| Error::from(ErrorKind::OutOfMemory))?;
+        let data = self.remote.get_object_range(name, 0, size).await?;
+        self.cache.put_object(name, data).await
+    }
+}
+
+#[async_trait]
+impl<R: PageStoreBackend, C: PageStoreBackend> PageStoreBackend for CachedBackend<R, C> {
+    async fn put_object(&self, name: &str, data: Vec<u8>) -> Result<()> {
+        self.remote.put_object(name, data.clone()).await?;
+        self.cache.put_object(name, data).await
+    }
+
+    async fn get_object_range(&self, name: &str, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.fill(name).await?;
+        self.cache.get_object_range(name, offset, len).await
+    }
+
+    async fn object_size(&self, name: &str) -> Result<u64> {
+        self.fill(name).await?;
+        self.cache.object_size(name).await
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<String>> {
+        self.remote.list_objects(prefix).await
+    }
+
+    async fn delete_object(&self, name: &str) -> Result<()> {
+        // The remote backend is the source of truth, so it goes first.
+        self.remote.delete_object(name).await?;
+        self.cache.delete_object(name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    async fn check_backend<B: PageStoreBackend>(backend: &B) {
+        backend.put_object("a1", b"hello".to_vec()).await.unwrap();
+        backend.put_object("a2", b"world".to_vec()).await.unwrap();
+        backend.put_object("b1", vec![]).await.unwrap();
+        assert_eq!(backend.object_size("a1").await.unwrap(), 5);
+        assert_eq!(
+            backend.get_object_range("a2", 1, 3).await.unwrap(),
+            b"orl".to_vec()
+        );
+        let err = backend.get_object_range("a2", 3, 3).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(backend.list_objects("a").await.unwrap(), ["a1", "a2"]);
+
+        // Objects are replaced as a whole.
+        backend.put_object("a1", b"hi".to_vec()).await.unwrap();
+        assert_eq!(backend.object_size("a1").await.unwrap(), 2);
+        backend.delete_object("a1").await.unwrap();
+        backend.delete_object("a1").await.unwrap();
+        let err = backend.object_size("a1").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(backend.list_objects("").await.unwrap(), ["a2", "b1"]);
+    }
+
+    #[photonio::test]
+    async fn memory_backend() {
+        check_backend(&MemoryBackend::new()).await;
+    }
+
+    #[photonio::test]
+    async fn local_backend() {
+        let dir = tempdir().unwrap();
+        let backend = LocalBackend::open(Photon, dir.path()).await.unwrap();
+        check_backend(&backend).await;
+    }
+
+    #[photonio::test]
+    async fn cached_backend() {
+        let dir = tempdir().unwrap();
+        let remote = MemoryBackend::new();
+        let cache = LocalBackend::open(Photon, dir.path()).await.unwrap();
+        let backend = CachedBackend::new(remote.clone(), cache);
+        check_backend(&backend).await;
+
+        // Objects written by others are fetched on the first read.
+        remote.put_object("c1", b"remote".to_vec()).await.unwrap();
+        assert!(backend.cache().list_objects("c").await.unwrap().is_empty());
+        assert_eq!(
+            backend.get_object_range("c1", 2, 4).await.unwrap(),
+            b"mote".to_vec()
+        );
+        assert_eq!(backend.cache().list_objects("c").await.unwrap(), ["c1"]);
+    }
+}
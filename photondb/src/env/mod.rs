@@ -1,8 +1,19 @@
 //! Environments for PhotonDB to interact with different runtimes and platforms.
 
-use std::{future::Future, io::Result, path::Path};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    io::Result,
+    path::Path,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
 
 pub use async_trait::async_trait;
+use parking_lot::{const_mutex, Condvar, Mutex, MutexGuard};
 pub use photonio::io::{Read, ReadAt, Write, WriteAt};
 
 mod stdenv;
@@ -11,6 +22,12 @@ pub use stdenv::Std;
 mod photon;
 pub use photon::Photon;
 
+mod sim;
+pub use sim::Sim;
+
+mod backend;
+pub use backend::{CachedBackend, LocalBackend, MemoryBackend, PageStoreBackend};
+
 /// Provides an environment to interact with a specific platform.
 #[async_trait]
 pub trait Env: Clone + Send + Sync + 'static {
@@ -39,6 +56,30 @@ pub trait Env: Clone + Send + Sync + 'static {
         F: Future + Send + 'static,
         F::Output: Send;
 
+    /// Gives other tasks a chance to run.
+    ///
+    /// This is called at the points where the interleaving of concurrent
+    /// operations matters. The default implementation returns a ready future,
+    /// for the environments that don't control the scheduling of tasks.
+    fn yield_now(&self) -> YieldNow {
+        YieldNow::ready()
+    }
+
+    /// Returns the current time of the environment.
+    ///
+    /// The default implementation returns the system time.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Waits until the duration has elapsed.
+    ///
+    /// The default implementation wakes the task from a dedicated timer
+    /// thread, for the environments whose runtimes don't provide timers.
+    async fn sleep(&self, duration: Duration) {
+        Sleep::new(duration).await;
+    }
+
     /// An async version of [`std::fs::rename`].
     async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
         &self,
@@ -73,6 +114,22 @@ pub trait Env: Clone + Send + Sync + 'static {
     async fn open_dir<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::Directory>;
 }
 
+/// The priority of an IO request.
+///
+/// This is a hint for environments to schedule IO requests, so that
+/// latency-critical reads are not queued behind background work. Environments
+/// that can't schedule IO requests ignore it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IoPriority {
+    /// Background work, like flushes and compactions of page files.
+    Low,
+    /// Foreground operations.
+    #[default]
+    Normal,
+    /// Latency-critical foreground operations.
+    High,
+}
+
 /// A reader that allows positional reads.
 #[async_trait]
 pub trait PositionalReader: Send + Sync + 'static {
@@ -86,6 +143,19 @@ pub trait PositionalReader: Send + Sync + 'static {
     /// Returns the number of bytes read.
     fn read_at<'a>(&'a self, buf: &'a mut [u8], pos: u64) -> Self::ReadAt<'a>;
 
+    /// Reads some bytes like [`Self::read_at`], with the priority of the read.
+    ///
+    /// The default implementation ignores the priority.
+    fn read_at_with_priority<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+        pos: u64,
+        priority: IoPriority,
+    ) -> Self::ReadAt<'a> {
+        let _ = priority;
+        self.read_at(buf, pos)
+    }
+
     /// Enable direct_io for the reader.
     /// return error if direct_io unsupported.
     fn direct_io_ify(&self) -> Result<()>;
@@ -100,6 +170,15 @@ pub trait PositionalReaderExt {
 
     /// Reads the exact number of bytes from this object at `pos` to fill `buf`.
     fn read_exact_at<'a>(&'a self, buf: &'a mut [u8], pos: u64) -> Self::ReadExactAt<'a>;
+
+    /// Reads the exact number of bytes like [`Self::read_exact_at`], with the
+    /// priority of the reads.
+    fn read_exact_at_with_priority<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+        pos: u64,
+        priority: IoPriority,
+    ) -> Self::ReadExactAt<'a>;
 }
 
 impl<T> PositionalReaderExt for T
@@ -108,22 +187,38 @@ where
 {
     type ReadExactAt<'a> = impl Future<Output = Result<()>> + 'a where Self: 'a;
 
-    fn read_exact_at<'a>(&'a self, mut buf: &'a mut [u8], mut pos: u64) -> Self::ReadExactAt<'a> {
-        async move {
-            while !buf.is_empty() {
-                match self.read_at(buf, pos).await {
-                    Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
-                    Ok(n) => {
-                        buf = &mut buf[n..];
-                        pos += n as u64;
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
-                    Err(e) => return Err(e),
-                }
+    fn read_exact_at<'a>(&'a self, buf: &'a mut [u8], pos: u64) -> Self::ReadExactAt<'a> {
+        read_exact_at(self, buf, pos, IoPriority::Normal)
+    }
+
+    fn read_exact_at_with_priority<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+        pos: u64,
+        priority: IoPriority,
+    ) -> Self::ReadExactAt<'a> {
+        read_exact_at(self, buf, pos, priority)
+    }
+}
+
+async fn read_exact_at<T: PositionalReader>(
+    reader: &T,
+    mut buf: &mut [u8],
+    mut pos: u64,
+    priority: IoPriority,
+) -> Result<()> {
+    while !buf.is_empty() {
+        match reader.read_at_with_priority(buf, pos, priority).await {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                pos += n as u64;
             }
-            Ok(())
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
     }
+    Ok(())
 }
 
 /// A writer that allows sequential writes.
@@ -139,6 +234,18 @@ pub trait SequentialWriter: Send + Sync + 'static {
     /// Returns the number of bytes written.
     fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::Write<'a>;
 
+    /// Writes some bytes like [`Self::write`], with the priority of the write.
+    ///
+    /// The default implementation ignores the priority.
+    fn write_with_priority<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        priority: IoPriority,
+    ) -> Self::Write<'a> {
+        let _ = priority;
+        self.write(buf)
+    }
+
     ///  Synchronizes all modified content but without metadata of this file to
     /// disk.
     ///
@@ -165,6 +272,14 @@ pub trait SequentialWriterExt {
 
     /// Writes all bytes from `buf` into this object.
     fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> Self::WriteAll<'a>;
+
+    /// Writes all bytes like [`Self::write_all`], with the priority of the
+    /// writes.
+    fn write_all_with_priority<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        priority: IoPriority,
+    ) -> Self::WriteAll<'a>;
 }
 
 /// Extension methods for [`SequentialWriter`].
@@ -176,17 +291,271 @@ where
     where
         Self: 'a;
 
-    fn write_all<'a>(&'a mut self, mut buf: &'a [u8]) -> Self::WriteAll<'a> {
-        async move {
-            while !buf.is_empty() {
-                match self.write(buf).await {
-                    Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
-                    Ok(n) => buf = &buf[n..],
-                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
-                    Err(e) => return Err(e),
+    fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> Self::WriteAll<'a> {
+        write_all(self, buf, IoPriority::Normal)
+    }
+
+    fn write_all_with_priority<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        priority: IoPriority,
+    ) -> Self::WriteAll<'a> {
+        write_all(self, buf, priority)
+    }
+}
+
+async fn write_all<T: SequentialWriter>(
+    writer: &mut T,
+    mut buf: &[u8],
+    priority: IoPriority,
+) -> Result<()> {
+    while !buf.is_empty() {
+        match writer.write_with_priority(buf, priority).await {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => buf = &buf[n..],
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Schedules IO requests by their priorities, for the environments that
+/// submit them to the same devices.
+///
+/// Requests with [`IoPriority::Low`] wait until there is no request with
+/// [`IoPriority::High`] in flight, so that background work doesn't compete
+/// with latency-critical reads for the devices. Other requests go ahead
+/// right away. Background work may be delayed for as long as high-priority
+/// requests keep coming.
+struct IoScheduler {
+    high: AtomicUsize,
+    waiters: Mutex<Vec<Waker>>,
+}
+
+static IO_SCHEDULER: IoScheduler = IoScheduler {
+    high: AtomicUsize::new(0),
+    waiters: const_mutex(Vec::new()),
+};
+
+impl IoScheduler {
+    /// Runs the IO request with the priority.
+    async fn schedule<F: Future>(&'static self, priority: IoPriority, f: F) -> F::Output {
+        match priority {
+            IoPriority::High => {
+                self.high.fetch_add(1, Ordering::AcqRel);
+                let _guard = HighPriorityGuard(self);
+                f.await
+            }
+            IoPriority::Normal => f.await,
+            IoPriority::Low => {
+                WaitHighPriority(self).await;
+                f.await
+            }
+        }
+    }
+
+    fn finish_high(&self) {
+        if self.high.fetch_sub(1, Ordering::AcqRel) == 1 {
+            for waker in self.waiters.lock().drain(..) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Finishes a high-priority request when dropped, even if it's cancelled.
+struct HighPriorityGuard(&'static IoScheduler);
+
+impl Drop for HighPriorityGuard {
+    fn drop(&mut self) {
+        self.0.finish_high();
+    }
+}
+
+/// A future that resolves once there is no high-priority request in flight.
+struct WaitHighPriority(&'static IoScheduler);
+
+impl Future for WaitHighPriority {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let scheduler = self.0;
+        if scheduler.high.load(Ordering::Acquire) == 0 {
+            return Poll::Ready(());
+        }
+        let mut waiters = scheduler.waiters.lock();
+        // Check again with the lock held, since the last high-priority request
+        // may have finished and woken the waiters in between.
+        if scheduler.high.load(Ordering::Acquire) == 0 {
+            return Poll::Ready(());
+        }
+        if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A future returned by [`Env::yield_now`].
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl YieldNow {
+    /// Returns a future that completes right away.
+    pub fn ready() -> Self {
+        YieldNow { yielded: true }
+    }
+
+    /// Returns a future that lets the other runnable tasks run once before it
+    /// completes.
+    pub fn new() -> Self {
+        YieldNow { yielded: false }
+    }
+}
+
+impl Default for YieldNow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Wakes sleeping tasks for the environments whose runtimes don't provide
+/// timers, so that sleeps don't block the threads running other tasks.
+///
+/// A dedicated thread, started on the first sleep, waits until the earliest
+/// deadline and wakes the tasks whose deadlines have passed.
+struct Timer {
+    state: Mutex<TimerState>,
+    cond: Condvar,
+}
+
+struct TimerState {
+    started: bool,
+    next_id: usize,
+    sleepers: BTreeMap<(Instant, usize), Waker>,
+}
+
+static TIMER: Timer = Timer {
+    state: const_mutex(TimerState {
+        started: false,
+        next_id: 0,
+        sleepers: BTreeMap::new(),
+    }),
+    cond: Condvar::new(),
+};
+
+impl Timer {
+    /// Registers the waker to be woken at the deadline.
+    ///
+    /// Returns the id of the sleeper, which replaces the waker registered with
+    /// the same id before.
+    fn register(&'static self, deadline: Instant, id: Option<usize>, waker: &Waker) -> usize {
+        let mut state = self.state.lock();
+        if !state.started {
+            thread::Builder::new()
+                .name("photondb-timer".into())
+                .spawn(move || self.run())
+                .expect("spawn timer thread");
+            state.started = true;
+        }
+        let id = id.unwrap_or_else(|| {
+            state.next_id += 1;
+            state.next_id
+        });
+        let earliest = state
+            .sleepers
+            .keys()
+            .next()
+            .map_or(true, |&(first, _)| deadline < first);
+        state.sleepers.insert((deadline, id), waker.clone());
+        if earliest {
+            self.cond.notify_one();
+        }
+        id
+    }
+
+    fn cancel(&self, deadline: Instant, id: usize) {
+        self.state.lock().sleepers.remove(&(deadline, id));
+    }
+
+    fn run(&self) {
+        let mut wakers = Vec::new();
+        let mut state = self.state.lock();
+        loop {
+            let now = Instant::now();
+            while let Some(entry) = state.sleepers.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                wakers.push(entry.remove());
+            }
+            if !wakers.is_empty() {
+                // Wake the tasks without the lock, since they may sleep again
+                // right away.
+                MutexGuard::unlocked(&mut state, || wakers.drain(..).for_each(Waker::wake));
+                continue;
+            }
+            match state.sleepers.keys().next() {
+                Some(&(deadline, _)) => {
+                    self.cond.wait_until(&mut state, deadline);
                 }
+                None => self.cond.wait(&mut state),
             }
-            Ok(())
+        }
+    }
+}
+
+/// A future that resolves once the duration has elapsed, without blocking the
+/// current thread.
+pub(in crate::env) struct Sleep {
+    deadline: Instant,
+    id: Option<usize>,
+}
+
+impl Sleep {
+    pub(in crate::env) fn new(duration: Duration) -> Self {
+        Sleep {
+            deadline: Instant::now() + duration,
+            id: None,
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            if let Some(id) = self.id.take() {
+                TIMER.cancel(self.deadline, id);
+            }
+            return Poll::Ready(());
+        }
+        let id = TIMER.register(self.deadline, self.id, cx.waker());
+        self.id = Some(id);
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            TIMER.cancel(self.deadline, id);
         }
     }
 }
@@ -233,3 +602,48 @@ pub trait Directory {
     /// Sync_all directory.
     async fn sync_all(&self) -> Result<()>;
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::{channel::oneshot, task::noop_waker_ref};
+
+    use super::*;
+
+    #[test]
+    fn io_scheduler() {
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        let (tx, rx) = oneshot::channel::<()>();
+        let mut high = Box::pin(IO_SCHEDULER.schedule(IoPriority::High, rx));
+        assert!(high.as_mut().poll(cx).is_pending());
+
+        // Only low-priority requests wait for high-priority ones.
+        let mut low = Box::pin(IO_SCHEDULER.schedule(IoPriority::Low, async {}));
+        assert!(low.as_mut().poll(cx).is_pending());
+        let mut normal = Box::pin(IO_SCHEDULER.schedule(IoPriority::Normal, async {}));
+        assert!(normal.as_mut().poll(cx).is_ready());
+
+        tx.send(()).unwrap();
+        assert!(high.as_mut().poll(cx).is_ready());
+        assert!(low.as_mut().poll(cx).is_ready());
+    }
+
+    #[test]
+    fn sleep_without_blocking() {
+        // The sleeps wait concurrently on the same thread.
+        let start = Instant::now();
+        let sleeps = (0..10).map(|_| Sleep::new(Duration::from_millis(100)));
+        futures::executor::block_on(futures::future::join_all(sleeps));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_millis(500));
+
+        // A cancelled sleep is not woken anymore.
+        let cx = &mut Context::from_waker(noop_waker_ref());
+        let mut sleep = Sleep::new(Duration::from_secs(60));
+        assert!(Pin::new(&mut sleep).poll(cx).is_pending());
+        let key = (sleep.deadline, sleep.id.unwrap());
+        assert!(TIMER.state.lock().sleepers.contains_key(&key));
+        drop(sleep);
+        assert!(!TIMER.state.lock().sleepers.contains_key(&key));
+    }
+}
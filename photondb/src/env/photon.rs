@@ -108,7 +108,15 @@ impl super::SequentialWriter for SequentialWriter {
     type Write<'a> = impl Future<Output = Result<usize>> + 'a + Send;
 
     fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::Write<'a> {
-        self.0.write(buf)
+        IO_SCHEDULER.schedule(IoPriority::Normal, self.0.write(buf))
+    }
+
+    fn write_with_priority<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        priority: IoPriority,
+    ) -> Self::Write<'a> {
+        IO_SCHEDULER.schedule(priority, self.0.write(buf))
     }
 
     // TODO: sync range(sync start->current => sync last_offset->current)
@@ -136,7 +144,16 @@ impl super::PositionalReader for PositionalReader {
     type ReadAt<'a> = impl Future<Output = Result<usize>> + 'a;
 
     fn read_at<'a>(&'a self, buf: &'a mut [u8], pos: u64) -> Self::ReadAt<'a> {
-        self.0.read_at(buf, pos)
+        IO_SCHEDULER.schedule(IoPriority::Normal, self.0.read_at(buf, pos))
+    }
+
+    fn read_at_with_priority<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+        pos: u64,
+        priority: IoPriority,
+    ) -> Self::ReadAt<'a> {
+        IO_SCHEDULER.schedule(priority, self.0.read_at(buf, pos))
     }
 
     fn direct_io_ify(&self) -> Result<()> {
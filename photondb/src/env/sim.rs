@@ -0,0 +1,408 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    future::Future,
+    io::Result,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use futures::task::{waker, ArcWake};
+use parking_lot::Mutex;
+
+use super::*;
+
+/// An implementation of [`Env`] that runs all tasks on the current thread in a
+/// deterministic order.
+///
+/// Whenever more than one task is runnable, the next task to poll is chosen by
+/// a pseudo-random generator initialized with the given seed. As a result, the
+/// same seed reproduces the same interleaving of tasks, which is useful to
+/// replay races found by tests.
+///
+/// Time is simulated as well. The clock of [`Env::now`] only advances when no
+/// task is runnable, to the earliest deadline of the tasks in [`Env::sleep`].
+///
+/// File operations are delegated to [`Std`].
+///
+/// Tasks only run inside [`Sim::block_on`], and background tasks that are
+/// still pending when it returns are not polled anymore.
+#[derive(Clone)]
+pub struct Sim {
+    scheduler: Arc<Scheduler>,
+    start: Instant,
+}
+
+impl Sim {
+    /// Creates a simulation environment with the given seed.
+    pub fn new(seed: u64) -> Self {
+        let state = State {
+            rng: seed,
+            next_id: MAIN_TASK_ID + 1,
+            tasks: BTreeMap::new(),
+            runnable: BTreeSet::new(),
+            clock: Duration::ZERO,
+            next_timer_id: 0,
+            timers: BTreeMap::new(),
+        };
+        Self {
+            scheduler: Arc::new(Scheduler {
+                state: Mutex::new(state),
+            }),
+            start: Instant::now(),
+        }
+    }
+
+    /// Runs the future and all tasks spawned in this environment until the
+    /// future completes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no task is runnable or sleeping before the future completes,
+    /// which means that all tasks are waiting for each other.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        futures::pin_mut!(future);
+        self.scheduler.wake(MAIN_TASK_ID);
+        loop {
+            let id = self
+                .scheduler
+                .pick()
+                .expect("deadlock: no task is runnable");
+            let waker = self.scheduler.waker(id);
+            let mut cx = Context::from_waker(&waker);
+            if id == MAIN_TASK_ID {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+                continue;
+            }
+
+            // The task is taken out during polling, so that it can wake or
+            // spawn other tasks.
+            let Some(mut task) = self.scheduler.state.lock().tasks.remove(&id) else {
+                continue;
+            };
+            if task.as_mut().poll(&mut cx).is_pending() {
+                self.scheduler.state.lock().tasks.insert(id, task);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Sim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.scheduler.state.lock();
+        f.debug_struct("Sim")
+            .field("tasks", &state.tasks.len())
+            .field("runnable", &state.runnable.len())
+            .field("clock", &state.clock)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Env for Sim {
+    type PositionalReader = <Std as Env>::PositionalReader;
+    type SequentialWriter = <Std as Env>::SequentialWriter;
+    type JoinHandle<T: Send> = JoinHandle<T>;
+    type Directory = <Std as Env>::Directory;
+
+    async fn open_positional_reader<P>(&self, path: P) -> Result<Self::PositionalReader>
+    where
+        P: AsRef<Path> + Send,
+    {
+        Std.open_positional_reader(path).await
+    }
+
+    async fn open_sequential_writer<P>(&self, path: P) -> Result<Self::SequentialWriter>
+    where
+        P: AsRef<Path> + Send,
+    {
+        Std.open_sequential_writer(path).await
+    }
+
+    fn spawn_background<F>(&self, f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let slot = Arc::new(Mutex::new(JoinSlot {
+            output: None,
+            waker: None,
+        }));
+        let task_slot = slot.clone();
+        let task = async move {
+            let output = f.await;
+            let mut slot = task_slot.lock();
+            slot.output = Some(output);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        };
+        self.scheduler.spawn(Box::pin(task));
+        JoinHandle { slot }
+    }
+
+    fn yield_now(&self) -> YieldNow {
+        YieldNow::new()
+    }
+
+    fn now(&self) -> Instant {
+        self.start + self.scheduler.state.lock().clock
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        Sleep {
+            scheduler: self.scheduler.clone(),
+            deadline: self.scheduler.state.lock().clock + duration,
+            id: None,
+        }
+        .await;
+    }
+
+    async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> Result<()> {
+        Std.rename(from, to).await
+    }
+
+    async fn remove_file<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        Std.remove_file(path).await
+    }
+
+    async fn create_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        Std.create_dir_all(path).await
+    }
+
+    async fn remove_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        Std.remove_dir_all(path).await
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<std::fs::ReadDir> {
+        Std.read_dir(path)
+    }
+
+    async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Metadata> {
+        Std.metadata(path).await
+    }
+
+    async fn open_dir<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::Directory> {
+        Std.open_dir(path).await
+    }
+}
+
+/// The id of the future passed to [`Sim::block_on`].
+const MAIN_TASK_ID: usize = 0;
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct Scheduler {
+    state: Mutex<State>,
+}
+
+struct State {
+    rng: u64,
+    next_id: usize,
+    tasks: BTreeMap<usize, Task>,
+    // Ordered so that the pick only depends on the seed.
+    runnable: BTreeSet<usize>,
+    // The time elapsed since the start of the simulation.
+    clock: Duration,
+    next_timer_id: usize,
+    timers: BTreeMap<(Duration, usize), Waker>,
+}
+
+impl Scheduler {
+    fn spawn(&self, task: Task) {
+        let mut state = self.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.tasks.insert(id, task);
+        state.runnable.insert(id);
+    }
+
+    fn wake(&self, id: usize) {
+        self.state.lock().runnable.insert(id);
+    }
+
+    /// Removes a random task from the runnable tasks.
+    ///
+    /// If no task is runnable, advances the clock to wake the sleeping tasks
+    /// with the earliest deadline.
+    fn pick(&self) -> Option<usize> {
+        loop {
+            let mut state = self.state.lock();
+            if !state.runnable.is_empty() {
+                let index = state.next_u64() as usize % state.runnable.len();
+                let id = *state.runnable.iter().nth(index).unwrap();
+                state.runnable.remove(&id);
+                return Some(id);
+            }
+            let (&(deadline, _), _) = state.timers.first_key_value()?;
+            state.clock = deadline;
+            let mut wakers = Vec::new();
+            while let Some(entry) = state.timers.first_entry() {
+                if entry.key().0 > deadline {
+                    break;
+                }
+                wakers.push(entry.remove());
+            }
+            // The wakers lock the state to schedule the tasks.
+            drop(state);
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+
+    fn waker(self: &Arc<Self>, id: usize) -> Waker {
+        waker(Arc::new(TaskWaker {
+            id,
+            scheduler: Arc::downgrade(self),
+        }))
+    }
+}
+
+impl State {
+    // SplitMix64, see https://prng.di.unimi.it/splitmix64.c
+    fn next_u64(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    scheduler: Weak<Scheduler>,
+}
+
+impl ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Some(scheduler) = arc_self.scheduler.upgrade() {
+            scheduler.wake(arc_self.id);
+        }
+    }
+}
+
+struct JoinSlot<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+pub struct JoinHandle<T> {
+    slot: Arc<Mutex<JoinSlot<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock();
+        match slot.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A future that resolves once the simulated clock reaches the deadline.
+struct Sleep {
+    scheduler: Arc<Scheduler>,
+    deadline: Duration,
+    id: Option<usize>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.scheduler.state.lock();
+        if state.clock >= this.deadline {
+            if let Some(id) = this.id.take() {
+                state.timers.remove(&(this.deadline, id));
+            }
+            return Poll::Ready(());
+        }
+        let id = *this.id.get_or_insert_with(|| {
+            state.next_timer_id += 1;
+            state.next_timer_id
+        });
+        state.timers.insert((this.deadline, id), cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.scheduler
+                .state
+                .lock()
+                .timers
+                .remove(&(self.deadline, id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_tasks(seed: u64) -> Vec<usize> {
+        let env = Sim::new(seed);
+        env.block_on(async {
+            let trace = Arc::new(Mutex::new(Vec::new()));
+            let mut handles = Vec::new();
+            for i in 0..4 {
+                let env_clone = env.clone();
+                let trace = trace.clone();
+                handles.push(env.spawn_background(async move {
+                    for _ in 0..4 {
+                        trace.lock().push(i);
+                        env_clone.yield_now().await;
+                    }
+                    i
+                }));
+            }
+            for (i, handle) in handles.into_iter().enumerate() {
+                assert_eq!(handle.await, i);
+            }
+            let trace = trace.lock().clone();
+            trace
+        })
+    }
+
+    #[test]
+    fn sim_clock() {
+        let env = Sim::new(0);
+        let start = env.now();
+        env.block_on(async {
+            let env_clone = env.clone();
+            let handle = env.spawn_background(async move {
+                env_clone.sleep(Duration::from_secs(60)).await;
+                env_clone.now()
+            });
+            env.sleep(Duration::from_secs(1)).await;
+            assert_eq!(env.now() - start, Duration::from_secs(1));
+            assert_eq!(handle.await - start, Duration::from_secs(60));
+        });
+    }
+
+    #[test]
+    fn sim_deterministic_schedule() {
+        for seed in 0..8 {
+            let trace = run_tasks(seed);
+            assert_eq!(trace.len(), 16);
+            assert_eq!(trace, run_tasks(seed));
+        }
+    }
+}
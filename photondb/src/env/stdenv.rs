@@ -106,8 +106,17 @@ impl super::PositionalReader for PositionalReader {
 
     #[cfg(unix)]
     fn read_at<'a>(&'a self, buf: &'a mut [u8], offset: u64) -> Self::ReadAt<'a> {
-        use std::os::unix::fs::FileExt;
-        async move { self.0.read_at(buf, offset) }
+        IO_SCHEDULER.schedule(IoPriority::Normal, read_at(&self.0, buf, offset))
+    }
+
+    #[cfg(unix)]
+    fn read_at_with_priority<'a>(
+        &'a self,
+        buf: &'a mut [u8],
+        offset: u64,
+        priority: IoPriority,
+    ) -> Self::ReadAt<'a> {
+        IO_SCHEDULER.schedule(priority, read_at(&self.0, buf, offset))
     }
 
     fn direct_io_ify(&self) -> Result<()> {
@@ -122,8 +131,15 @@ impl super::SequentialWriter for SequentialWriter {
     type Write<'a> = impl Future<Output = Result<usize>> + 'a + Send;
 
     fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::Write<'a> {
-        use std::io::Write as _;
-        async move { self.0.write(buf) }
+        IO_SCHEDULER.schedule(IoPriority::Normal, write(&self.0, buf))
+    }
+
+    fn write_with_priority<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        priority: IoPriority,
+    ) -> Self::Write<'a> {
+        IO_SCHEDULER.schedule(priority, write(&self.0, buf))
     }
 
     async fn sync_data(&mut self) -> Result<()> {
@@ -143,6 +159,17 @@ impl super::SequentialWriter for SequentialWriter {
     }
 }
 
+#[cfg(unix)]
+async fn read_at(file: &File, buf: &mut [u8], offset: u64) -> Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+async fn write(mut file: &File, buf: &[u8]) -> Result<usize> {
+    use std::io::Write as _;
+    file.write(buf)
+}
+
 pub struct JoinHandle<T> {
     handle: Option<thread::JoinHandle<T>>,
 }
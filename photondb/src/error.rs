@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::page_store::Error as PageError;
+use crate::page_store::{is_retryable, Error as PageError};
 
 /// A list of possible errors returned by PhotonDB.
 #[derive(Error, Debug)]
@@ -8,21 +8,190 @@ pub enum Error {
     /// Some data is corrupted.
     #[error("Corrupted")]
     Corrupted,
+    /// A cached page is corrupted in memory, which is only detected if
+    /// [`PageStoreOptions::verify_cache_integrity`] is enabled.
+    ///
+    /// [`PageStoreOptions::verify_cache_integrity`]: crate::PageStoreOptions::verify_cache_integrity
+    #[error("Corrupted in memory")]
+    CorruptedInMemory,
+    /// The page table maps a page to a page that is recorded with another id,
+    /// which means the mapping is corrupted on disk or in memory.
+    #[error("CorruptedMapping of page {page_id} to a page of {mismatched_id}")]
+    CorruptedMapping {
+        /// The id of the requested page.
+        page_id: u64,
+        /// The id recorded with the page that the requested page is mapped to.
+        mismatched_id: u64,
+    },
     /// Over Memory Limit(cache).
     #[error("MemoryLimit")]
     MemoryLimit,
     /// Put data is too large.
     #[error("TooLargeSize")]
     TooLargeSize,
+    /// Invalid argument.
+    #[error("InvalidArgument")]
+    InvalidArgument,
+    /// The table already exists.
+    #[error("AlreadyExists")]
+    AlreadyExists,
+    /// The table does not exist.
+    #[error("NotFound")]
+    NotFound,
+    /// The table is opened by another process, or by another table in this
+    /// process.
+    #[error("AlreadyLocked {path:?} by process {pid:?}")]
+    AlreadyLocked {
+        /// The path of the lock file.
+        path: std::path::PathBuf,
+        /// The process that holds the lock, if it is recorded in the lock
+        /// file.
+        pid: Option<u32>,
+    },
+    /// The table is sealed and rejects writes.
+    #[error("Sealed")]
+    Sealed,
+    /// Writes are rejected because the background jobs are paused.
+    ///
+    /// This is only returned if [`TableOptions::reject_writes_while_paused`]
+    /// is set, see [`Table::pause_background`].
+    ///
+    /// [`TableOptions::reject_writes_while_paused`]: crate::TableOptions::reject_writes_while_paused
+    /// [`Table::pause_background`]: crate::Table::pause_background
+    #[error("Paused")]
+    Paused,
+    /// Writes are rejected because a flush has failed with a full disk.
+    ///
+    /// Writes are accepted again once a flush succeeds after some space is
+    /// freed.
+    #[error("NoSpace")]
+    NoSpace,
+    /// The transaction is aborted because some of the keys it read have been
+    /// changed by others since they were read.
+    #[error("TxnConflict")]
+    TxnConflict,
+    /// The write raced with another write of the same key with the same LSN,
+    /// which has been applied first.
+    ///
+    /// Entries of the same key must be written with increasing LSNs, so the
+    /// write is rejected instead of stacking another entry with the same LSN
+    /// on top of it. It can be retried with an LSN larger than `latest`.
+    ///
+    /// Debug builds also return this for any write whose LSN is not larger
+    /// than the latest LSN of the key.
+    #[error("LsnConflict with latest LSN {latest}")]
+    LsnConflict {
+        /// The LSN of the latest entry of the key.
+        latest: u64,
+    },
+    /// The write comes with an LSN smaller than the largest LSN written
+    /// before the table was opened, which usually means that the source of
+    /// LSNs has gone backwards, like a clock stepped back or a counter reset.
+    ///
+    /// This is only returned if [`TableOptions::reject_lsn_regression`] is
+    /// set.
+    ///
+    /// [`TableOptions::reject_lsn_regression`]: crate::TableOptions::reject_lsn_regression
+    #[error("LsnRegression below LSN {high_water}")]
+    LsnRegression {
+        /// The largest LSN written before the table was opened.
+        high_water: u64,
+    },
+    /// The operation conflicted with concurrent changes of the table, like
+    /// page splits and consolidations, and has to be retried.
+    ///
+    /// Operations retry on this internally, so it's only reported as the last
+    /// error of [`Error::Timeout`].
+    #[error("Again")]
+    Again,
+    /// The operation kept conflicting with others until the deadline.
+    #[error("Timeout after {retries} retries: {last}")]
+    Timeout {
+        /// The number of retries before the operation gave up.
+        retries: usize,
+        /// The error of the last attempt.
+        #[source]
+        last: Box<Error>,
+    },
+    /// The operation needs more bytes than its budget, like
+    /// [`ReadOptions::max_bytes`] or [`WriteOptions::max_bytes`].
+    ///
+    /// [`ReadOptions::max_bytes`]: crate::ReadOptions::max_bytes
+    /// [`WriteOptions::max_bytes`]: crate::WriteOptions::max_bytes
+    #[error("BudgetExceeded after {consumed} bytes")]
+    BudgetExceeded {
+        /// The number of bytes that the operation has read, or that the write
+        /// would take.
+        consumed: u64,
+    },
+    /// The table rewritten by [`Table::rewrite_with_options`] doesn't have as
+    /// many live keys as the source.
+    ///
+    /// [`Table::rewrite_with_options`]: crate::Table::rewrite_with_options
+    #[error("RewriteMismatch of {src_keys} keys to {dst_keys} keys")]
+    RewriteMismatch {
+        /// The number of live keys in the source.
+        src_keys: u64,
+        /// The number of live keys in the destination.
+        dst_keys: u64,
+    },
+    /// An IO error.
+    #[error("Io {source}")]
+    Io {
+        /// The kind of the error.
+        kind: std::io::ErrorKind,
+        /// Whether the error is transient, so that the operation may succeed
+        /// if it's retried later.
+        ///
+        /// Reads of page files have been retried according to
+        /// [`PageStoreOptions::io_retry_policy`] before this is returned.
+        ///
+        /// [`PageStoreOptions::io_retry_policy`]: crate::PageStoreOptions::io_retry_policy
+        retryable: bool,
+        /// The underlying error.
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io {
+            kind: err.kind(),
+            retryable: is_retryable(err.kind()),
+            source: err,
+        }
+    }
 }
 
 impl From<PageError> for Error {
     fn from(err: PageError) -> Self {
         match err {
+            PageError::Again => Self::Again,
             PageError::Corrupted => Self::Corrupted,
+            PageError::CorruptedInMemory => Self::CorruptedInMemory,
+            PageError::CorruptedMapping {
+                page_id,
+                mismatched_id,
+            } => Self::CorruptedMapping {
+                page_id,
+                mismatched_id,
+            },
             PageError::MemoryLimit => Self::MemoryLimit,
             PageError::TooLargeSize => Self::TooLargeSize,
-            e => unreachable!("unexpected error: {:?}", e),
+            PageError::InvalidArgument => Self::InvalidArgument,
+            PageError::Sealed => Self::Sealed,
+            PageError::Paused => Self::Paused,
+            PageError::NoSpace => Self::NoSpace,
+            PageError::TxnConflict => Self::TxnConflict,
+            PageError::LsnConflict { latest } => Self::LsnConflict { latest },
+            PageError::LsnRegression { high_water } => Self::LsnRegression { high_water },
+            PageError::Timeout { retries, last } => Self::Timeout {
+                retries,
+                last: Box::new((*last).into()),
+            },
+            PageError::BudgetExceeded { consumed } => Self::BudgetExceeded { consumed },
+            PageError::Io(e) => e.into(),
         }
     }
 }
@@ -0,0 +1,104 @@
+//! Fault injection to test crash recovery.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use parking_lot::Mutex;
+
+/// A named point in the write, split, flush, and manifest code where a fault
+/// can be injected.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FaultPoint {
+    /// After a delta page is installed on a leaf page, but before the write
+    /// returns. The delta is only in the write buffer at this point.
+    AfterDeltaInstall,
+    /// After a split delta is installed on a non-root page, but before the
+    /// index of the right page is inserted to the parent.
+    SplitBeforeParentUpdate,
+    /// After the children of the root are built during a root split, but
+    /// before the root is replaced.
+    SplitBeforeRootReplace,
+    /// After a write buffer is written to a page file, but before the file is
+    /// recorded in the manifest.
+    FlushBeforeManifest,
+    /// In the middle of writing a manifest record, after its length is
+    /// written but before its payload is.
+    ManifestWrite,
+}
+
+/// What to do when an armed [`FaultPoint`] is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Panics the current thread.
+    Panic,
+    /// Aborts the whole process, like a crash.
+    Abort,
+}
+
+/// Injects faults at [`FaultPoint`]s to test crash recovery.
+///
+/// A point is armed with an action, which is triggered the next time the
+/// point is reached. The point is disarmed once triggered, so a table reopened
+/// with the same injector doesn't trigger it again.
+///
+/// Faults are only injected in debug builds. In release builds, all points are
+/// ignored.
+#[derive(Clone, Default)]
+pub struct FaultInjector {
+    faults: Arc<Mutex<HashMap<FaultPoint, FaultAction>>>,
+}
+
+impl FaultInjector {
+    /// Creates an injector with no armed points.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Arms the point to trigger the action the next time it is reached.
+    pub fn arm(&self, point: FaultPoint, action: FaultAction) {
+        self.faults.lock().insert(point, action);
+    }
+
+    /// Disarms the point.
+    pub fn disarm(&self, point: FaultPoint) {
+        self.faults.lock().remove(&point);
+    }
+
+    /// Returns true if the point is armed.
+    pub fn is_armed(&self, point: FaultPoint) -> bool {
+        self.faults.lock().contains_key(&point)
+    }
+
+    #[cfg(debug_assertions)]
+    fn trigger(&self, point: FaultPoint) {
+        let Some(action) = self.faults.lock().remove(&point) else {
+            return;
+        };
+        match action {
+            FaultAction::Panic => panic!("fault injected at {point:?}"),
+            FaultAction::Abort => {
+                log::error!("fault injected at {point:?}, abort");
+                std::process::abort()
+            }
+        }
+    }
+}
+
+impl fmt::Debug for FaultInjector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultInjector")
+            .field("faults", &*self.faults.lock())
+            .finish()
+    }
+}
+
+/// Triggers the fault armed at the point, if any.
+#[inline]
+pub(crate) fn inject(injector: Option<&FaultInjector>, point: FaultPoint) {
+    #[cfg(debug_assertions)]
+    if let Some(injector) = injector {
+        injector.trigger(point);
+    }
+    #[cfg(not(debug_assertions))]
+    let _ = (injector, point);
+}
@@ -40,17 +40,30 @@ pub mod raw;
 pub mod std;
 
 pub mod photon;
-pub use photon::{Table, TableStats};
+pub use photon::{
+    diff_tables, DiffReport, DropPrefixStats, DuplicateKeyRule, KeyDiff, PinnedBuffer,
+    ReplicationRecord, RewriteStats, ScanChunk, ScanFileStats, ScanSegment, Table, TableStats,
+};
 
 mod error;
 pub use error::{Error, Result};
 
+mod fault;
+pub use fault::{FaultAction, FaultInjector, FaultPoint};
+
 mod tree;
-pub use tree::{Options as TableOptions, PageIter, ReadOptions, TreeStats, WriteOptions};
+pub use tree::{
+    CompactionPlan, ConsolidateStats, DecisionEvent, DecisionKind, DecisionObserver, DeletedRegion,
+    IndexEntry, InnerConsolidation, KeyStatus, OpenMode, Options as TableOptions,
+    OptionsPatch as TableOptionsPatch, PageIter, ReadOptions, ScanOptions, ScanStats,
+    SizeHistogram, SplitGuard, TreeStats, ValueCodec, WriteOptions,
+};
 
 mod page_store;
 pub use page_store::{
-    ChecksumType, Compression, FlushOptions, Options as PageStoreOptions, StoreStats,
+    BackgroundStats, CandidateInfo, ChecksumType, Compression, FileStat, FlushOptions,
+    IoRetryPolicy, LatencyStats, Options as PageStoreOptions, RecoveryPhase, RecoveryProgress,
+    RecoveryReport, StoreStats, VerifyReport,
 };
 
 mod page;
@@ -64,37 +77,33 @@ mod tests {
     use tempfile::tempdir;
 
     use super::*;
-    use crate::page_store::{ChecksumType, Compression};
-
-    const OPTIONS: TableOptions = TableOptions {
-        page_size: 128,
-        page_chain_length: 4,
-        page_store: PageStoreOptions {
-            write_buffer_capacity: 1 << 20,
-            max_write_buffers: 8,
-            use_direct_io: false,
-            disable_space_reclaiming: false,
-            max_space_amplification_percent: 10,
-            space_used_high: u64::MAX,
-            file_base_size: 1 << 20,
-            cache_capacity: 2 << 10,
-            cache_estimated_entry_charge: 1,
-            cache_file_reader_capacity: 1000,
-            cache_strict_capacity_limit: false,
-            prepopulate_cache_on_flush: true,
-            compression_on_flush: Compression::SNAPPY,
-            compression_on_cold_compact: Compression::ZSTD,
-            page_checksum_type: ChecksumType::CRC32,
-            avoid_flush_during_shutdown: false,
-        },
-    };
-
-    async fn must_put(table: &Table, i: u64, lsn: u64) {
+
+    /// Returns the options with small pages, write buffers and caches, so that
+    /// small tests exercise splits, flushes and cache evictions.
+    pub(crate) fn options() -> TableOptions {
+        TableOptions {
+            page_size: 128,
+            verify_index_ranges: true,
+            page_store: PageStoreOptions {
+                write_buffer_capacity: 1 << 20,
+                max_space_amplification_percent: 10,
+                file_base_size: 1 << 20,
+                cache_capacity: 2 << 10,
+                cache_estimated_entry_charge: 1,
+                cache_file_reader_capacity: 1000,
+                page_checksum_type: ChecksumType::CRC32,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub(crate) async fn must_put(table: &Table, i: u64, lsn: u64) {
         let buf = i.to_be_bytes();
         table.put(&buf, lsn, &buf).await.unwrap()
     }
 
-    async fn must_get(table: &Table, i: u64, lsn: u64, expect: Option<u64>) {
+    pub(crate) async fn must_get(table: &Table, i: u64, lsn: u64, expect: Option<u64>) {
         let buf = i.to_be_bytes();
         let value = table.get(&buf, lsn).await.unwrap();
         assert_eq!(value, expect.map(|v| v.to_be_bytes().to_vec()));
@@ -103,7 +112,7 @@ mod tests {
     #[photonio::test]
     async fn crud() {
         let path = tempdir().unwrap();
-        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
         const N: u64 = 1 << 10;
         for i in 0..N {
             must_put(&table, i, i).await;
@@ -131,7 +140,7 @@ mod tests {
     #[photonio::test]
     async fn random_crud() {
         let path = tempdir().unwrap();
-        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
         const N: u64 = 1 << 12;
         for _ in 0..N {
             let i = random();
@@ -144,7 +153,7 @@ mod tests {
     #[photonio::test]
     async fn concurrent_crud() {
         let path = tempdir().unwrap();
-        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
         let mut tasks = Vec::new();
         for _ in 0..4 {
             let table = table.clone();
@@ -6,17 +6,27 @@ use std::{
     slice,
 };
 
+use super::codec::DecodeError;
+
 /// Page format {
 ///     epoch      : 6 bytes
 ///     flags      : 1 bytes
 ///     chain_len  : 1 bytes
 ///     chain_next : 8 bytes
+///     metadata   : optional
 ///     content    : multiple bytes
 /// }
+///
+/// If the page carries user metadata, the metadata is stored as a 1-byte
+/// length followed by the bytes, padded to 8 bytes to keep the content
+/// aligned. Pages without metadata keep the original layout.
 const PAGE_EPOCH_MAX: u64 = (1 << 48) - 1;
 const PAGE_EPOCH_LEN: usize = 6;
 const PAGE_HEADER_LEN: usize = 16;
 
+/// The maximum length of the user metadata of a page.
+pub(crate) const PAGE_METADATA_MAX_LEN: usize = 32;
+
 /// A raw pointer to a page.
 ///
 /// This is an unsafe interface for internal use.
@@ -43,6 +53,42 @@ impl PagePtr {
         self.flags().kind()
     }
 
+    /// Returns true if the page should be stored without compression.
+    pub(crate) fn no_compression(&self) -> bool {
+        self.flags().no_compression()
+    }
+
+    /// Updates whether the page should be stored without compression.
+    pub(crate) fn set_no_compression(&mut self, no_compression: bool) {
+        let flags = self.flags().with_no_compression(no_compression);
+        self.set_flags(flags);
+    }
+
+    /// Returns true if the page only contains puts of distinct keys that are
+    /// visible to all reads.
+    pub(crate) fn is_clean(&self) -> bool {
+        self.flags().is_clean()
+    }
+
+    /// Updates whether the page only contains puts of distinct keys that are
+    /// visible to all reads.
+    pub(crate) fn set_clean(&mut self, clean: bool) {
+        let flags = self.flags().with_clean(clean);
+        self.set_flags(flags);
+    }
+
+    /// Returns the user metadata carried by the page, if any.
+    pub(crate) fn metadata<'a>(&self) -> Option<&'a [u8]> {
+        if !self.flags().has_metadata() {
+            return None;
+        }
+        unsafe {
+            let ptr = self.metadata_ptr();
+            let len = ptr.read() as usize;
+            Some(slice::from_raw_parts(ptr.add(1), len))
+        }
+    }
+
     /// Returns the page epoch.
     pub(crate) fn epoch(&self) -> u64 {
         unsafe {
@@ -106,6 +152,15 @@ impl PagePtr {
         unsafe { slice::from_raw_parts_mut(self.content_ptr(), self.content_size()) }
     }
 
+    /// Copies the header and content of the page of the same size.
+    pub(crate) fn copy_from(&mut self, page: &PagePtr) {
+        assert_eq!(self.len, page.len);
+        unsafe {
+            self.as_ptr()
+                .copy_from_nonoverlapping(page.as_ptr(), self.len)
+        }
+    }
+
     /// Returns the page info.
     pub(crate) fn info(&self) -> PageInfo {
         let meta = unsafe { self.as_ptr().cast::<u64>().read() };
@@ -136,10 +191,14 @@ impl PagePtr {
         (self.as_ptr() as *mut u64).add(1)
     }
 
-    unsafe fn content_ptr(&self) -> *mut u8 {
+    unsafe fn metadata_ptr(&self) -> *mut u8 {
         self.as_ptr().add(PAGE_HEADER_LEN)
     }
 
+    unsafe fn content_ptr(&self) -> *mut u8 {
+        self.as_ptr().add(PAGE_HEADER_LEN + self.metadata_size())
+    }
+
     fn flags(&self) -> PageFlags {
         unsafe { PageFlags(self.flags_ptr().read()) }
     }
@@ -148,8 +207,16 @@ impl PagePtr {
         unsafe { self.flags_ptr().write(flags.0) }
     }
 
+    fn metadata_size(&self) -> usize {
+        if self.flags().has_metadata() {
+            metadata_size(unsafe { self.metadata_ptr().read() } as usize)
+        } else {
+            0
+        }
+    }
+
     fn content_size(&self) -> usize {
-        self.len - PAGE_HEADER_LEN
+        self.len - PAGE_HEADER_LEN - self.metadata_size()
     }
 }
 
@@ -189,6 +256,32 @@ impl<'a> PageBuf<'a> {
             PagePtr::new(ptr, buf.len()).into()
         }
     }
+
+    /// Creates an immutable page reference from a byte slice that may be
+    /// corrupted.
+    ///
+    /// Unlike [`Self::new`], this returns [`DecodeError`] instead of panicking
+    /// if the slice is misaligned, or the page header or metadata is
+    /// malformed.
+    pub(crate) fn try_new(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        if buf.len() < PAGE_HEADER_LEN || !buf.as_ptr().is_aligned_to(8) {
+            return Err(DecodeError);
+        }
+        let flags = PageFlags(buf[PAGE_EPOCH_LEN]);
+        if !matches!(
+            flags.0 & PAGE_KIND_MASK,
+            PAGE_KIND_DATA | PAGE_KIND_SPLIT | PAGE_KIND_BLOB
+        ) {
+            return Err(DecodeError);
+        }
+        if flags.has_metadata() {
+            let len = *buf.get(PAGE_HEADER_LEN).ok_or(DecodeError)? as usize;
+            if len > PAGE_METADATA_MAX_LEN || buf.len() < PAGE_HEADER_LEN + metadata_size(len) {
+                return Err(DecodeError);
+            }
+        }
+        Ok(Self::new(buf))
+    }
 }
 
 impl<'a> Deref for PageBuf<'a> {
@@ -321,11 +414,13 @@ impl From<u8> for PageTier {
 pub(crate) enum PageKind {
     Data = PAGE_KIND_DATA,
     Split = PAGE_KIND_SPLIT,
+    Blob = PAGE_KIND_BLOB,
 }
 
 const PAGE_KIND_MASK: u8 = 0b0000_1110;
 const PAGE_KIND_DATA: u8 = 0b0000_0000;
 const PAGE_KIND_SPLIT: u8 = 0b0000_0010;
+const PAGE_KIND_BLOB: u8 = 0b0000_0100;
 
 impl PageKind {
     pub(crate) fn is_data(&self) -> bool {
@@ -335,6 +430,10 @@ impl PageKind {
     pub(crate) fn is_split(&self) -> bool {
         self == &Self::Split
     }
+
+    pub(crate) fn is_blob(&self) -> bool {
+        self == &Self::Blob
+    }
 }
 
 impl From<u8> for PageKind {
@@ -342,11 +441,16 @@ impl From<u8> for PageKind {
         match value & PAGE_KIND_MASK {
             PAGE_KIND_DATA => Self::Data,
             PAGE_KIND_SPLIT => Self::Split,
+            PAGE_KIND_BLOB => Self::Blob,
             _ => unreachable!(),
         }
     }
 }
 
+const PAGE_NO_COMPRESSION: u8 = 0b0001_0000;
+const PAGE_CLEAN: u8 = 0b0010_0000;
+const PAGE_HAS_METADATA: u8 = 0b0100_0000;
+
 struct PageFlags(u8);
 
 impl PageFlags {
@@ -354,6 +458,42 @@ impl PageFlags {
         Self(tier as u8 | kind as u8)
     }
 
+    fn no_compression(&self) -> bool {
+        self.0 & PAGE_NO_COMPRESSION != 0
+    }
+
+    fn with_no_compression(self, no_compression: bool) -> Self {
+        if no_compression {
+            Self(self.0 | PAGE_NO_COMPRESSION)
+        } else {
+            Self(self.0 & !PAGE_NO_COMPRESSION)
+        }
+    }
+
+    fn is_clean(&self) -> bool {
+        self.0 & PAGE_CLEAN != 0
+    }
+
+    fn with_clean(self, clean: bool) -> Self {
+        if clean {
+            Self(self.0 | PAGE_CLEAN)
+        } else {
+            Self(self.0 & !PAGE_CLEAN)
+        }
+    }
+
+    fn has_metadata(&self) -> bool {
+        self.0 & PAGE_HAS_METADATA != 0
+    }
+
+    fn with_metadata(self, has_metadata: bool) -> Self {
+        if has_metadata {
+            Self(self.0 | PAGE_HAS_METADATA)
+        } else {
+            Self(self.0 & !PAGE_HAS_METADATA)
+        }
+    }
+
     fn tier(&self) -> PageTier {
         self.0.into()
     }
@@ -363,27 +503,56 @@ impl PageFlags {
     }
 }
 
+/// Returns the size of the metadata area for metadata of the length.
+fn metadata_size(len: usize) -> usize {
+    (1 + len + 7) & !7
+}
+
 /// Builds a page with basic information.
 pub(super) struct PageBuilder {
     tier: PageTier,
     kind: PageKind,
+    metadata: Option<Vec<u8>>,
 }
 
 impl PageBuilder {
     pub(super) fn new(tier: PageTier, kind: PageKind) -> Self {
-        Self { tier, kind }
+        Self {
+            tier,
+            kind,
+            metadata: None,
+        }
+    }
+
+    /// Sets the user metadata to carry in the page.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the metadata is longer than [`PAGE_METADATA_MAX_LEN`].
+    pub(super) fn set_metadata(&mut self, metadata: &[u8]) {
+        assert!(metadata.len() <= PAGE_METADATA_MAX_LEN);
+        self.metadata = Some(metadata.to_vec());
     }
 
     pub(super) fn size(&self, content_size: usize) -> usize {
-        PAGE_HEADER_LEN + content_size
+        let metadata_size = self.metadata.as_ref().map_or(0, |m| metadata_size(m.len()));
+        PAGE_HEADER_LEN + metadata_size + content_size
     }
 
     pub(super) fn build(&self, page: &mut PageBuf<'_>) {
-        let flags = PageFlags::new(self.tier, self.kind);
+        let flags = PageFlags::new(self.tier, self.kind).with_metadata(self.metadata.is_some());
         page.set_flags(flags);
         page.set_epoch(0);
         page.set_chain_len(1);
         page.set_chain_next(0);
+        if let Some(metadata) = &self.metadata {
+            unsafe {
+                let ptr = page.metadata_ptr();
+                ptr.write(metadata.len() as u8);
+                ptr.add(1)
+                    .copy_from_nonoverlapping(metadata.as_ptr(), metadata.len());
+            }
+        }
     }
 }
 
@@ -414,6 +583,12 @@ impl PageInfo {
         self.flags().kind()
     }
 
+    /// Returns true if the page should be stored without compression.
+    #[inline]
+    pub(crate) fn no_compression(&self) -> bool {
+        self.flags().no_compression()
+    }
+
     /// Returns the page epoch.
     #[inline]
     pub(crate) fn epoch(&self) -> u64 {
@@ -484,5 +659,24 @@ mod tests {
         assert_eq!(page.data().len(), PAGE_HEADER_LEN + 1);
         assert_eq!(page.content().len(), 1);
         assert_eq!(page.content_mut().len(), 1);
+        assert_eq!(page.metadata(), None);
+    }
+
+    #[test]
+    fn page_metadata() {
+        let metadata = [1u8, 2, 3];
+        let mut builder = PageBuilder::new(PageTier::Leaf, PageKind::Data);
+        builder.set_metadata(&metadata);
+        let size = builder.size(8);
+        assert_eq!(size, PAGE_HEADER_LEN + 8 + 8);
+        let mut buf = alloc_page(size);
+        let mut page = PageBuf::new(buf.as_mut());
+        builder.build(&mut page);
+        page.content_mut().fill(0xff);
+        assert!(page.tier().is_leaf());
+        assert!(page.kind().is_data());
+        assert_eq!(page.metadata(), Some(metadata.as_slice()));
+        assert_eq!(page.content().len(), 8);
+        assert!(page.content().iter().all(|&b| b == 0xff));
     }
 }
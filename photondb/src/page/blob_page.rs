@@ -0,0 +1,77 @@
+use super::{PageBuf, PageBuilder, PageKind, PageRef, PageTier};
+
+/// Builds a page that holds a single value.
+///
+/// Blob pages are not chained with other pages. They are referenced by
+/// [`Value::Indirect`] items in leaf pages instead.
+///
+/// [`Value::Indirect`]: super::Value::Indirect
+pub(crate) struct BlobPageBuilder<'a> {
+    base: PageBuilder,
+    value: &'a [u8],
+}
+
+impl<'a> BlobPageBuilder<'a> {
+    /// Creates a [`BlobPageBuilder`] that will build a page with the value.
+    pub(crate) fn new(value: &'a [u8]) -> Self {
+        Self {
+            base: PageBuilder::new(PageTier::Leaf, PageKind::Blob),
+            value,
+        }
+    }
+
+    /// Returns the size of the page that will be built.
+    pub(crate) fn size(&self) -> usize {
+        self.base.size(self.value.len())
+    }
+
+    /// Builds the page with the given information.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the page is not large enough to hold the value.
+    pub(crate) fn build(self, page: &mut PageBuf<'_>) {
+        assert!(page.size() >= self.size());
+        self.base.build(page);
+        page.content_mut()[..self.value.len()].copy_from_slice(self.value);
+    }
+}
+
+/// An immutable reference to a blob page.
+#[derive(Clone)]
+pub(crate) struct BlobPageRef<'a> {
+    page: PageRef<'a>,
+}
+
+impl<'a> BlobPageRef<'a> {
+    /// Returns the value in the page.
+    pub(crate) fn value(&self) -> &'a [u8] {
+        self.page.content()
+    }
+}
+
+impl<'a> From<PageRef<'a>> for BlobPageRef<'a> {
+    fn from(page: PageRef<'a>) -> Self {
+        debug_assert!(page.kind().is_blob());
+        Self { page }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tests::alloc_page, *};
+
+    #[test]
+    fn blob_page() {
+        let value = [1u8, 2, 3, 4, 5];
+        let builder = BlobPageBuilder::new(&value);
+        let mut buf = alloc_page(builder.size());
+        let mut page = PageBuf::new(buf.as_mut());
+        builder.build(&mut page);
+        assert_eq!(page.tier(), PageTier::Leaf);
+        assert_eq!(page.kind(), PageKind::Blob);
+
+        let page = BlobPageRef::from(PageRef::new(buf.as_ref()));
+        assert_eq!(page.value(), &value);
+    }
+}
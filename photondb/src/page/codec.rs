@@ -18,8 +18,25 @@ pub(crate) trait Codec {
     ///
     /// The decoder must have enough data to decode the object.
     unsafe fn decode_from(decoder: &mut Decoder) -> Self;
+
+    /// Decodes an object from the decoder, checking every read against the
+    /// remaining data.
+    ///
+    /// This is the decode path for data that may be corrupted, which returns
+    /// [`DecodeError`] instead of reading out of bounds.
+    ///
+    /// # Safety
+    ///
+    /// The buffer of the decoder must outlive the decoded object.
+    unsafe fn try_decode_from(decoder: &mut Decoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized;
 }
 
+/// An error returned when the data to decode is malformed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct DecodeError;
+
 // An unsafe, little-endian encoder.
 pub(crate) struct Encoder {
     buf: *mut u8,
@@ -95,6 +112,19 @@ macro_rules! get_int {
     };
 }
 
+macro_rules! try_get_int {
+    ($name:ident, $t:ty) => {
+        pub(super) fn $name(&mut self) -> Result<$t, DecodeError> {
+            let mut v: $t = 0;
+            let ptr = &mut v as *mut $t as *mut u8;
+            let len = mem::size_of::<$t>();
+            let cursor = self.try_take(len)?;
+            unsafe { cursor.copy_to_nonoverlapping(ptr, len) };
+            Ok(<$t>::from_le(v))
+        }
+    };
+}
+
 impl Decoder {
     pub(super) fn new(buf: &[u8]) -> Self {
         Self {
@@ -123,12 +153,30 @@ impl Decoder {
         ptr
     }
 
+    fn try_take(&mut self, len: usize) -> Result<*const u8, DecodeError> {
+        unsafe {
+            if len > self.remaining() {
+                return Err(DecodeError);
+            }
+            Ok(self.take(len))
+        }
+    }
+
     get_int!(get_u8, u8);
     get_int!(get_u32, u32);
     get_int!(get_u64, u64);
 
+    try_get_int!(try_get_u8, u8);
+    try_get_int!(try_get_u32, u32);
+    try_get_int!(try_get_u64, u64);
+
     pub(super) unsafe fn get_slice<'a>(&mut self, len: usize) -> &'a [u8] {
         let cursor = self.take(len);
         slice::from_raw_parts(cursor, len)
     }
+
+    pub(super) unsafe fn try_get_slice<'a>(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let cursor = self.try_take(len)?;
+        Ok(slice::from_raw_parts(cursor, len))
+    }
 }
@@ -1,4 +1,4 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, mem};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Key<'a> {
@@ -53,14 +53,40 @@ impl<'a> Range<'a> {
 pub(crate) enum Value<'a> {
     Put(&'a [u8]),
     Delete,
+    /// A value stored in a separate blob page at the given address.
+    Indirect(u64),
+    /// A value transformed by the value codec of the table, which must be
+    /// decoded before it is returned.
+    Encoded(&'a [u8]),
+    /// A value that carries a logical timestamp for conflict resolution.
+    ///
+    /// Among the consecutive timestamped versions of a key, the one with the
+    /// largest timestamp wins regardless of their LSNs.
+    PutTimestamped {
+        bytes: &'a [u8],
+        ts: u64,
+    },
 }
 
 impl<'a> Value<'a> {
     /// Returns the length of value.
+    ///
+    /// For indirect values, this is the length of the reference, and for
+    /// timestamped values, this includes the length of the timestamp.
     pub(crate) fn len(&self) -> usize {
         match self {
-            Value::Put(v) => v.len(),
+            Value::Put(v) | Value::Encoded(v) => v.len(),
             Value::Delete => 0,
+            Value::Indirect(_) => mem::size_of::<u64>(),
+            Value::PutTimestamped { bytes, .. } => bytes.len() + mem::size_of::<u64>(),
+        }
+    }
+
+    /// Returns the timestamp of a timestamped value.
+    pub(crate) fn timestamp(&self) -> Option<u64> {
+        match self {
+            Value::PutTimestamped { ts, .. } => Some(*ts),
+            _ => None,
         }
     }
 }
@@ -11,6 +11,12 @@ pub(crate) trait RewindableIterator: Iterator {
     fn rewind(&mut self);
 }
 
+impl<I: RewindableIterator + ?Sized> RewindableIterator for &mut I {
+    fn rewind(&mut self) {
+        (**self).rewind()
+    }
+}
+
 /// An extension of [`Iterator`] that can seek to a target.
 pub(crate) trait SeekableIterator<T: ?Sized>: Iterator {
     /// Positions the iterator at the first item that is at or after `target`.
@@ -316,11 +322,6 @@ where
         }
     }
 
-    /// Returns the number of iterators in the builder.
-    pub(crate) fn len(&self) -> usize {
-        self.iters.len()
-    }
-
     /// Adds an iterator to the builder.
     pub(crate) fn add(&mut self, iter: I) {
         let rank = self.iters.len();
@@ -7,19 +7,41 @@ mod data;
 pub(crate) use data::{Index, Key, Range, Value};
 
 mod codec;
+pub(crate) use codec::DecodeError;
 
 mod base_page;
 use base_page::PageBuilder;
-pub(crate) use base_page::{PageBuf, PageInfo, PageKind, PageRef, PageTier};
+pub(crate) use base_page::{PageBuf, PageInfo, PageKind, PageRef, PageTier, PAGE_METADATA_MAX_LEN};
 
 mod sorted_page;
 pub(crate) use sorted_page::{
-    SortedPageBuilder, SortedPageIter, SortedPageKey, SortedPageRef, SortedPageValue,
+    SortedPageBuilder, SortedPageIter, SortedPageKey, SortedPageRangeIter, SortedPageRef,
+    SortedPageValue,
 };
 
+mod blob_page;
+pub(crate) use blob_page::{BlobPageBuilder, BlobPageRef};
+
 pub(crate) type ValuePageRef<'a> = SortedPageRef<'a, Key<'a>, Value<'a>>;
 pub(crate) type IndexPageRef<'a> = SortedPageRef<'a, &'a [u8], Index>;
 
+/// Checks that a page read from storage is well-formed.
+///
+/// Pages that pass the check can be read without going out of bounds.
+pub(crate) fn validate_page(buf: &[u8]) -> Result<(), DecodeError> {
+    let page = PageRef::try_new(buf)?;
+    match (page.tier(), page.kind()) {
+        (_, PageKind::Blob) => {}
+        (PageTier::Leaf, PageKind::Data) => {
+            ValuePageRef::try_new(page)?;
+        }
+        _ => {
+            IndexPageRef::try_new(page)?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use std::{
@@ -37,6 +37,14 @@ where
         }
     }
 
+    /// Sets the user metadata to carry in the page, if any.
+    pub(crate) fn with_metadata(mut self, metadata: Option<&[u8]>) -> Self {
+        if let Some(metadata) = metadata {
+            self.base.set_metadata(metadata);
+        }
+        self
+    }
+
     /// Creates a [`SortedPageBuilder`] that will build a page from the given
     /// iterator.
     pub(crate) fn with_iter(mut self, mut iter: I) -> Self {
@@ -164,6 +172,38 @@ where
         }
     }
 
+    /// Creates a sorted page reference from a page that may be corrupted.
+    ///
+    /// This checks that the item offsets are in bounds and that every item
+    /// decodes, so the returned page can be read with the unchecked decode
+    /// path.
+    pub(crate) fn try_new(page: PageRef<'a>) -> Result<Self, DecodeError> {
+        let content = page.content();
+        if !content.is_empty() {
+            let offsets_size = Decoder::new(content).try_get_u32()? as usize;
+            if offsets_size % mem::size_of::<u32>() != 0 || offsets_size > content.len() {
+                return Err(DecodeError);
+            }
+        }
+        let this = Self::new(page);
+        let mut last_offset = this.offsets.len() * mem::size_of::<u32>();
+        for i in 0..this.len() {
+            let offset = this.item_offset(i).unwrap();
+            if offset < last_offset || offset > content.len() {
+                return Err(DecodeError);
+            }
+            last_offset = offset;
+        }
+        for i in 0..this.len() {
+            let mut dec = Decoder::new(this.item(i).unwrap());
+            unsafe {
+                K::try_decode_from(&mut dec)?;
+                V::try_decode_from(&mut dec)?;
+            }
+        }
+        Ok(this)
+    }
+
     /// Returns the number of items in the page.
     pub(crate) fn len(&self) -> usize {
         self.offsets.len()
@@ -218,6 +258,9 @@ where
     /// If a split separator is found, returns [`Option::Some`] with the split
     /// separator, an iterator over items before the separator, and another
     /// iterator over items at or after the separator.
+    ///
+    /// The separator is always larger than the first item, so it is never an
+    /// empty key.
     #[allow(clippy::type_complexity)]
     pub(crate) fn into_split_iter(
         self,
@@ -242,6 +285,48 @@ where
         None
     }
 
+    /// Finds a separator accepted by `accept` to split the page.
+    ///
+    /// Candidates are tried from the middle of the page outward, so the
+    /// separator is the one closest to the middle among the accepted ones.
+    /// Returns [`Option::None`] if no candidate is accepted.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn into_split_iter_with<F>(
+        self,
+        accept: F,
+    ) -> Option<(
+        K,
+        SortedPageRangeIter<'a, K, V>,
+        SortedPageRangeIter<'a, K, V>,
+    )>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let len = self.len();
+        let mid = len / 2;
+        let candidates = (0..len).map(|i| {
+            if i % 2 == 0 {
+                mid + i / 2
+            } else {
+                mid - i / 2 - 1
+            }
+        });
+        for i in candidates.filter(|&i| i < len) {
+            let (key, _) = self.get(i)?;
+            let sep = key.as_split_separator();
+            let index = match self.rank(&sep) {
+                Ok(i) => i,
+                Err(i) => i,
+            };
+            if index > 0 && accept(sep.as_raw()) {
+                let left_iter = SortedPageRangeIter::new(self.clone(), 0..index);
+                let right_iter = SortedPageRangeIter::new(self, index..len);
+                return Some((sep, left_iter, right_iter));
+            }
+        }
+        None
+    }
+
     fn item(&self, index: usize) -> Option<&[u8]> {
         if let Some(offset) = self.item_offset(index) {
             let next_offset = self.item_offset(index + 1).unwrap_or(self.content.len());
@@ -436,6 +521,11 @@ impl Codec for &[u8] {
         let len = dec.get_u32() as usize;
         dec.get_slice(len)
     }
+
+    unsafe fn try_decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let len = dec.try_get_u32()? as usize;
+        dec.try_get_slice(len)
+    }
 }
 
 impl SortedPageKey for &[u8] {
@@ -463,6 +553,12 @@ impl Codec for Key<'_> {
         let lsn = dec.get_u64();
         Self::new(raw, lsn)
     }
+
+    unsafe fn try_decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let raw = Codec::try_decode_from(dec)?;
+        let lsn = dec.try_get_u64()?;
+        Ok(Self::new(raw, lsn))
+    }
 }
 
 impl SortedPageKey for Key<'_> {
@@ -479,13 +575,13 @@ impl SortedPageKey for Key<'_> {
 /// These values are persisted to disk, don't change them.
 const VALUE_KIND_PUT: u8 = 0;
 const VALUE_KIND_DELETE: u8 = 1;
+const VALUE_KIND_INDIRECT: u8 = 2;
+const VALUE_KIND_ENCODED: u8 = 3;
+const VALUE_KIND_PUT_TIMESTAMPED: u8 = 4;
 
 impl Codec for Value<'_> {
     fn encode_size(&self) -> usize {
-        1 + match self {
-            Self::Put(v) => v.len(),
-            Self::Delete => 0,
-        }
+        1 + self.len()
     }
 
     unsafe fn encode_to(&self, enc: &mut Encoder) {
@@ -495,6 +591,19 @@ impl Codec for Value<'_> {
                 enc.put_slice(v);
             }
             Value::Delete => enc.put_u8(VALUE_KIND_DELETE),
+            Value::Indirect(addr) => {
+                enc.put_u8(VALUE_KIND_INDIRECT);
+                enc.put_u64(*addr);
+            }
+            Value::Encoded(v) => {
+                enc.put_u8(VALUE_KIND_ENCODED);
+                enc.put_slice(v);
+            }
+            Value::PutTimestamped { bytes, ts } => {
+                enc.put_u8(VALUE_KIND_PUT_TIMESTAMPED);
+                enc.put_u64(*ts);
+                enc.put_slice(bytes);
+            }
         }
     }
 
@@ -503,9 +612,37 @@ impl Codec for Value<'_> {
         match kind {
             VALUE_KIND_PUT => Self::Put(dec.get_slice(dec.remaining())),
             VALUE_KIND_DELETE => Self::Delete,
+            VALUE_KIND_INDIRECT => Self::Indirect(dec.get_u64()),
+            VALUE_KIND_ENCODED => Self::Encoded(dec.get_slice(dec.remaining())),
+            VALUE_KIND_PUT_TIMESTAMPED => {
+                let ts = dec.get_u64();
+                Self::PutTimestamped {
+                    bytes: dec.get_slice(dec.remaining()),
+                    ts,
+                }
+            }
             _ => unreachable!(),
         }
     }
+
+    unsafe fn try_decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let kind = dec.try_get_u8()?;
+        let value = match kind {
+            VALUE_KIND_PUT => Self::Put(dec.try_get_slice(dec.remaining())?),
+            VALUE_KIND_DELETE => Self::Delete,
+            VALUE_KIND_INDIRECT => Self::Indirect(dec.try_get_u64()?),
+            VALUE_KIND_ENCODED => Self::Encoded(dec.try_get_slice(dec.remaining())?),
+            VALUE_KIND_PUT_TIMESTAMPED => {
+                let ts = dec.try_get_u64()?;
+                Self::PutTimestamped {
+                    bytes: dec.try_get_slice(dec.remaining())?,
+                    ts,
+                }
+            }
+            _ => return Err(DecodeError),
+        };
+        Ok(value)
+    }
 }
 
 impl Codec for Index {
@@ -523,12 +660,18 @@ impl Codec for Index {
         let epoch = dec.get_u64();
         Self::new(id, epoch)
     }
+
+    unsafe fn try_decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let id = dec.try_get_u64()?;
+        let epoch = dec.try_get_u64()?;
+        Ok(Self::new(id, epoch))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::page::tests::*;
+    use crate::page::{tests::*, validate_page, ValuePageRef};
 
     #[test]
     fn sorted_page() {
@@ -561,6 +704,73 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sorted_page_values() {
+        let data = [
+            (Key::new(&[1], 3), Value::Put(&[3])),
+            (Key::new(&[1], 2), Value::Indirect(u64::MAX)),
+            (Key::new(&[1], 1), Value::Delete),
+            (
+                Key::new(&[2], 1),
+                Value::PutTimestamped {
+                    bytes: &[2],
+                    ts: u64::MAX,
+                },
+            ),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+        let page = owned_page.as_ref();
+        assert_eq!(page.len(), data.len());
+        for (i, item) in data.iter().enumerate() {
+            assert_eq!(page.get(i), Some(*item));
+        }
+    }
+
+    #[test]
+    fn sorted_page_corrupted() {
+        let data = [
+            (Key::new(&[1], 2), Value::Put(&[2])),
+            (Key::new(&[1], 1), Value::Delete),
+        ];
+        let build = || {
+            let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&data);
+            let mut buf = alloc_page(builder.size());
+            let mut page = PageBuf::new(buf.as_mut());
+            builder.build(&mut page);
+            buf
+        };
+        // The content follows the 16-byte header, and the first item follows the
+        // offsets of the two items.
+        let content = 16;
+        let item = content + 2 * mem::size_of::<u32>();
+
+        let buf = build();
+        let page = ValuePageRef::try_new(PageRef::try_new(&buf).unwrap()).unwrap();
+        assert_eq!(page.get(0), Some(data[0]));
+        assert_eq!(validate_page(&buf), Ok(()));
+        assert_eq!(validate_page(&buf[..8]), Err(DecodeError));
+
+        // Unknown page kind.
+        let mut buf = build();
+        buf[6] |= 0b0000_1110;
+        assert_eq!(validate_page(&buf), Err(DecodeError));
+
+        // Offsets out of bounds.
+        let mut buf = build();
+        buf[content..content + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(validate_page(&buf), Err(DecodeError));
+
+        // Key length out of bounds.
+        let mut buf = build();
+        buf[item..item + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(validate_page(&buf), Err(DecodeError));
+
+        // Unknown value kind.
+        let mut buf = build();
+        buf[item + 4 + 1 + 8] = u8::MAX;
+        assert_eq!(validate_page(&buf), Err(DecodeError));
+    }
+
     #[test]
     fn sorted_page_split() {
         // The middle key is ([3], 2), but it should split at ([3], 3).
@@ -598,6 +808,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sorted_page_split_with() {
+        let data = key_slice(&[([1], 2), ([1], 1), ([3], 3), ([5], 2), ([7], 1), ([9], 0)]);
+        let owned_page = OwnedSortedPage::from_slice(&data);
+        let split_key = |accept: &dyn Fn(&[u8]) -> bool| {
+            owned_page
+                .as_ref()
+                .into_split_iter_with(accept)
+                .map(|(key, left, right)| (key.raw[0], left.count(), right.count()))
+        };
+        assert_eq!(split_key(&|_| true), Some((5, 3, 3)));
+        // The closest accepted key to the middle is used.
+        assert_eq!(split_key(&|k| k != [5]), Some((3, 2, 4)));
+        assert_eq!(split_key(&|k| k == [7]), Some((7, 4, 2)));
+        // The first key can't be a separator.
+        assert_eq!(split_key(&|k| k == [1]), None);
+        assert_eq!(split_key(&|_| false), None);
+    }
+
     #[test]
     fn sorted_page_iter() {
         let data = raw_slice(&[[1], [3], [5]]);
@@ -358,6 +358,27 @@ impl BufferSet {
         write_buffer.seal().ok()
     }
 
+    /// Seals the active buffer so that it will be flushed by the flusher,
+    /// unless it is empty or sealing it would stall writes.
+    ///
+    /// Returns true if the buffer is sealed.
+    pub(crate) async fn try_flush_active_buffer(&self) -> bool {
+        let group_id = {
+            let current = self.current();
+            if current.current_buffer.is_empty() {
+                return false;
+            }
+            current.current_buffer.group_id()
+        };
+        // Permits are only taken by sealing the active buffer, so the switch
+        // won't stall if no one else has sealed it in the meantime.
+        if !self.write_buffer_permits.has_permits() {
+            return false;
+        }
+        self.switch_buffer(group_id).await;
+        true
+    }
+
     /// Seal the current write buffer and switch to new one, so the sealed
     /// buffer will be flushed by flusher.
     pub(crate) async fn flush_active_buffer(&self, opts: &FlushOptions) {
@@ -501,6 +522,11 @@ mod buffer_permits {
             }
         }
 
+        /// Returns true if there are available permits.
+        pub(crate) fn has_permits(&self) -> bool {
+            self.permits.load(Ordering::Acquire) > 1
+        }
+
         /// Try acquire a permit, [`None`] is returned if no available permits.
         pub(crate) fn try_acquire(&self) -> Option<()> {
             self.acquire_fast(AcquireKind::None)
@@ -0,0 +1,128 @@
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use parking_lot::Mutex;
+
+use crate::page_store::stats::CacheResizeEvent;
+
+/// The number of lookups in a window, over which the miss ratio is measured.
+const WINDOW_LOOKUPS: u64 = 1024;
+/// The miss ratio at or above which a window is considered to miss a lot.
+const HIGH_MISS_RATIO: f64 = 0.1;
+/// The number of consecutive windows that miss a lot before the cache grows.
+const GROW_AFTER_WINDOWS: u32 = 2;
+/// The maximum number of recent resize events to keep.
+const MAX_RESIZE_EVENTS: usize = 8;
+
+/// Grows the capacity of a cache up to a maximum based on its miss ratio.
+///
+/// The cache grows if lookups keep missing while it is full, which means
+/// that the working set doesn't fit. It never shrinks, so a table that turns
+/// out cold keeps a small cache.
+pub(crate) struct CacheAutosizer {
+    max_capacity: usize,
+    lookups: AtomicU64,
+    misses: AtomicU64,
+    state: Mutex<AutosizeState>,
+}
+
+#[derive(Default)]
+struct AutosizeState {
+    high_miss_windows: u32,
+    events: VecDeque<CacheResizeEvent>,
+}
+
+impl CacheAutosizer {
+    pub(crate) fn new(max_capacity: usize) -> Self {
+        Self {
+            max_capacity,
+            lookups: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Records a lookup, and returns the miss ratio of the window if the
+    /// lookup completes one.
+    pub(crate) fn record(&self, hit: bool) -> Option<f64> {
+        if !hit {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        let lookups = self.lookups.fetch_add(1, Ordering::Relaxed) + 1;
+        if lookups % WINDOW_LOOKUPS != 0 {
+            return None;
+        }
+        let misses = self.misses.swap(0, Ordering::Relaxed);
+        Some(misses.min(WINDOW_LOOKUPS) as f64 / WINDOW_LOOKUPS as f64)
+    }
+
+    /// Returns the new capacity if the cache with the capacity should grow
+    /// after a window with the miss ratio.
+    ///
+    /// A cache that is not full doesn't grow, since its misses are for pages
+    /// that have never been read.
+    pub(crate) fn on_window(&self, capacity: usize, miss_ratio: f64, full: bool) -> Option<usize> {
+        let mut state = self.state.lock();
+        if miss_ratio < HIGH_MISS_RATIO || !full {
+            state.high_miss_windows = 0;
+            return None;
+        }
+        state.high_miss_windows += 1;
+        if state.high_miss_windows < GROW_AFTER_WINDOWS || capacity >= self.max_capacity {
+            return None;
+        }
+        state.high_miss_windows = 0;
+        let new_capacity = capacity.saturating_mul(2).clamp(1, self.max_capacity);
+        if state.events.len() == MAX_RESIZE_EVENTS {
+            state.events.pop_front();
+        }
+        state.events.push_back(CacheResizeEvent {
+            old_capacity: capacity,
+            new_capacity,
+            miss_ratio,
+            lookups: self.lookups.load(Ordering::Relaxed),
+        });
+        Some(new_capacity)
+    }
+
+    /// Returns the recent resize events, from the oldest to the newest.
+    pub(crate) fn events(&self) -> Vec<CacheResizeEvent> {
+        self.state.lock().events.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_autosizer_grow() {
+        let autosizer = CacheAutosizer::new(300);
+        let window = |hits: u64| {
+            (0..WINDOW_LOOKUPS)
+                .filter_map(|i| autosizer.record(i < hits))
+                .last()
+                .unwrap()
+        };
+        assert_eq!(window(WINDOW_LOOKUPS / 2), 0.5);
+
+        // Windows that hit a lot or with a cache not full reset the count.
+        assert_eq!(autosizer.on_window(100, 0.5, true), None);
+        assert_eq!(autosizer.on_window(100, 0.05, true), None);
+        assert_eq!(autosizer.on_window(100, 0.5, true), None);
+        assert_eq!(autosizer.on_window(100, 0.5, false), None);
+        assert_eq!(autosizer.on_window(100, 0.5, true), None);
+        assert_eq!(autosizer.on_window(100, 0.5, true), Some(200));
+        assert_eq!(autosizer.on_window(200, 0.5, true), None);
+        assert_eq!(autosizer.on_window(200, 0.5, true), Some(300));
+        assert_eq!(autosizer.on_window(300, 0.5, true), None);
+        assert_eq!(autosizer.on_window(300, 0.5, true), None);
+
+        let events = autosizer.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].old_capacity, 100);
+        assert_eq!(events[1].new_capacity, 300);
+    }
+}
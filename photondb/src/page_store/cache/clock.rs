@@ -190,6 +190,7 @@ impl<T: Clone> ClockCacheHandleTable<T> {
                 need_evict_for_occupancy,
             );
             if let Err(err) = r {
+                self.stats.insert_fail.inc();
                 self.occupancy.fetch_sub(1, Ordering::Relaxed);
                 return Err(err);
             }
@@ -200,6 +201,7 @@ impl<T: Clone> ClockCacheHandleTable<T> {
                 need_evict_for_occupancy,
             );
             if !success {
+                self.stats.insert_fail.inc();
                 self.occupancy.fetch_sub(1, Ordering::Relaxed);
                 self.usage.fetch_add(total_charge, Ordering::Relaxed);
                 use_detached_insert = true;
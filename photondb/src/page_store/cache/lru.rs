@@ -14,7 +14,11 @@ use super::{
     AtomicCacheStats, Cache, CacheEntry, CacheToken, Handle, Key, LRUHandle, CACHE_AS_COLD,
     CACHE_DISCARD,
 };
-use crate::page_store::{cache::CACHE_AS_HOT, stats::CacheStats, CacheOption, Result};
+use crate::page_store::{
+    cache::CACHE_AS_HOT,
+    stats::{CacheShardStats, CacheStats},
+    CacheOption, Result,
+};
 
 pub(crate) struct LRUCache<T: Clone> {
     shards: Vec<Mutex<LRUCacheShard<T>>>,
@@ -91,6 +95,38 @@ impl<T: Clone> LRUCache<T> {
         }
     }
 
+    /// Returns the number of lookups that hit the cache, without taking the
+    /// locks of the shards.
+    pub(crate) fn lookup_hits(&self) -> u64 {
+        self.stats.iter().map(|s| s.lookup_hit.get()).sum()
+    }
+
+    /// Changes the capacity of the cache.
+    ///
+    /// If the cache is shrunk, unreferenced entries are evicted until the
+    /// usage of each shard fits into the new capacity.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        let num_shards = self.shards.len();
+        let per_shard_cap = (capacity + (num_shards - 1)) / num_shards;
+        for shard in &self.shards {
+            let mut shard = shard.lock();
+            shard.capacity = per_shard_cap;
+            unsafe { shard.evict_lru(0, CacheOption::default()) };
+        }
+    }
+
+    /// Returns true if the total usage of shards reaches their total capacity.
+    pub(crate) fn is_full(&self) -> bool {
+        let (usage, capacity) = self.shards.iter().fold((0, 0), |(usage, capacity), shard| {
+            let shard = shard.lock();
+            (
+                usage + shard.usage.load(Ordering::Relaxed),
+                capacity + shard.capacity,
+            )
+        });
+        usage >= capacity
+    }
+
     #[inline]
     fn shard(&self, hash: u32) -> u32 {
         self.shard_mask & hash
@@ -203,8 +239,13 @@ impl<T: Clone> Cache<T> for LRUCache<T> {
 
     fn stats(self: &std::sync::Arc<Self>) -> crate::page_store::stats::CacheStats {
         let mut summary = CacheStats::default();
-        for s in &self.stats {
-            let shard_stats = s.snapshot();
+        for (shard, s) in self.shards.iter().zip(&self.stats) {
+            let mut shard_stats = s.snapshot();
+            let shard = shard.lock();
+            shard_stats.shards.push(CacheShardStats {
+                usage: shard.usage.load(Ordering::Relaxed),
+                capacity: shard.capacity,
+            });
             summary = summary.add(&shard_stats);
         }
         summary
@@ -237,6 +278,7 @@ impl<T: Clone> LRUCacheShard<T> {
         option: CacheOption,
     ) -> Result<*mut LRUHandle<T>> {
         if !self.evict_lru(charge, option) {
+            self.stats.insert_fail.inc();
             let mut h = Box::new(LRUHandle {
                 key: key.into(),
                 hash,
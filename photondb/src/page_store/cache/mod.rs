@@ -16,6 +16,7 @@ use crate::{
     util::atomic::Counter,
 };
 
+pub(crate) mod autosize;
 pub(crate) mod clock;
 pub(crate) mod lru;
 
@@ -282,6 +283,7 @@ struct AtomicCacheStats {
     lookup_hit: CachePadded<Counter>,
     lookup_miss: CachePadded<Counter>,
     insert: CachePadded<Counter>,
+    insert_fail: CachePadded<Counter>,
     active_evict: CachePadded<Counter>,
     passive_evict: CachePadded<Counter>,
 }
@@ -292,9 +294,11 @@ impl AtomicCacheStats {
             lookup_hit: self.lookup_hit.get(),
             lookup_miss: self.lookup_miss.get(),
             insert: self.insert.get(),
+            insert_fail: self.insert_fail.get(),
             active_evict: self.active_evict.get(),
             passive_evict: self.passive_evict.get(),
             recommendation: vec![],
+            shards: vec![],
         }
     }
 }
@@ -393,6 +397,42 @@ mod tests {
         assert!(h.is_none());
     }
 
+    #[test]
+    fn test_lru_set_capacity() {
+        use super::lru::*;
+
+        let c = Arc::new(LRUCache::new(4, -1));
+        for i in 1..=4 {
+            let h = c
+                .insert(i, Some(vec![i]), 1, CacheOption::default())
+                .unwrap()
+                .unwrap();
+            drop(h);
+        }
+
+        // Shrinking the cache evicts the least recently used entries.
+        c.set_capacity(2);
+        assert!(c.lookup(1).is_none());
+        assert!(c.lookup(2).is_none());
+        assert!(c.lookup(3).is_some());
+        assert!(c.lookup(4).is_some());
+        assert!(c.is_full());
+
+        // Growing the cache keeps more entries.
+        c.set_capacity(8);
+        assert!(!c.is_full());
+        for i in 5..=8 {
+            let h = c
+                .insert(i, Some(vec![i]), 1, CacheOption::default())
+                .unwrap()
+                .unwrap();
+            drop(h);
+        }
+        for i in 3..=8 {
+            assert!(c.lookup(i).is_some());
+        }
+    }
+
     #[test]
     fn test_base_cache_op() {
         use super::clock::*;
@@ -6,14 +6,51 @@ pub(crate) enum Error {
     Again,
     #[error("Corrupted")]
     Corrupted,
+    #[error("Corrupted in memory")]
+    CorruptedInMemory,
+    #[error("Page {page_id} is mapped to a page of {mismatched_id}")]
+    CorruptedMapping { page_id: u64, mismatched_id: u64 },
     #[error("Invalid argument")]
     InvalidArgument,
     #[error("Memory Limit")]
     MemoryLimit,
     #[error("TooLarge put size")]
     TooLargeSize,
+    #[error("Sealed")]
+    Sealed,
+    #[error("Paused")]
+    Paused,
+    #[error("No space")]
+    NoSpace,
+    #[error("Transaction conflict")]
+    TxnConflict,
+    #[error("LSN conflict with latest LSN {latest}")]
+    LsnConflict { latest: u64 },
+    #[error("LSN regression below {high_water}")]
+    LsnRegression { high_water: u64 },
+    #[error("Timeout after {retries} retries: {last}")]
+    Timeout {
+        retries: usize,
+        #[source]
+        last: Box<Error>,
+    },
+    #[error("Budget exceeded after {consumed} bytes")]
+    BudgetExceeded { consumed: u64 },
     #[error("IO {0}")]
     Io(#[from] std::io::Error),
 }
 
+impl Error {
+    /// Returns true if the error is a transient IO error, which may go away if
+    /// the operation is retried.
+    pub(crate) fn is_retryable(&self) -> bool {
+        matches!(self, Error::Io(err) if super::is_retryable(err.kind()))
+    }
+
+    /// Returns true if the error is caused by a full disk.
+    pub(crate) fn is_no_space(&self) -> bool {
+        matches!(self, Error::Io(err) if err.kind() == std::io::ErrorKind::StorageFull)
+    }
+}
+
 pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
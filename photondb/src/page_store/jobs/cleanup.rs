@@ -4,20 +4,32 @@ use log::info;
 
 use crate::{
     env::Env,
-    page_store::{PageFiles, Version},
-    util::shutdown::{with_shutdown, Shutdown},
+    page_store::{PageFiles, PageTable, Version},
+    util::{
+        pause::PauseSwitch,
+        shutdown::{with_shutdown, Shutdown},
+    },
 };
 
 pub(crate) struct CleanupCtx<E: Env> {
     shutdown: Shutdown,
+    pause: PauseSwitch,
     page_files: Arc<PageFiles<E>>,
+    page_table: PageTable,
 }
 
 impl<E: Env> CleanupCtx<E> {
-    pub(crate) fn new(shutdown: Shutdown, page_files: Arc<PageFiles<E>>) -> Self {
+    pub(crate) fn new(
+        shutdown: Shutdown,
+        pause: PauseSwitch,
+        page_files: Arc<PageFiles<E>>,
+        page_table: PageTable,
+    ) -> Self {
         CleanupCtx {
             shutdown,
+            pause,
             page_files,
+            page_table,
         }
     }
 
@@ -30,6 +42,7 @@ impl<E: Env> CleanupCtx<E> {
 
             let files = next_version.obsoleted_files();
             std::mem::swap(&mut next_version, &mut version);
+            let retired_page_ids = next_version.retired_page_ids();
             if with_shutdown(&mut self.shutdown, next_version.wait_version_released())
                 .await
                 .is_none()
@@ -37,10 +50,21 @@ impl<E: Env> CleanupCtx<E> {
                 break;
             }
 
+            let Some(_active) = with_shutdown(&mut self.shutdown, self.pause.enter()).await else {
+                break;
+            };
+
             // Since all previous versions are invisible, releasing the former buffer is
             // safety.
             version.release_previous_buffers();
 
+            // No one reads the retired pages anymore.
+            for id in retired_page_ids.lock().expect("Poisoned").drain(..) {
+                // Safety: the pages are unreachable, and the guards that may have
+                // read them before are released with the version.
+                unsafe { self.page_table.dealloc(id) };
+            }
+
             // Now it is safety to cleanup the version.
             self.clean_obsoleted_files(&version, files).await;
         }
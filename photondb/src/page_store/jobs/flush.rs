@@ -1,21 +1,39 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use log::info;
+use futures::future::{self, Either};
+use log::{error, info};
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::{
     env::Env,
+    fault::{self, FaultPoint},
     page_store::{stats::AtomicJobStats, *},
-    util::shutdown::{with_shutdown, Shutdown},
+    util::{
+        pause::PauseSwitch,
+        shutdown::{with_shutdown, Shutdown},
+    },
 };
 
+/// The interval to retry a flush that fails because the disk is full.
+const DISK_FULL_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
 pub(crate) struct FlushCtx<E: Env> {
     options: Options,
+    live_options: Arc<LiveOptions>,
     shutdown: Shutdown,
+    pause: PauseSwitch,
     job_stats: Arc<AtomicJobStats>,
     version_owner: Arc<VersionOwner>,
     page_files: Arc<PageFiles<E>>,
     manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
+    max_lsn: Arc<AtomicU64>,
+    flushed_lsn: Arc<AtomicU64>,
 }
 
 #[derive(Default)]
@@ -29,21 +47,30 @@ struct FlushPageStats {
 }
 
 impl<E: Env> FlushCtx<E> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         options: Options,
+        live_options: Arc<LiveOptions>,
         shutdown: Shutdown,
+        pause: PauseSwitch,
         job_stats: Arc<AtomicJobStats>,
         version_owner: Arc<VersionOwner>,
         page_files: Arc<PageFiles<E>>,
         manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
+        max_lsn: Arc<AtomicU64>,
+        flushed_lsn: Arc<AtomicU64>,
     ) -> Self {
         FlushCtx {
             options,
+            live_options,
             shutdown,
+            pause,
             job_stats,
             version_owner,
             page_files,
             manifest,
+            max_lsn,
+            flushed_lsn,
         }
     }
 
@@ -56,16 +83,50 @@ impl<E: Env> FlushCtx<E> {
             // [`Notify`] is single permits. But this may also lead to [`WriteBuffer`]
             // flushed but notified is not consumed, so loop detection is required.
             while !write_buffer.is_flushable() {
-                if with_shutdown(&mut self.shutdown, version.buffer_set.wait_flushable())
-                    .await
-                    .is_none()
-                {
-                    break 'OUTER;
+                let next = future::select(
+                    Box::pin(version.buffer_set.wait_flushable()),
+                    Box::pin(self.page_files.wait_cache_pressure()),
+                );
+                match with_shutdown(&mut self.shutdown, next).await {
+                    Some(Either::Left(_)) => {}
+                    Some(Either::Right(_)) => {
+                        // Flush the active buffer regardless of its size, see
+                        // [`Options::cache_pressure_flush_threshold`].
+                        if version.buffer_set.try_flush_active_buffer().await {
+                            self.job_stats.cache_pressure_flushes.inc();
+                        }
+                    }
+                    None => break 'OUTER,
                 }
             }
 
-            match self.flush(write_buffer.as_ref()).await {
-                Ok(()) => {}
+            // Don't start a flush while the jobs are paused. The buffers are still
+            // flushed on shutdown then, see below.
+            let Some(_active) = with_shutdown(&mut self.shutdown, self.pause.enter()).await else {
+                break 'OUTER;
+            };
+            let write_buffers = self.flushable_buffers(&version, write_buffer);
+            let start_at = Instant::now();
+            let result = self.flush_buffers(&write_buffers).await;
+            self.job_stats.flush_latency.record(start_at.elapsed());
+            match result {
+                Ok(()) => {
+                    if self.page_files.set_disk_full(false) {
+                        info!("Flush succeeds after the disk was full, accept writes again");
+                    }
+                }
+                Err(err) if err.is_no_space() => {
+                    // Reject writes until the buffer is flushed, otherwise they would wait
+                    // for the buffers forever. This also triggers the reclaiming to free
+                    // up some space.
+                    if !self.page_files.set_disk_full(true) {
+                        error!("Flush write buffer: {err:?}, reject writes until it succeeds");
+                    }
+                    let sleep = self.page_files.env().sleep(DISK_FULL_RETRY_INTERVAL);
+                    if with_shutdown(&mut self.shutdown, sleep).await.is_none() {
+                        break 'OUTER;
+                    }
+                }
                 Err(err) => {
                     todo!("flush write buffer: {err:?}");
                 }
@@ -105,6 +166,79 @@ impl<E: Env> FlushCtx<E> {
         }
     }
 
+    /// Returns the consecutive flushable buffers from the first one, up to
+    /// [`Options::max_concurrent_flushes`].
+    fn flushable_buffers(
+        &self,
+        version: &Version,
+        first: Arc<WriteBuffer>,
+    ) -> Vec<Arc<WriteBuffer>> {
+        let limit = self.options.max_concurrent_flushes.max(1);
+        let mut buffers = vec![first];
+        for id in version.buffers_range().skip(1) {
+            if buffers.len() >= limit {
+                break;
+            }
+            match version.get(id) {
+                Some(buffer) if buffer.is_flushable() => buffers.push(buffer.clone()),
+                _ => break,
+            }
+        }
+        buffers
+    }
+
+    /// Flushes the buffers concurrently, and installs them in order.
+    ///
+    /// If a buffer fails to flush, the buffers before it are still installed,
+    /// and the files built for the buffers after it are removed.
+    async fn flush_buffers(&self, write_buffers: &[Arc<WriteBuffer>]) -> Result<()> {
+        if let [write_buffer] = write_buffers {
+            self.job_stats.flushes_in_flight.set(1);
+            let result = self.flush(write_buffer).await;
+            self.job_stats.flushes_in_flight.set(0);
+            return result;
+        }
+
+        self.job_stats
+            .flushes_in_flight
+            .set(write_buffers.len() as u64);
+        let builds = future::join_all(
+            write_buffers
+                .iter()
+                .map(|write_buffer| self.build_page_file(write_buffer)),
+        )
+        .await;
+        let mut result = Ok(());
+        for (i, (write_buffer, build)) in write_buffers.iter().zip(builds).enumerate() {
+            match build {
+                Ok((dealloc_pages, page_group, file_info)) if result.is_ok() => {
+                    self.install_page_file(
+                        write_buffer,
+                        dealloc_pages,
+                        page_group,
+                        file_info,
+                        true,
+                    )
+                    .await;
+                }
+                Ok((_, _, file_info)) => {
+                    // The buffers must be installed in order.
+                    let file_id = file_info.meta().file_id;
+                    self.page_files.remove_files(vec![file_id]).await;
+                }
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+            self.job_stats
+                .flushes_in_flight
+                .set((write_buffers.len() - i - 1) as u64);
+        }
+        result
+    }
+
     #[inline]
     async fn flush(&self, write_buffer: &WriteBuffer) -> Result<()> {
         self.flush_impl(write_buffer, true).await
@@ -125,12 +259,41 @@ impl<E: Env> FlushCtx<E> {
             start_at.elapsed().as_micros()
         );
 
+        self.install_page_file(write_buffer, dealloc_pages, page_group, file_info, wait)
+            .await;
+        Ok(())
+    }
+
+    /// Records the page file built from the buffer to the manifest, and
+    /// installs it to a new version.
+    async fn install_page_file(
+        &self,
+        write_buffer: &WriteBuffer,
+        dealloc_pages: Vec<u64>,
+        page_group: PageGroup,
+        file_info: FileInfo,
+        wait: bool,
+    ) {
+        fault::inject(
+            self.options.fault_injector.as_ref(),
+            FaultPoint::FlushBeforeManifest,
+        );
+        // Writes may come with LSNs that are not allocated by the store, which
+        // are recorded in the manifest as well.
+        if let Some((_, max_lsn)) = write_buffer.lsn_range() {
+            self.max_lsn.fetch_max(max_lsn, Ordering::AcqRel);
+        }
+        // A failed manifest write may leave a partial record, so it can't be
+        // retried like a failed page file.
         self.save_and_install_version(page_group, file_info, dealloc_pages, wait)
-            .await?;
+            .await
+            .expect("TODO: record flush version edit");
 
+        // The writes of the buffer are durable now.
+        if let Some((_, max_lsn)) = write_buffer.lsn_range() {
+            self.flushed_lsn.fetch_max(max_lsn, Ordering::AcqRel);
+        }
         write_buffer.on_flushed();
-
-        Ok(())
     }
 
     async fn save_and_install_version(
@@ -155,7 +318,10 @@ impl<E: Env> FlushCtx<E> {
         }
         file_infos.insert(file_id, file_info);
 
-        let edit = make_flush_version_edit(file_id, &obsoleted_files);
+        // All writes to the flushed buffer have finished and their LSNs have
+        // been recorded, so they are not larger than the current one.
+        let max_lsn = self.max_lsn.load(Ordering::Acquire);
+        let edit = make_flush_version_edit(file_id, &obsoleted_files, max_lsn);
         manifest
             .record_version_edit(edit, || version_snapshot(&version))
             .await?;
@@ -195,11 +361,32 @@ impl<E: Env> FlushCtx<E> {
             let mut lock = self.manifest.lock().await;
             lock.next_file_id()
         };
+        match self
+            .build_page_file_impl(write_buffer, file_id, &dealloc_pages, &skip_pages)
+            .await
+        {
+            Ok((page_group, file_info)) => Ok((dealloc_pages, page_group, file_info)),
+            Err(err) => {
+                // Remove the partial file, which may take the space of the retry.
+                self.page_files.remove_files(vec![file_id]).await;
+                Err(err)
+            }
+        }
+    }
+
+    async fn build_page_file_impl(
+        &self,
+        write_buffer: &WriteBuffer,
+        file_id: u32,
+        dealloc_pages: &[u64],
+        skip_pages: &FxHashSet<u32>,
+    ) -> Result<(PageGroup, FileInfo)> {
+        let group_id = write_buffer.group_id();
         let mut builder = self
             .page_files
             .new_file_builder(
                 file_id,
-                self.options.compression_on_flush,
+                self.live_options.compression_on_flush(),
                 self.options.page_checksum_type,
             )
             .await?;
@@ -220,7 +407,7 @@ impl<E: Env> FlushCtx<E> {
                 let _ = self.page_files.populate_cache(page_addr, content);
             }
         }
-        group_builder.add_dealloc_pages(&dealloc_pages);
+        group_builder.add_dealloc_pages(dealloc_pages);
         builder = group_builder.finish().await?;
         let (page_groups, file_info) = builder.finish(file_id).await?;
         let page_group = page_groups.get(&group_id).unwrap().clone();
@@ -228,7 +415,7 @@ impl<E: Env> FlushCtx<E> {
         self.job_stats.flush_write_bytes.add(write_bytes as u64);
         self.job_stats.flush_discard_bytes.add(discard_bytes as u64);
 
-        Ok((dealloc_pages, page_group, file_info))
+        Ok((page_group, file_info))
     }
 
     fn apply_dealloc_pages(
@@ -357,12 +544,18 @@ pub(super) fn version_snapshot(version: &Version) -> VersionEdit {
         new_files,
         deleted_files,
     };
+    // The largest LSN is filled by the manifest.
     VersionEdit {
         file_stream: Some(stream),
+        max_lsn: 0,
     }
 }
 
-fn make_flush_version_edit(file_id: u32, obsoleted_files: &FxHashSet<u32>) -> VersionEdit {
+fn make_flush_version_edit(
+    file_id: u32,
+    obsoleted_files: &FxHashSet<u32>,
+    max_lsn: u64,
+) -> VersionEdit {
     let deleted_files = obsoleted_files.iter().cloned().collect();
     let new_files = vec![NewFile::from(file_id)];
     let stream = StreamEdit {
@@ -371,6 +564,7 @@ fn make_flush_version_edit(file_id: u32, obsoleted_files: &FxHashSet<u32>) -> Ve
     };
     VersionEdit {
         file_stream: Some(stream),
+        max_lsn,
     }
 }
 
@@ -383,7 +577,7 @@ mod tests {
         env::Photon,
         page_store::{
             version::{DeltaVersion, Version, VersionOwner},
-            Manifest, PageFiles, WriteBuffer,
+            LiveOptions, Manifest, PageFiles, WriteBuffer,
         },
         util::shutdown::ShutdownNotifier,
         PageStoreOptions,
@@ -401,6 +595,7 @@ mod tests {
         };
         FlushCtx {
             options: opt.to_owned(),
+            live_options: Arc::new(LiveOptions::new(&opt)),
             shutdown,
             job_stats: Arc::default(),
             version_owner,
@@ -408,6 +603,8 @@ mod tests {
             manifest: Arc::new(futures::lock::Mutex::new(
                 Manifest::open(Photon, base).await.unwrap(),
             )),
+            max_lsn: Arc::default(),
+            flushed_lsn: Arc::default(),
         }
     }
 
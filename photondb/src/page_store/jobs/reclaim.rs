@@ -1,20 +1,24 @@
 use std::{sync::Arc, time::Instant};
 
-use log::{debug, info, trace};
+use futures::future::{self, Either};
+use log::{debug, info, trace, warn};
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::{
-    env::Env,
+    env::{Env, IoPriority},
     page::PageRef,
     page_store::{
         page_file::{FileBuilder, FileMetaHolder, FileReader, PageGroupBuilder},
         stats::AtomicJobStats,
-        strategy::ReclaimPickStrategy,
+        strategy::{CandidateInfo, ReclaimPickStrategy},
         version::{DeltaVersion, VersionOwner, VersionUpdateReason},
-        FileInfo, Manifest, NewFile, Options, PageFiles, PageGroup, Result, StrategyBuilder,
-        StreamEdit, Version, VersionEdit,
+        Error, FileInfo, LiveOptions, Manifest, NewFile, Options, PageFiles, PageGroup, Result,
+        StrategyBuilder, StreamEdit, Version, VersionEdit,
+    },
+    util::{
+        pause::PauseSwitch,
+        shutdown::{with_shutdown, Shutdown},
     },
-    util::shutdown::{with_shutdown, Shutdown},
 };
 
 pub(crate) struct ReclaimCtx<E>
@@ -22,13 +26,17 @@ where
     E: Env,
 {
     options: Options,
+    live_options: Arc<LiveOptions>,
     shutdown: Shutdown,
+    pause: PauseSwitch,
 
     strategy_builder: Box<dyn StrategyBuilder>,
 
     page_files: Arc<PageFiles<E>>,
     version_owner: Arc<VersionOwner>,
     manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
+    /// Serializes compactions from the background job and manual requests.
+    compaction_lock: Arc<futures::lock::Mutex<()>>,
 
     cleaned_files: FxHashSet<u32>,
 
@@ -59,11 +67,14 @@ struct ReclaimProgress {
     used_space: u64,
     base_size: u64,
     additional_size: u64,
+    /// Whether flushes are failing because the disk is full.
+    disk_full: bool,
 }
 
 #[derive(Debug)]
 enum ReclaimReason {
     None,
+    DiskFull,
     HighSpaceUsage,
     LargeSpaceAmp,
 }
@@ -84,34 +95,88 @@ where
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         options: Options,
+        live_options: Arc<LiveOptions>,
         shutdown: Shutdown,
+        pause: PauseSwitch,
         strategy_builder: Box<dyn StrategyBuilder>,
         page_files: Arc<PageFiles<E>>,
         version_owner: Arc<VersionOwner>,
         manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
+        compaction_lock: Arc<futures::lock::Mutex<()>>,
         job_stats: Arc<AtomicJobStats>,
     ) -> Self {
         ReclaimCtx {
             options,
+            live_options,
             shutdown,
+            pause,
             strategy_builder,
             page_files,
             version_owner,
             manifest,
+            compaction_lock,
             cleaned_files: FxHashSet::default(),
             job_stats,
         }
     }
 
+    /// Returns the files that can be compacted, in the order that the
+    /// background reclaiming picks them.
+    pub(crate) async fn candidates(&mut self) -> Vec<CandidateInfo> {
+        let now = {
+            let lock = self.manifest.lock().await;
+            lock.now()
+        };
+        let version = self.version_owner.current();
+        let mut strategy = self.build_strategy(now, &version, &FxHashSet::default());
+        strategy.candidates()
+    }
+
+    /// Compacts the file into a new file regardless of the reclaiming policy.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the file does not exist.
+    pub(crate) async fn compact(&mut self, file_id: u32) -> Result<()> {
+        let version = self.version_owner.current();
+        if !version.file_infos().contains_key(&file_id) {
+            return Err(Error::InvalidArgument);
+        }
+        let mut progress = ReclaimProgress::new(&self.options, &version, &FxHashSet::default());
+        let victims = FxHashSet::from_iter([file_id]);
+        self.reclaim_files(&mut progress, &version, victims).await
+    }
+
     pub(crate) async fn run(mut self, mut version: Arc<Version>) {
+        let page_files = self.page_files.clone();
         loop {
             if !self.options.disable_space_reclaiming {
+                let Some(_active) = with_shutdown(&mut self.shutdown, self.pause.enter()).await else {
+                    return;
+                };
                 self.reclaim(&version).await;
                 version.reclaimed();
             }
-            match with_shutdown(&mut self.shutdown, version.wait_next_version()).await {
-                Some(next_version) => version = next_version.refresh().unwrap_or(next_version),
-                None => break,
+            loop {
+                // Reclaim the version again once the disk becomes full, since flushes
+                // are blocked until some space is freed.
+                let next = future::select(
+                    Box::pin(version.wait_next_version()),
+                    Box::pin(page_files.wait_disk_full()),
+                );
+                let next_version = match with_shutdown(&mut self.shutdown, next).await {
+                    Some(Either::Left((next_version, _))) => Some(next_version),
+                    Some(Either::Right(_)) => None,
+                    None => return,
+                };
+                if let Some(next_version) = next_version {
+                    version = next_version.refresh().unwrap_or(next_version);
+                    break;
+                }
+                if !self.options.disable_space_reclaiming {
+                    let Some(_active) = with_shutdown(&mut self.shutdown, self.pause.enter()).await else {
+                        return;
+                    };
+                    self.reclaim(&version).await;
+                }
             }
         }
     }
@@ -120,7 +185,10 @@ where
         // Reclaim deleted files in `cleaned_files`.
         let cleaned_files = std::mem::take(&mut self.cleaned_files);
         let mut progress = ReclaimProgress::new(&self.options, version, &cleaned_files);
+        progress.disk_full = self.page_files.is_disk_full();
         progress.trace_log();
+        self.job_stats.file_bytes.set(progress.used_space);
+        self.job_stats.file_dead_bytes.set(progress.additional_size);
         if !progress.is_reclaimable() {
             return;
         }
@@ -145,7 +213,15 @@ where
             if let Some(job) = builder.add(file, active_size) {
                 match job {
                     ReclaimJob::Compact(victims) => {
-                        self.reclaim_files(progress, version, victims).await;
+                        match self.reclaim_files(progress, version, victims).await {
+                            Ok(()) => {}
+                            // Try again with the next version, which may free up some space.
+                            Err(err) if err.is_no_space() => {
+                                warn!("Reclaim files: {err:?}");
+                                break;
+                            }
+                            Err(err) => panic!("reclaim files: {err:?}"),
+                        }
                     }
                 }
             }
@@ -163,8 +239,18 @@ where
         &mut self,
         progress: &mut ReclaimProgress,
         version: &Arc<Version>,
-        victims: FxHashSet<u32>,
-    ) {
+        mut victims: FxHashSet<u32>,
+    ) -> Result<()> {
+        let compaction_lock = self.compaction_lock.clone();
+        let _guard = compaction_lock.lock().await;
+        // The victims might have been compacted by others since the version was
+        // taken.
+        let current = self.version_owner.current();
+        victims.retain(|id| current.file_infos().contains_key(id));
+        if victims.is_empty() {
+            return Ok(());
+        }
+
         let file_id = {
             let mut lock = self.manifest.lock().await;
             lock.next_file_id()
@@ -172,10 +258,17 @@ where
 
         let file_infos = version.file_infos();
         let page_groups = version.page_groups();
-        let (page_groups, file_info) = self
+        let (page_groups, file_info) = match self
             .compact_files(progress, file_id, file_infos, page_groups, &victims)
             .await
-            .unwrap();
+        {
+            Ok(output) => output,
+            Err(err) => {
+                // Remove the partial file, which may take the space of others.
+                self.page_files.remove_files(vec![file_id]).await;
+                return Err(err);
+            }
+        };
 
         // All input are obsoleted, since it doesn't relocate pages.
         let edit = make_compact_version_edit(&file_info, &victims);
@@ -183,8 +276,7 @@ where
         let version = self.version_owner.current();
         manifest
             .record_version_edit(edit, || super::version_snapshot(&version))
-            .await
-            .unwrap();
+            .await?;
 
         let mut delta = DeltaVersion::from(version.as_ref());
         delta.reason = VersionUpdateReason::Compact;
@@ -195,6 +287,7 @@ where
         delta.obsoleted_files = victims.into_iter().collect();
         // Safety: the mutable reference of [`Manifest`] is hold.
         unsafe { self.version_owner.install(delta) };
+        Ok(())
     }
 
     fn build_strategy(
@@ -232,7 +325,7 @@ where
             .page_files
             .new_file_builder(
                 new_file_id,
-                self.options.compression_on_cold_compact,
+                self.live_options.compression_on_cold_compact(),
                 self.options.page_checksum_type,
             )
             .await?;
@@ -256,7 +349,9 @@ where
         let up2 = up2_sum / (victims.len() as u32);
         let (page_groups, file_info) = builder.finish(up2).await?;
 
-        let elapsed = start_at.elapsed().as_micros();
+        let elapsed = start_at.elapsed();
+        self.job_stats.reclaim_latency.record(elapsed);
+        let elapsed = elapsed.as_micros();
         let CompactStats {
             num_active_pages,
             num_dealloc_pages,
@@ -361,7 +456,13 @@ where
             }
             page.truncate(page_size);
             self.page_files
-                .read_file_page_from_reader(reader, file_info.meta(), handle, &mut page)
+                .read_file_page_from_reader(
+                    reader,
+                    file_info.meta(),
+                    handle,
+                    &mut page,
+                    IoPriority::Low,
+                )
                 .await?;
             let page_id = *page_table.get(&page_addr).expect("Must exists");
             let page_ref = PageRef::new(page.as_slice());
@@ -409,6 +510,7 @@ impl ReclaimProgress {
             used_space,
             base_size,
             additional_size,
+            disk_full: false,
         }
     }
 
@@ -430,6 +532,10 @@ impl ReclaimProgress {
     }
 
     fn reclaim_reason(&self) -> ReclaimReason {
+        // Reclaim any space we can if the disk is full.
+        if self.disk_full && 0 < self.additional_size {
+            return ReclaimReason::DiskFull;
+        }
         // If space usage exceeds high watermark,
         if self.space_used_high < self.used_space
             // .. and enough space for reclaiming.
@@ -447,7 +553,9 @@ impl ReclaimProgress {
 
     fn is_reclaimable(&self) -> bool {
         match self.reclaim_reason() {
-            ReclaimReason::HighSpaceUsage | ReclaimReason::LargeSpaceAmp => true,
+            ReclaimReason::DiskFull
+            | ReclaimReason::HighSpaceUsage
+            | ReclaimReason::LargeSpaceAmp => true,
             ReclaimReason::None => false,
         }
     }
@@ -455,6 +563,14 @@ impl ReclaimProgress {
     fn trace_log(&self) {
         let space_amp = (self.additional_size as f64) / (self.base_size as f64);
         match self.reclaim_reason() {
+            ReclaimReason::DiskFull => {
+                trace!(
+                    "db is reclaimable: disk is full, base size {}, additional size {}, used space {}",
+                    self.base_size,
+                    self.additional_size,
+                    self.used_space
+                );
+            }
             ReclaimReason::HighSpaceUsage => {
                 trace!(
                     "db is reclaimable: space used {} exceeds water mark {}, base size {}, amp {:.4}",
@@ -548,6 +664,7 @@ fn make_compact_version_edit(
             new_files,
             deleted_files,
         }),
+        max_lsn: 0,
     }
 }
 
@@ -587,12 +704,15 @@ mod tests {
         )));
         let page_files = Arc::new(PageFiles::new(Photon, dir, &options).await);
         ReclaimCtx {
+            live_options: Arc::new(LiveOptions::new(&options)),
             options,
             shutdown,
+            pause: PauseSwitch::default(),
             strategy_builder,
             page_files,
             manifest,
             version_owner,
+            compaction_lock: Arc::default(),
             cleaned_files: HashSet::default(),
             job_stats: Arc::default(),
         }
@@ -722,7 +842,9 @@ mod tests {
         unsafe { ctx.version_owner.install(delta) };
         let version = ctx.version_owner.current();
         let mut progress = ReclaimProgress::new(&ctx.options, &version, &HashSet::default());
-        ctx.reclaim_files(&mut progress, &version, victims).await;
+        ctx.reclaim_files(&mut progress, &version, victims)
+            .await
+            .unwrap();
 
         let version = ctx.version_owner.current();
         let page_groups = version.page_groups();
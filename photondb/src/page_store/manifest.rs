@@ -1,9 +1,16 @@
-use std::{io::ErrorKind, path::PathBuf};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 
 use prost::Message;
 
 use super::{meta::VersionEdit, Error};
-use crate::{env::*, page_store::Result};
+use crate::{
+    env::*,
+    fault::{self, FaultInjector, FaultPoint},
+    page_store::Result,
+};
 
 const CURRENT_FILE_NAME: &str = "CURRENT";
 const MANIFEST_FILE_NAME: &str = "MANIFEST";
@@ -18,9 +25,13 @@ pub(crate) struct Manifest<E: Env> {
     max_file_size: u64,
 
     next_file_id: u32,
+    /// The largest LSN recorded in the manifest.
+    max_lsn: u64,
 
     current_file_num: Option<u32>,
     current_writer: Option<ManifestWriter<E::SequentialWriter>>,
+
+    fault_injector: Option<FaultInjector>,
 }
 
 struct ManifestWriter<W> {
@@ -32,23 +43,42 @@ impl<E: Env> Manifest<E> {
     // Open manifest in specified folder.
     // it will reopen manifest by find CURRENT and do some cleanup.
     pub(crate) async fn open(env: E, base: impl Into<PathBuf>) -> Result<Self> {
-        let base = base.into();
+        let mut manifest = Self::new(env, base.into());
+        manifest.create_base_dir_if_not_exist().await?;
+        manifest.current_file_num = manifest.load_current().await?;
+        manifest.open_base_dir().await?;
+        manifest.cleanup_obsolete_files().await?;
 
-        let mut manifest = Self {
+        Ok(manifest)
+    }
+
+    /// Opens the manifest in the folder without creating or removing any
+    /// files, so that it can be read while the folder is used by others.
+    ///
+    /// The returned manifest must not record version edits.
+    pub(crate) async fn open_read_only(env: E, base: impl Into<PathBuf>) -> Result<Self> {
+        let mut manifest = Self::new(env, base.into());
+        manifest.current_file_num = manifest.load_current().await?;
+        Ok(manifest)
+    }
+
+    fn new(env: E, base: PathBuf) -> Self {
+        Self {
             env,
             base,
             base_dir: None,
             max_file_size: MAX_MANIFEST_SIZE,
             next_file_id: 0,
+            max_lsn: 0,
             current_file_num: Default::default(),
             current_writer: None,
-        };
-        manifest.create_base_dir_if_not_exist().await?;
-        manifest.current_file_num = manifest.load_current().await?;
-        manifest.open_base_dir().await?;
-        manifest.cleanup_obsolete_files().await?;
+            fault_injector: None,
+        }
+    }
 
-        Ok(manifest)
+    /// Returns true if a manifest has been recorded in the folder.
+    pub(crate) async fn exists(env: &E, base: &Path) -> bool {
+        env.metadata(base.join(CURRENT_FILE_NAME)).await.is_ok()
     }
 
     async fn create_base_dir_if_not_exist(&self) -> Result<()> {
@@ -80,6 +110,10 @@ impl<E: Env> Manifest<E> {
         Ok(())
     }
 
+    pub(super) fn set_fault_injector(&mut self, fault_injector: Option<FaultInjector>) {
+        self.fault_injector = fault_injector;
+    }
+
     pub(super) fn reset_next_file_id(&mut self, next_id: u32) {
         self.next_file_id = next_id;
     }
@@ -94,6 +128,14 @@ impl<E: Env> Manifest<E> {
         self.next_file_id
     }
 
+    pub(super) fn reset_max_lsn(&mut self, max_lsn: u64) {
+        self.max_lsn = max_lsn;
+    }
+
+    pub(crate) fn max_lsn(&self) -> u64 {
+        self.max_lsn
+    }
+
     // Record a new version_edit to manifest file.
     // it will rolling file when the file size over `max_file_size`.
     // so it need pass-in a `version_snapshot` to get current snapshot when it
@@ -103,6 +145,8 @@ impl<E: Env> Manifest<E> {
         ve: VersionEdit,
         version_snapshot: impl FnOnce() -> VersionEdit,
     ) -> Result<()> {
+        // The largest LSN is carried over to the snapshot when the file rolls.
+        self.max_lsn = self.max_lsn.max(ve.max_lsn);
         let mut current = self.current_writer.take();
         let mut file_num = self.current_file_num.as_ref().unwrap_or(&0).to_owned();
 
@@ -130,12 +174,13 @@ impl<E: Env> Manifest<E> {
         let mut current = current.unwrap();
         let written = if rolled_path.is_some() {
             // TODO: remove new created file when write fail.
-            let base_snapshot = version_snapshot();
+            let mut base_snapshot = version_snapshot();
+            base_snapshot.max_lsn = self.max_lsn;
             let base_written = VersionEditEncoder(base_snapshot)
-                .encode(&mut current.current_writer)
+                .encode(&mut current.current_writer, None)
                 .await?;
             match VersionEditEncoder(ve)
-                .encode(&mut current.current_writer)
+                .encode(&mut current.current_writer, self.fault_injector.as_ref())
                 .await
             {
                 Ok(record_written) => base_written + record_written,
@@ -146,7 +191,7 @@ impl<E: Env> Manifest<E> {
             }
         } else {
             VersionEditEncoder(ve)
-                .encode(&mut current.current_writer)
+                .encode(&mut current.current_writer, self.fault_injector.as_ref())
                 .await?
         } as u64;
 
@@ -176,7 +221,13 @@ impl<E: Env> Manifest<E> {
 
     // List current versions.
     // the caller can recovery Versions by apply each version_edits.
-    pub(crate) async fn list_versions(&self) -> Result<Vec<VersionEdit>> {
+    //
+    // The number of records and bytes decoded so far are reported to the
+    // progress after each record.
+    pub(crate) async fn list_versions(
+        &self,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<Vec<VersionEdit>> {
         Ok(if let Some(current_file) = self.current_file_num {
             let path = self
                 .base
@@ -189,7 +240,8 @@ impl<E: Env> Manifest<E> {
             let mut decoder = VersionEditDecoder::new(reader);
             let mut ves = Vec::new();
             while let Some(ve) = decoder.next_record().await.expect("manifest decode error") {
-                ves.push(ve)
+                ves.push(ve);
+                progress(ves.len() as u64, decoder.offset);
             }
             ves
         } else {
@@ -320,11 +372,16 @@ impl<E: Env> Manifest<E> {
 struct VersionEditEncoder(VersionEdit);
 
 impl VersionEditEncoder {
-    async fn encode<W: SequentialWriter>(&self, w: &mut W) -> Result<usize> {
+    async fn encode<W: SequentialWriter>(
+        &self,
+        w: &mut W,
+        fault_injector: Option<&FaultInjector>,
+    ) -> Result<usize> {
         let bytes = self.0.encode_to_vec();
         w.write_all(&bytes.len().to_le_bytes())
             .await
             .expect("write version edit fail");
+        fault::inject(fault_injector, FaultPoint::ManifestWrite);
         w.write_all(&bytes).await.expect("write version edit fail");
         Ok(bytes.len() + core::mem::size_of::<u64>())
     }
@@ -361,10 +418,17 @@ impl<R: PositionalReader> VersionEditDecoder<R> {
         offset += core::mem::size_of::<u64>() as u64;
         let ve = {
             let mut ve_bytes = vec![0u8; len as usize];
-            self.reader
+            match self
+                .reader
                 .read_exact_at(&mut ve_bytes, offset as u64)
                 .await
-                .expect("read version edit record payload fail");
+            {
+                Ok(_) => {}
+                // The last record is torn by a crash in the middle of the
+                // write, so it's never applied.
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                e @ Err(_) => e.expect("read version edit record payload fail"),
+            };
             VersionEdit::decode(ve_bytes.as_slice()).expect("decode version edit fail")
         };
         self.offset = offset + len;
@@ -382,6 +446,38 @@ mod tests {
         ids.into_iter().map(Into::into).collect()
     }
 
+    #[photonio::test]
+    fn test_open_read_only() {
+        let env = crate::env::Photon;
+        let base = tempdir::TempDir::new("read_only_test").unwrap();
+        let ve = VersionEdit {
+            file_stream: Some(StreamEdit {
+                new_files: new_files(vec![1]),
+                deleted_files: vec![],
+            }),
+            max_lsn: 0,
+        };
+        let mut manifest = Manifest::open(env.to_owned(), base.as_ref()).await.unwrap();
+        manifest
+            .record_version_edit(ve, VersionEdit::default)
+            .await
+            .unwrap();
+
+        // A temporary file of the live manifest is left as it is.
+        let tmp_path = base.path().join(format!("curr.9.{TEMPFILE_SUFFIX}"));
+        std::fs::write(&tmp_path, b"").unwrap();
+        let read_only = Manifest::open_read_only(env.to_owned(), base.as_ref())
+            .await
+            .unwrap();
+        assert_eq!(read_only.list_versions(|_, _| {}).await.unwrap().len(), 2);
+        assert!(tmp_path.exists());
+
+        let missing = base.path().join("missing");
+        let read_only = Manifest::open_read_only(env, &missing).await.unwrap();
+        assert!(read_only.list_versions(|_, _| {}).await.unwrap().is_empty());
+        assert!(!missing.exists());
+    }
+
     #[photonio::test]
     fn test_cleanup_when_restart() {
         let env = crate::env::Photon;
@@ -400,6 +496,7 @@ mod tests {
                             new_files: new_files(vec![2, 3]),
                             deleted_files: vec![1],
                         }),
+                        max_lsn: 0,
                     },
                     version_snapshot,
                 )
@@ -413,6 +510,7 @@ mod tests {
                             new_files: new_files(vec![2, 3]),
                             deleted_files: vec![1],
                         }),
+                        max_lsn: 0,
                     },
                     version_snapshot,
                 )
@@ -425,6 +523,7 @@ mod tests {
                             new_files: new_files(vec![2, 3]),
                             deleted_files: vec![1],
                         }),
+                        max_lsn: 0,
                     },
                     version_snapshot,
                 )
@@ -466,6 +565,7 @@ mod tests {
                 new_files: vec![],
                 deleted_files: vec![],
             }),
+            max_lsn: 0,
         }));
 
         let ve_snapshot = || {
@@ -498,6 +598,7 @@ mod tests {
                     new_files: new_files(vec![0]),
                     deleted_files: vec![],
                 }),
+                max_lsn: 0,
             };
             manifest
                 .record_version_edit(ve.to_owned(), ve_snapshot)
@@ -513,6 +614,7 @@ mod tests {
                         new_files: new_files(vec![i]),
                         deleted_files: vec![r],
                     }),
+                    max_lsn: 0,
                 };
                 manifest
                     .record_version_edit(ve.to_owned(), ve_snapshot)
@@ -525,11 +627,12 @@ mod tests {
 
         {
             let mut manifest2 = Manifest::open(env, base.as_ref()).await.unwrap();
-            let versions = manifest2.list_versions().await.unwrap();
+            let versions = manifest2.list_versions(|_, _| {}).await.unwrap();
             assert_eq!(manifest2.current_file_num, Some(36));
 
             let mut recover_ver = VersionEdit {
                 file_stream: Some(StreamEdit::default()),
+                max_lsn: 0,
             };
             for ve in versions {
                 let recover_ver = recover_ver.file_stream.as_mut().unwrap();
@@ -550,6 +653,7 @@ mod tests {
                     new_files: new_files(vec![1]),
                     deleted_files: vec![],
                 }),
+                max_lsn: 0,
             };
             manifest2
                 .record_version_edit(ve.to_owned(), ve_snapshot)
@@ -576,6 +680,7 @@ mod tests {
                             new_files: new_files(vec![2, 3]),
                             deleted_files: vec![1],
                         }),
+                        max_lsn: 0,
                     },
                     version_snapshot,
                 )
@@ -588,6 +693,7 @@ mod tests {
                             new_files: new_files(vec![4]),
                             deleted_files: vec![],
                         }),
+                        max_lsn: 0,
                     },
                     version_snapshot,
                 )
@@ -600,6 +706,7 @@ mod tests {
                             new_files: new_files(vec![5]),
                             deleted_files: vec![],
                         }),
+                        max_lsn: 0,
                     },
                     version_snapshot,
                 )
@@ -609,8 +716,57 @@ mod tests {
 
         {
             let manifest2 = Manifest::open(env, base.as_ref()).await.unwrap();
-            let versions = manifest2.list_versions().await.unwrap();
+            let versions = manifest2.list_versions(|_, _| {}).await.unwrap();
             assert_eq!(versions.len(), 4);
         }
     }
+
+    #[photonio::test]
+    #[cfg(debug_assertions)]
+    fn test_torn_record() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        use futures::executor::block_on;
+
+        use crate::{FaultAction, FaultInjector};
+
+        let version_snapshot = VersionEdit::default;
+        let ve = |id| VersionEdit {
+            file_stream: Some(StreamEdit {
+                new_files: new_files(vec![id]),
+                deleted_files: vec![],
+            }),
+            max_lsn: 0,
+        };
+
+        let env = crate::env::Photon;
+        let base = tempdir::TempDir::new("torn_test").unwrap();
+        let injector = FaultInjector::new();
+        {
+            let mut manifest = Manifest::open(env.to_owned(), base.as_ref()).await.unwrap();
+            manifest.set_fault_injector(Some(injector.clone()));
+            manifest
+                .record_version_edit(ve(1), version_snapshot)
+                .await
+                .unwrap();
+            injector.arm(FaultPoint::ManifestWrite, FaultAction::Panic);
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                block_on(manifest.record_version_edit(ve(2), version_snapshot))
+            }));
+            assert!(result.is_err());
+        }
+
+        {
+            let mut manifest = Manifest::open(env, base.as_ref()).await.unwrap();
+            let versions = manifest.list_versions(|_, _| {}).await.unwrap();
+            // The base snapshot and the first record.
+            assert_eq!(versions.len(), 2);
+            manifest
+                .record_version_edit(ve(3), version_snapshot)
+                .await
+                .unwrap();
+            let versions = manifest.list_versions(|_, _| {}).await.unwrap();
+            assert_eq!(versions.len(), 2);
+        }
+    }
 }
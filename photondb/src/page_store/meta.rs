@@ -28,6 +28,9 @@ pub(crate) struct VersionEdit {
     /// A set of map files.
     #[prost(message, tag = "1")]
     pub file_stream: Option<StreamEdit>,
+    /// The largest LSN allocated by the page store so far.
+    #[prost(uint64, tag = "2")]
+    pub max_lsn: u64,
 }
 
 mod convert {
@@ -67,6 +70,7 @@ mod tests {
                 new_files,
                 deleted_files: vec![1, 2, 3],
             }),
+            max_lsn: 7,
         };
 
         let payload = edit.encode_to_vec();
@@ -1,13 +1,32 @@
-use std::{fmt, mem, path::Path, sync::Arc};
+use std::{
+    fmt,
+    future::Future,
+    mem,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use log::warn;
 
-use crate::{env::Env, util::shutdown::ShutdownNotifier};
+use crate::{
+    env::{Env, IoPriority},
+    util::{
+        pause::PauseSwitch,
+        shutdown::{with_shutdown, ShutdownNotifier},
+    },
+    FaultInjector,
+};
 
 mod error;
 pub(crate) use error::{Error, Result};
 
 mod page_txn;
 use futures::lock::Mutex;
-pub(crate) use page_txn::{CacheOption, Guard};
+pub(crate) use page_txn::{CacheEntryGuard, CacheOption, Guard, PageTxn};
 
 mod page_table;
 use page_table::PageTable;
@@ -34,7 +53,17 @@ mod page_file;
 pub(crate) use page_file::{FileInfo, PageFiles, PageGroup};
 
 mod recover;
+pub(crate) use recover::RecoveryCallback;
+
+mod retry;
+pub use recover::{RecoveryPhase, RecoveryProgress, RecoveryReport};
+pub(crate) use retry::is_retryable;
+pub use retry::IoRetryPolicy;
+mod verify;
+pub use verify::VerifyReport;
+
 mod strategy;
+pub use strategy::CandidateInfo;
 pub(crate) use strategy::{MinDeclineRateStrategyBuilder, StrategyBuilder};
 
 mod cache;
@@ -42,20 +71,27 @@ mod cache;
 pub(crate) use cache::{clock::ClockCache, lru::LRUCache, Cache, CacheEntry, CacheToken};
 
 mod stats;
-pub use page_file::{ChecksumType, Compression};
-pub use stats::StoreStats;
+pub use page_file::{ChecksumType, Compression, FileStat};
+pub(crate) use stats::AtomicLatency;
+pub use stats::{BackgroundStats, LatencyStats, StoreStats};
 
 use self::{
     jobs::wait_for_reclaiming,
     stats::{AtomicJobStats, AtomicWritebufStats},
 };
 
+/// The maximum number of shards of the page cache.
+const MAX_CACHE_SHARDS: usize = 1 << 19;
+
 /// Options to configure a page store.
 #[non_exhaustive]
 #[derive(Clone, Debug)]
 pub struct Options {
     /// The capacity of the write buffer. It should be power of two.
     ///
+    /// Each write buffer is flushed into a new page file, so this also bounds
+    /// the size of files written by flushes.
+    ///
     /// Default: 128MB
     pub write_buffer_capacity: u32,
 
@@ -67,6 +103,17 @@ pub struct Options {
     /// Default: 8
     pub max_write_buffers: usize,
 
+    /// The maximum number of write buffers that are flushed in parallel.
+    ///
+    /// When several write buffers are full at the same time, like under
+    /// bursts of writes, up to this many of them are written to page files
+    /// concurrently, and the rest are queued. The flushed buffers are still
+    /// installed in order. A smaller value trades flush throughput for less
+    /// contention with foreground IO. Zero is treated as one.
+    ///
+    /// Default: 1
+    pub max_concurrent_flushes: usize,
+
     /// If true, use O_DIRECT to read/write page files.
     ///
     /// Default: false
@@ -93,6 +140,12 @@ pub struct Options {
 
     /// Target file size for compaction.
     ///
+    /// Space is reclaimed at the granularity of files: files picked for
+    /// reclaiming are compacted together, and a new file is started once the
+    /// live pages collected from them reach this size. The compacted files are
+    /// deleted afterwards. Smaller files make reclaiming more selective, at the
+    /// cost of more files to open and track.
+    ///
     /// Default: 64MB
     pub file_base_size: usize,
 
@@ -101,6 +154,47 @@ pub struct Options {
     /// Default: 8 Mib
     pub cache_capacity: usize,
 
+    /// The initial capacity of the page cache in bytes, which grows up to
+    /// [`Self::cache_max_capacity`] while lookups keep missing the full cache.
+    ///
+    /// This is an adaptive alternative to a fixed [`Self::cache_capacity`],
+    /// which saves memory for tables that turn out cold. The current capacity
+    /// and the recent changes are reported in [`StoreStats`].
+    ///
+    /// Default: None (the capacity is fixed)
+    pub cache_initial_capacity: Option<usize>,
+
+    /// The maximum capacity in bytes that the page cache grows to from
+    /// [`Self::cache_initial_capacity`].
+    ///
+    /// Default: None ([`Self::cache_capacity`])
+    pub cache_max_capacity: Option<usize>,
+
+    /// The capacity in bytes of a dedicated cache for the info of flushed
+    /// pages, like their epochs and chain lengths.
+    ///
+    /// Tree traversals look up the info of pages much more often than their
+    /// content. This cache keeps the info hot in a small structure keyed by
+    /// page address, apart from the page cache, so that the lookups don't go
+    /// through the metadata of page files. Each entry takes about 24 bytes.
+    ///
+    /// Default: 0 (disabled)
+    pub page_info_cache_capacity: usize,
+
+    /// The number of shards of the page cache. It should be zero or a power of
+    /// two.
+    ///
+    /// Pages are distributed to shards by their addresses, and each shard is
+    /// locked independently with `cache_capacity / cache_shards` bytes of
+    /// capacity. More shards reduce lock contention under concurrent reads,
+    /// but a shard evicts its pages on its own even if other shards have
+    /// spare capacity.
+    ///
+    /// If zero, the number of shards is decided by the cache capacity.
+    ///
+    /// Default: 0
+    pub cache_shards: usize,
+
     /// The estimated average `charge` associated with cache entries.
     ///
     /// Default: 8 Kib
@@ -132,11 +226,39 @@ pub struct Options {
     /// Default: false
     pub cache_strict_capacity_limit: bool,
 
+    /// The number of failed inserts to the page cache that triggers a flush of
+    /// the active write buffer, regardless of whether it is full.
+    ///
+    /// An insert fails if the cache is full and the page is not worth evicting
+    /// others for, like the cold pages read by consolidations. Flushing the
+    /// active buffer early under such pressure releases the memory held by
+    /// unflushed writes sooner. Such flushes are counted in
+    /// [`StoreStats::jobs`].
+    ///
+    /// If zero, flushes are not affected by the page cache.
+    ///
+    /// Default: 0
+    pub cache_pressure_flush_threshold: u64,
+
     /// Insert warm pages into PageCache during flush if true.
     ///
     /// Default: true
     pub prepopulate_cache_on_flush: bool,
 
+    /// Whether to verify the checksum of cached pages on each cache hit, to
+    /// detect pages corrupted in memory, like bit flips of bad RAM. This is
+    /// separate from [`Self::page_checksum_type`], which only covers pages
+    /// on disk.
+    ///
+    /// If enabled, a checksum is computed when a page is inserted into the
+    /// cache, and [`Error::CorruptedInMemory`] is returned if it mismatches.
+    /// This costs a checksum computation for each page access.
+    ///
+    /// Default: false
+    ///
+    /// [`Error::CorruptedInMemory`]: crate::Error::CorruptedInMemory
+    pub verify_cache_integrity: bool,
+
     /// Compression method during flush new file.
     /// include hot rewrite.
     ///
@@ -159,6 +281,32 @@ pub struct Options {
     ///
     /// Default: false
     pub avoid_flush_during_shutdown: bool,
+
+    /// How to retry transient IO errors when reading pages from files.
+    ///
+    /// Default: [`IoRetryPolicy::default`]
+    pub io_retry_policy: IoRetryPolicy,
+
+    /// Injects faults at named points of the table to test crash recovery.
+    ///
+    /// This only takes effect in debug builds.
+    ///
+    /// Default: None
+    pub fault_injector: Option<FaultInjector>,
+
+    /// Whether to delete the orphan page files left by crashes when the table
+    /// is opened.
+    ///
+    /// Orphan files are not referenced by the recovered manifest, like the
+    /// files of interrupted flushes. Only the orphans older than the files
+    /// recorded in the manifest are deleted, since newer ones may be
+    /// referenced by edits that are not durable yet. The orphans that are left
+    /// are reported in [`RecoveryReport::orphan_files`].
+    ///
+    /// Default: true
+    ///
+    /// [`RecoveryReport::orphan_files`]: crate::RecoveryReport::orphan_files
+    pub delete_orphans_on_open: bool,
 }
 
 impl Default for Options {
@@ -166,24 +314,41 @@ impl Default for Options {
         Self {
             write_buffer_capacity: 128 << 20,
             max_write_buffers: 8,
+            max_concurrent_flushes: 1,
             use_direct_io: false,
             disable_space_reclaiming: false,
             max_space_amplification_percent: 100,
             space_used_high: u64::MAX,
             file_base_size: 64 << 20,
             cache_capacity: 8 << 20,
+            cache_initial_capacity: None,
+            cache_max_capacity: None,
+            page_info_cache_capacity: 0,
+            cache_shards: 0,
             cache_estimated_entry_charge: 8 << 10,
             cache_file_reader_capacity: 5000,
             cache_strict_capacity_limit: false,
+            cache_pressure_flush_threshold: 0,
             prepopulate_cache_on_flush: true,
+            verify_cache_integrity: false,
             compression_on_flush: Compression::SNAPPY,
             compression_on_cold_compact: Compression::ZSTD,
             page_checksum_type: ChecksumType::NONE,
             avoid_flush_during_shutdown: false,
+            io_retry_policy: IoRetryPolicy::default(),
+            fault_injector: None,
+            delete_orphans_on_open: true,
         }
     }
 }
 
+impl Options {
+    /// Returns the maximum capacity of the page cache.
+    pub(crate) fn max_cache_capacity(&self) -> usize {
+        self.cache_max_capacity.unwrap_or(self.cache_capacity)
+    }
+}
+
 /// Options that control manual flush operations.
 #[derive(Clone, Debug)]
 pub struct FlushOptions {
@@ -208,30 +373,129 @@ impl Default for FlushOptions {
     }
 }
 
+/// Options of a page store that can be changed while it is running.
+#[derive(Debug)]
+pub(crate) struct LiveOptions {
+    compression_on_flush: AtomicU8,
+    compression_on_cold_compact: AtomicU8,
+}
+
+impl LiveOptions {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            compression_on_flush: AtomicU8::new(options.compression_on_flush.bits()),
+            compression_on_cold_compact: AtomicU8::new(options.compression_on_cold_compact.bits()),
+        }
+    }
+
+    pub(crate) fn compression_on_flush(&self) -> Compression {
+        Compression::from_bits_truncate(self.compression_on_flush.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn compression_on_cold_compact(&self) -> Compression {
+        Compression::from_bits_truncate(self.compression_on_cold_compact.load(Ordering::Relaxed))
+    }
+}
+
 pub(crate) struct PageStore<E: Env> {
     options: Options,
-    #[allow(unused)]
+    live_options: Arc<LiveOptions>,
     env: E,
     table: PageTable,
+    /// Whether the store is opened at a historical generation, where nothing
+    /// is written to the path.
+    read_only: bool,
 
     version_owner: Arc<VersionOwner>,
     page_files: Arc<PageFiles<E>>,
     manifest: Arc<Mutex<Manifest<E>>>,
+    /// The largest LSN allocated by [`Self::next_lsn`] or written to the
+    /// flushed buffers, which is recorded in the manifest on every flush.
+    max_lsn: Arc<AtomicU64>,
+    /// The largest LSN of the writes in the flushed buffers, see
+    /// [`Self::durable_lsn`].
+    flushed_lsn: Arc<AtomicU64>,
+    compaction_lock: Arc<Mutex<()>>,
+    recovery_report: RecoveryReport,
 
     job_stats: Arc<AtomicJobStats>,
     writebuf_stats: Arc<AtomicWritebufStats>,
 
-    jobs: Vec<E::JoinHandle<()>>,
+    jobs: Mutex<Vec<E::JoinHandle<()>>>,
     shutdown: ShutdownNotifier,
+    pause: PauseSwitch,
 }
 
 impl<E: Env> PageStore<E> {
-    pub(crate) async fn open<P>(env: E, path: P, options: Options) -> Result<Self>
+    /// Opens a page store, and reports the progress of recovery to the
+    /// callback.
+    pub(crate) async fn open<P>(
+        env: E,
+        path: P,
+        options: Options,
+        progress: RecoveryCallback<'_>,
+    ) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let (next_page_file_id, manifest, table, page_files, delta) =
-            Self::recover(env.to_owned(), path, &options).await?;
+        let mut store = Self::open_impl(env, path, options, None, progress).await?;
+
+        // Spawn background jobs.
+        store.spawn_flush_job();
+        store.spawn_cleanup_job();
+        store.spawn_reclaim_job();
+
+        Ok(store)
+    }
+
+    /// Opens a read-only page store with the state as of the page file
+    /// `generation`, and reports the progress of recovery to the callback.
+    ///
+    /// The manifest is replayed up to the first version edit that adds page
+    /// files newer than the generation. Files of later generations are left
+    /// untouched, and no background jobs are spawned, so nothing is written to
+    /// the path.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the generation is not recorded in
+    /// the manifest, e.g. it has been folded into a snapshot when the manifest
+    /// rolled.
+    pub(crate) async fn open_at_generation<P>(
+        env: E,
+        path: P,
+        mut options: Options,
+        generation: u32,
+        progress: RecoveryCallback<'_>,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        options.disable_space_reclaiming = true;
+        Self::open_impl(env, path, options, Some(generation), progress).await
+    }
+
+    async fn open_impl<P>(
+        env: E,
+        path: P,
+        options: Options,
+        generation: Option<u32>,
+        progress: RecoveryCallback<'_>,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        if options.cache_shards != 0
+            && (!options.cache_shards.is_power_of_two() || options.cache_shards > MAX_CACHE_SHARDS)
+        {
+            return Err(Error::InvalidArgument);
+        }
+        if let Some(initial) = options.cache_initial_capacity {
+            if initial > options.max_cache_capacity() {
+                return Err(Error::InvalidArgument);
+            }
+        }
+
+        let (next_page_file_id, manifest, table, page_files, delta, recovery_report) =
+            Self::recover(env.to_owned(), path, &options, generation, progress).await?;
 
         let version = Version::new(
             options.write_buffer_capacity,
@@ -241,31 +505,78 @@ impl<E: Env> PageStore<E> {
         );
 
         let version_owner = Arc::new(VersionOwner::new(version));
+        let max_lsn = Arc::new(AtomicU64::new(manifest.max_lsn()));
+        let flushed_lsn = Arc::new(AtomicU64::new(manifest.max_lsn()));
         let manifest = Arc::new(futures::lock::Mutex::new(manifest));
         let page_files = Arc::new(page_files);
         let shutdown = ShutdownNotifier::new();
         let job_stats = Arc::default();
         let writebuf_stats = Arc::default();
+        let live_options = Arc::new(LiveOptions::new(&options));
 
-        let mut store = PageStore {
+        Ok(PageStore {
             options,
+            live_options,
             env,
             table,
+            read_only: generation.is_some(),
             version_owner,
             page_files,
             manifest,
+            max_lsn,
+            flushed_lsn,
+            compaction_lock: Arc::default(),
+            recovery_report,
             job_stats,
             writebuf_stats,
-            jobs: Vec::new(),
+            jobs: Mutex::default(),
             shutdown,
-        };
+            pause: PauseSwitch::default(),
+        })
+    }
 
-        // Spawn background jobs.
-        store.spawn_flush_job();
-        store.spawn_cleanup_job();
-        store.spawn_reclaim_job();
+    /// Allocates an LSN larger than all LSNs allocated before, including
+    /// those allocated before the store is reopened.
+    ///
+    /// Only LSNs recorded in the manifest survive a reopen, which covers the
+    /// writes that have been flushed.
+    pub(crate) fn next_lsn(&self) -> u64 {
+        self.max_lsn.fetch_add(1, Ordering::AcqRel) + 1
+    }
 
-        Ok(store)
+    /// Returns the largest LSN allocated by [`Self::next_lsn`] or written to
+    /// the flushed buffers, including those before the store is reopened.
+    pub(crate) fn max_lsn(&self) -> u64 {
+        self.max_lsn.load(Ordering::Acquire)
+    }
+
+    /// Returns the largest LSN that all writes with LSNs not larger than it
+    /// have been flushed.
+    ///
+    /// It starts from the LSN recorded in the manifest when the store is
+    /// opened, and is bounded by the smallest LSN of the writes that are
+    /// still in the write buffers.
+    pub(crate) fn durable_lsn(&self) -> u64 {
+        let mut lsn = self.flushed_lsn.load(Ordering::Acquire);
+        let version = self.version();
+        for buffer_id in version.buffers_range() {
+            let Some(buf) = version.get(buffer_id) else {
+                continue;
+            };
+            if let Some((min_lsn, _)) = buf.lsn_range() {
+                lsn = lsn.min(min_lsn.saturating_sub(1));
+            }
+        }
+        lsn
+    }
+
+    pub(crate) fn env(&self) -> &E {
+        &self.env
+    }
+
+    /// Returns true if there is a page store in the path.
+    pub(crate) async fn exists(env: &E, path: &Path) -> bool {
+        Manifest::exists(env, path).await
     }
 
     #[inline]
@@ -278,8 +589,13 @@ impl<E: Env> PageStore<E> {
         )
     }
 
+    /// Returns the durations of the background jobs of the store.
+    pub(crate) fn background_stats(&self) -> BackgroundStats {
+        self.job_stats.background_stats()
+    }
+
     pub(crate) fn stats(&self) -> StoreStats {
-        let (page_cache, file_reader_cache) = self.page_files.stats();
+        let (page_cache, file_reader_cache, page_info_cache) = self.page_files.stats();
         let writebuf = self.writebuf_stats.snapshot();
         let jobs = self.job_stats.snapshot();
         let buffer_set = self.version().buffer_set.stats();
@@ -289,20 +605,57 @@ impl<E: Env> PageStore<E> {
             writebuf,
             jobs,
             buffer_set,
+            page_info_cache,
+            page_cache_capacity: self.page_files.cache_capacity(),
+            page_cache_resizes: self.page_files.cache_resize_events(),
         }
     }
 
-    pub(crate) async fn close(mut self) {
+    /// Returns what the recovery found when the store was opened.
+    pub(crate) fn recovery_report(&self) -> &RecoveryReport {
+        &self.recovery_report
+    }
+
+    pub(crate) async fn close(self) {
+        self.shutdown().await;
+    }
+
+    /// Stops background jobs and waits for them to exit.
+    ///
+    /// The jobs flush the write buffers before exiting, unless
+    /// [`Options::avoid_flush_during_shutdown`] is set.
+    pub(crate) async fn shutdown(&self) {
         self.shutdown.terminate();
-        let jobs = mem::take(&mut self.jobs);
+        let jobs = mem::take(&mut *self.jobs.lock().await);
         for job in jobs {
             job.await;
         }
     }
 
+    /// Pauses the background jobs, and waits for the work in progress to
+    /// finish.
+    pub(crate) async fn pause_background(&self) {
+        self.pause.pause();
+        self.pause.quiesced().await;
+    }
+
+    /// Resumes the background jobs paused by [`Self::pause_background`].
+    pub(crate) fn resume_background(&self) {
+        self.pause.resume();
+    }
+
+    pub(crate) fn is_background_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
     /// Flush the active write buffer if it is not empty.
+    ///
+    /// This does nothing if the store is read-only.
     #[inline]
     pub(crate) async fn flush(&self, opts: &FlushOptions) {
+        if self.read_only {
+            return;
+        }
         self.version().buffer_set.flush_active_buffer(opts).await
     }
 
@@ -312,6 +665,59 @@ impl<E: Env> PageStore<E> {
         wait_for_reclaiming(&self.options, self.version()).await;
     }
 
+    /// Returns the files that can be compacted, in the order that the
+    /// background reclaiming picks them.
+    pub(crate) async fn compaction_candidates(&self) -> Vec<CandidateInfo> {
+        self.reclaim_ctx().candidates().await
+    }
+
+    /// Returns the statistics of all page files, sorted by file ids.
+    pub(crate) fn file_stats(&self) -> Vec<FileStat> {
+        let version = self.version();
+        let page_groups = version.page_groups();
+        let mut stats: Vec<_> = version
+            .file_infos()
+            .values()
+            .map(|info| info.stat(page_groups))
+            .collect();
+        stats.sort_unstable_by_key(|stat| stat.file_id);
+        stats
+    }
+
+    /// Compacts the file regardless of the reclaiming policy.
+    ///
+    /// Returns [`Error::Sealed`] if the store is read-only.
+    pub(crate) async fn compact_file(&self, file_id: u32) -> Result<()> {
+        if self.read_only {
+            return Err(Error::Sealed);
+        }
+        self.reclaim_ctx().compact(file_id).await
+    }
+
+    /// Returns the number of lookups that hit the page cache.
+    pub(crate) fn cache_hits(&self) -> u64 {
+        self.page_files.cache_hits()
+    }
+
+    /// Changes the capacity of the page cache.
+    pub(crate) fn set_cache_capacity(&self, capacity: usize) {
+        self.page_files.set_cache_capacity(capacity);
+    }
+
+    /// Changes the compression method used by subsequent flushes.
+    pub(crate) fn set_compression_on_flush(&self, compression: Compression) {
+        self.live_options
+            .compression_on_flush
+            .store(compression.bits(), Ordering::Relaxed);
+    }
+
+    /// Changes the compression method used by subsequent cold compactions.
+    pub(crate) fn set_compression_on_cold_compact(&self, compression: Compression) {
+        self.live_options
+            .compression_on_cold_compact
+            .store(compression.bits(), Ordering::Relaxed);
+    }
+
     #[inline]
     fn version(&self) -> Arc<Version> {
         self.version_owner.current()
@@ -320,40 +726,99 @@ impl<E: Env> PageStore<E> {
     fn spawn_flush_job(&mut self) {
         let job = FlushCtx::new(
             self.options.clone(),
+            self.live_options.clone(),
             self.shutdown.subscribe(),
+            self.pause.clone(),
             self.job_stats.clone(),
             self.version_owner.clone(),
             self.page_files.clone(),
             self.manifest.clone(),
+            self.max_lsn.clone(),
+            self.flushed_lsn.clone(),
         );
         let handle = self.env.spawn_background(job.run());
-        self.jobs.push(handle);
+        self.jobs.get_mut().push(handle);
     }
 
     fn spawn_cleanup_job(&mut self) {
-        let job = CleanupCtx::new(self.shutdown.subscribe(), self.page_files.clone());
+        let job = CleanupCtx::new(
+            self.shutdown.subscribe(),
+            self.pause.clone(),
+            self.page_files.clone(),
+            self.table.clone(),
+        );
         let handle = self.env.spawn_background(job.run(self.version()));
-        self.jobs.push(handle);
+        self.jobs.get_mut().push(handle);
     }
 
     fn spawn_reclaim_job(&mut self) {
+        let job = self.reclaim_ctx();
+        let handle = self.env.spawn_background(job.run(self.version()));
+        self.jobs.get_mut().push(handle);
+    }
+
+    /// Spawns a background job that runs `f` every `interval` until the store
+    /// is shut down.
+    ///
+    /// Each run gets a new guard that reads page files with
+    /// [`IoPriority::Low`], so that it doesn't hold back the versions and
+    /// write buffers released in between.
+    pub(crate) fn spawn_periodic_job<F, Fut>(&mut self, interval: Duration, f: F)
+    where
+        F: Fn(Guard<E>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let env = self.env.clone();
+        let mut shutdown = self.shutdown.subscribe();
+        let pause = self.pause.clone();
+        let version_owner = self.version_owner.clone();
+        let table = self.table.clone();
+        let page_files = self.page_files.clone();
+        let writebuf_stats = self.writebuf_stats.clone();
+        let job = async move {
+            while with_shutdown(&mut shutdown, env.sleep(interval))
+                .await
+                .is_some()
+            {
+                let Some(_active) = with_shutdown(&mut shutdown, pause.enter()).await else {
+                    break;
+                };
+                let mut guard = Guard::new(
+                    version_owner.current(),
+                    table.clone(),
+                    page_files.clone(),
+                    writebuf_stats.clone(),
+                );
+                guard.set_io_priority(IoPriority::Low);
+                f(guard).await;
+            }
+        };
+        let handle = self.env.spawn_background(job);
+        self.jobs.get_mut().push(handle);
+    }
+
+    fn reclaim_ctx(&self) -> ReclaimCtx<E> {
         let strategy_builder = Box::new(MinDeclineRateStrategyBuilder);
-        let job = ReclaimCtx::new(
+        ReclaimCtx::new(
             self.options.clone(),
+            self.live_options.clone(),
             self.shutdown.subscribe(),
+            self.pause.clone(),
             strategy_builder,
             self.page_files.clone(),
             self.version_owner.clone(),
             self.manifest.clone(),
+            self.compaction_lock.clone(),
             self.job_stats.clone(),
-        );
-        let handle = self.env.spawn_background(job.run(self.version()));
-        self.jobs.push(handle);
+        )
     }
 }
 
 impl<E: Env> Drop for PageStore<E> {
     fn drop(&mut self) {
+        if !self.jobs.get_mut().is_empty() {
+            warn!("page store is dropped without shutdown, background jobs may outlive it");
+        }
         self.shutdown.terminate();
     }
 }
@@ -363,3 +828,612 @@ impl<E: Env> fmt::Debug for PageStore<E> {
         f.debug_struct("PageStore").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Instant,
+    };
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        env::Photon,
+        tests::{must_get, must_put, options},
+        Error, PageStoreOptions, Table, TableOptions, WriteOptions,
+    };
+
+    #[photonio::test]
+    async fn periodic_job_stops_on_shutdown() {
+        let path = tempdir().unwrap();
+        let mut store = PageStore::open(Photon, path.path(), Options::default(), &mut |_| {})
+            .await
+            .unwrap();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job_runs = runs.clone();
+        store.spawn_periodic_job(Duration::from_millis(10), move |_| {
+            job_runs.fetch_add(1, Ordering::Relaxed);
+            async {}
+        });
+        // Shutdown doesn't wait out the interval of the job.
+        store.spawn_periodic_job(Duration::from_secs(3600), |_| async {});
+        while runs.load(Ordering::Relaxed) < 2 {
+            Photon.sleep(Duration::from_millis(10)).await;
+        }
+        let start = Instant::now();
+        store.close().await;
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[photonio::test]
+    async fn manual_compaction() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            page_store: PageStoreOptions {
+                disable_space_reclaiming: true,
+                ..options().page_store
+            },
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for lsn in [1, 2] {
+            for i in 0..N {
+                must_put(&table, i, lsn).await;
+            }
+            table.flush(&FlushOptions::default()).await;
+        }
+
+        let candidates = table.compaction_candidates().await;
+        assert!(!candidates.is_empty());
+        assert!(candidates.windows(2).all(|w| w[0].score >= w[1].score));
+        let file_id = candidates[0].file_id;
+        let stats = table.file_stats();
+        assert!(stats.windows(2).all(|w| w[0].file_id < w[1].file_id));
+        let stat = stats.iter().find(|s| s.file_id == file_id).unwrap();
+        assert_eq!(stat.dead_bytes, candidates[0].dead_bytes);
+        assert!(stat.dead_bytes > 0);
+        assert!(stat.live_pages < stat.pages);
+        assert!(stat.live_bytes + stat.dead_bytes <= stat.file_size);
+        table.compact_file(file_id).await.unwrap();
+        let candidates = table.compaction_candidates().await;
+        assert!(candidates.iter().all(|c| c.file_id != file_id));
+        assert!(table.file_stats().iter().all(|s| s.file_id != file_id));
+        assert!(matches!(
+            table.compact_file(file_id).await,
+            Err(Error::InvalidArgument)
+        ));
+
+        for i in 0..N {
+            must_get(&table, i, 2, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn background_stats() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            page_store: PageStoreOptions {
+                disable_space_reclaiming: true,
+                ..options().page_store
+            },
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        let stats = table.background_stats();
+        assert_eq!(stats.flush, LatencyStats::default());
+        assert_eq!(stats.reclaim, LatencyStats::default());
+
+        const N: u64 = 1 << 10;
+        for lsn in [1, 2] {
+            for i in 0..N {
+                must_put(&table, i, lsn).await;
+            }
+            table.flush(&FlushOptions::default()).await;
+        }
+        table.consolidate_all().await.unwrap();
+        let file_id = table.compaction_candidates().await[0].file_id;
+        table.compact_file(file_id).await.unwrap();
+
+        let stats = table.background_stats();
+        for latency in [stats.flush, stats.consolidation, stats.reclaim] {
+            assert!(latency.count > 0);
+            assert!(latency.min <= latency.max);
+        }
+        assert_eq!(stats.reclaim.count, 1);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn open_at_generation() {
+        fn last_generation(path: &std::path::Path) -> u32 {
+            std::fs::read_dir(path)
+                .unwrap()
+                .filter_map(|entry| {
+                    let name = entry.unwrap().file_name().into_string().unwrap();
+                    name.strip_prefix("map_")?.parse().ok()
+                })
+                .max()
+                .unwrap()
+        }
+
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            page_store: PageStoreOptions {
+                disable_space_reclaiming: true,
+                ..options().page_store
+            },
+            ..options()
+        };
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        let generation = last_generation(path.path());
+        for i in 0..N {
+            table.delete(&i.to_be_bytes(), 2).await.unwrap();
+        }
+        table.close().await.unwrap();
+
+        let table = Table::open_at_generation(&path, generation, opts.clone())
+            .await
+            .unwrap();
+        for i in 0..N {
+            must_get(&table, i, 2, Some(i)).await;
+        }
+        table.unseal();
+        assert!(matches!(table.put(b"k", 3, b"v").await, Err(Error::Sealed)));
+        table.flush(&FlushOptions::default()).await;
+        assert!(matches!(
+            table.compact_file(generation).await,
+            Err(Error::Sealed)
+        ));
+        table.close().await.unwrap();
+        assert!(matches!(
+            Table::open_at_generation(&path, u32::MAX, opts.clone()).await,
+            Err(Error::InvalidArgument)
+        ));
+
+        // The latest state is intact.
+        let table = Table::open(&path, opts).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, 2, None).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn open_with_progress() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.close().await.unwrap();
+
+        let mut progress = Vec::new();
+        let table = Table::open_with_progress(&path, options(), |p| progress.push(p))
+            .await
+            .unwrap();
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+
+        let mut phases = progress.iter().map(|p| p.phase).collect::<Vec<_>>();
+        phases.dedup();
+        assert_eq!(
+            phases,
+            vec![
+                RecoveryPhase::ManifestReplay,
+                RecoveryPhase::FileScan,
+                RecoveryPhase::IndexRebuild
+            ]
+        );
+        for phase in [RecoveryPhase::ManifestReplay, RecoveryPhase::FileScan] {
+            let last = progress.iter().filter(|p| p.phase == phase).last().unwrap();
+            assert!(last.records_replayed > 0);
+            assert!(last.bytes_scanned > 0);
+        }
+    }
+
+    #[photonio::test]
+    async fn write_without_compression() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            value_inline_threshold: 64,
+            ..options()
+        };
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        let write_opts = WriteOptions {
+            compression: Some(Compression::NONE),
+            ..Default::default()
+        };
+        const N: u64 = 1 << 8;
+        let value = |i: u64| i.to_be_bytes().repeat(i as usize % 16 + 1);
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            if i % 2 == 0 {
+                table
+                    .put_with_options(&key, i, &value(i), &write_opts)
+                    .await
+                    .unwrap();
+            } else {
+                table.put(&key, i, &value(i)).await.unwrap();
+            }
+        }
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, opts).await.unwrap();
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(table.get(&key, i).await.unwrap(), Some(value(i)));
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn cache_pressure_flush() {
+        let path = tempdir().unwrap();
+        let mut opts = TableOptions {
+            cold_cache_delta_pages: true,
+            ..options()
+        };
+        opts.page_store.prepopulate_cache_on_flush = false;
+        opts.page_store.cache_pressure_flush_threshold = 1;
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        must_put(&table, N, 1).await;
+
+        // Delta pages read from files can't be cached once the cache is full,
+        // which flushes the active buffer.
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while table.stats().store.jobs.cache_pressure_flushes == 0 {
+            assert!(std::time::Instant::now() < deadline);
+            for i in 0..N {
+                must_get(&table, i, 1, Some(i)).await;
+            }
+        }
+        assert!(table.stats().store.page_cache.insert_fail > 0);
+        must_get(&table, N, 1, Some(N)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn max_concurrent_flushes() {
+        let path = tempdir().unwrap();
+        let mut opts = options();
+        opts.page_store.write_buffer_capacity = 4 << 10;
+        opts.page_store.max_concurrent_flushes = 4;
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        const N: u64 = 1 << 12;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        let stats = table.stats().store.jobs;
+        assert!(stats.flush_write_bytes > 0);
+        assert!(stats.flushes_in_flight <= 4);
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, opts).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn disk_full() {
+        use std::{
+            fs,
+            os::unix::fs::symlink,
+            thread,
+            time::{Duration, Instant},
+        };
+
+        let path = tempdir().unwrap();
+        let table = crate::std::Table::open(&path, options()).unwrap();
+        // The page files of the following flushes are written to a full disk.
+        let files = (0..1000)
+            .map(|id| path.path().join(format!("map_{id}")))
+            .filter(|file| !file.exists())
+            .collect::<Vec<_>>();
+        for file in &files {
+            symlink("/dev/full", file).unwrap();
+        }
+        let value = [0; 1024];
+        let put = |i: u64| table.put(&i.to_be_bytes(), 1, &value);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut i = 0;
+        loop {
+            match put(i) {
+                Ok(()) => i += 1,
+                Err(Error::NoSpace) => break,
+                Err(err) => panic!("unexpected error {err:?}"),
+            }
+            assert!(Instant::now() < deadline);
+        }
+
+        // Writes are accepted again once a flush succeeds.
+        for file in &files {
+            let _ = fs::remove_file(file);
+        }
+        loop {
+            match put(i) {
+                Ok(()) => break,
+                Err(Error::NoSpace) => thread::sleep(Duration::from_millis(10)),
+                Err(err) => panic!("unexpected error {err:?}"),
+            }
+            assert!(Instant::now() < deadline);
+        }
+        for i in 0..=i {
+            let value = table.get(&i.to_be_bytes(), 1).unwrap();
+            assert_eq!(value.as_deref(), Some(&[0; 1024][..]));
+        }
+        table.close().unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn shutdown_on_full_disk() {
+        use std::{os::unix::fs::symlink, time::Instant};
+
+        let path = tempdir().unwrap();
+        let mut opts = options();
+        opts.page_store.disable_space_reclaiming = true;
+        opts.page_store.avoid_flush_during_shutdown = true;
+        let table = crate::std::Table::open(&path, opts).unwrap();
+        for id in 0..1000 {
+            let file = path.path().join(format!("map_{id}"));
+            if !file.exists() {
+                symlink("/dev/full", file).unwrap();
+            }
+        }
+        let value = [0; 1024];
+        let mut i = 0u64;
+        while table.put(&i.to_be_bytes(), 1, &value).is_ok() {
+            i += 1;
+        }
+
+        // The flush job is waiting to retry, which is interrupted by the shutdown.
+        let start_at = Instant::now();
+        table.shutdown();
+        assert!(start_at.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[photonio::test]
+    async fn cache_shards() {
+        let path = tempdir().unwrap();
+        let opts = |cache_shards| TableOptions {
+            page_store: PageStoreOptions {
+                cache_capacity: 64 << 10,
+                cache_shards,
+                ..options().page_store
+            },
+            ..options()
+        };
+        assert!(matches!(
+            Table::open(&path, opts(3)).await,
+            Err(Error::InvalidArgument)
+        ));
+
+        let table = Table::open(&path, opts(4)).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        let shards = table.stats().store.page_cache.shards;
+        assert_eq!(shards.len(), 4);
+        assert!(shards.iter().all(|s| s.capacity == 16 << 10));
+        assert!(shards.iter().any(|s| s.usage > 0));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn cache_autosizing() {
+        let path = tempdir().unwrap();
+        let opts = |initial| TableOptions {
+            page_store: PageStoreOptions {
+                cache_initial_capacity: Some(initial),
+                cache_max_capacity: Some(64 << 10),
+                prepopulate_cache_on_flush: false,
+                ..options().page_store
+            },
+            ..options()
+        };
+        assert!(matches!(
+            Table::open(&path, opts(128 << 10)).await,
+            Err(Error::InvalidArgument)
+        ));
+
+        let table = Table::open(&path, opts(1 << 10)).await.unwrap();
+        assert_eq!(table.stats().store.page_cache_capacity, 1 << 10);
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        // The working set doesn't fit into the initial capacity.
+        for _ in 0..8 {
+            for i in 0..N {
+                must_get(&table, i, 1, Some(i)).await;
+            }
+        }
+        let stats = table.stats().store;
+        assert!(stats.page_cache_capacity > 1 << 10);
+        assert!(stats.page_cache_capacity <= 64 << 10);
+        let resizes = stats.page_cache_resizes;
+        assert!(!resizes.is_empty());
+        assert_eq!(resizes[0].old_capacity, 1 << 10);
+        assert_eq!(
+            resizes.last().unwrap().new_capacity,
+            stats.page_cache_capacity
+        );
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn page_info_cache() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            page_store: PageStoreOptions {
+                page_info_cache_capacity: 64 << 10,
+                ..options().page_store
+            },
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+
+        crate::perf::reset_perf_ctx();
+        for _ in 0..2 {
+            for i in 0..N {
+                must_get(&table, i, 1, Some(i)).await;
+            }
+        }
+        let (count, from_cache) =
+            crate::perf::with(|ctx| (ctx.get_page_info_count, ctx.get_page_info_from_cache_count));
+        assert!(from_cache <= count);
+        let stats = table.stats().store.page_info_cache;
+        assert!(stats.insert > 0);
+        // The second pass finds the info of the same pages in the cache.
+        assert!(stats.lookup_hit > 0);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn close_flushes_write_buffers() {
+        let path = tempdir().unwrap();
+        const N: u64 = 16;
+        let table = Table::open(&path, options()).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, i + 1).await;
+        }
+        table.close().await.unwrap();
+
+        let opts = TableOptions {
+            page_store: PageStoreOptions {
+                avoid_flush_during_shutdown: true,
+                ..options().page_store
+            },
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        assert_eq!(table.durable_lsn(), N);
+        for i in 0..N {
+            must_get(&table, i, N, Some(i)).await;
+        }
+        // The write buffer is not flushed this time.
+        must_put(&table, N, N + 1).await;
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, options()).await.unwrap();
+        assert_eq!(table.durable_lsn(), N);
+        must_get(&table, N, N + 1, None).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn delete_orphans_on_open() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        must_put(&table, 1, 1).await;
+        table.close().await.unwrap();
+
+        // File 0 is never allocated, and file 1000 is newer than the manifest.
+        let orphan = |id: u32| path.path().join(format!("map_{id}"));
+        std::fs::write(orphan(0), b"orphan").unwrap();
+        std::fs::write(orphan(1000), b"orphan").unwrap();
+        let mut opts = options();
+        opts.page_store.delete_orphans_on_open = false;
+        let table = Table::open(&path, opts).await.unwrap();
+        assert_eq!(table.recovery_report().orphan_files, vec![0, 1000]);
+        assert!(table.recovery_report().deleted_orphan_files.is_empty());
+        must_get(&table, 1, 1, Some(1)).await;
+        table.close().await.unwrap();
+        assert!(orphan(0).exists());
+
+        let table = Table::open(&path, options()).await.unwrap();
+        assert_eq!(table.recovery_report().orphan_files, vec![1000]);
+        assert_eq!(table.recovery_report().deleted_orphan_files, vec![0]);
+        // New files don't overwrite the orphans that are left.
+        must_put(&table, 2, 2).await;
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+        assert!(!orphan(0).exists());
+        assert_eq!(std::fs::read(orphan(1000)).unwrap(), b"orphan");
+
+        let table = Table::open(&path, options()).await.unwrap();
+        assert!(table.recovery_report().orphan_files.is_empty());
+        assert_eq!(table.recovery_report().deleted_orphan_files, vec![1000]);
+        must_get(&table, 2, 2, Some(2)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn verify_checksums() {
+        let path = tempdir().unwrap();
+        let mut opts = options();
+        opts.page_store.disable_space_reclaiming = true;
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        let report = table.verify_checksums(None).await.unwrap();
+        assert!(report.is_ok());
+        assert!(report.files > 0);
+        assert!(report.pages > 0);
+        assert_eq!(report.unchecked_pages, 0);
+
+        // The reads are paced to the rate limit.
+        let start_at = std::time::Instant::now();
+        let paced = table
+            .verify_checksums(Some(report.bytes * 4))
+            .await
+            .unwrap();
+        assert_eq!(paced, report);
+        assert!(start_at.elapsed() >= std::time::Duration::from_millis(250));
+
+        // Flip a byte of the first page in the oldest file.
+        let file_id = std::fs::read_dir(&path)
+            .unwrap()
+            .filter_map(|entry| {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                name.strip_prefix("map_")?.parse::<u32>().ok()
+            })
+            .min()
+            .unwrap();
+        let file = path.path().join(format!("map_{file_id}"));
+        let mut content = std::fs::read(&file).unwrap();
+        content[0] ^= 0xff;
+        std::fs::write(&file, content).unwrap();
+        let report = table.verify_checksums(Some(64 << 20)).await.unwrap();
+        assert_eq!(report.corrupted_pages.len(), 1);
+        table.close().await.unwrap();
+    }
+}
@@ -6,7 +6,7 @@ use super::{
     ChecksumType, PageGroupMeta,
 };
 use crate::{
-    env::{Directory, Env, SequentialWriter, SequentialWriterExt},
+    env::{Directory, Env, IoPriority, SequentialWriter, SequentialWriterExt},
     page::PageInfo,
     page_store::{Error, Result},
 };
@@ -40,8 +40,14 @@ impl CommonFileBuilder {
         page_info: PageInfo,
         page_content: &[u8],
     ) -> Result<()> {
-        let mut tmp_buf = vec![0u8; compress_max_len(self.compression, page_content)]; // TODO: pool this.
-        let page_content = compress_page(self.compression, page_content, &mut tmp_buf)?;
+        // Pages can opt out of compression, see [`PageInfo::no_compression`].
+        let compression = if page_info.no_compression() {
+            Compression::NONE
+        } else {
+            self.compression
+        };
+        let mut tmp_buf = vec![0u8; compress_max_len(compression, page_content)]; // TODO: pool this.
+        let page_content = compress_page(compression, page_content, &mut tmp_buf)?;
         let checksum = checksum::checksum(self.checksum, page_content);
         let file_offset = writer.write_with_checksum(page_content, checksum).await?;
         self.index.add_data_block(page_addr, file_offset, page_info);
@@ -87,13 +93,9 @@ impl CommonFileBuilder {
         data_handle: BlockHandle,
     ) -> Arc<PageGroupMeta> {
         let (indexes, offsets) = self.index.index_block.as_meta_file_cached(data_handle);
-        Arc::new(PageGroupMeta::new(
-            self.group_id,
-            file_id,
-            base_offset,
-            indexes,
-            offsets,
-        ))
+        let mut meta = PageGroupMeta::new(self.group_id, file_id, base_offset, indexes, offsets);
+        meta.set_page_ids(&self.page_table.0);
+        Arc::new(meta)
     }
 }
 
@@ -396,10 +398,10 @@ impl<'a, E: Env> BufferedWriter<'a, E> {
             let align_len = ceil_to_block_hi_pos(self.buf_pos, self.align_size);
             self.buf_pos = align_len;
         }
+        // A failed write, like a full disk, can be retried with a new file.
         self.file
-            .write_all(&self.buffer.as_bytes()[..self.buf_pos])
-            .await
-            .expect("flush page file error");
+            .write_all_with_priority(&self.buffer.as_bytes()[..self.buf_pos], IoPriority::Low)
+            .await?;
         self.buf_pos = 0;
         Ok(())
     }
@@ -1,6 +1,6 @@
 use super::file_builder::*;
 use crate::{
-    env::{PositionalReader, PositionalReaderExt},
+    env::{IoPriority, PositionalReader, PositionalReaderExt},
     page_store::Result,
     util::atomic::Counter,
 };
@@ -27,14 +27,25 @@ impl<R: PositionalReader> FileReader<R> {
 
     /// Reads the exact number of bytes from the page specified by `offset`.
     pub(crate) async fn read_exact_at(&self, buf: &mut [u8], req_offset: u64) -> Result<()> {
+        self.read_exact_at_with_priority(buf, req_offset, IoPriority::Normal)
+            .await
+    }
+
+    /// Reads the exact number of bytes like [`Self::read_exact_at`], with the
+    /// priority of the reads.
+    pub(crate) async fn read_exact_at_with_priority(
+        &self,
+        buf: &mut [u8],
+        req_offset: u64,
+        priority: IoPriority,
+    ) -> Result<()> {
         if buf.is_empty() {
             return Ok(());
         }
         if !self.use_direct {
             self.reader
-                .read_exact_at(buf, req_offset)
-                .await
-                .expect("read page data fail");
+                .read_exact_at_with_priority(buf, req_offset, priority)
+                .await?;
             self.read_bytes.add(buf.len() as u64);
             return Ok(());
         }
@@ -47,9 +58,8 @@ impl<R: PositionalReader> FileReader<R> {
         let mut align_buf = AlignBuffer::new(align_buf_size, self.align_size); // TODO: pool this buf?
         let read_buf = align_buf.as_bytes_mut();
 
-        self.inner_read_exact_at(&self.reader, read_buf, align_offset as u64)
-            .await
-            .expect("read page data fail");
+        self.inner_read_exact_at(&self.reader, read_buf, align_offset as u64, priority)
+            .await?;
 
         buf.copy_from_slice(&read_buf[offset_ahead..offset_ahead + buf.len()]);
         self.read_bytes.add(buf.len() as u64);
@@ -62,11 +72,12 @@ impl<R: PositionalReader> FileReader<R> {
         r: &R,
         mut buf: &mut [u8],
         mut pos: u64,
+        priority: IoPriority,
     ) -> std::io::Result<()> {
         assert!(is_block_aligned_ptr(buf.as_ptr(), self.align_size));
         assert!(is_block_aligned_pos(pos as usize, self.align_size));
         while !buf.is_empty() {
-            match r.read_at(buf, pos).await {
+            match r.read_at_with_priority(buf, pos, priority).await {
                 Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
                 Ok(n) => {
                     buf = &mut buf[n..];
@@ -8,6 +8,7 @@ pub(crate) use file_reader::FileReader;
 
 mod types;
 pub(crate) use facade::PageFiles;
+pub use types::FileStat;
 pub(crate) use types::{FileInfo, PageGroup, PageGroupMeta};
 
 mod map_file_builder;
@@ -34,7 +35,15 @@ pub(crate) mod constant {
 }
 
 pub(crate) mod facade {
-    use std::{path::PathBuf, sync::Arc};
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use log::{info, warn};
 
     use super::{
         cache::FileReaderCache,
@@ -44,15 +53,22 @@ pub(crate) mod facade {
         *,
     };
     use crate::{
-        env::{Env, PositionalReader, SequentialWriter},
+        env::{Env, IoPriority, PositionalReader, SequentialWriter},
+        page::{validate_page, PageInfo},
         page_store::{
-            page_txn::CacheOption, stats::CacheStats, Cache, CacheEntry, Error, LRUCache, Result,
+            cache::{autosize::CacheAutosizer, CACHE_DISCARD},
+            page_txn::CacheOption,
+            stats::{CacheResizeEvent, CacheStats},
+            Cache, CacheEntry, Error, IoRetryPolicy, LRUCache, Result,
         },
+        util::notify::Notify,
         PageStoreOptions,
     };
 
     pub(crate) const FILE_PREFIX: &str = "map";
 
+    const CACHE_CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
+
     /// The facade for page_file module.
     /// it hides the detail about disk location for caller(after it be created).
     pub(crate) struct PageFiles<E: Env> {
@@ -62,9 +78,24 @@ pub(crate) mod facade {
 
         use_direct: bool,
         prepopulate_cache_on_flush: bool,
+        verify_cache_integrity: bool,
+        io_retry_policy: IoRetryPolicy,
 
         reader_cache: cache::FileReaderCache<E>,
         page_cache: Arc<LRUCache<Vec<u8>>>,
+        /// The cache of the info of pages, if it is enabled.
+        page_info_cache: Option<Arc<LRUCache<PageInfo>>>,
+        /// The current capacity of the page cache.
+        cache_capacity: AtomicUsize,
+        cache_autosizer: Option<CacheAutosizer>,
+        cache_pressure_flush_threshold: u64,
+        /// The number of failed inserts to the page cache by reads.
+        cache_insert_fails: AtomicU64,
+        cache_pressure_notify: Notify,
+
+        /// Whether new files can't be written because the disk is full.
+        disk_full: AtomicBool,
+        disk_full_notify: Notify,
     }
 
     impl<E: Env> PageFiles<E> {
@@ -78,17 +109,87 @@ pub(crate) mod facade {
             let base = base.into();
             let base_dir = env.open_dir(&base).await.expect("open base dir fail");
             let reader_cache = FileReaderCache::new(options.cache_file_reader_capacity);
-            let page_cache = Arc::new(LRUCache::new(options.cache_capacity, -1));
+            let num_shard_bits = match options.cache_shards {
+                0 => -1,
+                n => n.trailing_zeros() as i32,
+            };
+            // Shards are decided by the maximum capacity, so that they are
+            // not too few if an autosized cache grows.
+            let page_cache = Arc::new(LRUCache::new(options.max_cache_capacity(), num_shard_bits));
+            let cache_capacity = options
+                .cache_initial_capacity
+                .unwrap_or(options.cache_capacity);
+            page_cache.set_capacity(cache_capacity);
+            let cache_autosizer = options
+                .cache_initial_capacity
+                .map(|_| CacheAutosizer::new(options.max_cache_capacity()));
+            let page_info_cache = match options.page_info_cache_capacity {
+                0 => None,
+                capacity => Some(Arc::new(LRUCache::new(capacity, num_shard_bits))),
+            };
             let use_direct = options.use_direct_io;
             let prepopulate_cache_on_flush = options.prepopulate_cache_on_flush;
+            let verify_cache_integrity = options.verify_cache_integrity;
+            let io_retry_policy = options.io_retry_policy.clone();
             Self {
                 env,
                 base,
                 base_dir,
                 use_direct,
                 prepopulate_cache_on_flush,
+                verify_cache_integrity,
+                io_retry_policy,
                 reader_cache,
                 page_cache,
+                page_info_cache,
+                cache_capacity: AtomicUsize::new(cache_capacity),
+                cache_autosizer,
+                cache_pressure_flush_threshold: options.cache_pressure_flush_threshold,
+                cache_insert_fails: AtomicU64::new(0),
+                cache_pressure_notify: Notify::new(),
+                disk_full: AtomicBool::new(false),
+                disk_full_notify: Notify::new(),
+            }
+        }
+
+        pub(crate) fn env(&self) -> &E {
+            &self.env
+        }
+
+        /// Returns true if new files can't be written because the disk is
+        /// full.
+        pub(crate) fn is_disk_full(&self) -> bool {
+            self.disk_full.load(Ordering::Acquire)
+        }
+
+        /// Updates whether the disk is full, and returns the previous state.
+        pub(crate) fn set_disk_full(&self, full: bool) -> bool {
+            let prev = self.disk_full.swap(full, Ordering::AcqRel);
+            if full && !prev {
+                self.disk_full_notify.notify_one();
+            }
+            prev
+        }
+
+        /// Waits until the disk becomes full.
+        pub(crate) async fn wait_disk_full(&self) {
+            self.disk_full_notify.notified().await;
+        }
+
+        /// Waits until the failed inserts to the page cache reach
+        /// [`PageStoreOptions::cache_pressure_flush_threshold`] again.
+        pub(crate) async fn wait_cache_pressure(&self) {
+            self.cache_pressure_notify.notified().await;
+        }
+
+        fn record_cache_insert_fail(&self) {
+            let threshold = self.cache_pressure_flush_threshold;
+            if threshold == 0 {
+                return;
+            }
+            let fails = self.cache_insert_fails.fetch_add(1, Ordering::Relaxed) + 1;
+            if fails % threshold == 0 {
+                self.cache_pressure_notify.notify_one();
             }
         }
 
@@ -101,11 +202,7 @@ pub(crate) mod facade {
         ) -> Result<FileBuilder<E>> {
             // TODO: switch to env in suitable time.
             let path = self.base.join(format!("{}_{file_id}", FILE_PREFIX));
-            let writer = self
-                .env
-                .open_sequential_writer(path.to_owned())
-                .await
-                .expect("open writer for file_id: {file_id} fail");
+            let writer = self.env.open_sequential_writer(path.to_owned()).await?;
             let use_direct = self.use_direct && writer.direct_io_ify().is_ok();
             Ok(FileBuilder::new(
                 file_id,
@@ -125,16 +222,41 @@ pub(crate) mod facade {
             addr: u64,
             handle: PageHandle,
             hint: CacheOption,
+            priority: IoPriority,
         ) -> Result<(CacheEntry<Vec<u8>, LRUCache<Vec<u8>>>, /* hit */ bool)> {
             if let Some(cache_entry) = self.page_cache.lookup(addr) {
+                if self.verify_cache_integrity {
+                    verify_cached_page(cache_entry.value())?;
+                }
+                self.autosize_cache(true);
                 return Ok((cache_entry, true));
             }
+            self.autosize_cache(false);
 
-            let buf = self.read_file_page(file_id, file_meta, handle).await?;
+            let buf = self
+                .read_file_page_with_priority(file_id, file_meta, handle, priority)
+                .await?;
+            // Reject malformed pages before they are cached, so that readers
+            // never decode them out of bounds.
+            if validate_page(&buf).is_err() {
+                warn!("Read malformed page {addr} from file {file_id}");
+                return Err(Error::Corrupted);
+            }
+            let buf = self.seal_cached_page(buf);
 
             let charge = buf.len();
-            let cache_entry = self.page_cache.insert(addr, Some(buf), charge, hint)?;
-            Ok((cache_entry.unwrap(), false))
+            let cache_entry = self
+                .page_cache
+                .insert(addr, Some(buf), charge, hint)?
+                .unwrap();
+            // The page is returned without being cached if there is no room.
+            if cache_entry
+                .cache_token()
+                .returning_behavior_match(CACHE_DISCARD)
+            {
+                self.record_cache_insert_fail();
+            }
+            Ok((cache_entry, false))
         }
 
         pub(crate) async fn read_file_page(
@@ -143,12 +265,21 @@ pub(crate) mod facade {
             file_meta: &FileMeta,
             handle: PageHandle,
         ) -> Result<Vec<u8>> {
-            const CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
+            self.read_file_page_with_priority(file_id, file_meta, handle, IoPriority::Normal)
+                .await
+        }
 
+        async fn read_file_page_with_priority(
+            &self,
+            file_id: u32,
+            file_meta: &FileMeta,
+            handle: PageHandle,
+            priority: IoPriority,
+        ) -> Result<Vec<u8>> {
             let reader = self.open_page_reader(file_id, file_meta.block_size).await?;
 
             let mut buf = vec![0u8; handle.size as usize]; // TODO: aligned buffer pool
-            self.read_file_page_from_reader(&reader, file_meta, handle, &mut buf)
+            self.read_file_page_from_reader(&reader, file_meta, handle, &mut buf, priority)
                 .await?;
             Ok(buf)
         }
@@ -159,10 +290,25 @@ pub(crate) mod facade {
             file_meta: &FileMeta,
             handle: PageHandle,
             output: &mut Vec<u8>,
+            priority: IoPriority,
         ) -> Result<()> {
             const CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
 
-            reader.read_exact_at(output, handle.offset as u64).await?;
+            let mut retries = 0;
+            while let Err(err) = reader
+                .read_exact_at_with_priority(output, handle.offset as u64, priority)
+                .await
+            {
+                let Some(backoff) = self.io_retry_policy.backoff(&err, retries) else {
+                    return Err(err);
+                };
+                retries += 1;
+                warn!(
+                    "Read file {} failed: {err}, retry {retries} after {backoff:?}",
+                    file_meta.file_id
+                );
+                self.env.sleep(backoff).await;
+            }
 
             if file_meta.checksum_type != ChecksumType::NONE {
                 let checksum = u32::from_le_bytes(
@@ -175,7 +321,7 @@ pub(crate) mod facade {
             }
 
             let compression = file_meta.compression;
-            if compression != Compression::NONE {
+            if compression != Compression::NONE && !handle.no_compression {
                 let (decompress_len, skip) = compression::decompress_len(compression, output)?;
                 let mut dec_buf = vec![0u8; decompress_len];
                 compression::decompress_into(compression, &output[skip..], &mut dec_buf)?;
@@ -188,6 +334,29 @@ pub(crate) mod facade {
             Ok(())
         }
 
+        /// Reads the page from the reader and checks it against the checksum
+        /// stored with it, without decompressing it.
+        ///
+        /// Returns false if the checksum mismatches. The file must be written
+        /// with a checksum type other than [`ChecksumType::NONE`].
+        pub(crate) async fn verify_file_page(
+            &self,
+            reader: &FileReader<<E as Env>::PositionalReader>,
+            file_meta: &FileMeta,
+            handle: PageHandle,
+        ) -> Result<bool> {
+            const CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
+
+            let mut buf = vec![0u8; handle.size as usize];
+            reader.read_exact_at(&mut buf, handle.offset as u64).await?;
+            if buf.len() < CHECKSUM_LEN {
+                return Ok(false);
+            }
+            let (content, checksum) = buf.split_at(buf.len() - CHECKSUM_LEN);
+            let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+            Ok(checksum::check_checksum(file_meta.checksum_type, content, checksum).is_ok())
+        }
+
         /// Open page_reader for a page_file.
         /// page_store could get file_id & block_size from page_addr's high bit
         /// and version.active_files.
@@ -262,23 +431,120 @@ pub(crate) mod facade {
                 return Ok(());
             }
             let val = page_content.to_owned(); // TODO: aligned buffer pool
-            let guard = match self.page_cache.insert(
+            let val = self.seal_cached_page(val);
+            let charge = val.len();
+            let guard =
+                match self
+                    .page_cache
+                    .insert(page_addr, Some(val), charge, CacheOption::default())
+                {
+                    Ok(guard) => guard,
+                    Err(Error::MemoryLimit) => return Ok(()),
+                    Err(err) => return Err(err),
+                };
+            drop(guard);
+            Ok(())
+        }
+
+        /// Returns the info of the page from the page info cache.
+        pub(crate) fn lookup_page_info(&self, page_addr: u64) -> Option<PageInfo> {
+            let cache = self.page_info_cache.as_ref()?;
+            let entry = cache.lookup(page_addr)?;
+            Some(entry.value().clone())
+        }
+
+        /// Inserts the info of the page into the page info cache, if it is
+        /// enabled.
+        pub(crate) fn insert_page_info(&self, page_addr: u64, info: PageInfo) {
+            let Some(cache) = self.page_info_cache.as_ref() else {
+                return;
+            };
+            // Failing to cache the info is fine, since it is read from the
+            // file metadata next time.
+            let _ = cache.insert(
                 page_addr,
-                Some(val),
-                page_content.len(),
+                Some(info),
+                std::mem::size_of::<PageInfo>(),
                 CacheOption::default(),
-            ) {
-                Ok(guard) => guard,
-                Err(Error::MemoryLimit) => return Ok(()),
-                Err(err) => return Err(err),
+            );
+        }
+
+        /// Returns the page content of a buffer returned by
+        /// [`PageFiles::read_page`], without the checksum appended by
+        /// [`PageStoreOptions::verify_cache_integrity`].
+        pub(crate) fn cached_page_content<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+            if self.verify_cache_integrity {
+                &buf[..buf.len() - CACHE_CHECKSUM_LEN]
+            } else {
+                buf
+            }
+        }
+
+        /// Appends the checksum of the page to the buffer before it is
+        /// inserted into the page cache, if the cache integrity is verified.
+        fn seal_cached_page(&self, mut buf: Vec<u8>) -> Vec<u8> {
+            if self.verify_cache_integrity {
+                let checksum = crc32fast::hash(&buf);
+                buf.extend_from_slice(&checksum.to_le_bytes());
+            }
+            buf
+        }
+
+        /// Changes the capacity of the page cache.
+        pub(crate) fn set_cache_capacity(&self, capacity: usize) {
+            self.page_cache.set_capacity(capacity);
+            self.cache_capacity.store(capacity, Ordering::Relaxed);
+        }
+
+        /// Returns the number of lookups that hit the page cache.
+        pub(crate) fn cache_hits(&self) -> u64 {
+            self.page_cache.lookup_hits()
+        }
+
+        /// Returns the current capacity of the page cache.
+        pub(crate) fn cache_capacity(&self) -> usize {
+            self.cache_capacity.load(Ordering::Relaxed)
+        }
+
+        /// Returns the recent changes of the page cache capacity made by
+        /// autosizing.
+        pub(crate) fn cache_resize_events(&self) -> Vec<CacheResizeEvent> {
+            self.cache_autosizer
+                .as_ref()
+                .map(|autosizer| autosizer.events())
+                .unwrap_or_default()
+        }
+
+        /// Records a lookup to the page cache, and grows the cache if the
+        /// autosizer decides so.
+        fn autosize_cache(&self, hit: bool) {
+            let Some(autosizer) = &self.cache_autosizer else {
+                return;
             };
-            drop(guard);
-            Ok(())
+            let Some(miss_ratio) = autosizer.record(hit) else {
+                return;
+            };
+            let capacity = self.cache_capacity();
+            let full = self.page_cache.is_full();
+            if let Some(new_capacity) = autosizer.on_window(capacity, miss_ratio, full) {
+                info!(
+                    "Grow page cache from {capacity} to {new_capacity} bytes with miss ratio {miss_ratio:.2}"
+                );
+                self.set_cache_capacity(new_capacity);
+            }
+        }
+
+        /// Returns true if the usage of the page cache reaches its capacity.
+        pub(crate) fn is_cache_full(&self) -> bool {
+            self.page_cache.is_full()
         }
 
         pub(crate) fn evict_cached_pages(&self, files: &[u32]) {
             for file_id in files {
                 self.page_cache.erase_file_pages(*file_id);
+                if let Some(cache) = &self.page_info_cache {
+                    cache.erase_file_pages(*file_id);
+                }
             }
         }
 
@@ -307,11 +573,30 @@ pub(crate) mod facade {
             Ok(files)
         }
 
-        pub(crate) fn stats(&self) -> (CacheStats, CacheStats) {
+        pub(crate) fn stats(&self) -> (CacheStats, CacheStats, CacheStats) {
             let page_cache = self.page_cache.stats();
             let table_cache = self.reader_cache.stats();
-            (page_cache, table_cache)
+            let page_info_cache = self
+                .page_info_cache
+                .as_ref()
+                .map(|cache| cache.stats())
+                .unwrap_or_default();
+            (page_cache, table_cache, page_info_cache)
+        }
+    }
+
+    /// Verifies the checksum appended by [`PageFiles::seal_cached_page`], to
+    /// detect pages corrupted in memory after they are cached.
+    fn verify_cached_page(buf: &[u8]) -> Result<()> {
+        let Some(split) = buf.len().checked_sub(CACHE_CHECKSUM_LEN) else {
+            return Err(Error::CorruptedInMemory);
+        };
+        let (content, checksum) = buf.split_at(split);
+        let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+        if crc32fast::hash(content) != checksum {
+            return Err(Error::CorruptedInMemory);
         }
+        Ok(())
     }
 
     #[cfg(test)]
@@ -398,6 +683,120 @@ pub(crate) mod facade {
             }
         }
 
+        #[photonio::test]
+        fn test_verify_cache_integrity() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_verify_cache").unwrap();
+            let mut opt = test_option();
+            opt.cache_capacity = 1 << 20;
+            opt.verify_cache_integrity = true;
+            let files = PageFiles::new(env, base.path(), &opt).await;
+            let file_id = 2;
+            let addr = page_addr(2, 2);
+            let (group, info) = {
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(123);
+                // An empty leaf data page.
+                b.add_page(1, addr, empty_page_info(), &[0].repeat(128))
+                    .await
+                    .unwrap();
+                let builder = b.finish().await.unwrap();
+                let (groups, info) = builder.finish(1).await.unwrap();
+                (groups.get(&123).unwrap().clone(), info)
+            };
+            let hd = group.get_page_handle(addr).unwrap();
+
+            for expect_hit in [false, true] {
+                let (entry, hit) = files
+                    .read_page(
+                        file_id,
+                        info.meta(),
+                        addr,
+                        hd,
+                        CacheOption::default(),
+                        IoPriority::Normal,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(hit, expect_hit);
+                assert_eq!(files.cached_page_content(entry.value()), [0].repeat(128));
+            }
+
+            // Flips a bit of the cached page.
+            let (entry, _) = files
+                .read_page(
+                    file_id,
+                    info.meta(),
+                    addr,
+                    hd,
+                    CacheOption::default(),
+                    IoPriority::Normal,
+                )
+                .await
+                .unwrap();
+            let mut buf = entry.value().clone();
+            drop(entry);
+            buf[0] ^= 1;
+            let charge = buf.len();
+            files
+                .page_cache
+                .insert(addr, Some(buf), charge, CacheOption::default())
+                .unwrap();
+            assert!(matches!(
+                files
+                    .read_page(
+                        file_id,
+                        info.meta(),
+                        addr,
+                        hd,
+                        CacheOption::default(),
+                        IoPriority::Normal
+                    )
+                    .await,
+                Err(Error::CorruptedInMemory)
+            ));
+        }
+
+        #[photonio::test]
+        fn test_read_malformed_page() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_malformed").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+            let file_id = 2;
+            let addr = page_addr(2, 2);
+            let (group, info) = {
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(123);
+                // The flags byte has an unknown page kind.
+                b.add_page(1, addr, empty_page_info(), &[7].repeat(128))
+                    .await
+                    .unwrap();
+                let builder = b.finish().await.unwrap();
+                let (groups, info) = builder.finish(1).await.unwrap();
+                (groups.get(&123).unwrap().clone(), info)
+            };
+            let hd = group.get_page_handle(addr).unwrap();
+            assert!(matches!(
+                files
+                    .read_page(
+                        file_id,
+                        info.meta(),
+                        addr,
+                        hd,
+                        CacheOption::default(),
+                        IoPriority::Normal
+                    )
+                    .await,
+                Err(Error::Corrupted)
+            ));
+        }
+
         #[photonio::test]
         fn test_simple_write_reader() {
             let env = crate::env::Photon;
@@ -438,6 +837,54 @@ pub(crate) mod facade {
             }
         }
 
+        #[photonio::test]
+        fn test_read_uncompressed_page() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_read_uncompressed").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+
+            // Sets the flag of pages that are stored without compression.
+            let no_compression_info = PageInfo::from_raw(0b0001_0000 << 48, 0, 0);
+            assert!(no_compression_info.no_compression());
+            let file_id = 2;
+            {
+                let b = files
+                    .new_file_builder(file_id, Compression::SNAPPY, ChecksumType::CRC32)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(1);
+                b.add_page(1, page_addr(2, 1), empty_page_info(), &[7].repeat(8192))
+                    .await
+                    .unwrap();
+                b.add_page(2, page_addr(2, 2), no_compression_info, &[8].repeat(8192))
+                    .await
+                    .unwrap();
+                let b = b.finish().await.unwrap();
+                b.finish(1).await.unwrap();
+            };
+            {
+                let meta = files.read_file_meta(file_id).await.unwrap();
+                let group = meta.page_groups.get(&1).unwrap();
+                let (_, handle) = group.get_page_handle(page_addr(2, 1)).unwrap();
+                assert!(!handle.no_compression);
+                assert!((handle.size as usize) < 8192);
+                let buf = files
+                    .read_file_page(file_id, &meta.file_meta, handle)
+                    .await
+                    .unwrap();
+                assert_eq!(buf, [7].repeat(8192));
+
+                let (_, handle) = group.get_page_handle(page_addr(2, 2)).unwrap();
+                assert!(handle.no_compression);
+                assert!((handle.size as usize) > 8192);
+                let buf = files
+                    .read_file_page(file_id, &meta.file_meta, handle)
+                    .await
+                    .unwrap();
+                assert_eq!(buf, [8].repeat(8192));
+            }
+        }
+
         #[photonio::test]
         fn test_query_page_id_by_addr() {
             let env = crate::env::Photon;
@@ -466,7 +913,9 @@ pub(crate) mod facade {
                 let b = b.finish().await.unwrap();
                 let (groups, _) = b.finish(1).await.unwrap();
                 let group = groups.get(&1).unwrap();
-                assert!(group.get_page_handle(page_addr1).is_some())
+                assert!(group.get_page_handle(page_addr1).is_some());
+                assert_eq!(group.get_page_id(page_addr1), Some(1));
+                assert_eq!(group.get_page_id(page_addr3), Some(2));
             }
 
             {
@@ -475,6 +924,9 @@ pub(crate) mod facade {
                 assert_eq!(*page_table.get(&page_addr1).unwrap(), 1);
                 assert_eq!(*page_table.get(&page_addr2).unwrap(), 1);
                 assert_eq!(*page_table.get(&page_addr3).unwrap(), 2);
+                let group = meta.page_groups.get(&1).unwrap();
+                assert_eq!(group.get_page_id(page_addr2), Some(1));
+                assert_eq!(group.get_page_id(page_addr3), Some(2));
             }
         }
 
@@ -39,9 +39,10 @@ impl FileMetaHolder {
         for page_index in &page_indexes {
             let index_block = Self::read_page_group_index_block(&reader, page_index).await?;
             let (indexes, offsets) = index_block.as_meta_file_cached(page_index.data_handle);
-            let file_meta =
+            let mut file_meta =
                 PageGroupMeta::new(page_index.file_id, file_id, offset, indexes, offsets);
             let page_table = Self::read_page_table(&reader, &file_meta).await?;
+            file_meta.set_page_ids(&page_table);
             file_meta_map.insert(page_index.file_id, Arc::new(file_meta));
             page_tables.insert(page_index.file_id, page_table);
             offset = page_index.meta_handle.offset + page_index.meta_handle.length;
@@ -3,18 +3,24 @@ use std::{collections::BTreeMap, sync::Arc};
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use super::{compression::Compression, BlockHandle, ChecksumType};
-use crate::{page::PageInfo, util::bitmap::FixedBitmap};
+use crate::{page::PageInfo, page_store::NAN_ID, util::bitmap::FixedBitmap};
 
 #[derive(Debug, Copy, Clone)]
 pub(crate) struct PageHandle {
     pub(crate) offset: u32,
     pub(crate) size: u32,
+    /// Whether the page is stored uncompressed regardless of the file's
+    /// compression.
+    pub(crate) no_compression: bool,
 }
 
 struct PageMeta {
     index: u32,
     info: PageInfo,
     handle: PageHandle,
+    /// The id of the page recorded in the page table of the group, or
+    /// [`NAN_ID`] for blob pages.
+    page_id: u64,
 }
 
 /// A group of pages has same id.
@@ -63,6 +69,30 @@ pub(crate) struct FileInfo {
     meta: Arc<FileMeta>,
 }
 
+/// Statistics of a page file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileStat {
+    /// The id of the page file.
+    pub file_id: u32,
+    /// The size of the file, including its metadata.
+    pub file_size: usize,
+    /// The size of pages in the file that are still in use.
+    pub live_bytes: usize,
+    /// The size of pages in the file that are no longer used.
+    pub dead_bytes: usize,
+    /// The number of pages in the file.
+    pub pages: usize,
+    /// The number of pages in the file that are still in use.
+    pub live_pages: usize,
+    /// The generation that the reclaiming takes as the age of the file.
+    ///
+    /// This is the generation that the file is created at, until pages in it
+    /// are deallocated in two later generations, after which it is the older
+    /// of the two. Files written by compactions start with the average
+    /// generation of the compacted files.
+    pub generation: u32,
+}
+
 /// The meta of files.
 pub(crate) struct FileMeta {
     pub(crate) file_id: u32,
@@ -123,6 +153,12 @@ impl PageGroup {
         self.meta.get_page_info(page_addr)
     }
 
+    /// Get the id of the specified page.
+    #[inline]
+    pub(crate) fn get_page_id(&self, page_addr: u64) -> Option<u64> {
+        self.meta.get_page_id(page_addr)
+    }
+
     #[inline]
     pub(crate) fn meta(&self) -> &Arc<PageGroupMeta> {
         &self.meta
@@ -202,6 +238,32 @@ impl PageGroupMeta {
         None
     }
 
+    pub(crate) fn get_page_id(&self, page_addr: u64) -> Option<u64> {
+        let addr = page_addr as u32;
+        self.page_meta_map.get(&addr).map(|meta| meta.page_id)
+    }
+
+    /// Records the ids of pages from the page table of the group.
+    pub(crate) fn set_page_ids(&mut self, page_table: &BTreeMap<u64, u64>) {
+        for (&page_addr, &page_id) in page_table {
+            if let Some(meta) = self.page_meta_map.get_mut(&(page_addr as u32)) {
+                meta.page_id = page_id;
+            }
+        }
+    }
+
+    /// Returns the addresses and handles of all pages in the group, including
+    /// inactive pages, in the order of their offsets.
+    pub(crate) fn page_handles(&self) -> Vec<(u64, PageHandle)> {
+        let mut pages = self
+            .page_meta_map
+            .iter()
+            .map(|(&addr, meta)| (((self.group_id as u64) << 32) | addr as u64, meta.handle))
+            .collect::<Vec<_>>();
+        pages.sort_by_key(|(_, handle)| handle.offset);
+        pages
+    }
+
     /// Return the total page (include inactive page).
     #[inline]
     pub(crate) fn total_pages(&self) -> usize {
@@ -242,9 +304,14 @@ impl PageGroupMeta {
             page_meta_map.insert(
                 addr,
                 PageMeta {
-                    info,
                     index,
-                    handle: PageHandle { offset, size: 0 },
+                    handle: PageHandle {
+                        offset,
+                        size: 0,
+                        no_compression: info.no_compression(),
+                    },
+                    info,
+                    page_id: NAN_ID,
                 },
             );
             if let Some(last_addr) = last_addr {
@@ -298,6 +365,31 @@ impl FileInfo {
     pub(crate) fn up2(&self) -> u32 {
         self.up2
     }
+
+    /// Returns the statistics of the file with the deallocated pages in the
+    /// page groups.
+    pub(crate) fn stat(&self, page_groups: &FxHashMap<u32, PageGroup>) -> FileStat {
+        let mut stat = FileStat {
+            file_id: self.meta.file_id,
+            file_size: self.meta.file_size,
+            live_bytes: 0,
+            dead_bytes: 0,
+            pages: 0,
+            live_pages: 0,
+            generation: self.up2,
+        };
+        let mut total_page_size = 0;
+        for (group_id, meta) in &self.meta.page_groups {
+            if let Some(page_group) = page_groups.get(group_id) {
+                stat.live_pages += page_group.num_active_pages();
+                stat.live_bytes += page_group.effective_size();
+            }
+            stat.pages += meta.total_pages();
+            total_page_size += meta.total_page_size();
+        }
+        stat.dead_bytes = total_page_size.saturating_sub(stat.live_bytes);
+        stat
+    }
 }
 
 impl FileMeta {
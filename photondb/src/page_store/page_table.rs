@@ -28,6 +28,17 @@ impl PageTableBuilder {
         self.max_id = self.max_id.max(id);
     }
 
+    /// Unmaps the ids whose addresses don't satisfy the predicate, so that
+    /// they are freed by [`Self::build`].
+    pub(crate) fn retain<F: FnMut(u64) -> bool>(&mut self, mut f: F) {
+        for id in MIN_ID..=self.max_id {
+            let addr = self.get(id);
+            if addr != 0 && !f(addr) {
+                self.inner.index(id).store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
     pub(crate) fn build(mut self) -> PageTable {
         let mut free = NAN_ID;
         // We prefer smaller ids so we scan backward to build the free list.
@@ -48,6 +59,11 @@ impl PageTableBuilder {
 }
 
 /// A table that maps page ids to page addresses.
+///
+/// Page ids are dense, so the table is a radix tree of fixed-size arrays
+/// indexed by the id. A lookup is at most three indexed loads without
+/// hashing, and the arrays of upper levels are installed lazily with CAS, so
+/// reads and updates never take a lock.
 #[derive(Clone, Default)]
 pub(crate) struct PageTable {
     inner: Arc<Inner>,
@@ -77,6 +93,24 @@ impl PageTable {
     pub(crate) unsafe fn dealloc(&self, id: u64) {
         self.inner.dealloc(id)
     }
+
+    /// Copies the addresses of the ids allocated so far, indexed by the ids.
+    ///
+    /// Entries are copied one by one, so the copy may mix the addresses before
+    /// and after concurrent updates.
+    pub(crate) fn copy(&self) -> Vec<u64> {
+        let next = self.inner.next.load(Ordering::Acquire);
+        (NAN_ID..next).map(|id| self.get(id)).collect()
+    }
+
+    /// Reserves the id so that it will never be returned by [`Self::alloc`].
+    ///
+    /// # Safety
+    ///
+    /// The id must not be in the free list.
+    pub(crate) unsafe fn reserve(&self, id: u64) {
+        self.inner.next.fetch_max(id + 1, Ordering::Relaxed);
+    }
 }
 
 struct Inner {
@@ -253,6 +287,13 @@ mod tests {
             assert_eq!(table.alloc(), Some(2));
             assert_eq!(table.alloc(), Some(1));
         }
+
+        let table = PageTable::default();
+        unsafe {
+            table.reserve(MIN_ID);
+            table.reserve(MIN_ID);
+            assert_eq!(table.alloc(), Some(MIN_ID + 1));
+        }
     }
 
     #[test]
@@ -277,6 +318,8 @@ mod tests {
         builder.set(1, 1);
         builder.set(3, 3);
         builder.set(5, 5);
+        builder.set(7, 7);
+        builder.retain(|addr| addr != 7);
         let table = builder.build();
         assert_eq!(table.get(1), 1);
         assert_eq!(table.get(3), 3);
@@ -285,6 +328,8 @@ mod tests {
             assert_eq!(table.alloc(), Some(2));
             assert_eq!(table.alloc(), Some(4));
             assert_eq!(table.alloc(), Some(6));
+            assert_eq!(table.alloc(), Some(7));
+            assert_eq!(table.alloc(), Some(8));
             table.dealloc(2);
             assert_eq!(table.alloc(), Some(2));
         }
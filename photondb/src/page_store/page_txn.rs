@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    ops::Range,
     sync::{Arc, Mutex},
     time::Instant,
 };
@@ -14,7 +15,7 @@ use super::{
     CacheEntry, Error, LRUCache, PageFiles, PageTable, Result, WriteBuffer, NAN_ID,
 };
 use crate::{
-    env::Env,
+    env::{Env, IoPriority},
     page::{PageBuf, PageInfo, PageRef},
 };
 
@@ -38,7 +39,7 @@ impl Default for CacheOption {
     }
 }
 
-type CacheEntryGuard = CacheEntry<Vec<u8>, LRUCache<Vec<u8>>>;
+pub(crate) type CacheEntryGuard = CacheEntry<Vec<u8>, LRUCache<Vec<u8>>>;
 
 pub(crate) struct Guard<E: Env>
 where
@@ -48,6 +49,15 @@ where
     page_table: PageTable,
     page_files: Arc<PageFiles<E>>,
     cache_guards: Mutex<Vec<CacheEntryGuard>>,
+    /// Flushed write buffers that are read by this guard but are no longer
+    /// referenced by the version.
+    buffer_guards: Mutex<Vec<Arc<WriteBuffer>>>,
+    /// A copy of the page table that the guard reads page addresses from
+    /// instead, see [`Self::freeze`].
+    frozen_addrs: Option<Vec<u64>>,
+    /// The priority of the reads of page files, see
+    /// [`Self::set_io_priority`].
+    io_priority: IoPriority,
     writebuf_stats: Arc<AtomicWritebufStats>,
 }
 
@@ -63,10 +73,62 @@ impl<E: Env> Guard<E> {
             page_table,
             page_files,
             cache_guards: Mutex::default(),
+            buffer_guards: Mutex::default(),
+            frozen_addrs: None,
+            io_priority: IoPriority::default(),
             writebuf_stats,
         }
     }
 
+    /// Sets the priority of the reads of page files by this guard.
+    pub(crate) fn set_io_priority(&mut self, priority: IoPriority) {
+        self.io_priority = priority;
+    }
+
+    /// Freezes the page addresses that the guard returns to the current ones.
+    ///
+    /// Pages are never modified in place, and the pages that the version of
+    /// the guard can read are kept until the guard is released. So a frozen
+    /// guard keeps reading the pages as of now, while the page table moves on
+    /// with new writes, consolidations, and compactions.
+    ///
+    /// The page table is copied entry by entry, so it is up to the caller to
+    /// make sure that no related entries change during the copy.
+    pub(crate) fn freeze(&mut self) {
+        self.frozen_addrs = Some(self.page_table.copy());
+    }
+
+    /// Returns true if the page addresses are frozen by [`Self::freeze`].
+    pub(crate) fn is_frozen(&self) -> bool {
+        self.frozen_addrs.is_some()
+    }
+
+    /// Takes the cached page that holds the bytes out of the guard, with the
+    /// range of the bytes in the page.
+    ///
+    /// The page stays in memory until the returned entry is dropped, even after
+    /// the guard is released. Returns `None` if the bytes are not in a page
+    /// read from the cache by this guard, like the pages in write buffers.
+    ///
+    /// # Safety
+    ///
+    /// Other references to the same cached page read by this guard must not be
+    /// used after the returned entry is dropped.
+    pub(crate) unsafe fn take_cached_page(
+        &self,
+        bytes: &[u8],
+    ) -> Option<(CacheEntryGuard, Range<usize>)> {
+        let mut owned_pages = self.cache_guards.lock().expect("Poisoned");
+        let start = bytes.as_ptr() as usize;
+        let (index, offset) = owned_pages.iter().enumerate().find_map(|(i, entry)| {
+            let page = entry.value();
+            let offset = start.checked_sub(page.as_ptr() as usize)?;
+            (offset + bytes.len() <= page.len()).then_some((i, offset))
+        })?;
+        let entry = owned_pages.swap_remove(index);
+        Some((entry, offset..offset + bytes.len()))
+    }
+
     pub(crate) async fn begin(&self) -> PageTxn<E> {
         let buffer_id = self.version.buffer_set.acquire_active_buffer_id().await;
         PageTxn {
@@ -78,6 +140,32 @@ impl<E: Env> Guard<E> {
         }
     }
 
+    /// Gives other tasks a chance to run before the next step of an operation.
+    #[inline]
+    pub(crate) async fn yield_now(&self) {
+        self.page_files.env().yield_now().await
+    }
+
+    /// Returns the current time of the environment.
+    #[inline]
+    pub(crate) fn now(&self) -> Instant {
+        self.page_files.env().now()
+    }
+
+    /// Retires the id of a page that is no longer reachable, so that it is
+    /// freed once no guard may read the page anymore.
+    ///
+    /// The pages mapped by the id must have been deallocated, so that the id is
+    /// freed on recovery as well.
+    pub(crate) fn retire_page_id(&self, id: u64) {
+        self.version.retire_page_id(id);
+    }
+
+    /// Returns true if flushes are failing because the disk is full.
+    pub(crate) fn is_disk_full(&self) -> bool {
+        self.page_files.is_disk_full()
+    }
+
     /// Returns the address of the corresponding page.
     ///
     /// Returns 0 if the page is not found.
@@ -89,7 +177,36 @@ impl<E: Env> Guard<E> {
     /// [`MAX_ID`]: super::MAX_ID
     #[inline]
     pub(crate) fn page_addr(&self, id: u64) -> u64 {
-        self.page_table.get(id)
+        match &self.frozen_addrs {
+            Some(addrs) => addrs.get(id as usize).copied().unwrap_or(NAN_ID),
+            None => self.page_table.get(id),
+        }
+    }
+
+    /// Verifies that the page at the address is recorded with the id, which
+    /// catches corrupted mappings that would return another page as if it
+    /// were the requested one.
+    ///
+    /// Pages whose ids are not recorded yet, like those being inserted by
+    /// concurrent transactions, are not verified.
+    pub(crate) fn verify_page_id(&self, id: u64, addr: u64) -> Result<()> {
+        let logical_id = (addr >> 32) as u32;
+        let recorded_id = if let Some(buf) = self.version.get(logical_id) {
+            // Safety: the id is written once when the page is mapped, and a
+            // racing read sees either `NAN_ID` or the final id.
+            unsafe { buf.page_id(addr) }
+        } else if let Some(group) = self.version.page_groups().get(&logical_id) {
+            group.get_page_id(addr).unwrap_or(NAN_ID)
+        } else {
+            NAN_ID
+        };
+        if recorded_id != NAN_ID && recorded_id != id {
+            return Err(Error::CorruptedMapping {
+                page_id: id,
+                mismatched_id: recorded_id,
+            });
+        }
+        Ok(())
     }
 
     pub(crate) fn read_page_info(&self, addr: u64) -> Result<PageInfo> {
@@ -102,6 +219,15 @@ impl<E: Env> Guard<E> {
             return Ok(page.info());
         }
 
+        if let Some(page_info) = self.page_files.lookup_page_info(addr) {
+            crate::perf::with(|ctx| {
+                ctx.add_get_page_info(start_at.elapsed());
+                ctx.inc_get_page_info_count();
+                ctx.inc_get_page_info_from_cache_count();
+            });
+            return Ok(page_info);
+        }
+
         let Some(file_info) = self.version.page_groups().get(&logical_id) else {
             panic!("File {logical_id} (addr {addr}) is not exists");
         };
@@ -109,6 +235,7 @@ impl<E: Env> Guard<E> {
         let Some(page_info) = file_info.get_page_info(addr) else {
             panic!("The addr {addr} is not belongs to the target file");
         };
+        self.page_files.insert_page_info(addr, page_info.clone());
 
         crate::perf::with(|ctx| {
             ctx.add_get_page_info(start_at.elapsed());
@@ -122,13 +249,50 @@ impl<E: Env> Guard<E> {
         addr: u64,
         hint: CacheOption,
     ) -> Result<(PageRef, Option<CacheToken>)> {
+        let (page, cache_token, _) = self.read_page_impl(addr, hint).await?;
+        Ok((page, cache_token))
+    }
+
+    /// Reads the page and fills it to the page cache as recently used.
+    ///
+    /// Returns the page, and true if it is read from a page file instead of
+    /// the cache or a write buffer.
+    pub(crate) async fn load_page(&self, addr: u64) -> Result<(PageRef, bool)> {
+        let (page, _, loaded) = self.read_page_impl(addr, CacheOption::default()).await?;
+        Ok((page, loaded))
+    }
+
+    /// Returns true if the page cache is full, so that filling more pages to
+    /// it evicts others.
+    pub(crate) fn is_cache_full(&self) -> bool {
+        self.page_files.is_cache_full()
+    }
+
+    async fn read_page_impl(
+        &self,
+        addr: u64,
+        hint: CacheOption,
+    ) -> Result<(PageRef, Option<CacheToken>, bool)> {
         let start_at = Instant::now();
         let logical_id = (addr >> 32) as u32;
         if let Some(buf) = self.version.get(logical_id) {
             self.writebuf_stats.read_in_buf.inc();
             // Safety: all mutable references are released.
-            crate::perf::with(|ctx| ctx.add_get_page(start_at.elapsed()));
-            return Ok((unsafe { buf.page(addr) }, None));
+            crate::perf::with(|ctx| {
+                ctx.add_get_page(start_at.elapsed());
+                ctx.inc_get_page_from_write_buffer_count();
+            });
+            return Ok((unsafe { buf.page(addr) }, None, false));
+        }
+        if let Some(buf) = self.read_flushed_buffer(logical_id) {
+            self.writebuf_stats.read_in_buf.inc();
+            // Safety: the buffer is flushed, so it is immutable, and it is kept
+            // alive by `buffer_guards`.
+            crate::perf::with(|ctx| {
+                ctx.add_get_page(start_at.elapsed());
+                ctx.inc_get_page_from_write_buffer_count();
+            });
+            return Ok((unsafe { buf.page(addr) }, None, false));
         }
         self.writebuf_stats.read_in_file.inc();
 
@@ -146,14 +310,21 @@ impl<E: Env> Guard<E> {
 
         let (entry, hit) = self
             .page_files
-            .read_page(physical_id, file_info.meta(), addr, handle, hint)
+            .read_page(
+                physical_id,
+                file_info.meta(),
+                addr,
+                handle,
+                hint,
+                self.io_priority,
+            )
             .await?;
 
         let mut owned_pages = self.cache_guards.lock().expect("Poisoned");
         owned_pages.push(entry);
 
         let last_guard = owned_pages.last().unwrap();
-        let page = last_guard.value();
+        let page = self.page_files.cached_page_content(last_guard.value());
         if !hit {
             self.writebuf_stats.read_file_bytes.add(page.len() as u64);
         }
@@ -175,7 +346,23 @@ impl<E: Env> Guard<E> {
                 ctx.inc_get_page_from_cache_miss_count();
             }
         });
-        Ok((page, Some(cache_token)))
+        Ok((page, Some(cache_token), !hit))
+    }
+
+    /// Returns the write buffer of the group if it has been flushed but not
+    /// released yet.
+    ///
+    /// A version only references the write buffers that are not flushed when
+    /// it is installed, but flushed buffers are retained until all previous
+    /// versions are released. Reading pages from them avoids reading the file
+    /// and filling the cache with pages that are still in memory.
+    fn read_flushed_buffer(&self, group_id: u32) -> Option<Arc<WriteBuffer>> {
+        let buf = Arc::clone(&*self.version.buffer_set.get(group_id)?);
+        let mut buffer_guards = self.buffer_guards.lock().expect("Poisoned");
+        if !buffer_guards.iter().any(|b| Arc::ptr_eq(b, &buf)) {
+            buffer_guards.push(buf.clone());
+        }
+        Some(buf)
     }
 }
 
@@ -218,6 +405,17 @@ impl<'a, E: Env> PageTxn<'a, E> {
         Ok((addr, buf))
     }
 
+    /// Records that the pages allocated by this transaction carry a write with
+    /// the `lsn`, so that the store knows when the write becomes durable.
+    ///
+    /// It should be called after a page is allocated.
+    pub(crate) fn record_lsn(&self, lsn: u64) {
+        debug_assert!(self.hold_write_guard);
+        if let Some(buf) = self.guard.version.get(self.buffer_id) {
+            buf.record_lsn(lsn);
+        }
+    }
+
     /// Inserts a new page into the store. Insertion happens when page splits or
     /// tree initializes. It returns the id of the inserted page.
     ///
@@ -241,6 +439,33 @@ impl<'a, E: Env> PageTxn<'a, E> {
         page_id
     }
 
+    /// Inserts a new page with the given id into the store, if no page has been
+    /// inserted with the id. This is used to insert pages with well-known ids,
+    /// like the root of a tree, which may race with others.
+    ///
+    /// On success, commits all operations in the transaction.
+    /// On failure, the transaction is aborted and the current address of the
+    /// page is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `addr` is not allocated by this transaction.
+    pub(crate) fn insert_page_at(mut self, id: u64, addr: u64) -> Result<(), u64> {
+        let header = self.records.get_mut(&addr).expect("no such pages");
+        if header.is_tombstone() {
+            panic!("insert page with tombstone");
+        }
+
+        // Safety: pages with well-known ids are never deallocated, so the id is
+        // not in the free list.
+        unsafe { self.guard.page_table.reserve(id) };
+        self.guard.page_table.cas(id, 0, addr)?;
+
+        header.set_page_id(id);
+        self.commit();
+        Ok(())
+    }
+
     /// Updates the page address to `new_addr` if its current value is the same
     /// as `old_addr`.
     ///
@@ -549,9 +774,85 @@ mod tests {
         page_txn.commit();
 
         assert_eq!(page_table.get(id), addr);
+        assert!(guard.verify_page_id(id, addr).is_ok());
+        assert!(matches!(
+            guard.verify_page_id(id + 1, addr),
+            Err(Error::CorruptedMapping { page_id, mismatched_id })
+                if page_id == id + 1 && mismatched_id == id
+        ));
         assert_current_buffer_is_flushable(version);
     }
 
+    #[photonio::test]
+    async fn page_txn_insert_page_at() {
+        let env = crate::env::Photon;
+        let base = tempdir::TempDir::new("test_page_insert_page_at").unwrap();
+        let files = Arc::new(PageFiles::new(env, base.path(), &test_option()).await);
+
+        let version = new_version(512);
+        let page_table = PageTable::default();
+        let guard = Guard::new(
+            version.clone(),
+            page_table.clone(),
+            files,
+            Default::default(),
+        );
+        // Two transactions race to insert the same page.
+        let mut page_txn_1 = guard.begin().await;
+        let (addr_1, _) = page_txn_1.alloc_page(123).await.unwrap();
+        let mut page_txn_2 = guard.begin().await;
+        let (addr_2, _) = page_txn_2.alloc_page(123).await.unwrap();
+        assert!(page_txn_1.insert_page_at(1, addr_1).is_ok());
+        assert_eq!(page_txn_2.insert_page_at(1, addr_2), Err(addr_1));
+
+        assert_eq!(page_table.get(1), addr_1);
+        let mut page_txn = guard.begin().await;
+        let (addr, _) = page_txn.alloc_page(123).await.unwrap();
+        assert_eq!(page_txn.insert_page(addr), 2);
+        page_txn.commit();
+        assert_current_buffer_is_flushable(version);
+    }
+
+    #[photonio::test]
+    async fn page_txn_read_flushed_buffer() {
+        let env = crate::env::Photon;
+        let base = tempdir::TempDir::new("test_page_txn_read_flushed_buffer").unwrap();
+        let files = Arc::new(PageFiles::new(env, base.path(), &test_option()).await);
+
+        let version = new_version(1 << 10);
+        let page_table = PageTable::default();
+        let guard = Guard::new(
+            version.clone(),
+            page_table.clone(),
+            files.clone(),
+            Default::default(),
+        );
+        let mut page_txn = guard.begin().await;
+        let (addr, mut buf) = page_txn.alloc_page(123).await.unwrap();
+        buf.set_epoch(7);
+        page_txn.insert_page(addr);
+        page_txn.commit();
+        guard.begin().await.seal_write_buffer().await;
+
+        // A version that no longer references the first buffer, as if it has
+        // been flushed.
+        let next_version = Arc::new(Version::with_buffer_set(
+            2,
+            version.buffer_set.clone(),
+            DeltaVersion::default(),
+        ));
+        let guard = Guard::new(next_version, page_table, files, Default::default());
+        crate::perf::reset_perf_ctx();
+        let (page, token) = guard.read_page(addr, CacheOption::default()).await.unwrap();
+        assert_eq!(page.epoch(), 7);
+        assert!(token.is_none());
+        crate::perf::with(|ctx| {
+            assert_eq!(ctx.get_page_from_write_buffer_count, 1);
+            assert_eq!(ctx.get_page_from_cache_count, 0);
+        });
+        assert_eq!(guard.buffer_guards.lock().unwrap().len(), 1);
+    }
+
     fn assert_current_buffer_is_flushable(version: Arc<Version>) {
         let current = version.buffer_set.current();
         let buf = current.last_writer_buffer();
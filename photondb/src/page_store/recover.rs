@@ -3,13 +3,13 @@ use std::{
     path::Path,
 };
 
-use log::debug;
+use log::{debug, info, warn};
 use rustc_hash::FxHashMap;
 
 use super::{
-    page_table::{PageTable, PageTableBuilder},
+    page_table::{PageTable, PageTableBuilder, NAN_ID},
     version::DeltaVersion,
-    FileInfo, NewFile, PageFiles, PageGroup, PageStore, Result, VersionEdit,
+    Error, FileInfo, NewFile, PageFiles, PageGroup, PageStore, Result, VersionEdit,
 };
 use crate::{env::Env, page_store::Manifest};
 
@@ -29,6 +29,66 @@ struct FileInfoBuilder<'a, E: Env> {
     dealloc_pages: HashMap<u32, Vec<u64>>,
 }
 
+/// The phases of recovery when a table is opened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryPhase {
+    /// Replaying the version edits in the manifest.
+    ManifestReplay,
+    /// Reading the metadata of the active page files.
+    ///
+    /// Page files take the place of a write-ahead log, so this is where most
+    /// of the recovery time goes for a large table.
+    FileScan,
+    /// Rebuilding the page table and the usage of page files.
+    IndexRebuild,
+}
+
+/// The progress of recovery when a table is opened.
+#[derive(Clone, Debug)]
+pub struct RecoveryProgress {
+    /// The current phase.
+    pub phase: RecoveryPhase,
+    /// The number of records replayed in the current phase.
+    ///
+    /// These are version edits for [`RecoveryPhase::ManifestReplay`], page
+    /// files for [`RecoveryPhase::FileScan`], and deallocated pages for
+    /// [`RecoveryPhase::IndexRebuild`].
+    pub records_replayed: u64,
+    /// The number of bytes covered by the replayed records in the current
+    /// phase.
+    pub bytes_scanned: u64,
+}
+
+impl RecoveryProgress {
+    fn new(phase: RecoveryPhase, records_replayed: u64, bytes_scanned: u64) -> Self {
+        Self {
+            phase,
+            records_replayed,
+            bytes_scanned,
+        }
+    }
+}
+
+/// A report of what recovery finds when a table is opened.
+#[derive(Clone, Debug, Default)]
+pub struct RecoveryReport {
+    /// The page files that are not referenced by the recovered manifest, but
+    /// are left in the path, sorted by id.
+    ///
+    /// These are left by crashes, like the files of interrupted flushes. They
+    /// are kept if [`PageStoreOptions::delete_orphans_on_open`] is false, or
+    /// if they are newer than the files recorded in the manifest, in case they
+    /// are referenced by edits that are not durable yet.
+    ///
+    /// [`PageStoreOptions::delete_orphans_on_open`]: crate::PageStoreOptions::delete_orphans_on_open
+    pub orphan_files: Vec<u32>,
+    /// The orphan page files deleted by recovery, sorted by id.
+    pub deleted_orphan_files: Vec<u32>,
+}
+
+/// A callback to report the progress of recovery.
+pub(crate) type RecoveryCallback<'a> = &'a mut (dyn FnMut(RecoveryProgress) + Send);
+
 struct FilesSummary {
     active_files: HashMap<u32, NewFile>,
     obsoleted_files: HashSet<u32>,
@@ -39,41 +99,100 @@ impl<E: Env> PageStore<E> {
         env: E,
         path: P,
         options: &crate::PageStoreOptions,
+        generation: Option<u32>,
+        progress: RecoveryCallback<'_>,
     ) -> Result<(
         u32, /* next page file id */
         Manifest<E>,
         PageTable,
         PageFiles<E>,
         DeltaVersion,
+        RecoveryReport,
     )> {
-        let mut manifest = Manifest::open(env.to_owned(), path.as_ref()).await?;
-        let versions = manifest.list_versions().await?;
-        let summary = Self::apply_version_edits(versions);
+        // Historical views are opened while the table may be open, so they must
+        // not clean up the files of the live manifest.
+        let mut manifest = if generation.is_some() {
+            Manifest::open_read_only(env.to_owned(), path.as_ref()).await?
+        } else {
+            Manifest::open(env.to_owned(), path.as_ref()).await?
+        };
+        manifest.set_fault_injector(options.fault_injector.clone());
+        progress(RecoveryProgress::new(RecoveryPhase::ManifestReplay, 0, 0));
+        let versions = manifest
+            .list_versions(|records, bytes| {
+                progress(RecoveryProgress::new(
+                    RecoveryPhase::ManifestReplay,
+                    records,
+                    bytes,
+                ))
+            })
+            .await?;
+        let max_lsn = versions.iter().map(|edit| edit.max_lsn).max();
+        let summary = Self::apply_version_edits(versions, generation)?;
         debug!("Recover with file summary {summary:?}");
 
         let page_files = PageFiles::new(env, path.as_ref(), options).await;
 
         let mut builder = FileInfoBuilder::new(&page_files);
-        Self::recover_page_groups(&mut builder, &summary.active_files).await?;
-        let (page_groups, file_infos, page_table) = builder.build();
+        Self::recover_page_groups(&mut builder, &summary.active_files, progress).await?;
+        progress(RecoveryProgress::new(RecoveryPhase::IndexRebuild, 0, 0));
+        let (page_groups, file_infos, page_table) = builder.build(progress);
 
-        Self::delete_unreferenced_page_files(&page_files, &summary).await?;
+        // The files of later generations are still used by the latest state.
+        let report = if generation.is_none() {
+            Self::delete_unreferenced_page_files(
+                &page_files,
+                &summary,
+                options.delete_orphans_on_open,
+            )
+            .await?
+        } else {
+            RecoveryReport::default()
+        };
 
-        let next_file_id = summary.next_file_id();
-        manifest.reset_next_file_id(summary.next_file_id());
+        // Don't overwrite the orphan files that are left.
+        let next_file_id = report
+            .orphan_files
+            .iter()
+            .map(|&id| id + 1)
+            .fold(summary.next_file_id(), u32::max);
+        manifest.reset_next_file_id(next_file_id);
+        manifest.reset_max_lsn(max_lsn.unwrap_or_default());
         let delta = DeltaVersion {
             page_groups,
             file_infos,
             ..Default::default()
         };
-        Ok((next_file_id, manifest, page_table, page_files, delta))
+        Ok((
+            next_file_id,
+            manifest,
+            page_table,
+            page_files,
+            delta,
+            report,
+        ))
     }
 
-    fn apply_version_edits(versions: Vec<VersionEdit>) -> FilesSummary {
+    /// Applies the version edits to summarize the files.
+    ///
+    /// If `generation` is given, only the edits before the first one that adds
+    /// files newer than it are applied, and [`Error::InvalidArgument`] is
+    /// returned if no applied edit adds the file of the generation.
+    fn apply_version_edits(
+        versions: Vec<VersionEdit>,
+        generation: Option<u32>,
+    ) -> Result<FilesSummary> {
         let mut active_files = HashMap::new();
         let mut obsoleted_files = HashSet::new();
+        let mut found = generation.is_none();
         for edit in versions {
             if let Some(edit) = edit.file_stream {
+                if let Some(generation) = generation {
+                    if edit.new_files.iter().any(|file| file.id > generation) {
+                        break;
+                    }
+                    found |= edit.new_files.iter().any(|file| file.id == generation);
+                }
                 for file in edit.new_files {
                     active_files.insert(file.id, file);
                 }
@@ -84,53 +203,84 @@ impl<E: Env> PageStore<E> {
             }
         }
 
-        FilesSummary {
+        if !found {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(FilesSummary {
             active_files,
             obsoleted_files,
-        }
+        })
     }
 
     async fn recover_page_groups(
         builder: &mut FileInfoBuilder<'_, E>,
         active_files: &HashMap<u32, NewFile>,
+        progress: RecoveryCallback<'_>,
     ) -> Result<()> {
         // ensure recover files in order.
         let mut files = active_files.values().cloned().collect::<Vec<_>>();
         files.sort_unstable();
-        for file in files {
-            builder.recover_file(file).await?;
+        progress(RecoveryProgress::new(RecoveryPhase::FileScan, 0, 0));
+        let mut bytes_scanned = 0;
+        for (i, file) in files.into_iter().enumerate() {
+            bytes_scanned += builder.recover_file(file).await? as u64;
+            progress(RecoveryProgress::new(
+                RecoveryPhase::FileScan,
+                i as u64 + 1,
+                bytes_scanned,
+            ));
         }
         Ok(())
     }
 
+    /// Deletes the page files that are not referenced by the manifest.
+    ///
+    /// The files obsoleted by the manifest are always deleted. The others are
+    /// orphans, which are deleted only if `delete_orphans` is true and they
+    /// are older than the files recorded in the manifest.
     async fn delete_unreferenced_page_files(
         page_files: &PageFiles<E>,
         summary: &FilesSummary,
-    ) -> Result<()> {
+        delete_orphans: bool,
+    ) -> Result<RecoveryReport> {
         let exist_files = page_files.list_files()?;
-        let deleted_files = Self::filter_obsoleted_files(
+        let (obsoleted_files, orphan_files) = Self::filter_unreferenced_files(
             exist_files,
             &summary.active_files,
-            summary.obsoleted_files.clone(),
+            &summary.obsoleted_files,
         );
-        page_files.remove_files(deleted_files).await;
-        Ok(())
+        let next_file_id = summary.next_file_id();
+        let (deleted_orphan_files, orphan_files): (Vec<_>, Vec<_>) = orphan_files
+            .into_iter()
+            .partition(|&id| delete_orphans && id < next_file_id);
+        if !deleted_orphan_files.is_empty() {
+            info!("Delete orphan page files {deleted_orphan_files:?}");
+        }
+        if !orphan_files.is_empty() {
+            warn!("Leave orphan page files {orphan_files:?}");
+        }
+        page_files.remove_files(obsoleted_files).await;
+        page_files.remove_files(deleted_orphan_files.clone()).await;
+        Ok(RecoveryReport {
+            orphan_files,
+            deleted_orphan_files,
+        })
     }
 
-    fn filter_obsoleted_files(
+    /// Returns the existing files obsoleted by the manifest, and the existing
+    /// files unknown to the manifest, both sorted by id.
+    fn filter_unreferenced_files(
         exist_files: Vec<u32>,
         active_files: &HashMap<u32, NewFile>,
-        obsoleted_files: HashSet<u32>,
-    ) -> Vec<u32> {
-        let mut obsoleted_files = obsoleted_files;
-        let exist_files = exist_files.into_iter().collect::<HashSet<_>>();
-        obsoleted_files.retain(|fid| exist_files.contains(fid));
-        for file_id in exist_files {
-            if !active_files.contains_key(&file_id) {
-                obsoleted_files.insert(file_id);
-            }
-        }
-        obsoleted_files.into_iter().collect::<Vec<_>>()
+        obsoleted_files: &HashSet<u32>,
+    ) -> (Vec<u32>, Vec<u32>) {
+        let (mut obsoleted, mut orphans): (Vec<_>, Vec<_>) = exist_files
+            .into_iter()
+            .filter(|fid| !active_files.contains_key(fid))
+            .partition(|fid| obsoleted_files.contains(fid));
+        obsoleted.sort_unstable();
+        orphans.sort_unstable();
+        (obsoleted, orphans)
     }
 }
 
@@ -147,7 +297,9 @@ impl<'a, E: Env> FileInfoBuilder<'a, E> {
     }
 
     /// Recover a file, the specified file id must be monotonically increasing.
-    async fn recover_file(&mut self, file: NewFile) -> Result<()> {
+    ///
+    /// Returns the size of the file.
+    async fn recover_file(&mut self, file: NewFile) -> Result<usize> {
         let meta_reader = self.facade.read_file_meta(file.id).await?;
 
         // 1. recover page groups
@@ -164,40 +316,62 @@ impl<'a, E: Env> FileInfoBuilder<'a, E> {
         }
 
         let file_meta = meta_reader.file_meta;
+        let file_size = file_meta.file_size;
         self.file_infos
             .insert(file.id, FileInfo::new(file.up1, file.up2, file_meta));
 
         // 3. recover page table.
         for (_, page_table) in meta_reader.page_tables {
             for (page_addr, page_id) in page_table {
+                // Blob pages are not mapped by the page table.
+                if page_id == NAN_ID {
+                    continue;
+                }
                 if self.page_table_builder.get(page_id) < page_addr {
                     self.page_table_builder.set(page_id, page_addr);
                 }
             }
         }
 
-        Ok(())
+        Ok(file_size)
     }
 
     /// Build page groups, file infos, orphan page files, and page table.
     fn build(
         mut self,
+        progress: RecoveryCallback<'_>,
     ) -> (
         FxHashMap<u32, PageGroup>,
         FxHashMap<u32, FileInfo>,
         PageTable,
     ) {
-        self.maintain_active_pages();
+        let num_dealloc_pages = self.maintain_active_pages();
+        progress(RecoveryProgress::new(
+            RecoveryPhase::IndexRebuild,
+            num_dealloc_pages,
+            0,
+        ));
         self.page_groups.retain(|_, g| !g.is_empty());
+        // The ids whose latest pages are deallocated are retired, like those of
+        // the pages detached from trees.
+        let page_groups = &self.page_groups;
+        self.page_table_builder.retain(|addr| {
+            page_groups
+                .get(&((addr >> 32) as u32))
+                .map_or(false, |group| group.get_page_handle(addr).is_some())
+        });
         let page_table = self.page_table_builder.build();
         (self.page_groups, self.file_infos, page_table)
     }
 
-    fn maintain_active_pages(&mut self) {
+    /// Returns the number of deallocated pages applied.
+    fn maintain_active_pages(&mut self) -> u64 {
+        let mut num_dealloc_pages = 0;
         let mut updates = self.dealloc_pages.keys().cloned().collect::<Vec<_>>();
         updates.sort_unstable();
         for updated_at in updates {
             let delete_pages = self.dealloc_pages.get(&updated_at).expect("Always exists");
+            num_dealloc_pages += delete_pages.len() as u64;
             for &page_addr in delete_pages {
                 let group_id = (page_addr >> 32) as u32;
                 if let Some(info) = self.page_groups.get_mut(&group_id) {
@@ -210,6 +384,7 @@ impl<'a, E: Env> FileInfoBuilder<'a, E> {
                 }
             }
         }
+        num_dealloc_pages
     }
 }
 
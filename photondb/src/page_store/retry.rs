@@ -0,0 +1,89 @@
+use std::{io, time::Duration};
+
+use super::Error;
+
+/// A policy to retry transient IO errors when reading page files.
+///
+/// Errors like [`io::ErrorKind::Interrupted`] or [`io::ErrorKind::TimedOut`]
+/// may go away if the operation is retried, which is common on networked
+/// storage. Other errors, like [`io::ErrorKind::NotFound`], are returned
+/// immediately.
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct IoRetryPolicy {
+    /// The maximum number of retries before the error is returned.
+    ///
+    /// Default: 3
+    pub max_retries: usize,
+
+    /// The backoff before the first retry, which is doubled for each of the
+    /// following retries.
+    ///
+    /// Default: 1ms
+    pub initial_backoff: Duration,
+
+    /// The maximum backoff between two retries.
+    ///
+    /// Default: 100ms
+    pub max_backoff: Duration,
+}
+
+impl Default for IoRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl IoRetryPolicy {
+    /// Returns the backoff before retrying the operation that failed with the
+    /// error, or `None` if it should not be retried.
+    ///
+    /// `retries` is the number of retries made so far.
+    pub(crate) fn backoff(&self, err: &Error, retries: usize) -> Option<Duration> {
+        if !err.is_retryable() || retries >= self.max_retries {
+            return None;
+        }
+        let factor = 1u32.checked_shl(retries as u32).unwrap_or(u32::MAX);
+        let backoff = self.initial_backoff.saturating_mul(factor);
+        Some(backoff.min(self.max_backoff))
+    }
+}
+
+/// Returns true if IO errors of the kind are transient.
+pub(crate) fn is_retryable(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ResourceBusy
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_retry_policy_backoff() {
+        let policy = IoRetryPolicy {
+            max_retries: 8,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        };
+        let err = Error::Io(io::ErrorKind::Interrupted.into());
+        let backoffs = (0..10)
+            .map_while(|retries| policy.backoff(&err, retries))
+            .map(|backoff| backoff.as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(backoffs, vec![1, 2, 4, 8, 10, 10, 10, 10]);
+
+        let err = Error::Io(io::ErrorKind::NotFound.into());
+        assert_eq!(policy.backoff(&err, 0), None);
+        assert_eq!(policy.backoff(&Error::Corrupted, 0), None);
+    }
+}
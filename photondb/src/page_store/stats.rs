@@ -1,4 +1,8 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use crate::util::atomic::Counter;
 
@@ -9,12 +13,22 @@ pub struct StoreStats {
     pub page_cache: CacheStats,
     /// Statistics of file reader cache.
     pub file_reader_cache: CacheStats,
+    /// Statistics of page info cache.
+    pub page_info_cache: CacheStats,
     /// Statistics of writebuf.
     pub writebuf: WritebufStats,
     /// Statistics of jobs.
     pub jobs: JobStats,
     /// Statistics of buffer set.
     pub buffer_set: BufferSetStats,
+    /// The current capacity of the page cache in bytes.
+    pub page_cache_capacity: usize,
+    /// The recent changes of the page cache capacity, from the oldest to the
+    /// newest, if the cache is autosized with
+    /// [`PageStoreOptions::cache_initial_capacity`].
+    ///
+    /// [`PageStoreOptions::cache_initial_capacity`]: crate::PageStoreOptions::cache_initial_capacity
+    pub page_cache_resizes: Vec<CacheResizeEvent>,
 }
 
 impl StoreStats {
@@ -23,9 +37,12 @@ impl StoreStats {
         StoreStats {
             page_cache: self.page_cache.sub(&o.page_cache),
             file_reader_cache: self.file_reader_cache.sub(&o.file_reader_cache),
+            page_info_cache: self.page_info_cache.sub(&o.page_info_cache),
             writebuf: self.writebuf.sub(&o.writebuf),
             jobs: self.jobs.sub(&o.jobs),
             buffer_set: self.buffer_set.sub(&o.buffer_set),
+            page_cache_capacity: self.page_cache_capacity,
+            page_cache_resizes: self.page_cache_resizes.to_owned(),
         }
     }
 }
@@ -35,12 +52,15 @@ impl Display for StoreStats {
         self.writebuf.fmt(f)?;
         writeln!(
             f,
-            "PageCacheStats: lookup_hit: {}, lookup_miss: {}, hit_rate: {:.2}%, insert: {}, active_evict: {}, passive_evict: {}, recommendation: {:?}",
+            "PageCacheStats: capacity: {}, resizes: {}, lookup_hit: {}, lookup_miss: {}, hit_rate: {:.2}%, insert: {}, insert_fail: {}, active_evict: {}, passive_evict: {}, recommendation: {:?}",
+            self.page_cache_capacity,
+            self.page_cache_resizes.len(),
             self.page_cache.lookup_hit,
             self.page_cache.lookup_miss,
             (self.page_cache.lookup_hit as f64) * 100.
                 / (self.page_cache.lookup_hit + self.page_cache.lookup_miss) as f64,
             self.page_cache.insert,
+            self.page_cache.insert_fail,
             self.page_cache.active_evict,
             self.page_cache.passive_evict,
             self.page_cache.recommendation,
@@ -57,6 +77,17 @@ impl Display for StoreStats {
             self.file_reader_cache.passive_evict,
             self.file_reader_cache.recommendation,
         )?;
+        writeln!(
+            f,
+            "PageInfoCacheStats: lookup_hit: {}, lookup_miss: {}, hit_rate: {:.2}%, insert: {}, insert_fail: {}, passive_evict: {}",
+            self.page_info_cache.lookup_hit,
+            self.page_info_cache.lookup_miss,
+            (self.page_info_cache.lookup_hit as f64) * 100.
+                / (self.page_info_cache.lookup_hit + self.page_info_cache.lookup_miss) as f64,
+            self.page_info_cache.insert,
+            self.page_info_cache.insert_fail,
+            self.page_info_cache.passive_evict,
+        )?;
         self.buffer_set.fmt(f)?;
         self.jobs.fmt(f)
     }
@@ -68,9 +99,37 @@ pub struct CacheStats {
     pub lookup_hit: u64,
     pub lookup_miss: u64,
     pub insert: u64,
+    /// The number of inserts that can't find room in the cache, so the values
+    /// are not cached.
+    pub insert_fail: u64,
     pub active_evict: u64,
     pub passive_evict: u64,
     pub recommendation: Vec<String>,
+    /// The usage and capacity of each shard, in bytes.
+    pub shards: Vec<CacheShardStats>,
+}
+
+/// A change of the page cache capacity made by autosizing.
+#[derive(Clone, Copy, Debug)]
+pub struct CacheResizeEvent {
+    /// The capacity before the change.
+    pub old_capacity: usize,
+    /// The capacity after the change.
+    pub new_capacity: usize,
+    /// The miss ratio of the lookups that triggered the change.
+    pub miss_ratio: f64,
+    /// The number of lookups to the cache since it was opened, when the
+    /// change was made.
+    pub lookups: u64,
+}
+
+/// Statistics of a cache shard.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct CacheShardStats {
+    /// The charge of entries in the shard.
+    pub usage: usize,
+    /// The capacity of the shard.
+    pub capacity: usize,
 }
 
 impl CacheStats {
@@ -79,9 +138,11 @@ impl CacheStats {
             lookup_hit: self.lookup_hit.wrapping_sub(o.lookup_hit),
             lookup_miss: self.lookup_miss.wrapping_sub(o.lookup_miss),
             insert: self.insert.wrapping_sub(o.insert),
+            insert_fail: self.insert_fail.wrapping_sub(o.insert_fail),
             active_evict: self.active_evict.wrapping_sub(o.active_evict),
             passive_evict: self.passive_evict.wrapping_sub(o.passive_evict),
             recommendation: self.recommendation.to_owned(),
+            shards: self.shards.to_owned(),
         }
     }
 
@@ -90,9 +151,11 @@ impl CacheStats {
             lookup_hit: self.lookup_hit.wrapping_add(o.lookup_hit),
             lookup_miss: self.lookup_miss.wrapping_add(o.lookup_miss),
             insert: self.insert.wrapping_add(o.insert),
+            insert_fail: self.insert_fail.wrapping_add(o.insert_fail),
             active_evict: self.active_evict.wrapping_add(o.active_evict),
             passive_evict: self.passive_evict.wrapping_add(o.passive_evict),
             recommendation: [self.recommendation.to_owned(), o.recommendation.to_owned()].concat(),
+            shards: [self.shards.to_owned(), o.shards.to_owned()].concat(),
         }
     }
 }
@@ -163,6 +226,18 @@ pub struct JobStats {
     pub compact_input_bytes: u64,
     /// The total bytes read during compaction.
     pub read_file_bytes: u64,
+    /// The total size of page files, sampled when checking whether space
+    /// should be reclaimed.
+    pub file_bytes: u64,
+    /// The total size of obsolete pages in page files, sampled along with
+    /// `file_bytes`.
+    pub file_dead_bytes: u64,
+    /// The total number of write buffers flushed early because of the page
+    /// cache pressure.
+    pub cache_pressure_flushes: u64,
+    /// The number of write buffers being flushed, sampled when the stats are
+    /// taken.
+    pub flushes_in_flight: u64,
 }
 
 #[derive(Default, Debug)]
@@ -172,6 +247,12 @@ pub(crate) struct AtomicJobStats {
     pub(super) compact_write_bytes: Counter,
     pub(super) compact_input_bytes: Counter,
     pub(super) read_file_bytes: Counter,
+    pub(super) file_bytes: Counter,
+    pub(super) file_dead_bytes: Counter,
+    pub(super) cache_pressure_flushes: Counter,
+    pub(super) flushes_in_flight: Counter,
+    pub(super) flush_latency: AtomicLatency,
+    pub(super) reclaim_latency: AtomicLatency,
 }
 
 impl JobStats {
@@ -182,6 +263,23 @@ impl JobStats {
             compact_write_bytes: self.compact_write_bytes.wrapping_sub(o.compact_write_bytes),
             compact_input_bytes: self.compact_input_bytes.wrapping_sub(o.compact_input_bytes),
             read_file_bytes: self.read_file_bytes.wrapping_sub(o.read_file_bytes),
+            file_bytes: self.file_bytes,
+            file_dead_bytes: self.file_dead_bytes,
+            cache_pressure_flushes: self
+                .cache_pressure_flushes
+                .wrapping_sub(o.cache_pressure_flushes),
+            flushes_in_flight: self.flushes_in_flight,
+        }
+    }
+
+    /// Returns the ratio of obsolete bytes to the total size of page files.
+    ///
+    /// Returns zero if space reclaiming is disabled or no page file exists.
+    pub fn file_fragmentation_ratio(&self) -> f64 {
+        if self.file_bytes == 0 {
+            0.0
+        } else {
+            self.file_dead_bytes as f64 / self.file_bytes as f64
         }
     }
 }
@@ -202,13 +300,23 @@ impl Display for JobStats {
             compact_input_bytes: {}, \
             compact_write_bytes: {}, \
             read_file_bytes: {}, \
-            write_amp: {:.2}",
+            write_amp: {:.2}, \
+            file_bytes: {}, \
+            file_dead_bytes: {}, \
+            file_fragmentation_ratio: {:.2}, \
+            cache_pressure_flushes: {}, \
+            flushes_in_flight: {}",
             self.flush_write_bytes,
             self.flush_discard_bytes,
             self.compact_input_bytes,
             self.compact_write_bytes,
             self.read_file_bytes,
-            write_amp
+            write_amp,
+            self.file_bytes,
+            self.file_dead_bytes,
+            self.file_fragmentation_ratio(),
+            self.cache_pressure_flushes,
+            self.flushes_in_flight
         )
     }
 }
@@ -221,6 +329,96 @@ impl AtomicJobStats {
             compact_write_bytes: self.compact_write_bytes.get(),
             compact_input_bytes: self.compact_input_bytes.get(),
             read_file_bytes: self.read_file_bytes.get(),
+            file_bytes: self.file_bytes.get(),
+            file_dead_bytes: self.file_dead_bytes.get(),
+            cache_pressure_flushes: self.cache_pressure_flushes.get(),
+            flushes_in_flight: self.flushes_in_flight.get(),
+        }
+    }
+
+    pub(crate) fn background_stats(&self) -> BackgroundStats {
+        BackgroundStats {
+            flush: self.flush_latency.snapshot(),
+            consolidation: LatencyStats::default(),
+            reclaim: self.reclaim_latency.snapshot(),
+        }
+    }
+}
+
+/// The durations of the background operations of a table.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BackgroundStats {
+    /// The durations of flushing write buffers to page files. Write buffers
+    /// flushed together are counted as one flush.
+    pub flush: LatencyStats,
+    /// The durations of consolidating page chains, either after writes or
+    /// when pages are rewritten.
+    pub consolidation: LatencyStats,
+    /// The durations of compacting page files to reclaim space.
+    pub reclaim: LatencyStats,
+}
+
+/// The durations of an operation since the table was opened.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// The number of times the operation finished.
+    pub count: u64,
+    /// The shortest duration, or zero if the operation never finished.
+    pub min: Duration,
+    /// The longest duration.
+    pub max: Duration,
+    /// The sum of the durations.
+    pub total: Duration,
+}
+
+impl LatencyStats {
+    /// Returns the average duration, or `None` if the operation never
+    /// finished.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|&count| count > 0)?;
+        Some(self.total / count)
+    }
+}
+
+/// Records the durations of an operation with atomics.
+#[derive(Debug)]
+pub(crate) struct AtomicLatency {
+    count: Counter,
+    min_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    total_nanos: AtomicU64,
+}
+
+impl Default for AtomicLatency {
+    fn default() -> Self {
+        Self {
+            count: Counter::default(),
+            min_nanos: AtomicU64::new(u64::MAX),
+            max_nanos: AtomicU64::new(0),
+            total_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+impl AtomicLatency {
+    pub(crate) fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        self.min_nanos.fetch_min(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.count.inc();
+    }
+
+    pub(crate) fn snapshot(&self) -> LatencyStats {
+        let count = self.count.get();
+        if count == 0 {
+            return LatencyStats::default();
+        }
+        LatencyStats {
+            count,
+            min: Duration::from_nanos(self.min_nanos.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+            total: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed)),
         }
     }
 }
@@ -15,6 +15,21 @@ pub(crate) trait ReclaimPickStrategy: Send + Sync {
 
     /// Return the most suitable files for reclaiming under the strategy.
     fn apply(&mut self) -> Option<(u32, usize /* active size */)>;
+
+    /// Return the remaining files in the order that they will be applied.
+    fn candidates(&mut self) -> Vec<CandidateInfo>;
+}
+
+/// Information about a page file that can be picked for compaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandidateInfo {
+    /// The id of the page file.
+    pub file_id: u32,
+    /// The estimated size of pages in the file that are no longer used.
+    pub dead_bytes: usize,
+    /// The priority score of the file. Files with higher scores are compacted
+    /// first.
+    pub score: f64,
 }
 
 pub(crate) struct MinDeclineRateStrategy {
@@ -33,6 +48,7 @@ struct FileScore {
     write_amplify: f64,
     active_size: usize,
     file_id: u32,
+    free_size: usize,
 }
 
 #[derive(Debug)]
@@ -67,8 +83,21 @@ impl MinDeclineRateStrategy {
             write_amplify,
             active_size: summary.effective_size,
             score,
+            free_size: summary
+                .total_page_size
+                .saturating_sub(summary.effective_size),
         });
     }
+
+    fn sort(&mut self) {
+        if !self.sorted {
+            self.sorted = true;
+            self.scores.sort_unstable_by(|a, b| {
+                a.partial_cmp(b)
+                    .unwrap_or_else(|| a.file_id.cmp(&b.file_id))
+            });
+        }
+    }
 }
 
 impl ReclaimPickStrategy for MinDeclineRateStrategy {
@@ -79,20 +108,26 @@ impl ReclaimPickStrategy for MinDeclineRateStrategy {
     }
 
     fn apply(&mut self) -> Option<(u32, usize)> {
-        if !self.sorted {
-            self.sorted = true;
-            self.scores.sort_unstable_by(|a, b| {
-                a.partial_cmp(b)
-                    .unwrap_or_else(|| a.file_id.cmp(&b.file_id))
-            });
-        }
-
+        self.sort();
         if self.scores.len() < 2 {
             return None;
         }
 
         self.scores.pop().map(|f| (f.file_id, f.active_size))
     }
+
+    fn candidates(&mut self) -> Vec<CandidateInfo> {
+        self.sort();
+        self.scores
+            .iter()
+            .rev()
+            .map(|f| CandidateInfo {
+                file_id: f.file_id,
+                dead_bytes: f.free_size,
+                score: f.score,
+            })
+            .collect()
+    }
 }
 
 impl StrategyBuilder for MinDeclineRateStrategyBuilder {
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use log::warn;
+
+use super::{ChecksumType, PageStore, Result};
+use crate::env::Env;
+
+/// The result of [`Table::verify_checksums`].
+///
+/// [`Table::verify_checksums`]: crate::raw::Table::verify_checksums
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The number of page files scanned.
+    pub files: u64,
+    /// The number of pages whose checksums are verified.
+    pub pages: u64,
+    /// The number of bytes read from page files.
+    pub bytes: u64,
+    /// The number of pages that can't be verified, because their files are
+    /// written without checksums.
+    pub unchecked_pages: u64,
+    /// The addresses of the pages whose checksums mismatch.
+    pub corrupted_pages: Vec<u64>,
+}
+
+impl VerifyReport {
+    /// Returns true if no corrupted page is found.
+    pub fn is_ok(&self) -> bool {
+        self.corrupted_pages.is_empty()
+    }
+}
+
+impl<E: Env> PageStore<E> {
+    /// Reads every page of the active page files and checks it against its
+    /// checksum, regardless of whether the page is still in use.
+    ///
+    /// If `rate_limit` is given, the reads are paced to that many bytes per
+    /// second.
+    pub(crate) async fn verify_checksums(&self, rate_limit: Option<u64>) -> Result<VerifyReport> {
+        let start_at = self.env.now();
+        let mut report = VerifyReport::default();
+        // The files are not deleted while the version is held.
+        let version = self.version();
+        let mut file_ids = version.file_infos().keys().cloned().collect::<Vec<_>>();
+        file_ids.sort_unstable();
+        for file_id in file_ids {
+            let file_meta = version.file_infos()[&file_id].meta().clone();
+            report.files += 1;
+            if file_meta.checksum_type == ChecksumType::NONE {
+                report.unchecked_pages += file_meta
+                    .page_groups
+                    .values()
+                    .map(|group| group.total_pages() as u64)
+                    .sum::<u64>();
+                continue;
+            }
+
+            let reader = self
+                .page_files
+                .open_page_reader(file_id, file_meta.block_size)
+                .await?;
+            let mut groups = file_meta.page_groups.values().collect::<Vec<_>>();
+            groups.sort_unstable_by_key(|group| group.group_id);
+            for group in groups {
+                for (addr, handle) in group.page_handles() {
+                    let ok = self
+                        .page_files
+                        .verify_file_page(&reader, &file_meta, handle)
+                        .await?;
+                    report.pages += 1;
+                    report.bytes += handle.size as u64;
+                    if !ok {
+                        warn!("Page {addr} of file {file_id} is corrupted, checksum mismatches");
+                        report.corrupted_pages.push(addr);
+                    }
+                    if let Some(rate_limit) = rate_limit.filter(|&rate| rate > 0) {
+                        let expect =
+                            Duration::from_secs_f64(report.bytes as f64 / rate_limit as f64);
+                        let elapsed = self.env.now().duration_since(start_at);
+                        if let Some(wait) = expect.checked_sub(elapsed) {
+                            self.env.sleep(wait).await;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+}
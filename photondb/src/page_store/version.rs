@@ -28,6 +28,10 @@ pub(crate) struct Version {
     /// Records the ID of the file that can be deleted.
     obsoleted_files: FxHashSet<u32>,
 
+    /// The ids of the pages retired while this is the latest version, which
+    /// are freed once the version is released, see [`Self::retire_page_id`].
+    retired_page_ids: Arc<Mutex<Vec<u64>>>,
+
     next_version: AtomicPtr<Arc<Version>>,
     new_version_latch: Latch,
     reclaiming_latch: Latch,
@@ -113,15 +117,15 @@ impl VersionOwner {
     fn switch_version(&self, next: Box<Arc<Version>>, guard: Guard) {
         let raw_former = self.raw.load(Ordering::Acquire);
         let raw_next = Box::into_raw(next.clone());
-        self.raw
-            .compare_exchange(raw_former, raw_next, Ordering::AcqRel, Ordering::Acquire)
-            .expect("There has already exists a version");
 
         // Safety:
         // 1. Obtained from `Box::new`, so it is aligned and not null.
         // 2. There is not mutable references pointer to it.
         let former = unsafe { &*raw_former };
 
+        // Link the new version before publishing it, so that the latest version
+        // reachable from any version is never older than the current one, see
+        // `Version::retire_page_id`.
         former
             .next_version
             .compare_exchange(
@@ -131,6 +135,9 @@ impl VersionOwner {
                 Ordering::Acquire,
             )
             .expect("There has already exists a version");
+        self.raw
+            .compare_exchange(raw_former, raw_next, Ordering::AcqRel, Ordering::Acquire)
+            .expect("There has already exists a version");
         former.new_version_latch.count_down();
 
         let raw_former = raw_former as usize;
@@ -185,6 +192,7 @@ impl Version {
             page_groups: delta.page_groups,
             files: delta.file_infos,
             obsoleted_files: delta.obsoleted_files,
+            retired_page_ids: Arc::default(),
             buffer_set,
 
             next_version: AtomicPtr::default(),
@@ -201,6 +209,23 @@ impl Version {
         self.try_next().expect("New version has been installed")
     }
 
+    /// Retires the id of a page that is no longer reachable, so that it is
+    /// freed once the latest version is released.
+    ///
+    /// Guards that may still read the page hold this version or the former
+    /// ones, which keep the latest version alive.
+    pub(crate) fn retire_page_id(self: &Arc<Self>, id: u64) {
+        let latest = self.refresh().unwrap_or_else(|| self.clone());
+        latest.retired_page_ids.lock().expect("Poisoned").push(id);
+    }
+
+    /// Returns the ids of the pages retired while this is the latest version.
+    ///
+    /// The ids are only safe to free after the version is released.
+    pub(crate) fn retired_page_ids(&self) -> Arc<Mutex<Vec<u64>>> {
+        self.retired_page_ids.clone()
+    }
+
     /// Wait until all reference pointed to the [`Version`] has been released.
     ///
     /// There can only be one waiter per [`Version`].
@@ -413,6 +438,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn version_retire_page_id() {
+        let version = Version::new(1 << 10, 1, 8, DeltaVersion::default());
+        let owner = VersionOwner::new(version);
+        let former = owner.current();
+        let delta = DeltaVersion {
+            reason: VersionUpdateReason::Compact,
+            ..Default::default()
+        };
+        // Safety: no concurrent operations
+        unsafe { owner.install(delta) };
+        let latest = owner.current();
+
+        // The ids retired through a former version wait for the latest one.
+        former.retire_page_id(1);
+        latest.retire_page_id(2);
+        assert!(former.retired_page_ids().lock().unwrap().is_empty());
+        assert_eq!(*latest.retired_page_ids().lock().unwrap(), vec![1, 2]);
+    }
+
     #[test]
     fn version_access_newly_buffers() {
         let version = Version::new(1 << 10, 1, 8, DeltaVersion::default());
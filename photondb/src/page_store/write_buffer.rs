@@ -26,6 +26,10 @@ where
     // The state of current buffer, see [`BufferState`] for details.
     buffer_state: AtomicU64,
 
+    /// The range of the lsn of the records written to this buffer.
+    min_lsn: AtomicU64,
+    max_lsn: AtomicU64,
+
     /// A latch for flushed notify.
     flush_latch: Latch,
 }
@@ -104,6 +108,8 @@ impl WriteBuffer {
             buf,
             buf_size,
             buffer_state: AtomicU64::new(default_state.apply()),
+            min_lsn: AtomicU64::new(u64::MAX),
+            max_lsn: AtomicU64::new(0),
             flush_latch: Latch::new(1),
         }
     }
@@ -128,6 +134,24 @@ impl WriteBuffer {
         self.buffer_state().allocated == 0
     }
 
+    /// Records that a record with the `lsn` is written to this buffer. It
+    /// must be called by a writer that holds the writer guard of this buffer.
+    #[inline]
+    pub(crate) fn record_lsn(&self, lsn: u64) {
+        self.min_lsn.fetch_min(lsn, Ordering::AcqRel);
+        self.max_lsn.fetch_max(lsn, Ordering::AcqRel);
+    }
+
+    /// Returns the range of the lsn recorded by [`Self::record_lsn`], if any.
+    #[inline]
+    pub(crate) fn lsn_range(&self) -> Option<(u64, u64)> {
+        let min_lsn = self.min_lsn.load(Ordering::Acquire);
+        if min_lsn == u64::MAX {
+            return None;
+        }
+        Some((min_lsn, self.max_lsn.load(Ordering::Acquire)))
+    }
+
     #[inline]
     pub(crate) async fn wait_flushed(&self) {
         self.flush_latch.wait().await;
@@ -320,6 +344,37 @@ impl WriteBuffer {
     /// Users need to ensure that the accessed page has no mutable references,
     /// so as not to violate the rules of pointer aliasing.
     pub(crate) unsafe fn page<'a>(&self, page_addr: u64) -> PageRef<'a> {
+        let header = self.page_header(page_addr);
+        if let Some(RecordRef::Page(page_ref)) = header.record_ref() {
+            return page_ref;
+        }
+
+        panic!("The specified addr is not a valid page");
+    }
+
+    /// Returns the id of the page at the specified address, or [`NAN_ID`] if
+    /// the page is not inserted into the page table yet.
+    ///
+    /// # Panic
+    ///
+    /// See [`WriteBuffer::page`].
+    ///
+    /// # Safety
+    ///
+    /// See [`WriteBuffer::page`].
+    ///
+    /// [`NAN_ID`]: super::NAN_ID
+    pub(crate) unsafe fn page_id(&self, page_addr: u64) -> u64 {
+        let header = self.page_header(page_addr);
+        if let Some(RecordRef::Page(_)) = header.record_ref() {
+            return header.page_id();
+        }
+
+        panic!("The specified addr is not a valid page");
+    }
+
+    /// Returns the [`RecordHeader`] of the page at the specified address.
+    unsafe fn page_header(&self, page_addr: u64) -> &RecordHeader {
         const ALIGN: u32 = core::mem::size_of::<usize>() as u32;
 
         let file_id = (page_addr >> 32) as u32;
@@ -337,12 +392,7 @@ impl WriteBuffer {
             .checked_sub(core::mem::size_of::<RecordHeader>() as u32)
             .expect("The specified addr is not a valid page");
 
-        let header = self.record(offset);
-        if let Some(RecordRef::Page(page_ref)) = header.record_ref() {
-            return page_ref;
-        }
-
-        panic!("The specified addr is not a valid page");
+        self.record(offset)
     }
 
     /// Construct the reference of [`RecordHeader`] of the corresponding offset.
@@ -18,7 +18,9 @@ pub struct PerfCtx {
     pub split_page: Duration,
     pub get_page_from_cache_count: u64,
     pub get_page_from_cache_miss_count: u64,
+    pub get_page_from_write_buffer_count: u64,
     pub get_page_info_count: u64,
+    pub get_page_info_from_cache_count: u64,
     pub consolidate_page_size: usize,
     pub consolidate_length: usize,
 }
@@ -37,6 +39,25 @@ pub fn reset_perf_ctx() {
 }
 
 impl PerfCtx {
+    /// Returns the phase that takes the most time and its duration, or `None`
+    /// if no phase is recorded.
+    pub fn dominant_phase(&self) -> Option<(&'static str, Duration)> {
+        [
+            ("find_leaf", self.find_leaf),
+            ("find_value", self.find_value),
+            ("write_build_page", self.write_build_page),
+            ("replace_page", self.replace_page),
+            ("collect_info", self.collect_info),
+            ("get_page_info", self.get_page_info),
+            ("get_page", self.get_page),
+            ("consolidate_page", self.consolidate_page),
+            ("split_page", self.split_page),
+        ]
+        .into_iter()
+        .filter(|(_, elapsed)| !elapsed.is_zero())
+        .max_by_key(|(_, elapsed)| *elapsed)
+    }
+
     fn reset(&mut self) {
         self.total = Duration::ZERO;
         self.find_leaf = Duration::ZERO;
@@ -50,7 +71,9 @@ impl PerfCtx {
         self.split_page = Duration::ZERO;
         self.get_page_from_cache_count = 0;
         self.get_page_from_cache_miss_count = 0;
+        self.get_page_from_write_buffer_count = 0;
         self.get_page_info_count = 0;
+        self.get_page_info_from_cache_count = 0;
         self.consolidate_page_size = 0;
         self.consolidate_length = 0;
     }
@@ -95,9 +118,15 @@ impl PerfCtx {
     pub(crate) fn inc_get_page_from_cache_count(&mut self) {
         self.get_page_from_cache_count += 1;
     }
+    pub(crate) fn inc_get_page_from_write_buffer_count(&mut self) {
+        self.get_page_from_write_buffer_count += 1;
+    }
     pub(crate) fn inc_get_page_info_count(&mut self) {
         self.get_page_info_count += 1;
     }
+    pub(crate) fn inc_get_page_info_from_cache_count(&mut self) {
+        self.get_page_info_from_cache_count += 1;
+    }
     pub(crate) fn add_consolidate_page_size(&mut self, size: usize) {
         self.consolidate_page_size += size;
     }
@@ -105,3 +134,21 @@ impl PerfCtx {
         self.consolidate_length += len;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dominant_phase() {
+        let mut ctx = PerfCtx::default();
+        assert_eq!(ctx.dominant_phase(), None);
+        ctx.set_total(Duration::from_millis(300));
+        ctx.set_find_leaf(Duration::from_millis(280));
+        ctx.add_get_page(Duration::from_millis(10));
+        assert_eq!(
+            ctx.dominant_phase(),
+            Some(("find_leaf", Duration::from_millis(280)))
+        );
+    }
+}
@@ -5,8 +5,23 @@
 
 use std::{ops::Deref, path::Path};
 
-pub use crate::raw::TableStats;
-use crate::{env::Photon, raw, Result, TableOptions};
+pub use crate::raw::{
+    DiffReport, DropPrefixStats, DuplicateKeyRule, KeyDiff, PinnedBuffer, ReplicationRecord,
+    RewriteStats, ScanChunk, ScanFileStats, ScanSegment, TableStats,
+};
+use crate::{env::Photon, raw, RecoveryProgress, Result, TableOptions};
+
+/// Compares the entries of two tables visible to the LSN key by key.
+///
+/// This is the same as [`raw::diff_tables`] with the [`Photon`] environment.
+pub async fn diff_tables(
+    a: &Table,
+    b: &Table,
+    lsn: u64,
+    max_diffs: Option<usize>,
+) -> Result<DiffReport> {
+    raw::diff_tables(&a.0, &b.0, lsn, max_diffs).await
+}
 
 /// A reference to a latch-free, log-structured table that stores sorted
 /// key-value entries.
@@ -25,6 +40,56 @@ impl Table {
         Ok(Self(table))
     }
 
+    /// Opens a table in the path with the given options, and reports the
+    /// progress of recovery to the callback.
+    ///
+    /// This is the same as [`raw::Table::open_with_progress`] with the
+    /// [`Photon`] environment.
+    pub async fn open_with_progress<P, F>(
+        path: P,
+        options: TableOptions,
+        progress: F,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        F: FnMut(RecoveryProgress) + Send,
+    {
+        let table = raw::Table::open_with_progress(Photon, path, options, progress).await?;
+        Ok(Self(table))
+    }
+
+    /// Opens a read-only view of the table in the path as of the page file
+    /// `generation`.
+    ///
+    /// This is the same as [`raw::Table::open_at_generation`] with the
+    /// [`Photon`] environment.
+    pub async fn open_at_generation<P: AsRef<Path>>(
+        path: P,
+        generation: u32,
+        options: TableOptions,
+    ) -> Result<Self> {
+        let table = raw::Table::open_at_generation(Photon, path, generation, options).await?;
+        Ok(Self(table))
+    }
+
+    /// Rewrites the table in `src` to a new table in `dst` with the options.
+    ///
+    /// This is the same as [`raw::Table::rewrite_with_options`] with the
+    /// [`Photon`] environment.
+    pub async fn rewrite_with_options<P, Q, F>(
+        src: P,
+        dst: Q,
+        options: TableOptions,
+        progress: F,
+    ) -> Result<RewriteStats>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(&RewriteStats),
+    {
+        raw::Table::rewrite_with_options(Photon, src, dst, options, progress).await
+    }
+
     /// Closes the table if this is the only reference to it.
     ///
     /// This is the same as [`raw::Table::close`] with the [`Photon`]
@@ -45,5 +110,11 @@ impl Deref for Table {
 /// A handle that holds some resources of a table for user operations.
 pub type Guard<'a> = raw::Guard<'a, Photon>;
 
+/// A transaction that spans multiple operations on a table.
+pub type Txn<'a> = raw::Txn<'a, Photon>;
+
 /// An iterator over pages in a table.
 pub type Pages<'a, 't> = raw::Pages<'a, 't, Photon>;
+
+/// A point-in-time view of a table.
+pub type Snapshot<'a> = raw::Snapshot<'a, Photon>;
@@ -0,0 +1,171 @@
+//! Compares tables key by key, like to validate a migration or replication.
+
+use std::collections::VecDeque;
+
+use super::{Pages, Table};
+use crate::{env::Env, ReadOptions, Result, ScanOptions};
+
+/// The differences between two tables, see [`diff_tables`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiffReport {
+    /// The number of keys with the same values in both tables.
+    pub matched: u64,
+    /// The keys that differ, in the order of keys.
+    pub diffs: Vec<KeyDiff>,
+    /// Whether the comparison stopped at the maximum number of differences,
+    /// so that the keys after the last difference are not compared.
+    pub truncated: bool,
+}
+
+impl DiffReport {
+    /// Returns true if no difference is found.
+    pub fn is_identical(&self) -> bool {
+        self.diffs.is_empty()
+    }
+}
+
+/// A key that differs between two tables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyDiff {
+    /// The key is only present in the first table.
+    OnlyInA(Vec<u8>),
+    /// The key is only present in the second table.
+    OnlyInB(Vec<u8>),
+    /// The key is present in both tables with different values.
+    ValueMismatch(Vec<u8>),
+}
+
+/// Compares the entries of two tables visible to the LSN key by key.
+///
+/// The tables are scanned side by side in the order of keys, so only a page
+/// of each table is held in memory at a time. If `max_diffs` is set, the
+/// comparison stops once that many differences are found, which saves walking
+/// the rest of tables that turn out to be wildly different.
+///
+/// Both tables are pinned during the comparison, see [`Table::pin`].
+pub async fn diff_tables<E: Env>(
+    a: &Table<E>,
+    b: &Table<E>,
+    lsn: u64,
+    max_diffs: Option<usize>,
+) -> Result<DiffReport> {
+    let options = ReadOptions {
+        max_lsn: lsn,
+        ..Default::default()
+    };
+    let (guard_a, guard_b) = (a.pin(), b.pin());
+    let mut a =
+        Cursor::new(guard_a.pages_with_read_options(options.clone(), ScanOptions::default()));
+    let mut b = Cursor::new(guard_b.pages_with_read_options(options, ScanOptions::default()));
+    let mut report = DiffReport::default();
+    loop {
+        if max_diffs.map_or(false, |max| report.diffs.len() >= max) {
+            report.truncated = a.peek().await?.is_some() || b.peek().await?.is_some();
+            break;
+        }
+        let diff = match (a.peek().await?, b.peek().await?) {
+            (None, None) => break,
+            (Some(_), None) => KeyDiff::OnlyInA(a.pop().0),
+            (None, Some(_)) => KeyDiff::OnlyInB(b.pop().0),
+            (Some((ka, _)), Some((kb, _))) if ka < kb => KeyDiff::OnlyInA(a.pop().0),
+            (Some((ka, _)), Some((kb, _))) if ka > kb => KeyDiff::OnlyInB(b.pop().0),
+            (Some(_), Some(_)) => {
+                let (key, va) = a.pop();
+                let (_, vb) = b.pop();
+                if va == vb {
+                    report.matched += 1;
+                    continue;
+                }
+                KeyDiff::ValueMismatch(key)
+            }
+        };
+        report.diffs.push(diff);
+    }
+    Ok(report)
+}
+
+/// Returns the entries of a scan one by one, with the entries of the current
+/// page copied out of it.
+struct Cursor<'a, 't, E: Env> {
+    pages: Pages<'a, 't, E>,
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a, 't, E: Env> Cursor<'a, 't, E> {
+    fn new(pages: Pages<'a, 't, E>) -> Self {
+        Self {
+            pages,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next entry without consuming it.
+    async fn peek(&mut self) -> Result<Option<&(Vec<u8>, Vec<u8>)>> {
+        while self.entries.is_empty() {
+            let Some(page) = self.pages.next().await? else {
+                return Ok(None);
+            };
+            self.entries
+                .extend(page.map(|(k, v)| (k.to_vec(), v.to_vec())));
+        }
+        Ok(self.entries.front())
+    }
+
+    /// Consumes the entry returned by the last [`Self::peek`].
+    fn pop(&mut self) -> (Vec<u8>, Vec<u8>) {
+        self.entries.pop_front().expect("peeked entry")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{
+        tests::{must_put, options},
+        *,
+    };
+
+    #[photonio::test]
+    async fn diff_tables() {
+        let path_a = tempdir().unwrap();
+        let a = Table::open(&path_a, options()).await.unwrap();
+        let path_b = tempdir().unwrap();
+        let b = Table::open(&path_b, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&a, i, 1).await;
+            must_put(&b, i, 1).await;
+        }
+        let report = crate::diff_tables(&a, &b, u64::MAX, None).await.unwrap();
+        assert!(report.is_identical());
+        assert_eq!(report.matched, N);
+
+        let buf = 1u64.to_be_bytes();
+        b.put(&buf, 2, b"other").await.unwrap();
+        must_put(&a, N, 2).await;
+        must_put(&b, N + 1, 2).await;
+        let key = |i: u64| i.to_be_bytes().to_vec();
+        let report = crate::diff_tables(&a, &b, u64::MAX, None).await.unwrap();
+        assert_eq!(report.matched, N - 1);
+        assert_eq!(
+            report.diffs,
+            [
+                KeyDiff::ValueMismatch(key(1)),
+                KeyDiff::OnlyInA(key(N)),
+                KeyDiff::OnlyInB(key(N + 1)),
+            ]
+        );
+        assert!(!report.truncated);
+
+        // Entries written after the LSN are not compared.
+        let report = crate::diff_tables(&a, &b, 1, None).await.unwrap();
+        assert!(report.is_identical());
+
+        let report = crate::diff_tables(&a, &b, u64::MAX, Some(1)).await.unwrap();
+        assert_eq!(report.diffs, [KeyDiff::ValueMismatch(key(1))]);
+        assert!(report.truncated);
+        a.close().await.unwrap();
+        b.close().await.unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+//! A portable format to exchange table data with other systems.
+//!
+//! The format is independent of the internal page format and is stable across
+//! releases. An exported stream consists of a header, a sequence of entries
+//! sorted by keys, and a footer. All integers are encoded in little-endian.
+//!
+//! The header:
+//!
+//! | Field           | Size     | Description                                 |
+//! |-----------------|----------|---------------------------------------------|
+//! | magic           | 8        | `b"PHOTONDB"`                               |
+//! | version         | 4        | The format version, which is 1 for now      |
+//! | comparator len  | 4        | The length of the comparator name           |
+//! | comparator      | variable | The name of the key order, `"bytewise"`     |
+//! | lsn             | 8        | The LSN that the entries are exported at    |
+//!
+//! Each entry:
+//!
+//! | Field     | Size     | Description                                  |
+//! |-----------|----------|----------------------------------------------|
+//! | key len   | 4        | The length of the key, must not be u32::MAX  |
+//! | key       | variable | The key                                      |
+//! | value len | 4        | The length of the value                      |
+//! | value     | variable | The value                                    |
+//!
+//! The footer:
+//!
+//! | Field       | Size | Description                                   |
+//! |-------------|------|-----------------------------------------------|
+//! | end         | 4    | `u32::MAX`, marks the end of entries          |
+//! | num entries | 8    | The number of entries to detect truncation    |
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{Error, Result};
+
+const MAGIC: &[u8; 8] = b"PHOTONDB";
+const FORMAT_VERSION: u32 = 1;
+/// Keys are compared bytewise.
+const COMPARATOR: &str = "bytewise";
+const END_OF_ENTRIES: u32 = u32::MAX;
+
+/// Writes entries in the export format.
+pub(super) struct ExportWriter<W> {
+    writer: W,
+    num_entries: u64,
+}
+
+impl<W: AsyncWrite + Unpin> ExportWriter<W> {
+    /// Creates a writer and writes the header with the LSN.
+    pub(super) async fn new(mut writer: W, lsn: u64) -> Result<Self> {
+        writer.write_all(MAGIC).await?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes()).await?;
+        writer
+            .write_all(&(COMPARATOR.len() as u32).to_le_bytes())
+            .await?;
+        writer.write_all(COMPARATOR.as_bytes()).await?;
+        writer.write_all(&lsn.to_le_bytes()).await?;
+        Ok(Self {
+            writer,
+            num_entries: 0,
+        })
+    }
+
+    /// Appends an entry. Entries must be added in the order of keys.
+    pub(super) async fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let key_len = u32::try_from(key.len())
+            .ok()
+            .filter(|&len| len != END_OF_ENTRIES)
+            .ok_or(Error::TooLargeSize)?;
+        let value_len = u32::try_from(value.len()).map_err(|_| Error::TooLargeSize)?;
+        self.writer.write_all(&key_len.to_le_bytes()).await?;
+        self.writer.write_all(key).await?;
+        self.writer.write_all(&value_len.to_le_bytes()).await?;
+        self.writer.write_all(value).await?;
+        self.num_entries += 1;
+        Ok(())
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    pub(super) fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Writes the footer and flushes the writer.
+    ///
+    /// Returns the number of entries written and the underlying writer.
+    pub(super) async fn finish(mut self) -> Result<(u64, W)> {
+        self.writer.write_all(&END_OF_ENTRIES.to_le_bytes()).await?;
+        self.writer
+            .write_all(&self.num_entries.to_le_bytes())
+            .await?;
+        self.writer.flush().await?;
+        Ok((self.num_entries, self.writer))
+    }
+}
+
+/// Reads entries in the export format.
+pub(super) struct ExportReader<R> {
+    reader: R,
+    lsn: u64,
+    num_entries: u64,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> ExportReader<R> {
+    /// Creates a reader and validates the header.
+    ///
+    /// Returns [`Error::Corrupted`] if the stream is not in the export format,
+    /// or [`Error::InvalidArgument`] if the format version or comparator is
+    /// not supported.
+    pub(super) async fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic).await?;
+        if &magic != MAGIC {
+            return Err(Error::Corrupted);
+        }
+        if read_u32(&mut reader).await? != FORMAT_VERSION {
+            return Err(Error::InvalidArgument);
+        }
+        let comparator = read_bytes(&mut reader).await?;
+        if comparator != COMPARATOR.as_bytes() {
+            return Err(Error::InvalidArgument);
+        }
+        let lsn = read_u64(&mut reader).await?;
+        Ok(Self {
+            reader,
+            lsn,
+            num_entries: 0,
+            done: false,
+        })
+    }
+
+    /// Returns the LSN that the entries are exported at.
+    pub(super) fn lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    /// Returns the next entry, or `None` at the end of the stream.
+    pub(super) async fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if self.done {
+            return Ok(None);
+        }
+        let key_len = read_u32(&mut self.reader).await?;
+        if key_len == END_OF_ENTRIES {
+            self.done = true;
+            if read_u64(&mut self.reader).await? != self.num_entries {
+                return Err(Error::Corrupted);
+            }
+            return Ok(None);
+        }
+        let mut key = vec![0; key_len as usize];
+        self.reader.read_exact(&mut key).await?;
+        let value = read_bytes(&mut self.reader).await?;
+        self.num_entries += 1;
+        Ok(Some((key, value)))
+    }
+}
+
+async fn read_u32<R: AsyncRead + Unpin>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf).await?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+async fn read_u64<R: AsyncRead + Unpin>(reader: &mut R) -> Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf).await?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+async fn read_bytes<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = read_u32(reader).await?;
+    let mut buf = vec![0; len as usize];
+    reader.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        tests::{must_get, must_put, options},
+        Table,
+    };
+
+    #[test]
+    fn export_format() {
+        block_on(async {
+            let mut writer = ExportWriter::new(Vec::new(), 7).await.unwrap();
+            writer.add(b"a", b"1").await.unwrap();
+            writer.add(b"", b"").await.unwrap();
+            let mut buf = writer.writer.clone();
+            assert_eq!(writer.finish().await.unwrap().0, 2);
+
+            // A footer that mismatches the number of entries.
+            buf.extend_from_slice(&END_OF_ENTRIES.to_le_bytes());
+            buf.extend_from_slice(&3u64.to_le_bytes());
+            let mut reader = ExportReader::new(buf.as_slice()).await.unwrap();
+            assert_eq!(reader.lsn(), 7);
+            assert!(reader.next().await.unwrap().is_some());
+            assert!(reader.next().await.unwrap().is_some());
+            assert!(matches!(reader.next().await, Err(Error::Corrupted)));
+
+            assert!(matches!(
+                ExportReader::new(&b"PHOTONDX"[..]).await,
+                Err(Error::Corrupted)
+            ));
+        });
+    }
+
+    #[photonio::test]
+    async fn export_and_import() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+            if i % 3 == 0 {
+                table.delete(&i.to_be_bytes(), 2).await.unwrap();
+            }
+        }
+        let mut buf = Vec::new();
+        let exported = table.export(&mut buf, 2).await.unwrap();
+        assert_eq!(exported, N - (N + 2) / 3);
+        table.close().await.unwrap();
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        let imported = table.import(buf.as_slice()).await.unwrap();
+        assert_eq!(imported, exported);
+        for i in 0..N {
+            let expect = if i % 3 == 0 { None } else { Some(i) };
+            must_get(&table, i, 2, expect).await;
+        }
+        table.close().await.unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! A lock file that prevents a table from being opened more than once at the
+//! same time.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    path::Path,
+};
+
+use crate::{Error, Result};
+
+const LOCK_FILE_NAME: &str = "LOCK";
+
+/// An exclusive lock on the directory of a table, which is released when
+/// dropped.
+///
+/// The lock is an advisory `flock` on the lock file, so the OS releases it when
+/// the owner exits, even if the process crashes. The lock file records the pid
+/// of the owner to tell who holds the lock.
+#[derive(Debug)]
+pub(super) struct LockFile {
+    #[allow(unused)]
+    file: File,
+}
+
+impl LockFile {
+    /// Acquires the lock of the directory, and creates the directory if it
+    /// doesn't exist.
+    ///
+    /// Returns [`Error::AlreadyLocked`] if the lock is held by others. If
+    /// `ignore_stale` is true and the recorded owner doesn't exist anymore,
+    /// which happens if the owner crashes after the lock is inherited by its
+    /// child processes, the lock file is taken over without the lock.
+    pub(super) fn acquire(dir: &Path, ignore_stale: bool) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)?;
+        if let Err(err) = try_lock(&file) {
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(err.into());
+            }
+            let pid = read_pid(&mut file);
+            let stale = pid.map_or(false, |pid| !process_exists(pid));
+            if !ignore_stale || !stale {
+                return Err(Error::AlreadyLocked { path, pid });
+            }
+            log::warn!("take over the stale lock {path:?} of process {pid:?}");
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(std::process::id().to_string().as_bytes())?;
+        file.sync_data()?;
+        Ok(Self { file })
+    }
+}
+
+/// Reads the pid recorded in the lock file, if any.
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if res == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn try_lock(_: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    // Signals to non-positive pids are sent to process groups.
+    let Ok(pid) = i32::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // The signal 0 only checks if the process exists.
+    let res = unsafe { libc::kill(pid, 0) };
+    res == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_exists(_: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{tests::options, *};
+
+    #[photonio::test]
+    #[cfg(unix)]
+    async fn lock_file() {
+        use ::std::{fs::File, os::unix::io::AsRawFd};
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        let pid = ::std::process::id();
+        assert!(matches!(
+            Table::open(&path, options()).await,
+            Err(Error::AlreadyLocked { pid: Some(p), .. }) if p == pid
+        ));
+        // The lock is released on close.
+        table.close().await.unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        drop(table);
+
+        // A lock held by others, whose recorded owner doesn't exist.
+        let lock_path = path.path().join("LOCK");
+        let file = File::open(&lock_path).unwrap();
+        assert_eq!(
+            unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) },
+            0
+        );
+        let stale_pid = i32::MAX as u32;
+        ::std::fs::write(&lock_path, stale_pid.to_string()).unwrap();
+        assert!(matches!(
+            Table::open(&path, options()).await,
+            Err(Error::AlreadyLocked { pid: Some(p), .. }) if p == stale_pid
+        ));
+        let opts = TableOptions {
+            ignore_stale_lock: true,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        table.close().await.unwrap();
+        drop(file);
+    }
+}
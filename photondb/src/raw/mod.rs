@@ -1,7 +1,18 @@
 //! Raw PhotonDB APIs that can can run with different environments.
 
 mod table;
-pub use table::{Guard, Pages, Table, TableStats};
+pub use table::{
+    DropPrefixStats, DuplicateKeyRule, Guard, Pages, PinnedBuffer, ReplicationRecord,
+    ReplicationStream, RewriteStats, ScanChunk, ScanFileStats, ScanSegment, SegmentedScan,
+    Snapshot, Table, TableStats, Txn,
+};
+
+mod diff;
+pub use diff::{diff_tables, DiffReport, KeyDiff};
+
+mod export;
+mod lock;
+mod multimap;
 
 #[cfg(test)]
 mod tree_test {
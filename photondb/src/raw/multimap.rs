@@ -0,0 +1,114 @@
+//! A key encoding that stores multiple values under the same key.
+//!
+//! Each value of a key is stored as an entry with a composite key and an empty
+//! value. The composite key consists of the escaped key, a terminator, and the
+//! value:
+//!
+//! | Field      | Size     | Description                                   |
+//! |------------|----------|-----------------------------------------------|
+//! | key        | variable | The key with `0x00` escaped as `0x00 0xFF`    |
+//! | terminator | 2        | `0x00 0x01`                                   |
+//! | value      | variable | The value                                     |
+//!
+//! The escaped key with the terminator is a prefix of the composite keys of
+//! exactly one key, so all values of a key can be read with a prefix scan, in
+//! the bytewise order of values. The encoding also preserves the order of keys.
+
+const ESCAPE: u8 = 0x00;
+const ESCAPED: u8 = 0xFF;
+const TERMINATOR: [u8; 2] = [0x00, 0x01];
+
+/// Returns the prefix shared by the composite keys of the key.
+pub(super) fn encode_prefix(key: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(key.len() + TERMINATOR.len());
+    for &b in key {
+        buf.push(b);
+        if b == ESCAPE {
+            buf.push(ESCAPED);
+        }
+    }
+    buf.extend_from_slice(&TERMINATOR);
+    buf
+}
+
+/// Returns the composite key of the key-value pair.
+pub(super) fn encode_key(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut buf = encode_prefix(key);
+    buf.extend_from_slice(value);
+    buf
+}
+
+/// Returns the value of the composite key if it starts with the prefix.
+pub(super) fn decode_value<'a>(prefix: &[u8], composite: &'a [u8]) -> Option<&'a [u8]> {
+    composite.strip_prefix(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{tests::options, Table, TableOptions};
+
+    #[test]
+    fn multimap_encoding() {
+        let keys: [&[u8]; 6] = [b"", b"\x00", b"\x00\x00", b"\x00\x01", b"a", b"a\x00"];
+        let values: [&[u8]; 3] = [b"", b"\x00", b"x"];
+        let mut composites = Vec::new();
+        for key in keys {
+            for value in values {
+                let composite = encode_key(key, value);
+                for other in keys {
+                    let decoded = decode_value(&encode_prefix(other), &composite);
+                    if other == key {
+                        assert_eq!(decoded, Some(value));
+                    } else {
+                        assert_eq!(decoded, None);
+                    }
+                }
+                composites.push(composite);
+            }
+        }
+        // Composite keys are sorted by keys and then values.
+        let mut sorted = composites.clone();
+        sorted.sort();
+        assert_eq!(sorted, composites);
+    }
+
+    #[photonio::test]
+    async fn multimap() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            page_size: 4 << 10,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 6;
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            for v in (0..4u8).rev() {
+                table.put_multi(&key, &[v], 1).await.unwrap();
+            }
+            table.put_multi(&key, &[0], 2).await.unwrap();
+            table.delete_multi(&key, &[1], 3).await.unwrap();
+        }
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(
+                table.get_multi(&key, 1).await.unwrap(),
+                vec![vec![0], vec![1], vec![2], vec![3]]
+            );
+            assert_eq!(
+                table.get_multi(&key, 3).await.unwrap(),
+                vec![vec![0], vec![2], vec![3]]
+            );
+            assert!(table.get_multi(&key, 0).await.unwrap().is_empty());
+        }
+        assert!(table
+            .get_multi(&N.to_be_bytes(), 3)
+            .await
+            .unwrap()
+            .is_empty());
+        table.close().await.unwrap();
+    }
+}
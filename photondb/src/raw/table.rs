@@ -1,13 +1,39 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    ops::{Deref, Range, RangeBounds},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::io::{AsyncRead, AsyncWrite};
 
+use super::{
+    export::{ExportReader, ExportWriter},
+    lock::LockFile,
+    multimap,
+};
 use crate::{
-    env::Env,
-    page::{Key, Value},
-    page_store::{FlushOptions, PageStore, StoreStats},
+    env::{Env, IoPriority, SequentialWriter, SequentialWriterExt},
+    page::{Key, MergingIterBuilder, Value},
+    page_store::{
+        BackgroundStats, CacheEntryGuard, CandidateInfo, FileStat, FlushOptions, PageStore,
+        RecoveryProgress, RecoveryReport, StoreStats, VerifyReport,
+    },
     tree::*,
-    Result,
+    Error, Result,
 };
 
+/// How often the inner pages deferred by [`Options::inner_consolidation`] are
+/// consolidated.
+const DEFERRED_CONSOLIDATION_INTERVAL: Duration = Duration::from_millis(10);
+/// The default size of a batch read by a [`ReplicationStream`].
+const REPLICATION_BATCH_BYTES: usize = 1 << 20;
+
 /// A reference to a latch-free, log-structured table that stores sorted
 /// key-value entries.
 ///
@@ -16,25 +42,225 @@ use crate::{
 pub struct Table<E: Env> {
     tree: Arc<Tree>,
     store: Arc<PageStore<E>>,
+    /// The lock of the path, which is not held by read-only views.
+    lock: Option<Arc<LockFile>>,
 }
 
 impl<E: Env> Table<E> {
     /// Opens a table in the path with the given options.
+    ///
+    /// Whether the table is created depends on [`Options::open_mode`].
+    ///
+    /// The path is locked until the table is closed or dropped, and opening a
+    /// locked path fails with [`Error::AlreadyLocked`], even in the same
+    /// process.
     pub async fn open<P: AsRef<Path>>(env: E, path: P, options: Options) -> Result<Self> {
+        Self::open_with_progress(env, path, options, |_| {}).await
+    }
+
+    /// Opens a table in the path with the given options, and reports the
+    /// progress of recovery to the callback.
+    ///
+    /// The callback is invoked at the start of each [`RecoveryPhase`] and
+    /// whenever more records are replayed in the phase. It runs inline with
+    /// the recovery, so it should return quickly.
+    pub async fn open_with_progress<P, F>(
+        env: E,
+        path: P,
+        options: Options,
+        mut progress: F,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        F: FnMut(RecoveryProgress) + Send,
+    {
+        // Fails early without creating the directory for the lock.
+        if options.open_mode == OpenMode::OpenExisting
+            && !PageStore::exists(&env, path.as_ref()).await
+        {
+            return Err(Error::NotFound);
+        }
+        // The table is checked under the lock, so that it isn't created or
+        // removed by others after the check.
+        let lock = LockFile::acquire(path.as_ref(), options.ignore_stale_lock)?;
+        let exists = PageStore::exists(&env, path.as_ref()).await;
+        match options.open_mode {
+            OpenMode::CreateNew if exists => return Err(Error::AlreadyExists),
+            OpenMode::OpenExisting if !exists => return Err(Error::NotFound),
+            _ => {}
+        }
         let tree = Arc::new(Tree::new(options.clone()));
-        let store = PageStore::open(env, path, options.page_store).await?;
+        let mut store = PageStore::open(env, path, options.page_store, &mut progress).await?;
+        tree.set_high_water_lsn(store.max_lsn());
+        let txn = tree.begin(store.guard());
+        txn.init().await?;
+        if tree.defers_inner_consolidation() {
+            let tree = tree.clone();
+            store.spawn_periodic_job(DEFERRED_CONSOLIDATION_INTERVAL, move |guard| {
+                let tree = tree.clone();
+                async move {
+                    tree.begin(guard).consolidate_deferred_pages().await;
+                }
+            });
+        }
+        Ok(Self {
+            tree,
+            store: Arc::new(store),
+            lock: Some(Arc::new(lock)),
+        })
+    }
+
+    /// Opens a read-only view of the table in the path as of the page file
+    /// `generation`, for debugging and forensics.
+    ///
+    /// Each flush writes a new page file, whose id is the generation of the
+    /// table after the flush, like the [`CandidateInfo::file_id`] of the
+    /// files to compact. The view replays the manifest up to the first
+    /// change that adds page files newer than the generation, which gives the
+    /// state before the later flushes and compactions. This only works if the
+    /// page files of the generation have not been reclaimed yet.
+    ///
+    /// The view is sealed permanently, so writes are rejected with
+    /// [`Error::Sealed`], even after [`Table::unseal`]. No background jobs
+    /// are started and nothing is written to the path, so the table can be
+    /// opened normally later. The path is not locked, so the view can be
+    /// opened while the table is open. [`Options::open_mode`] is ignored.
+    ///
+    /// Returns [`Error::NotFound`] if the table does not exist, or
+    /// [`Error::InvalidArgument`] if the generation is not recorded in the
+    /// manifest, which keeps the changes since it rolled last time.
+    pub async fn open_at_generation<P: AsRef<Path>>(
+        env: E,
+        path: P,
+        generation: u32,
+        options: Options,
+    ) -> Result<Self> {
+        if !PageStore::exists(&env, path.as_ref()).await {
+            return Err(Error::NotFound);
+        }
+        let mut tree = Tree::new(options.clone());
+        tree.set_read_only();
+        let tree = Arc::new(tree);
+        let store =
+            PageStore::open_at_generation(env, path, options.page_store, generation, &mut |_| {})
+                .await?;
         let txn = tree.begin(store.guard());
         txn.init().await?;
         Ok(Self {
             tree,
             store: Arc::new(store),
+            lock: None,
         })
     }
 
+    /// Rewrites the table in `src` to a new table in `dst` with the options.
+    ///
+    /// This migrates a table to settings that are fixed for its files, like
+    /// [`Options::page_size`], or the compression and checksum of page files.
+    /// Every version retained by the source is copied with its LSN, including
+    /// tombstones and the timestamps of [`Table::put_timestamped`], so reads at
+    /// or after the safe LSN of the source see the same entries in the new
+    /// table. The versions are copied leaf page by leaf page, so the memory
+    /// usage is bounded by the page size, and `progress` is invoked after each
+    /// leaf page is copied.
+    ///
+    /// The source is opened with [`OpenMode::OpenExisting`] and the destination
+    /// is created with [`OpenMode::CreateNew`], regardless of
+    /// [`Options::open_mode`]. Both tables are closed before this returns.
+    ///
+    /// Returns [`Error::RewriteMismatch`] if the numbers of live keys in the
+    /// tables differ after the rewrite. If the rewrite fails after the
+    /// destination is created, the destination is removed.
+    pub async fn rewrite_with_options<P, Q, F>(
+        env: E,
+        src: P,
+        dst: Q,
+        options: Options,
+        mut progress: F,
+    ) -> Result<RewriteStats>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(&RewriteStats),
+    {
+        let src_options = Options {
+            open_mode: OpenMode::OpenExisting,
+            ..options.clone()
+        };
+        let src = Self::open(env.clone(), src, src_options).await?;
+        let dst_options = Options {
+            open_mode: OpenMode::CreateNew,
+            ..options
+        };
+        let dst_path = dst.as_ref().to_path_buf();
+        let dst = match Self::open(env.clone(), &dst_path, dst_options).await {
+            Ok(dst) => dst,
+            Err(err) => {
+                let _ = src.close().await;
+                return Err(err);
+            }
+        };
+        let result = src.rewrite_to(&dst, &mut progress).await;
+        let _ = src.close().await;
+        let _ = dst.close().await;
+        if result.is_err() {
+            // The destination is created by this rewrite, so it's safe to remove.
+            let _ = env.remove_dir_all(&dst_path).await;
+        }
+        result
+    }
+
+    async fn rewrite_to<F>(&self, dst: &Self, progress: &mut F) -> Result<RewriteStats>
+    where
+        F: FnMut(&RewriteStats),
+    {
+        let mut stats = RewriteStats::default();
+        let txn = self.begin();
+        let mut iter = TreeIter::new(&txn, ReadOptions::default(), ScanOptions::default());
+        while let Some(versions) = iter.next_versions().await? {
+            for version in &versions {
+                let (key, lsn) = (&version.key, version.lsn);
+                match (&version.value, version.ts) {
+                    (Some(value), Some(ts)) => dst.put_timestamped(key, lsn, value, ts).await?,
+                    (Some(value), None) => dst.put(key, lsn, value).await?,
+                    (None, _) => dst.delete(key, lsn).await?,
+                }
+                stats.bytes += (key.len() + version.value.as_ref().map_or(0, Vec::len)) as u64;
+            }
+            stats.pages += 1;
+            stats.versions += versions.len() as u64;
+            progress(&stats);
+        }
+        dst.set_safe_lsn(self.safe_lsn());
+
+        let keys = self.count_range(&[], None, u64::MAX).await?;
+        let dst_keys = dst.count_range(&[], None, u64::MAX).await?;
+        if dst_keys != keys {
+            log::warn!("rewrite copied {keys} live keys but got {dst_keys}");
+            return Err(Error::RewriteMismatch {
+                src_keys: keys,
+                dst_keys,
+            });
+        }
+        stats.keys = keys;
+        Ok(stats)
+    }
+
     /// Closes the table if this is the only reference to it.
     ///
+    /// The write buffers are flushed to page files before the table is
+    /// closed, and the files are synced to disk like any other flush, so all
+    /// acknowledged writes are durable once this returns without an explicit
+    /// [`Table::flush`]. Setting
+    /// [`PageStoreOptions::avoid_flush_during_shutdown`] skips the flush to
+    /// close faster, and the writes that have not been flushed are lost
+    /// then.
+    ///
     /// If this is not the only reference, returns [`Result::Err`] with this
     /// reference.
+    ///
+    /// [`PageStoreOptions::avoid_flush_during_shutdown`]:
+    /// crate::PageStoreOptions::avoid_flush_during_shutdown
     pub async fn close(self) -> Result<(), Self> {
         match Arc::try_unwrap(self.store) {
             Ok(store) => {
@@ -44,20 +270,75 @@ impl<E: Env> Table<E> {
             Err(store) => Err(Self {
                 tree: self.tree,
                 store,
+                lock: self.lock,
             }),
         }
     }
 
+    /// Stops the background jobs of the table and waits for them to exit.
+    ///
+    /// Unlike [`Table::close`], this works even if there are other references
+    /// to the table, which makes it a barrier for the background jobs before
+    /// the table is dropped. The write buffers are flushed before the jobs
+    /// exit, unless [`PageStoreOptions::avoid_flush_during_shutdown`] is set.
+    ///
+    /// The table should not be used afterwards, since nothing flushes the
+    /// writes anymore. Dropping a table without [`Table::close`] or this logs
+    /// a warning, because the background jobs may outlive the table.
+    ///
+    /// [`PageStoreOptions::avoid_flush_during_shutdown`]:
+    /// crate::PageStoreOptions::avoid_flush_during_shutdown
+    pub async fn shutdown(&self) {
+        self.store.shutdown().await;
+    }
+
     /// Begins a tree transaction.
     fn begin(&self) -> TreeTxn<'_, E> {
         self.tree.begin(self.store.guard())
     }
 
+    /// Begins a tree transaction that reads page files with the priority.
+    fn begin_with_priority(&self, priority: IoPriority) -> TreeTxn<'_, E> {
+        let mut guard = self.store.guard();
+        guard.set_io_priority(priority);
+        self.tree.begin(guard)
+    }
+
+    /// Begins a [`Txn`] that spans multiple operations on the table.
+    pub fn begin_txn(&self) -> Txn<'_, E> {
+        Txn::new(self)
+    }
+
     /// Returns a [`Guard`] that pins the table for user operations.
     pub fn pin(&self) -> Guard<'_, E> {
         Guard::new(self)
     }
 
+    /// Takes a snapshot of the table to scan with the read options.
+    ///
+    /// A scan of the table walks the live pages, and restarts from where it
+    /// is if a page splits under it. Reading at a fixed LSN hides new entries,
+    /// but doesn't stop such changes. A snapshot instead copies the page table
+    /// when it is taken, and keeps the pages as of then readable until it is
+    /// dropped, so its scans never restart and see the same entries no matter
+    /// what writes, consolidations, and compactions happen meanwhile.
+    ///
+    /// The copy takes memory in proportion to the number of pages, and holding
+    /// the snapshot delays the release of the write buffers and the page files
+    /// that are replaced after it is taken. A warning is logged if a snapshot
+    /// is held longer than [`Options::snapshot_warn_lifetime`].
+    pub async fn scan_snapshot(&self, options: ReadOptions) -> Snapshot<'_, E> {
+        let mut guard = self.store.guard();
+        guard.set_io_priority(options.priority);
+        Snapshot {
+            table: self,
+            txn: self.tree.begin_snapshot(guard).await,
+            options,
+            taken_at: self.store.env().now(),
+            warned: AtomicBool::new(false),
+        }
+    }
+
     /// Gets the value corresponding to the key.
     pub async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>> {
         let key = Key::new(key, lsn);
@@ -66,7 +347,183 @@ impl<E: Env> Table<E> {
         Ok(value.map(|v| v.to_vec()))
     }
 
+    /// Gets the value corresponding to the key with the given options.
+    ///
+    /// The value is read at [`ReadOptions::max_lsn`], with the page files read
+    /// at [`ReadOptions::priority`]. Other options only apply to scans.
+    pub async fn get_with_options(
+        &self,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = Key::new(key, options.max_lsn);
+        let txn = self.begin_with_priority(options.priority);
+        let value = txn.get(key).await?;
+        Ok(value.map(|v| v.to_vec()))
+    }
+
+    /// Gets the value corresponding to the key as an owned [`PinnedBuffer`].
+    ///
+    /// Unlike the values borrowed from a [`Guard`], the buffer can be moved
+    /// across tasks or handed to foreign code. If the value is in a cached
+    /// page, the buffer refers to the page without copying the value, which
+    /// keeps the page in memory until the buffer is dropped. Otherwise, like
+    /// values in write buffers or decoded by [`Options::value_codec`], the
+    /// value is copied.
+    pub async fn get_owned_pinned(&self, key: &[u8], lsn: u64) -> Result<Option<PinnedBuffer>> {
+        let key = Key::new(key, lsn);
+        let txn = self.begin();
+        let Some(value) = txn.get(key).await? else {
+            return Ok(None);
+        };
+        // Safety: nothing else read by the transaction is used after this.
+        let buffer = match unsafe { txn.take_cached_page(value) } {
+            Some((page, range)) => Pinned::Cached { page, range },
+            None => Pinned::Owned(value.to_vec()),
+        };
+        Ok(Some(PinnedBuffer(buffer)))
+    }
+
+    /// Gets the value corresponding to the key, and whether the key is
+    /// present, deleted, or absent at the LSN.
+    ///
+    /// Unlike [`Table::get`], this tells a key whose latest visible version is
+    /// a tombstone apart from a key that has no visible versions at all.
+    pub async fn get_with_status(
+        &self,
+        key: &[u8],
+        lsn: u64,
+    ) -> Result<(Option<Vec<u8>>, KeyStatus)> {
+        let key = Key::new(key, lsn);
+        let txn = self.begin();
+        let (value, status) = txn.get_with_status(key).await?;
+        Ok((value.map(|v| v.to_vec()), status))
+    }
+
+    /// Gets the value corresponding to the key, and the LSN that the value is
+    /// written at.
+    ///
+    /// The LSN tells if the key has been written since the value is read,
+    /// which is useful for optimistic concurrency control.
+    pub async fn get_versioned(&self, key: &[u8], lsn: u64) -> Result<Option<(Vec<u8>, u64)>> {
+        let key = Key::new(key, lsn);
+        let txn = self.begin();
+        let value = txn.get_versioned(key).await?;
+        Ok(value.map(|(v, lsn)| (v.to_vec(), lsn)))
+    }
+
+    /// Returns true if the key has a value at the LSN.
+    ///
+    /// This is cheaper than [`Table::get`] for existence checks, since the
+    /// value is not read or copied. A key whose latest visible version is a
+    /// tombstone doesn't exist.
+    pub async fn contains(&self, key: &[u8], lsn: u64) -> Result<bool> {
+        let key = Key::new(key, lsn);
+        let txn = self.begin();
+        Ok(txn.contains(key).await?)
+    }
+
+    /// Gets the versions of the key whose LSNs are in the range, in
+    /// descending order of LSNs.
+    ///
+    /// Each version is returned with its LSN and its value, or `None` if it is
+    /// a tombstone. Versions that are not visible to the [`Table::safe_lsn`]
+    /// anymore may have been dropped by consolidation, so they are not
+    /// returned.
+    pub async fn get_history<R: RangeBounds<u64>>(
+        &self,
+        key: &[u8],
+        range: R,
+    ) -> Result<Vec<(u64, Option<Vec<u8>>)>> {
+        let txn = self.begin();
+        let history = txn.get_history(key, range).await?;
+        Ok(history
+            .into_iter()
+            .map(|(lsn, value)| (lsn, value.map(|v| v.to_vec())))
+            .collect())
+    }
+
+    /// Counts the keys in `[start, end)` that have a value at the LSN.
+    ///
+    /// If `end` is `None`, the range is unbounded. The LSN should not be
+    /// smaller than [`Table::safe_lsn`]. See [`Table::scan_filter`] for how
+    /// empty and inverted ranges are handled.
+    ///
+    /// Leaf pages that are consolidated into a single base page without
+    /// tombstones or versions invisible to the safe LSN are counted without
+    /// decoding their entries, which makes the count close to O(pages) for
+    /// such ranges. Other pages, like those with outstanding deltas or
+    /// tombstones, fall back to a full count of their entries. The count is
+    /// exact in both cases, but it is only cheap when no uncompacted tombstones
+    /// or delta pages intersect the range.
+    pub async fn count_range(&self, start: &[u8], end: Option<&[u8]>, lsn: u64) -> Result<u64> {
+        if is_empty_range(start, end)? {
+            return Ok(0);
+        }
+        let txn = self.begin();
+        let options = ReadOptions {
+            max_lsn: lsn,
+            ..Default::default()
+        };
+        let iter = TreeIter::new(&txn, options, ScanOptions::default()).start_at(start);
+        Ok(iter.count(start, end).await?)
+    }
+
+    /// Counts the live keys of the table, and resets
+    /// [`TableStats::approx_key_count`] to the count.
+    ///
+    /// The approximate count is maintained in memory only, so it starts from
+    /// zero when the table is opened. This can be used to seed it.
+    pub async fn recount_keys(&self) -> Result<u64> {
+        let count = self.count_range(&[], None, u64::MAX).await?;
+        self.tree.set_approx_key_count(count);
+        Ok(count)
+    }
+
+    /// Loads the leaf pages that contain the keys into the page cache, so that
+    /// later reads of the keys don't need to read page files.
+    ///
+    /// Warming stops once the cache is full, since loading more pages would
+    /// evict the warmed ones. Pages that are already cached or still in write
+    /// buffers are skipped.
+    ///
+    /// Returns the number of pages loaded from page files.
+    pub async fn warmup<'k, I>(&self, keys: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = &'k [u8]>,
+    {
+        let txn = self.begin();
+        Ok(txn.warmup(keys).await?)
+    }
+
+    /// Loads the leaf pages of keys in `[start, end)` into the page cache.
+    ///
+    /// If `end` is `None`, the range is unbounded. See [`Table::warmup`] for
+    /// details, and [`Table::scan_filter`] for how empty and inverted ranges
+    /// are handled.
+    pub async fn warmup_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<u64> {
+        if is_empty_range(start, end)? {
+            return Ok(0);
+        }
+        let txn = self.begin();
+        let iter =
+            TreeIter::new(&txn, ReadOptions::default(), ScanOptions::default()).start_at(start);
+        Ok(iter.warmup(end).await?)
+    }
+
     /// Puts a key-value entry to the table.
+    ///
+    /// Any byte string is a valid key, including the empty key, which sorts
+    /// before all other keys.
+    ///
+    /// Entries of the same key must be written with increasing LSNs. If this
+    /// races with another write of the key with the same LSN, whichever is
+    /// applied first wins, and the other one returns [`Error::LsnConflict`]
+    /// without being applied. Debug builds also verify that the LSN is larger
+    /// than the latest LSN of the key, and log and reject the write with the
+    /// same error otherwise.
+    ///
+    /// [`Error::LsnConflict`]: crate::Error::LsnConflict
     pub async fn put(&self, key: &[u8], lsn: u64, value: &[u8]) -> Result<()> {
         let key = Key::new(key, lsn);
         let value = Value::Put(value);
@@ -75,6 +532,69 @@ impl<E: Env> Table<E> {
         Ok(())
     }
 
+    /// Puts a key-value entry to the table with the given options.
+    pub async fn put_with_options(
+        &self,
+        key: &[u8],
+        lsn: u64,
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> Result<()> {
+        let key = Key::new(key, lsn);
+        let value = Value::Put(value);
+        let txn = self.begin_with_priority(options.priority);
+        txn.write_with_options(key, value, options).await?;
+        Ok(())
+    }
+
+    /// Puts a key-value entry with a logical timestamp to the table.
+    ///
+    /// The timestamp resolves conflicts between writes to the same key in a
+    /// last-writer-wins fashion, which allows the table to serve as a store of
+    /// replicated registers. It is independent of the LSN, which still decides
+    /// which versions are visible to a read:
+    ///
+    /// - Among the consecutive timestamped versions of a key that are visible
+    ///   to a read, the one with the largest timestamp wins regardless of their
+    ///   LSNs. Ties go to the one with the larger LSN, so timestamps should be
+    ///   unique across replicas to converge, e.g. by reserving low bits for the
+    ///   replica id.
+    /// - A version written by [`Table::put`] or [`Table::delete`] shadows all
+    ///   older versions, including timestamped ones, and a timestamped version
+    ///   written after it doesn't compete with the versions it shadows.
+    ///
+    /// Consolidation keeps only the winner of the versions visible to the safe
+    /// LSN, so reads at or after [`Table::safe_lsn`] resolve to the same value
+    /// before and after it. [`Table::get_versioned`] returns the LSN of the
+    /// winner, which may be smaller than the LSN of the latest version.
+    ///
+    /// Timestamped values are always stored inline and are never transformed
+    /// by [`Options::value_codec`].
+    pub async fn put_timestamped(&self, key: &[u8], lsn: u64, value: &[u8], ts: u64) -> Result<()> {
+        let key = Key::new(key, lsn);
+        let value = Value::PutTimestamped { bytes: value, ts };
+        let txn = self.begin();
+        txn.write(key, value).await?;
+        Ok(())
+    }
+
+    /// Puts a key-value entry to the table, and returns the previous value of
+    /// the key.
+    ///
+    /// The previous value is the one visible to `lsn - 1` right before the
+    /// entry is written, which is read along with the write.
+    pub async fn put_returning_old(
+        &self,
+        key: &[u8],
+        lsn: u64,
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        let key = Key::new(key, lsn);
+        let value = Value::Put(value);
+        let txn = self.begin();
+        Ok(txn.write_returning_old(key, value).await?)
+    }
+
     /// Deletes the entry corresponding to the key from the table.
     pub async fn delete(&self, key: &[u8], lsn: u64) -> Result<()> {
         let key = Key::new(key, lsn);
@@ -84,14 +604,232 @@ impl<E: Env> Table<E> {
         Ok(())
     }
 
+    /// Gets the latest value corresponding to the key.
+    ///
+    /// This is the same as [`Table::get`] with an LSN that sees all writes.
+    pub async fn get_simple(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get(key, u64::MAX).await
+    }
+
+    /// Puts a key-value entry to the table with an LSN allocated by the
+    /// table.
+    ///
+    /// Returns [`Error::InvalidArgument`] if [`Options::auto_lsn`] is not
+    /// enabled.
+    pub async fn put_simple(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let lsn = self.next_lsn()?;
+        self.put(key, lsn, value).await
+    }
+
+    /// Deletes the entry corresponding to the key from the table with an LSN
+    /// allocated by the table.
+    ///
+    /// Returns [`Error::InvalidArgument`] if [`Options::auto_lsn`] is not
+    /// enabled.
+    pub async fn delete_simple(&self, key: &[u8]) -> Result<()> {
+        let lsn = self.next_lsn()?;
+        self.delete(key, lsn).await
+    }
+
+    /// Allocates an LSN for writes without LSNs.
+    fn next_lsn(&self) -> Result<u64> {
+        if !self.tree.is_auto_lsn() {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(self.store.next_lsn())
+    }
+
+    /// Adds a value to the key in the multimap stored in the table.
+    ///
+    /// Unlike [`Table::put`], this keeps the other values of the key. Adding a
+    /// value that the key already has is a no-op. Each value is stored as an
+    /// individual entry, whose key is derived from both the key and the value,
+    /// so multimap entries must not share a table with ordinary entries.
+    pub async fn put_multi(&self, key: &[u8], value: &[u8], lsn: u64) -> Result<()> {
+        let key = multimap::encode_key(key, value);
+        self.put(&key, lsn, &[]).await
+    }
+
+    /// Gets all values of the key in the multimap stored in the table, in the
+    /// bytewise order of values.
+    pub async fn get_multi(&self, key: &[u8], lsn: u64) -> Result<Vec<Vec<u8>>> {
+        let prefix = multimap::encode_prefix(key);
+        let txn = self.begin();
+        let options = ReadOptions {
+            max_lsn: lsn,
+            ..Default::default()
+        };
+        let mut iter = TreeIter::new(&txn, options, ScanOptions::default()).start_at(&prefix);
+        let mut values = Vec::new();
+        while let Some(page) = iter.next_page().await? {
+            for (k, _) in page {
+                let Some(value) = multimap::decode_value(&prefix, k) else {
+                    return Ok(values);
+                };
+                values.push(value.to_vec());
+            }
+        }
+        Ok(values)
+    }
+
+    /// Removes a value from the key in the multimap stored in the table.
+    ///
+    /// The other values of the key are kept.
+    pub async fn delete_multi(&self, key: &[u8], value: &[u8], lsn: u64) -> Result<()> {
+        let key = multimap::encode_key(key, value);
+        self.delete(&key, lsn).await
+    }
+
+    /// Seals the table for read-only serving.
+    ///
+    /// Subsequent writes are rejected with [`Error::Sealed`], and all pages
+    /// are consolidated so that reads don't need to walk through delta pages.
+    /// Writes that are already in progress when the table is sealed may still
+    /// complete.
+    pub async fn seal(&self) -> Result<()> {
+        self.tree.set_sealed(true);
+        let txn = self.begin();
+        txn.consolidate_all().await?;
+        Ok(())
+    }
+
+    /// Consolidates all pages in the table, so that reads don't need to walk
+    /// through delta pages.
+    ///
+    /// Concurrent writes are allowed. Pages that change during the
+    /// consolidation are retried, but deltas installed after a page is
+    /// consolidated are kept.
+    ///
+    /// Returns [`Error::Sealed`] if the table is opened read-only.
+    pub async fn consolidate_all(&self) -> Result<ConsolidateStats> {
+        let txn = self.begin();
+        Ok(txn.consolidate_all().await?)
+    }
+
+    /// Estimates the work of consolidating the pages that overlap with
+    /// `[start, end)` the way [`Table::consolidate_all`] does, without doing
+    /// it.
+    ///
+    /// This reads the pages that would be consolidated and sizes the pages
+    /// that would be built, so that the consolidation can be scheduled for a
+    /// convenient time if it turns out to be expensive. The sizes are those of
+    /// uncompressed pages. Chains longer than [`Options::max_merge_chain`]
+    /// are estimated by their first segments.
+    ///
+    /// [`Options::max_merge_chain`]: crate::TableOptions::max_merge_chain
+    pub async fn plan_compaction(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+    ) -> Result<CompactionPlan> {
+        if is_empty_range(start, end)? {
+            return Ok(CompactionPlan::default());
+        }
+        let txn = self.begin();
+        Ok(txn.plan_consolidation(start, end).await?)
+    }
+
+    /// Unseals the table so that it accepts writes again.
+    pub fn unseal(&self) {
+        self.tree.set_sealed(false);
+    }
+
+    /// Returns true if the table is sealed.
+    pub fn is_sealed(&self) -> bool {
+        self.tree.is_sealed()
+    }
+
+    /// Pauses the background jobs of the table, and waits for the work in
+    /// progress to finish.
+    ///
+    /// Once this returns, no flush, compaction, or deletion of obsolete files
+    /// happens until [`Table::resume_background`], so the files of the table
+    /// stay as they are, like for a backup taken by external tools. Reads
+    /// work as usual. Writes fill the write buffers and stall once they are
+    /// full, or are rejected if [`Options::reject_writes_while_paused`] is
+    /// set, and flushes that wait for the buffers to be written wait for the
+    /// resume as well. Closing or shutting down the table still flushes the
+    /// write buffers.
+    pub async fn pause_background(&self) {
+        self.tree.set_paused(true);
+        self.store.pause_background().await;
+    }
+
+    /// Resumes the background jobs paused by [`Table::pause_background`].
+    pub fn resume_background(&self) {
+        self.store.resume_background();
+        self.tree.set_paused(false);
+    }
+
+    /// Returns true if the background jobs are paused.
+    pub fn is_background_paused(&self) -> bool {
+        self.store.is_background_paused()
+    }
+
     /// Returns the statistics of the table.
     pub fn stats(&self) -> TableStats {
         TableStats {
             tree: self.tree.stats(),
             store: self.store.stats(),
+            approx_key_count: self.tree.approx_key_count(),
+            background_paused: self.is_background_paused(),
+        }
+    }
+
+    /// Returns the number of reads that have succeeded, as counted in
+    /// [`TreeStats::success`].
+    ///
+    /// This reads a single counter instead of building the whole
+    /// [`TableStats`], so it's cheap enough to be polled in tight monitoring
+    /// loops.
+    pub fn read_count(&self) -> u64 {
+        self.tree.read_count()
+    }
+
+    /// Returns the number of writes that have succeeded, as counted in
+    /// [`TreeStats::success`].
+    pub fn write_count(&self) -> u64 {
+        self.tree.write_count()
+    }
+
+    /// Returns the number of lookups that hit the page cache, as counted in
+    /// [`StoreStats::page_cache`].
+    ///
+    /// The counters of the cache shards are summed without locking them.
+    pub fn cache_hit_count(&self) -> u64 {
+        self.store.cache_hits()
+    }
+
+    /// Returns the durations of the background operations since the table was
+    /// opened.
+    ///
+    /// The durations are recorded with atomics as the operations finish, so
+    /// they can be sampled periodically to correlate foreground latency spikes
+    /// with slow background operations.
+    pub fn background_stats(&self) -> BackgroundStats {
+        BackgroundStats {
+            consolidation: self.tree.consolidation_latency(),
+            ..self.store.background_stats()
         }
     }
 
+    /// Returns what the recovery found when the table was opened, like the
+    /// orphan page files left by crashes.
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        self.store.recovery_report()
+    }
+
+    /// Returns the hottest leaf pages with their approximate access counts,
+    /// sorted by the counts in descending order.
+    ///
+    /// The counts are sampled from reads according to
+    /// [`Options::access_sampling_rate`], and are reset every
+    /// [`Options::access_sampling_reset_interval`]. Returns nothing if the
+    /// sampling is disabled.
+    pub fn hot_pages(&self) -> Vec<(u64, u64)> {
+        self.tree.hot_pages()
+    }
+
     /// Returns the minimal LSN that the table can safely read with.
     ///
     /// The table guarantees that entries visible to the returned LSN are
@@ -109,6 +847,60 @@ impl<E: Env> Table<E> {
         self.tree.set_safe_lsn(lsn);
     }
 
+    /// Returns the largest LSN that the writes up to it have been flushed to
+    /// page files.
+    ///
+    /// After the table is reopened, it starts from the largest LSN recorded in
+    /// the manifest.
+    pub fn durable_lsn(&self) -> u64 {
+        self.store.durable_lsn()
+    }
+
+    /// Advances the safe LSN to `lsn` once the writes up to it are durable,
+    /// e.g. from a callback that observes the progress of flushes.
+    ///
+    /// Returns [`Error::InvalidArgument`] if `lsn` is larger than
+    /// [`Self::durable_lsn`]. Like [`Self::set_safe_lsn`], advancing it with a
+    /// smaller value has no effect.
+    pub fn advance_safe_lsn(&self, lsn: u64) -> Result<()> {
+        if lsn > self.durable_lsn() {
+            return Err(Error::InvalidArgument);
+        }
+        self.tree.set_safe_lsn(lsn);
+        Ok(())
+    }
+
+    /// Applies changes to the options of the table without reopening it.
+    ///
+    /// The patch is validated as a whole before any change is applied. If it
+    /// contains fields that can not be changed on a live table, returns
+    /// [`Error::InvalidArgument`] and leaves the table untouched. See
+    /// [`OptionsPatch`] for the fields that can be changed.
+    pub fn update_options(&self, patch: OptionsPatch) -> Result<()> {
+        if patch.page_size.is_some() {
+            return Err(Error::InvalidArgument);
+        }
+        if let Some(len) = patch.page_chain_length {
+            self.tree.set_page_chain_length(len);
+        }
+        if let Some(n) = patch.consolidate_keep_recent_deltas {
+            self.tree.set_consolidate_keep_recent_deltas(n);
+        }
+        if let Some(n) = patch.max_merge_chain {
+            self.tree.set_max_merge_chain(n);
+        }
+        if let Some(capacity) = patch.cache_capacity {
+            self.store.set_cache_capacity(capacity);
+        }
+        if let Some(compression) = patch.compression_on_flush {
+            self.store.set_compression_on_flush(compression);
+        }
+        if let Some(compression) = patch.compression_on_cold_compact {
+            self.store.set_compression_on_cold_compact(compression);
+        }
+        Ok(())
+    }
+
     /// Flush all write buffer data.
     pub async fn flush(&self, opts: &FlushOptions) {
         self.store.flush(opts).await;
@@ -118,39 +910,428 @@ impl<E: Env> Table<E> {
     pub async fn wait_for_reclaiming(&self) {
         self.store.wait_for_reclaiming().await;
     }
-}
 
-/// A handle that holds some resources of a table for user operations.
-pub struct Guard<'a, E: Env> {
-    table: &'a Table<E>,
-    txn: TreeTxn<'a, E>,
-}
+    /// Exports entries visible to the LSN in a portable format.
+    ///
+    /// Only the latest version of each key is exported. See
+    /// [`Table::import`] to load the entries into another table.
+    ///
+    /// Returns the number of exported entries.
+    pub async fn export<W>(&self, writer: W, lsn: u64) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut writer = ExportWriter::new(writer, lsn).await?;
+        let txn = self.begin();
+        let options = ReadOptions {
+            max_lsn: lsn,
+            ..Default::default()
+        };
+        let mut iter = TreeIter::new(&txn, options, ScanOptions::default());
+        while let Some(page) = iter.next_page().await? {
+            for (key, value) in page {
+                writer.add(key, value).await?;
+            }
+        }
+        Ok(writer.finish().await?.0)
+    }
 
-impl<'a, E: Env> Guard<'a, E> {
-    fn new(table: &'a Table<E>) -> Self {
-        Self {
-            table,
-            txn: table.begin(),
+    /// Writes entries in `[start, end)` visible to the LSN to a file in the
+    /// format of [`Table::export`].
+    ///
+    /// If `end` is `None`, the range is unbounded. Entries are written to the
+    /// file page by page as the scan goes, so the memory usage is bounded by
+    /// the page size regardless of the size of the range. The file is created
+    /// or truncated, and it can be loaded with [`Table::import`]. An empty
+    /// range writes a file without entries, and an inverted one is rejected
+    /// as [`Table::scan_filter`] does.
+    pub async fn scan_to_file<P>(
+        &self,
+        path: P,
+        start: &[u8],
+        end: Option<&[u8]>,
+        lsn: u64,
+    ) -> Result<ScanFileStats>
+    where
+        P: AsRef<Path> + Send,
+    {
+        // An empty range stops at the first entry of the scan.
+        is_empty_range(start, end)?;
+        let mut file = self.store.env().open_sequential_writer(path).await?;
+        let mut writer = ExportWriter::new(Vec::new(), lsn).await?;
+        let mut stats = ScanFileStats::default();
+        let txn = self.begin();
+        let options = ReadOptions {
+            max_lsn: lsn,
+            ..Default::default()
+        };
+        let mut iter = TreeIter::new(&txn, options, ScanOptions::default()).start_at(start);
+        'scan: while let Some(page) = iter.next_page().await? {
+            for (key, value) in page {
+                if end.map_or(false, |end| key >= end) {
+                    break 'scan;
+                }
+                writer.add(key, value).await?;
+            }
+            let buf = writer.get_mut();
+            file.write_all(buf).await?;
+            stats.bytes += buf.len() as u64;
+            buf.clear();
         }
+        let (rows, buf) = writer.finish().await?;
+        file.write_all(&buf).await?;
+        file.sync_data().await?;
+        stats.rows = rows;
+        stats.bytes += buf.len() as u64;
+        Ok(stats)
     }
 
-    /// Re-pins the table so that the current pinned resources can be released.
-    pub fn repin(&mut self) {
-        self.txn = self.table.begin();
+    /// Returns entries in `[start, end)` that satisfy the predicate.
+    ///
+    /// If `end` is `None`, the range is unbounded. A prefix can be scanned with
+    /// the prefix as `start` and its successor as `end`. The predicate runs
+    /// against the entries borrowed from the pages as the scan walks them, so
+    /// only the accepted entries are copied out of the table.
+    ///
+    /// Like other range-based APIs, an empty range, where `start` equals to
+    /// `end`, returns nothing without walking the table. An inverted range,
+    /// where `start` is larger than `end`, returns
+    /// [`Error::InvalidArgument`].
+    pub async fn scan_filter<F>(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        options: ReadOptions,
+        predicate: F,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>>
+    where
+        F: Fn(&[u8], &[u8]) -> bool,
+    {
+        if is_empty_range(start, end)? {
+            return Ok(Vec::new());
+        }
+        let txn = self.begin_with_priority(options.priority);
+        let mut iter = TreeIter::new(&txn, options, ScanOptions::default()).start_at(start);
+        let mut entries = Vec::new();
+        'scan: while let Some(page) = iter.next_page().await? {
+            for (key, value) in page {
+                if end.map_or(false, |end| key >= end) {
+                    break 'scan;
+                }
+                if predicate(key, value) {
+                    entries.push((key.to_vec(), value.to_vec()));
+                }
+            }
+        }
+        Ok(entries)
     }
 
-    /// Gets the value corresponding to the key.
-    //
-    /// On success, if the value is found, returns [`Option::Some`] with the
-    /// value; if the value is not found, returns [`Option::None`].
-    pub async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<&[u8]>> {
-        let key = Key::new(key, lsn);
+    /// Returns the next chunk of entries in `[start, end)`, which holds about
+    /// `chunk_bytes` bytes of keys and values.
+    ///
+    /// Entries are added to the chunk until the next one would push the total
+    /// size of the keys and values over `chunk_bytes`. An entry larger than
+    /// `chunk_bytes` forms a chunk of its own. The returned chunk carries a
+    /// cursor, the key of the first entry after the chunk, so the scan can
+    /// be resumed with the cursor as `start`, even in another request. The
+    /// cursor is `None` once the range is exhausted.
+    ///
+    /// An empty range returns an empty chunk, and an inverted one is rejected
+    /// as [`Table::scan_filter`] does.
+    pub async fn scan_bytes_chunked(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        options: ReadOptions,
+        chunk_bytes: usize,
+    ) -> Result<ScanChunk> {
+        let mut chunk = ScanChunk::default();
+        if is_empty_range(start, end)? {
+            return Ok(chunk);
+        }
+        let txn = self.begin_with_priority(options.priority);
+        let mut iter = TreeIter::new(&txn, options, ScanOptions::default()).start_at(start);
+        'scan: while let Some(page) = iter.next_page().await? {
+            for (key, value) in page {
+                if end.map_or(false, |end| key >= end) {
+                    break 'scan;
+                }
+                let size = key.len() + value.len();
+                if !chunk.entries.is_empty() && chunk.bytes + size > chunk_bytes {
+                    chunk.cursor = Some(key.to_vec());
+                    break 'scan;
+                }
+                chunk.entries.push((key.to_vec(), value.to_vec()));
+                chunk.bytes += size;
+            }
+        }
+        Ok(chunk)
+    }
+
+    /// Drops all keys with the prefix, like the keys of an expired bucket.
+    ///
+    /// Instead of deleting the keys one by one, the leaf pages within the
+    /// range of the prefix are detached from their parents, which replaces
+    /// the children of each parent in the range with an empty page in one
+    /// update, and deallocates the detached pages. This drops all versions of
+    /// the keys in those pages. The keys in the leaf pages that straddle the
+    /// range boundaries are deleted with the LSN instead, so their older
+    /// versions remain visible to reads below the LSN until they are
+    /// consolidated away. The keys that have been written with the same or a
+    /// larger LSN are kept.
+    ///
+    /// Writes to the prefix that race with this may be dropped as well. The
+    /// approximate key count is not corrected for the detached pages.
+    pub async fn drop_prefix(&self, prefix: &[u8], lsn: u64) -> Result<DropPrefixStats> {
+        let end = prefix_end(prefix);
+        let txn = self.begin();
+        let pages = txn.detach_range(prefix, end.as_deref()).await?;
+        // Only the straddling pages and the empty pages are left in the range.
+        let mut keys = Vec::new();
+        let mut iter =
+            TreeIter::new(&txn, ReadOptions::default(), ScanOptions::default()).start_at(prefix);
+        'scan: while let Some(page) = iter.next_page().await? {
+            for (key, _) in page {
+                if end.as_deref().map_or(false, |end| key >= end) {
+                    break 'scan;
+                }
+                keys.push(key.to_vec());
+            }
+        }
+        let mut deleted = 0;
+        for key in &keys {
+            // Deleting a key written at or after the LSN would break the
+            // increasing LSNs of the key, and the newer version would remain
+            // visible anyway.
+            if let Some((_, latest)) = self.get_versioned(key, u64::MAX).await? {
+                if latest >= lsn {
+                    continue;
+                }
+            }
+            self.delete(key, lsn).await?;
+            deleted += 1;
+        }
+        Ok(DropPrefixStats {
+            pages,
+            keys: deleted,
+        })
+    }
+
+    /// Imports entries exported by [`Table::export`].
+    ///
+    /// The entries are written with the LSN that they are exported at, so
+    /// reads with that LSN see the same entries as the exported table.
+    ///
+    /// Returns the number of imported entries.
+    pub async fn import<R>(&self, reader: R) -> Result<u64>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut reader = ExportReader::new(reader).await?;
+        let lsn = reader.lsn();
+        let mut num_entries = 0;
+        while let Some((key, value)) = reader.next().await? {
+            self.put(&key, lsn, &value).await?;
+            num_entries += 1;
+        }
+        Ok(num_entries)
+    }
+
+    /// Loads entries merged from multiple sorted inputs with the LSN.
+    ///
+    /// Each input must yield entries sorted by keys. The inputs are merged
+    /// into one sorted stream as they are consumed, so they don't need to be
+    /// sorted together beforehand. If more than one entry has the same key,
+    /// only the one picked by `rule` is loaded.
+    ///
+    /// Returns [`Error::InvalidArgument`] if an input is not sorted, or if a
+    /// key is duplicated with [`DuplicateKeyRule::Reject`]. The entries before
+    /// the invalid one have been loaded in this case.
+    ///
+    /// Returns the number of loaded entries.
+    pub async fn bulk_load_merge<I>(
+        &self,
+        inputs: Vec<I>,
+        lsn: u64,
+        rule: DuplicateKeyRule,
+    ) -> Result<u64>
+    where
+        I: Iterator<Item = (Vec<u8>, Vec<u8>)>,
+    {
+        let mut builder = MergingIterBuilder::with_capacity(inputs.len());
+        for input in inputs {
+            builder.add(input);
+        }
+        // Entries with the same key are merged in the order of inputs.
+        let mut iter = builder.build();
+        let mut next = iter.next();
+        let mut num_entries = 0;
+        while let Some((key, mut value)) = next.take() {
+            loop {
+                match iter.next() {
+                    Some((k, v)) if k == key => match rule {
+                        DuplicateKeyRule::FirstInput => {}
+                        DuplicateKeyRule::LastInput => value = v,
+                        DuplicateKeyRule::Reject => return Err(Error::InvalidArgument),
+                    },
+                    Some((k, _)) if k < key => return Err(Error::InvalidArgument),
+                    entry => {
+                        next = entry;
+                        break;
+                    }
+                }
+            }
+            self.put(&key, lsn, &value).await?;
+            num_entries += 1;
+        }
+        Ok(num_entries)
+    }
+
+    /// Returns the leaf pages of the table in the order of keys.
+    ///
+    /// Only inner pages are read, so this is much cheaper than a scan. Each
+    /// leaf page covers the keys from its start key to the start key of the
+    /// next one, which can be used to split the key space into ranges of
+    /// similar sizes for parallel scans. The entries are a snapshot and may be
+    /// outdated by concurrent splits.
+    pub async fn index_entries(&self) -> Result<Vec<IndexEntry>> {
+        let txn = self.begin();
+        Ok(txn.index_entries().await?)
+    }
+
+    /// Returns the contiguous key ranges of leaf pages in which most keys are
+    /// deleted at the LSN.
+    ///
+    /// Tombstones stay in leaf pages until the pages are consolidated, so
+    /// these ranges show the deletions that are not physically applied yet,
+    /// which are good targets for [`Table::consolidate_all`] or compactions.
+    /// Every leaf page is read, so this is as expensive as a full scan.
+    pub async fn deleted_regions(&self, lsn: u64) -> Result<Vec<DeletedRegion>> {
+        let txn = self.begin();
+        let options = ReadOptions {
+            max_lsn: lsn,
+            ..Default::default()
+        };
+        let iter = TreeIter::new(&txn, options, ScanOptions::default());
+        Ok(iter.deleted_regions().await?)
+    }
+
+    /// Returns a stream of the writes with LSNs larger than `from_lsn`, which
+    /// can be applied to another table to replicate this one.
+    ///
+    /// See [`ReplicationStream`] for the records it yields.
+    pub fn replication_stream(&self, from_lsn: u64) -> ReplicationStream<E> {
+        ReplicationStream::new(self.clone(), from_lsn)
+    }
+
+    /// Reads every page in the page files of the table and verifies it
+    /// against its checksum.
+    ///
+    /// The page files are scanned directly instead of the tree, so this also
+    /// covers cold pages that reads never touch, and pages that are not in use
+    /// anymore but still take space. Pages in files written without checksums
+    /// are counted as unchecked. If `rate_limit` is given, the scan reads at
+    /// most that many bytes per second, so that an audit doesn't disturb
+    /// foreground reads and writes.
+    pub async fn verify_checksums(&self, rate_limit: Option<u64>) -> Result<VerifyReport> {
+        Ok(self.store.verify_checksums(rate_limit).await?)
+    }
+
+    /// Returns the page files that can be compacted.
+    ///
+    /// The candidates are sorted by their priority scores in descending order,
+    /// which is the order that the background reclaiming picks them.
+    pub async fn compaction_candidates(&self) -> Vec<CandidateInfo> {
+        self.store.compaction_candidates().await
+    }
+
+    /// Returns the statistics of the page files, sorted by file ids.
+    ///
+    /// Files with many dead bytes are the ones that compactions reclaim space
+    /// from, see [`Table::compaction_candidates`] for the order that the
+    /// background reclaiming picks them, and [`Table::compact_file`] to
+    /// compact one of them. Pages in write buffers that have not been flushed
+    /// are not included.
+    pub fn file_stats(&self) -> Vec<FileStat> {
+        self.store.file_stats()
+    }
+
+    /// Compacts the page file with the given id.
+    ///
+    /// The file is compacted regardless of whether the background reclaiming
+    /// would pick it. This is useful to schedule compactions at a convenient
+    /// time, see [`Table::compaction_candidates`] for the files to choose
+    /// from.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the file does not exist.
+    pub async fn compact_file(&self, file_id: u32) -> Result<()> {
+        self.store.compact_file(file_id).await?;
+        Ok(())
+    }
+}
+
+/// A handle that holds some resources of a table for user operations.
+pub struct Guard<'a, E: Env> {
+    table: &'a Table<E>,
+    txn: TreeTxn<'a, E>,
+}
+
+impl<'a, E: Env> Guard<'a, E> {
+    fn new(table: &'a Table<E>) -> Self {
+        Self {
+            table,
+            txn: table.begin(),
+        }
+    }
+
+    /// Re-pins the table so that the current pinned resources can be released.
+    pub fn repin(&mut self) {
+        self.txn = self.table.begin();
+    }
+
+    /// Gets the value corresponding to the key.
+    //
+    /// On success, if the value is found, returns [`Option::Some`] with the
+    /// value; if the value is not found, returns [`Option::None`].
+    pub async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<&[u8]>> {
+        let key = Key::new(key, lsn);
         Ok(self.txn.get(key).await?)
     }
 
     /// Returns an iterator over pages in the table.
     pub fn pages(&self) -> Pages<'_, 'a, E> {
-        Pages::new(&self.txn)
+        self.pages_with_options(ScanOptions::default())
+    }
+
+    /// Returns an iterator over pages in the table with the given options.
+    pub fn pages_with_options(&self, options: ScanOptions) -> Pages<'_, 'a, E> {
+        self.pages_with_read_options(ReadOptions::default(), options)
+    }
+
+    /// Returns an iterator over pages in the table, which reads the pages
+    /// with the given read options.
+    ///
+    /// If [`ReadOptions::max_bytes`] is set, the iterator fails with
+    /// [`Error::BudgetExceeded`] once it has read more bytes than that.
+    pub fn pages_with_read_options(
+        &self,
+        read_options: ReadOptions,
+        options: ScanOptions,
+    ) -> Pages<'_, 'a, E> {
+        Pages::new(&self.txn, read_options, options)
+    }
+
+    /// Returns an iterator over entries in the segments, each of which is
+    /// read with its own options.
+    ///
+    /// The segments must be sorted by their ranges and must not overlap, so
+    /// that the entries are returned in the order of keys. Otherwise, returns
+    /// [`Error::InvalidArgument`].
+    pub fn scan_segmented<'s>(
+        &'s self,
+        segments: &'s [ScanSegment],
+    ) -> Result<SegmentedScan<'s, 'a, E>> {
+        SegmentedScan::new(&self.txn, segments)
     }
 }
 
@@ -160,9 +1341,9 @@ pub struct Pages<'a, 't: 'a, E: Env> {
 }
 
 impl<'a, 't: 'a, E: Env> Pages<'a, 't, E> {
-    fn new(txn: &'a TreeTxn<'t, E>) -> Self {
+    fn new(txn: &'a TreeTxn<'t, E>, read_options: ReadOptions, options: ScanOptions) -> Self {
         Self {
-            iter: TreeIter::new(txn, ReadOptions::default()),
+            iter: TreeIter::new(txn, read_options, options),
         }
     }
 
@@ -170,6 +1351,490 @@ impl<'a, 't: 'a, E: Env> Pages<'a, 't, E> {
     pub async fn next(&mut self) -> Result<Option<PageIter<'_>>> {
         Ok(self.iter.next_page().await?)
     }
+
+    /// Returns the statistics of the scan so far.
+    pub fn stats(&self) -> ScanStats {
+        self.iter.stats()
+    }
+}
+
+/// A point-in-time view of a table, see [`Table::scan_snapshot`].
+pub struct Snapshot<'a, E: Env> {
+    table: &'a Table<E>,
+    txn: TreeTxn<'a, E>,
+    options: ReadOptions,
+    taken_at: Instant,
+    warned: AtomicBool,
+}
+
+impl<'a, E: Env> Snapshot<'a, E> {
+    /// Returns an iterator over pages in the snapshot.
+    pub fn pages(&self) -> Pages<'_, 'a, E> {
+        self.check_lifetime();
+        Pages::new(&self.txn, self.options.clone(), ScanOptions::default())
+    }
+
+    /// Returns how long the snapshot has been held.
+    pub fn age(&self) -> Duration {
+        self.table
+            .store
+            .env()
+            .now()
+            .saturating_duration_since(self.taken_at)
+    }
+
+    /// Logs a warning once the snapshot is held too long.
+    fn check_lifetime(&self) {
+        let age = self.age();
+        if age > self.table.tree.snapshot_warn_lifetime()
+            && !self.warned.swap(true, Ordering::Relaxed)
+        {
+            log::warn!("snapshot held for {age:?} delays the release of buffers and files");
+        }
+    }
+}
+
+impl<E: Env> Drop for Snapshot<'_, E> {
+    fn drop(&mut self) {
+        self.check_lifetime();
+    }
+}
+
+/// Returns the smallest key larger than all keys with the prefix, or `None` if
+/// there is no such key.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Returns true if `[start, end)` is empty, or [`Error::InvalidArgument`] if
+/// it is inverted.
+fn is_empty_range(start: &[u8], end: Option<&[u8]>) -> Result<bool> {
+    match end {
+        Some(end) if start > end => Err(Error::InvalidArgument),
+        Some(end) => Ok(start == end),
+        None => Ok(false),
+    }
+}
+
+/// A range of keys to scan with [`Guard::scan_segmented`].
+#[derive(Clone, Debug)]
+pub struct ScanSegment {
+    /// The key that the range starts at.
+    pub start: Vec<u8>,
+    /// The key that the range ends before, or `None` if the range is
+    /// unbounded.
+    pub end: Option<Vec<u8>>,
+    /// The options to read the entries in the range with.
+    pub options: ReadOptions,
+}
+
+impl ScanSegment {
+    /// Creates a segment of `[start, end)` with the options.
+    pub fn new(start: Vec<u8>, end: Option<Vec<u8>>, options: ReadOptions) -> Self {
+        Self {
+            start,
+            end,
+            options,
+        }
+    }
+}
+
+/// An iterator over entries in the segments of a table.
+///
+/// Entries are copied out of the table page by page, so they don't borrow
+/// from the iterator.
+pub struct SegmentedScan<'a, 't: 'a, E: Env> {
+    txn: &'a TreeTxn<'t, E>,
+    segments: std::slice::Iter<'a, ScanSegment>,
+    /// The scan of the current segment.
+    iter: Option<(TreeIter<'a, 't, E>, &'a ScanSegment)>,
+    entries: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a, 't: 'a, E: Env> SegmentedScan<'a, 't, E> {
+    fn new(txn: &'a TreeTxn<'t, E>, segments: &'a [ScanSegment]) -> Result<Self> {
+        let mut last_end = Some(&[][..]);
+        for segment in segments {
+            match last_end {
+                Some(end) if end <= segment.start.as_slice() => {}
+                _ => return Err(Error::InvalidArgument),
+            }
+            if let Some(end) = segment.end.as_ref() {
+                if end <= &segment.start {
+                    return Err(Error::InvalidArgument);
+                }
+            }
+            last_end = segment.end.as_deref();
+        }
+        Ok(Self {
+            txn,
+            segments: segments.iter(),
+            iter: None,
+            entries: Vec::new().into_iter(),
+        })
+    }
+
+    /// Returns the next entry in the segments.
+    pub async fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Ok(Some(entry));
+            }
+            if self.iter.is_none() {
+                let Some(segment) = self.segments.next() else {
+                    return Ok(None);
+                };
+                let iter = TreeIter::new(self.txn, segment.options.clone(), ScanOptions::default())
+                    .start_at(&segment.start);
+                self.iter = Some((iter, segment));
+            }
+            let (iter, segment) = self.iter.as_mut().unwrap();
+            let segment = *segment;
+            let mut done = true;
+            if let Some(page) = iter.next_page().await? {
+                let mut entries = Vec::new();
+                done = false;
+                for (key, value) in page {
+                    if segment
+                        .end
+                        .as_ref()
+                        .map_or(false, |end| key >= end.as_slice())
+                    {
+                        done = true;
+                        break;
+                    }
+                    entries.push((key.to_vec(), value.to_vec()));
+                }
+                self.entries = entries.into_iter();
+            }
+            if done {
+                self.iter = None;
+            }
+        }
+    }
+}
+
+/// A record yielded by a [`ReplicationStream`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplicationRecord {
+    /// A version of a key, whose value is `None` if it is a tombstone.
+    Entry {
+        /// The key.
+        key: Vec<u8>,
+        /// The LSN that the version is written with.
+        lsn: u64,
+        /// The value of the version.
+        value: Option<Vec<u8>>,
+    },
+    /// A marker that all the versions with LSNs not larger than this one have
+    /// been yielded.
+    SafeLsn(u64),
+}
+
+/// A stream of the writes to a table.
+///
+/// The stream ships the versions written with LSNs up to the
+/// [`Table::safe_lsn`], which marks the writes that are complete, and then
+/// yields a [`ReplicationRecord::SafeLsn`] marker. The versions up to a
+/// marker are read in batches of consecutive leaf pages, so only about
+/// [`ReplicationStream::with_batch_bytes`] of them are buffered at a time.
+/// Each batch is sorted by LSNs and then keys, and all versions of a key are
+/// in the same batch. A follower can put or delete every entry with the same
+/// LSN to reconstruct the table at the marker, and resume from the last
+/// marker it has applied with [`Table::replication_stream`]. Entries applied
+/// after that marker are yielded again then, and applying them again returns
+/// [`Error::LsnConflict`], which can be ignored.
+///
+/// Versions newer than the last marker yielded are retained by the table until
+/// the stream moves past them, so a stream that lags behind holds back garbage
+/// collection instead of missing versions. Multiple versions of a key that
+/// were not visible to the safe LSN before the stream was created may have
+/// been merged into the latest one, and tombstones may have been dropped.
+pub struct ReplicationStream<E: Env> {
+    table: Table<E>,
+    /// The LSN of the last marker yielded.
+    shipped_lsn: Arc<AtomicU64>,
+    /// The LSN of the next marker and the key to read the next batch from,
+    /// if the versions up to the marker are not all read yet.
+    next_batch: Option<(u64, Vec<u8>)>,
+    batch_bytes: usize,
+    pending: VecDeque<ReplicationRecord>,
+}
+
+impl<E: Env> ReplicationStream<E> {
+    fn new(table: Table<E>, from_lsn: u64) -> Self {
+        let shipped_lsn = table.tree.register_replication(from_lsn);
+        Self {
+            table,
+            shipped_lsn,
+            next_batch: None,
+            batch_bytes: REPLICATION_BATCH_BYTES,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Sets the approximate size of the keys and values read in a batch.
+    ///
+    /// Leaf pages are read until the batch reaches the size, so a batch may
+    /// exceed it by up to the versions of one page.
+    ///
+    /// Default: 1MB
+    pub fn with_batch_bytes(mut self, batch_bytes: usize) -> Self {
+        self.batch_bytes = batch_bytes;
+        self
+    }
+
+    /// Returns the next record in the stream.
+    ///
+    /// Returns `None` if all the versions up to the safe LSN have been
+    /// yielded. The stream can be polled again after the safe LSN advances.
+    pub async fn next(&mut self) -> Result<Option<ReplicationRecord>> {
+        if self.pending.is_empty() {
+            self.fill().await?;
+        }
+        let record = self.pending.pop_front();
+        if let Some(ReplicationRecord::SafeLsn(lsn)) = record {
+            self.shipped_lsn.store(lsn, Ordering::Release);
+        }
+        Ok(record)
+    }
+
+    /// Returns the LSN of the last marker yielded.
+    pub fn shipped_lsn(&self) -> u64 {
+        self.shipped_lsn.load(Ordering::Acquire)
+    }
+
+    /// Reads the next batch of versions written since the last marker into
+    /// the buffer, followed by the next marker if it is the last batch.
+    ///
+    /// Batches without versions are skipped, so the buffer is only left empty
+    /// if there is nothing new to yield.
+    async fn fill(&mut self) -> Result<()> {
+        let after = self.shipped_lsn();
+        let (upto, mut start) = match self.next_batch.take() {
+            Some(next_batch) => next_batch,
+            None => {
+                let upto = self.table.safe_lsn();
+                if upto <= after {
+                    return Ok(());
+                }
+                (upto, Vec::new())
+            }
+        };
+        loop {
+            let txn = self.table.begin();
+            let iter = TreeIter::new(&txn, ReadOptions::default(), ScanOptions::default())
+                .start_at(&start);
+            let (versions, next) = iter.versions((after, upto), self.batch_bytes).await?;
+            self.pending.extend(
+                versions
+                    .into_iter()
+                    .map(|(key, lsn, value)| ReplicationRecord::Entry { key, lsn, value }),
+            );
+            let Some(next) = next else {
+                self.pending.push_back(ReplicationRecord::SafeLsn(upto));
+                return Ok(());
+            };
+            if !self.pending.is_empty() {
+                self.next_batch = Some((upto, next));
+                return Ok(());
+            }
+            start = next;
+        }
+    }
+}
+
+/// A transaction that spans multiple operations on a table.
+///
+/// Reads see the latest values of the table and the writes of the transaction
+/// itself. Writes are buffered until [`Txn::commit`], which applies all of them
+/// atomically if none of the keys read has been changed since it was read.
+///
+/// For now, all keys of a transaction must belong to the same leaf page.
+pub struct Txn<'a, E: Env> {
+    table: &'a Table<E>,
+    /// The keys read and the LSNs of their values, or `None` if the keys had
+    /// no value.
+    reads: BTreeMap<Vec<u8>, Option<u64>>,
+    /// The keys written and their values, or `None` for deletes.
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a, E: Env> Txn<'a, E> {
+    fn new(table: &'a Table<E>) -> Self {
+        Self {
+            table,
+            reads: BTreeMap::new(),
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Gets the latest value corresponding to the key.
+    ///
+    /// The value written by this transaction is returned if any, otherwise the
+    /// key is recorded to be validated on commit.
+    pub async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        let txn = self.table.begin();
+        let value = txn.get_versioned(Key::new(key, u64::MAX)).await?;
+        // Keep the first LSN read, so that a change between two reads is
+        // detected on commit.
+        let lsn = value.map(|(_, lsn)| lsn);
+        self.reads.entry(key.to_vec()).or_insert(lsn);
+        Ok(value.map(|(v, _)| v.to_vec()))
+    }
+
+    /// Puts a key-value pair to the transaction.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    /// Deletes a key from the transaction.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Commits the writes of the transaction with the LSN.
+    ///
+    /// Returns [`Error::TxnConflict`] if any key read by the transaction has
+    /// been changed since it was read, in which case nothing is written.
+    /// Returns [`Error::InvalidArgument`] if the keys don't belong to the
+    /// same leaf page.
+    ///
+    /// [`Error::TxnConflict`]: crate::Error::TxnConflict
+    /// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
+    pub async fn commit(self, lsn: u64) -> Result<()> {
+        let reads = self
+            .reads
+            .iter()
+            .map(|(k, lsn)| (k.as_slice(), *lsn))
+            .collect::<Vec<_>>();
+        let writes = self
+            .writes
+            .iter()
+            .map(|(k, v)| {
+                let value = v.as_deref().map_or(Value::Delete, Value::Put);
+                (Key::new(k, lsn), value)
+            })
+            .collect::<Vec<_>>();
+        let txn = self.table.begin();
+        Ok(txn.commit(&reads, &writes).await?)
+    }
+}
+
+/// An owned value returned by [`Table::get_owned_pinned`].
+///
+/// The buffer either refers to the cached page that holds the value, which
+/// stays in memory until the buffer is dropped, or holds a copy of the value.
+pub struct PinnedBuffer(Pinned);
+
+enum Pinned {
+    Cached {
+        page: CacheEntryGuard,
+        range: Range<usize>,
+    },
+    Owned(Vec<u8>),
+}
+
+impl PinnedBuffer {
+    /// Returns true if the buffer refers to a cached page instead of holding a
+    /// copy of the value.
+    pub fn is_cached(&self) -> bool {
+        matches!(self.0, Pinned::Cached { .. })
+    }
+}
+
+impl Deref for PinnedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Pinned::Cached { page, range } => &page.value()[range.clone()],
+            Pinned::Owned(value) => value,
+        }
+    }
+}
+
+impl AsRef<[u8]> for PinnedBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for PinnedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedBuffer")
+            .field("cached", &self.is_cached())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Statistics of a scan written to a file by [`Table::scan_to_file`].
+#[derive(Clone, Debug, Default)]
+pub struct ScanFileStats {
+    /// The number of entries written.
+    pub rows: u64,
+    /// The size of the file.
+    pub bytes: u64,
+}
+
+/// A chunk of entries returned by [`Table::scan_bytes_chunked`].
+#[derive(Clone, Debug, Default)]
+pub struct ScanChunk {
+    /// The entries in the chunk.
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The total size of the keys and values in the chunk.
+    pub bytes: usize,
+    /// The key to resume the scan at, or `None` if the range is exhausted.
+    pub cursor: Option<Vec<u8>>,
+}
+
+/// Statistics of a rewrite by [`Table::rewrite_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct RewriteStats {
+    /// The number of leaf pages copied.
+    pub pages: u64,
+    /// The number of versions copied, including tombstones.
+    pub versions: u64,
+    /// The total size of the keys and values copied.
+    pub bytes: u64,
+    /// The number of live keys in the new table, which is set once the
+    /// rewrite finishes.
+    pub keys: u64,
+}
+
+/// Statistics of [`Table::drop_prefix`].
+#[derive(Clone, Debug, Default)]
+pub struct DropPrefixStats {
+    /// The number of leaf pages detached with all their keys.
+    pub pages: u64,
+    /// The number of keys deleted one by one in the pages that straddle the
+    /// prefix boundaries.
+    pub keys: u64,
+}
+
+/// How [`Table::bulk_load_merge`] picks the entry to load among the entries
+/// with the same key.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeyRule {
+    /// Loads the entry from the first input in the order of inputs.
+    #[default]
+    FirstInput,
+    /// Loads the entry from the last input in the order of inputs.
+    LastInput,
+    /// Fails with [`Error::InvalidArgument`].
+    Reject,
 }
 
 /// Statstistic of a table.
@@ -179,6 +1844,15 @@ pub struct TableStats {
     pub tree: TreeStats,
     /// The stats of store.
     pub store: StoreStats,
+    /// The approximate number of live keys.
+    ///
+    /// Puts are counted as new keys and deletes as removed keys when they are
+    /// written, and the count is corrected once the deltas are consolidated,
+    /// so overwrites and deletes of absent keys only skew it for a while.
+    pub approx_key_count: u64,
+    /// Whether the background jobs are paused, see
+    /// [`Table::pause_background`].
+    pub background_paused: bool,
 }
 
 impl TableStats {
@@ -187,8 +1861,37 @@ impl TableStats {
         TableStats {
             tree: self.tree.sub(&o.tree),
             store: self.store.sub(&o.store),
+            approx_key_count: self.approx_key_count,
+            background_paused: self.background_paused,
         }
     }
+
+    /// Returns the average ratio of used bytes to the page size of the leaf
+    /// base pages, sampled from leaf page consolidations.
+    ///
+    /// A low ratio after many deletes means that the table may benefit from a
+    /// compaction.
+    pub fn avg_leaf_fill_ratio(&self) -> f64 {
+        self.tree.avg_leaf_fill_ratio()
+    }
+
+    /// Returns the ratio of obsolete bytes to the total size of page files,
+    /// sampled when checking whether space should be reclaimed.
+    pub fn file_fragmentation_ratio(&self) -> f64 {
+        self.store.jobs.file_fragmentation_ratio()
+    }
+
+    /// Returns the sizes of the keys of the sampled writes, see
+    /// [`Options::size_sampling_rate`].
+    pub fn key_size_histogram(&self) -> &SizeHistogram {
+        &self.tree.key_sizes
+    }
+
+    /// Returns the sizes of the values of the sampled puts, see
+    /// [`Options::size_sampling_rate`].
+    pub fn value_size_histogram(&self) -> &SizeHistogram {
+        &self.tree.value_sizes
+    }
 }
 
 impl std::fmt::Display for TableStats {
@@ -222,7 +1925,1608 @@ impl std::fmt::Display for TableStats {
                 user_read_bytes: {user_read_bytes}, \
                 front_read_bytes: {front_read_bytes}, \
                 background_read_bytes: {background_read_bytes}, \
-                read_amp: {read_amp:.2}"
+                read_amp: {read_amp:.2}, \
+                approx_key_count: {}",
+            self.approx_key_count,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{
+        tests::{must_get, must_put, options},
+        *,
+    };
+
+    #[photonio::test]
+    async fn io_priority() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 8;
+        let write_opts = WriteOptions {
+            priority: env::IoPriority::Low,
+            ..Default::default()
+        };
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            table
+                .put_with_options(&buf, i, &buf, &write_opts)
+                .await
+                .unwrap();
+        }
+        table.close().await.unwrap();
+
+        // The pages are read from page files after reopening.
+        let table = Table::open(&path, options()).await.unwrap();
+        let read_opts = ReadOptions {
+            priority: env::IoPriority::High,
+            ..Default::default()
+        };
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            let value = table.get_with_options(&buf, &read_opts).await.unwrap();
+            assert_eq!(value, Some(buf.to_vec()));
+        }
+        let entries = table
+            .scan_filter(&[], None, read_opts, |_, _| true)
+            .await
+            .unwrap();
+        assert_eq!(entries.len() as u64, N);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn put_returning_old() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            let old = table.put_returning_old(&buf, 1, &buf).await.unwrap();
+            assert_eq!(old, None);
+        }
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            let old = table.put_returning_old(&buf, 3, &[]).await.unwrap();
+            assert_eq!(old, Some(buf.to_vec()));
+        }
+        for i in N..N * 2 {
+            let buf = i.to_be_bytes();
+            table.delete(&buf, 1).await.unwrap();
+            let old = table.put_returning_old(&buf, 2, &buf).await.unwrap();
+            assert_eq!(old, None);
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_restarts() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.set_safe_lsn(N);
+
+        let guard = table.pin();
+        let options = ScanOptions {
+            max_restarts: 0,
+            ..Default::default()
+        };
+        let mut pages = guard.pages_with_options(options);
+        let mut i = 0u64;
+        let page = pages.next().await.unwrap().unwrap();
+        for (k, v) in page {
+            assert_eq!(k, &i.to_be_bytes());
+            assert_eq!(v, &i.to_be_bytes());
+            i += 1;
+        }
+
+        // Split the remaining pages under the scan.
+        for j in 0..N {
+            let buf = j.to_be_bytes();
+            table.put(&buf, N + 1 + j, &[0; 8]).await.unwrap();
+        }
+
+        // The scan is pinned to the safe LSN after the first restart.
+        while let Some(page) = pages.next().await.unwrap() {
+            for (k, v) in page {
+                assert_eq!(k, &i.to_be_bytes());
+                assert_eq!(v, &i.to_be_bytes());
+                i += 1;
+            }
+        }
+        assert_eq!(i, N);
+        assert!(pages.stats().restarts > 0);
+        assert!(pages.stats().pages > 1);
+        drop(pages);
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_tombstones() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        for i in (0..N).step_by(2) {
+            table.delete(&i.to_be_bytes(), 2).await.unwrap();
+        }
+        // A key that is put again is not a tombstone.
+        must_put(&table, 0, 3).await;
+
+        let guard = table.pin();
+        for include_tombstones in [false, true] {
+            let options = ScanOptions {
+                include_tombstones,
+                ..Default::default()
+            };
+            let mut pages = guard.pages_with_options(options);
+            let mut entries = Vec::new();
+            while let Some(mut page) = pages.next().await.unwrap() {
+                while let Some((k, v)) = page.next_entry() {
+                    let k = u64::from_be_bytes(k.try_into().unwrap());
+                    entries.push((k, v.map(|v| u64::from_be_bytes(v.try_into().unwrap()))));
+                }
+            }
+            let expect: Vec<_> = (0..N)
+                .filter(|&i| include_tombstones || i == 0 || i % 2 == 1)
+                .map(|i| (i, (i == 0 || i % 2 == 1).then_some(i)))
+                .collect();
+            assert_eq!(entries, expect);
+        }
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn update_options() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+
+        let patch = TableOptionsPatch {
+            page_size: Some(256),
+            page_chain_length: Some(8),
+            ..Default::default()
+        };
+        assert!(matches!(
+            table.update_options(patch),
+            Err(Error::InvalidArgument)
+        ));
+
+        let patch = TableOptionsPatch {
+            page_chain_length: Some(8),
+            cache_capacity: Some(8 << 10),
+            compression_on_flush: Some(Compression::NONE),
+            compression_on_cold_compact: Some(Compression::SNAPPY),
+            ..Default::default()
+        };
+        table.update_options(patch).unwrap();
+        assert_eq!(table.stats().store.page_cache_capacity, 8 << 10);
+
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn update_consolidation_options() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        let mut lsn = 0;
+
+        // Grows a chain of 16 deltas without consolidating it.
+        let patch = TableOptionsPatch {
+            page_chain_length: Some(32),
+            ..Default::default()
+        };
+        table.update_options(patch).unwrap();
+        for _ in 0..16 {
+            lsn += 1;
+            must_put(&table, 0, lsn).await;
+        }
+        assert_eq!(table.stats().tree.consolidate_leaf_page, 0);
+
+        // The next write consolidates the chain at the new length.
+        let patch = TableOptionsPatch {
+            page_chain_length: Some(2),
+            ..Default::default()
+        };
+        table.update_options(patch).unwrap();
+        lsn += 1;
+        must_put(&table, 0, lsn).await;
+        let stats = table.stats().tree;
+        assert_eq!(stats.consolidate_leaf_page, 1);
+        assert_eq!(stats.segmented_consolidations, 0);
+
+        // Long chains are consolidated in segments once the merges are bounded.
+        let patch = TableOptionsPatch {
+            page_chain_length: Some(32),
+            ..Default::default()
+        };
+        table.update_options(patch).unwrap();
+        for _ in 0..16 {
+            lsn += 1;
+            must_put(&table, 0, lsn).await;
+        }
+        let patch = TableOptionsPatch {
+            page_chain_length: Some(2),
+            max_merge_chain: Some(4),
+            ..Default::default()
+        };
+        table.update_options(patch).unwrap();
+        lsn += 1;
+        must_put(&table, 0, lsn).await;
+        let stats = table.stats().tree;
+        assert_eq!(stats.consolidate_leaf_page, 2);
+        assert!(stats.segmented_consolidations > 0);
+
+        must_get(&table, 0, u64::MAX, Some(0)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn rewrite_with_options() {
+        let dir = tempdir().unwrap();
+        let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+        let table = Table::open(&src, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            must_put(&table, i, 1).await;
+            if i % 3 == 0 {
+                table.delete(&key, 2).await.unwrap();
+            } else if i % 3 == 1 {
+                table.put_timestamped(&key, 2, b"b", 2).await.unwrap();
+                table.put_timestamped(&key, 3, b"a", 1).await.unwrap();
+            }
+        }
+        table.close().await.unwrap();
+
+        let opts = TableOptions {
+            page_size: 4 << 10,
+            page_store: PageStoreOptions {
+                compression_on_flush: Compression::NONE,
+                page_checksum_type: ChecksumType::NONE,
+                ..options().page_store
+            },
+            ..options()
+        };
+        let mut pages = 0;
+        let stats = Table::rewrite_with_options(&src, &dst, opts.clone(), |stats| {
+            assert!(stats.pages > pages);
+            pages = stats.pages;
+        })
+        .await
+        .unwrap();
+        assert_eq!(stats.pages, pages);
+        // Every third key is deleted, and the next one is put with timestamps
+        // twice.
+        let (deleted, timestamped) = ((N + 2) / 3, (N + 1) / 3);
+        assert_eq!(stats.versions, N + deleted + timestamped * 2);
+        assert_eq!(stats.keys, N - deleted);
+
+        // The destination must be new.
+        assert!(matches!(
+            Table::rewrite_with_options(&src, &dst, opts.clone(), |_| {}).await,
+            Err(Error::AlreadyExists)
+        ));
+
+        let table = Table::open(&dst, opts).await.unwrap();
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            must_get(&table, i, 1, Some(i)).await;
+            let expect = match i % 3 {
+                0 => None,
+                1 => Some((b"b".to_vec(), 2)),
+                _ => Some((key.to_vec(), 1)),
+            };
+            assert_eq!(table.get_versioned(&key, 3).await.unwrap(), expect);
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn open_modes() {
+        let path = tempdir().unwrap();
+        let path = path.path().join("table");
+        let opts = |open_mode| TableOptions {
+            open_mode,
+            ..options()
+        };
+        assert!(matches!(
+            Table::open(&path, opts(OpenMode::OpenExisting)).await,
+            Err(Error::NotFound)
+        ));
+        assert!(!path.exists());
+
+        let table = Table::open(&path, opts(OpenMode::CreateNew)).await.unwrap();
+        must_put(&table, 1, 1).await;
+        table.close().await.unwrap();
+        assert!(matches!(
+            Table::open(&path, opts(OpenMode::CreateNew)).await,
+            Err(Error::AlreadyExists)
+        ));
+
+        let table = Table::open(&path, opts(OpenMode::OpenExisting))
+            .await
+            .unwrap();
+        must_get(&table, 1, 1, Some(1)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn get_owned_pinned() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        must_put(&table, 1, 1).await;
+        table.close().await.unwrap();
+
+        // The value is read from a page file, so it stays in the cached page.
+        let table = Table::open(&path, options()).await.unwrap();
+        let key = 1u64.to_be_bytes();
+        let buf = table.get_owned_pinned(&key, 1).await.unwrap().unwrap();
+        assert!(buf.is_cached());
+        assert_eq!(&*buf, key);
+        let value = ::std::thread::spawn(move || buf.to_vec()).join().unwrap();
+        assert_eq!(value, key);
+
+        // The value is in a write buffer, so it is copied.
+        must_put(&table, 2, 2).await;
+        let key = 2u64.to_be_bytes();
+        let buf = table.get_owned_pinned(&key, 2).await.unwrap().unwrap();
+        assert!(!buf.is_cached());
+        assert_eq!(&*buf, key);
+        assert!(table.get_owned_pinned(&key, 1).await.unwrap().is_none());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn contains() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            value_inline_threshold: 16,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            let value = if i % 3 == 0 {
+                vec![1; 64]
+            } else {
+                key.to_vec()
+            };
+            table.put(&key, 1, &value).await.unwrap();
+            if i % 2 == 0 {
+                table.delete(&key, 2).await.unwrap();
+            }
+        }
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert!(!table.contains(&key, 0).await.unwrap());
+            assert!(table.contains(&key, 1).await.unwrap());
+            // A tombstone above the value hides it.
+            assert_eq!(table.contains(&key, 2).await.unwrap(), i % 2 != 0);
+        }
+        assert!(!table.contains(&N.to_be_bytes(), 2).await.unwrap());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn get_with_status() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+            if i % 2 == 0 {
+                table.delete(&i.to_be_bytes(), 2).await.unwrap();
+            }
+        }
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(
+                table.get_with_status(&key, 0).await.unwrap(),
+                (None, KeyStatus::Absent)
+            );
+            assert_eq!(
+                table.get_with_status(&key, 1).await.unwrap(),
+                (Some(key.to_vec()), KeyStatus::Present)
+            );
+            let expect = if i % 2 == 0 {
+                (None, KeyStatus::Deleted)
+            } else {
+                (Some(key.to_vec()), KeyStatus::Present)
+            };
+            assert_eq!(table.get_with_status(&key, 2).await.unwrap(), expect);
+        }
+        assert_eq!(
+            table.get_with_status(&N.to_be_bytes(), 2).await.unwrap(),
+            (None, KeyStatus::Absent)
+        );
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn get_versioned() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+            if i % 2 == 0 {
+                must_put(&table, i, 3).await;
+            } else {
+                table.delete(&i.to_be_bytes(), 3).await.unwrap();
+            }
+        }
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(table.get_versioned(&key, 0).await.unwrap(), None);
+            assert_eq!(
+                table.get_versioned(&key, 2).await.unwrap(),
+                Some((key.to_vec(), 1))
+            );
+            let expect = (i % 2 == 0).then(|| (key.to_vec(), 3));
+            assert_eq!(table.get_versioned(&key, 4).await.unwrap(), expect);
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn put_timestamped() {
+        const N: u64 = 1 << 10;
+        async fn check(table: &Table) {
+            for i in 0..N {
+                let key = i.to_be_bytes();
+                let expect = if i % 2 == 0 { (b"d", 4) } else { (b"b", 1) };
+                assert_eq!(
+                    table.get_versioned(&key, 4).await.unwrap(),
+                    Some((expect.0.to_vec(), expect.1))
+                );
+            }
+            let mut count = 0;
+            let guard = table.pin();
+            let mut pages = guard.pages();
+            while let Some(page) = pages.next().await.unwrap() {
+                for (_, v) in page {
+                    assert!(v == b"b" || v == b"d");
+                    count += 1;
+                }
+            }
+            assert_eq!(count, N);
+        }
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            table.put_timestamped(&key, 1, b"b", 2).await.unwrap();
+            table.put_timestamped(&key, 2, b"a", 1).await.unwrap();
+            if i % 2 == 0 {
+                table.put(&key, 3, b"c").await.unwrap();
+                table.put_timestamped(&key, 4, b"d", 0).await.unwrap();
+            }
+        }
+        for i in 0..N {
+            let value = table.get(&i.to_be_bytes(), 2).await.unwrap();
+            assert_eq!(value, Some(b"b".to_vec()));
+        }
+        check(&table).await;
+
+        // Consolidation keeps the winners visible to the safe LSN.
+        table.set_safe_lsn(2);
+        table.consolidate_all().await.unwrap();
+        check(&table).await;
+        table.set_safe_lsn(4);
+        table.consolidate_all().await.unwrap();
+        check(&table).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn get_history() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            page_size: 4 << 10,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 8;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+            table.delete(&i.to_be_bytes(), 2).await.unwrap();
+            table.put(&i.to_be_bytes(), 3, &[]).await.unwrap();
+        }
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(
+                table.get_history(&key, ..).await.unwrap(),
+                vec![(3, Some(vec![])), (2, None), (1, Some(key.to_vec()))]
+            );
+            assert_eq!(
+                table.get_history(&key, 1..3).await.unwrap(),
+                vec![(2, None), (1, Some(key.to_vec()))]
+            );
+            assert!(table.get_history(&key, 4..).await.unwrap().is_empty());
+        }
+        assert!(table
+            .get_history(&N.to_be_bytes(), ..)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Versions that are not visible to the safe LSN are dropped.
+        table.set_safe_lsn(3);
+        for i in 0..N {
+            table.put(&i.to_be_bytes(), 4, &[]).await.unwrap();
+        }
+        for i in 0..N {
+            let history = table.get_history(&i.to_be_bytes(), ..).await.unwrap();
+            assert_eq!(&history[..2], &[(4, Some(vec![])), (3, Some(vec![]))]);
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn byte_budget() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 8;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+
+        let write_opts = WriteOptions {
+            max_bytes: Some(16),
+            ..Default::default()
+        };
+        let key = N.to_be_bytes();
+        assert!(matches!(
+            table.put_with_options(&key, 1, &[0; 16], &write_opts).await,
+            Err(Error::BudgetExceeded { consumed: 24 })
+        ));
+        assert_eq!(table.get(&key, 1).await.unwrap(), None);
+        table
+            .put_with_options(&key, 1, &key, &write_opts)
+            .await
+            .unwrap();
+
+        let guard = table.pin();
+        let mut pages = guard.pages();
+        while pages.next().await.unwrap().is_some() {}
+        let total_bytes = pages.stats().bytes;
+        assert!(total_bytes > 0);
+        drop(pages);
+
+        let read_opts = ReadOptions {
+            max_bytes: Some(total_bytes / 2),
+            ..Default::default()
+        };
+        let mut pages = guard.pages_with_read_options(read_opts, ScanOptions::default());
+        let consumed = loop {
+            match pages.next().await {
+                Ok(Some(_)) => continue,
+                Ok(None) => panic!("the scan should exceed the budget"),
+                Err(Error::BudgetExceeded { consumed }) => break consumed,
+                Err(err) => panic!("unexpected error {err:?}"),
+            }
+        };
+        assert!(consumed > total_bytes / 2);
+        assert!(consumed <= total_bytes);
+        drop(pages);
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn advance_safe_lsn() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 4;
+        for i in 1..=N {
+            must_put(&table, i, i).await;
+        }
+        assert_eq!(table.durable_lsn(), 0);
+        assert!(matches!(
+            table.advance_safe_lsn(1),
+            Err(Error::InvalidArgument)
+        ));
+        assert_eq!(table.safe_lsn(), 0);
+
+        table.flush(&FlushOptions::default()).await;
+        assert_eq!(table.durable_lsn(), N);
+        table.advance_safe_lsn(N / 2).unwrap();
+        assert_eq!(table.safe_lsn(), N / 2);
+        table.advance_safe_lsn(1).unwrap();
+        assert_eq!(table.safe_lsn(), N / 2);
+
+        must_put(&table, N + 1, N + 1).await;
+        assert_eq!(table.durable_lsn(), N);
+        assert!(table.advance_safe_lsn(N + 1).is_err());
+        table.advance_safe_lsn(N).unwrap();
+        assert_eq!(table.safe_lsn(), N);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn page_metadata() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        let metadata = b"schema-v1".to_vec();
+        let write_opts = WriteOptions {
+            page_metadata: Some(metadata.clone()),
+            ..Default::default()
+        };
+        const N: u64 = 1 << 8;
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            table
+                .put_with_options(&buf, 1, &buf, &write_opts)
+                .await
+                .unwrap();
+        }
+        let oversized = WriteOptions {
+            page_metadata: Some(vec![0; 33]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            table.put_with_options(&[0], 1, &[0], &oversized).await,
+            Err(Error::InvalidArgument)
+        ));
+        table.consolidate_all().await.unwrap();
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+
+        // The metadata survives splits, consolidations and flushes.
+        let table = Table::open(&path, options()).await.unwrap();
+        let guard = table.pin();
+        let mut pages = guard.pages();
+        let mut num_pages = 0;
+        while let Some(page) = pages.next().await.unwrap() {
+            assert_eq!(page.page_metadata(), Some(metadata.as_slice()));
+            num_pages += 1;
+        }
+        assert!(num_pages > 1);
+        drop(pages);
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn shutdown() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        let other = table.clone();
+        table.shutdown().await;
+        // Shutting down again is a no-op.
+        other.shutdown().await;
+        drop(table);
+        drop(other);
+
+        let table = Table::open(&path, options()).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn seal() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+
+        table.seal().await.unwrap();
+        assert!(table.is_sealed());
+        let consolidated = table.stats().tree.success.consolidate_page;
+        // All pages have been consolidated, so there is nothing left to do.
+        table.seal().await.unwrap();
+        assert_eq!(table.stats().tree.success.consolidate_page, consolidated);
+        let key = N.to_be_bytes();
+        assert!(matches!(table.put(&key, N, &key).await, Err(Error::Sealed)));
+        assert!(matches!(table.delete(&key, N).await, Err(Error::Sealed)));
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+
+        table.unseal();
+        assert!(!table.is_sealed());
+        must_put(&table, N, N).await;
+        must_get(&table, N, N, Some(N)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn consolidate_all() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+
+        // Writes concurrently with the consolidation.
+        let writer = {
+            let table = table.clone();
+            photonio::task::spawn(async move {
+                for i in N..N * 2 {
+                    must_put(&table, i, i).await;
+                }
+            })
+        };
+        let stats = table.consolidate_all().await.unwrap();
+        assert!(stats.pages > 0);
+        assert!(stats.bytes > 0);
+        writer.await.unwrap();
+
+        table.consolidate_all().await.unwrap();
+        // All pages have been consolidated, so there is nothing left to do.
+        let stats = table.consolidate_all().await.unwrap();
+        assert_eq!(stats.pages, 0);
+        assert_eq!(stats.bytes, 0);
+        assert!(!table.is_sealed());
+        for i in 0..N * 2 {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn plan_compaction() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+
+        let plan = table.plan_compaction(&[], None).await.unwrap();
+        assert!(plan.pages > 0);
+        assert!(plan.read_bytes > 0);
+        assert!(plan.write_bytes > 0);
+        let empty = table
+            .plan_compaction(b"a", Some(b"a".as_slice()))
+            .await
+            .unwrap();
+        assert_eq!(empty.pages, 0);
+
+        // Planning leaves the pages to consolidate as they are.
+        let stats = table.consolidate_all().await.unwrap();
+        assert!(stats.pages >= plan.pages);
+        let plan = table.plan_compaction(&[], None).await.unwrap();
+        assert_eq!(plan.pages, 0);
+        assert_eq!(plan.read_bytes, 0);
+        assert!(plan.duration.is_some());
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn txn() {
+        let path = tempdir().unwrap();
+        // Keep the keys below in the same leaf page.
+        let opts = TableOptions {
+            page_size: 1 << 10,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        let key = |i: u64| i.to_be_bytes();
+        must_put(&table, 0, 1).await;
+        must_put(&table, 1, 1).await;
+
+        let mut txn = table.begin_txn();
+        assert_eq!(txn.get(&key(0)).await.unwrap(), Some(key(0).to_vec()));
+        assert_eq!(txn.get(&key(2)).await.unwrap(), None);
+        txn.put(&key(2), &key(2));
+        txn.delete(&key(1));
+        // Reads see the writes of the transaction.
+        assert_eq!(txn.get(&key(2)).await.unwrap(), Some(key(2).to_vec()));
+        assert_eq!(txn.get(&key(1)).await.unwrap(), None);
+        txn.commit(2).await.unwrap();
+        must_get(&table, 1, 2, None).await;
+        must_get(&table, 2, 2, Some(2)).await;
+
+        // A key read by the transaction is changed by others.
+        let mut txn = table.begin_txn();
+        assert!(txn.get(&key(0)).await.unwrap().is_some());
+        txn.put(&key(3), &key(3));
+        must_put(&table, 0, 3).await;
+        assert!(matches!(txn.commit(4).await, Err(Error::TxnConflict)));
+        must_get(&table, 3, 4, None).await;
+
+        // An absent key read by the transaction is written by others.
+        let mut txn = table.begin_txn();
+        assert!(txn.get(&key(4)).await.unwrap().is_none());
+        txn.put(&key(3), &key(3));
+        must_put(&table, 4, 4).await;
+        assert!(matches!(txn.commit(5).await, Err(Error::TxnConflict)));
+        must_get(&table, 3, 5, None).await;
+
+        // Keys that span multiple leaf pages are not supported yet.
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 6).await;
+        }
+        let mut txn = table.begin_txn();
+        txn.put(&key(0), &key(0));
+        txn.put(&key(N - 1), &key(N - 1));
+        assert!(matches!(txn.commit(7).await, Err(Error::InvalidArgument)));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn auto_lsn() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        assert!(matches!(
+            table.put_simple(b"a", b"1").await,
+            Err(Error::InvalidArgument)
+        ));
+        table.close().await.unwrap();
+
+        let opts = TableOptions {
+            auto_lsn: true,
+            ..options()
+        };
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        table.put_simple(b"a", b"1").await.unwrap();
+        table.put_simple(b"b", b"2").await.unwrap();
+        table.put_simple(b"a", b"3").await.unwrap();
+        table.delete_simple(b"b").await.unwrap();
+        assert_eq!(table.get_simple(b"a").await.unwrap(), Some(b"3".to_vec()));
+        assert_eq!(table.get_simple(b"b").await.unwrap(), None);
+        let (_, lsn) = table.get_versioned(b"a", u64::MAX).await.unwrap().unwrap();
+        table.close().await.unwrap();
+
+        // The counter continues from the LSNs recorded before the reopen.
+        let table = Table::open(&path, opts).await.unwrap();
+        assert_eq!(table.get_simple(b"a").await.unwrap(), Some(b"3".to_vec()));
+        table.put_simple(b"a", b"5").await.unwrap();
+        let (value, new_lsn) = table.get_versioned(b"a", u64::MAX).await.unwrap().unwrap();
+        assert_eq!(value, b"5");
+        assert!(new_lsn > lsn);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn fill_and_fragmentation_ratios() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        let stats = table.stats();
+        assert_eq!(stats.avg_leaf_fill_ratio(), 0.0);
+        assert_eq!(stats.file_fragmentation_ratio(), 0.0);
+
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.seal().await.unwrap();
+        let stats = table.stats();
+        assert!(stats.tree.consolidate_leaf_page > 0);
+        assert_eq!(stats.tree.page_size, options().page_size);
+        assert!(stats.avg_leaf_fill_ratio() > 0.0);
+
+        table.flush(&FlushOptions::default()).await;
+        table.wait_for_reclaiming().await;
+        let ratio = table.stats().file_fragmentation_ratio();
+        assert!((0.0..1.0).contains(&ratio));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_snapshot() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in (0..N).step_by(2) {
+            must_put(&table, i, 1).await;
+        }
+        let snapshot = table.scan_snapshot(ReadOptions::default()).await;
+        let mut pages = snapshot.pages();
+        let mut keys = Vec::new();
+        let mut changed = false;
+        while let Some(page) = pages.next().await.unwrap() {
+            for (key, _) in page {
+                keys.push(u64::from_be_bytes(key.try_into().unwrap()));
+            }
+            // Split and consolidate the pages ahead of the scan.
+            if !changed {
+                for i in (1..N).step_by(2) {
+                    must_put(&table, i, 2).await;
+                }
+                for i in (0..N).step_by(4) {
+                    table.delete(&i.to_be_bytes(), 3).await.unwrap();
+                }
+                table.consolidate_all().await.unwrap();
+                changed = true;
+            }
+        }
+        assert_eq!(keys, (0..N).step_by(2).collect::<Vec<_>>());
+        assert_eq!(pages.stats().restarts, 0);
+        drop(pages);
+        drop(snapshot);
+
+        for i in 0..N {
+            let expect = if i % 4 == 0 { None } else { Some(i) };
+            must_get(&table, i, 3, expect).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn drop_prefix() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for bucket in 0..3u64 {
+            for i in 0..N {
+                must_put(&table, bucket << 32 | i, 1).await;
+            }
+        }
+        let prefix = (1u64 << 32).to_be_bytes();
+        let stats = table.drop_prefix(&prefix[..4], 2).await.unwrap();
+        assert!(stats.pages > 0);
+        assert!(stats.keys < N);
+        for bucket in 0..3u64 {
+            for i in 0..N {
+                let key = bucket << 32 | i;
+                let expect = if bucket == 1 { None } else { Some(key) };
+                must_get(&table, key, 2, expect).await;
+            }
+        }
+        // The empty range can be written again.
+        must_put(&table, 1 << 32, 3).await;
+        must_get(&table, 1 << 32, 3, Some(1 << 32)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn drop_prefix_with_linked_split() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const B: u64 = 8;
+        const N: u64 = 1 << 6;
+        for bucket in 0..B {
+            for i in 0..N {
+                must_put(&table, bucket << 32 | i, 1).await;
+            }
+        }
+        table.consolidate_all().await.unwrap();
+
+        // Find a leaf page that starts within a bucket and ends within the
+        // next one.
+        let entries = table.index_entries().await.unwrap();
+        let bucket = (1..B - 1)
+            .find(|&bucket| {
+                let start = (bucket << 32).to_be_bytes();
+                let end = ((bucket + 1) << 32).to_be_bytes();
+                let index = entries.partition_point(|e| e.start.as_slice() <= &end[..]) - 1;
+                let page_start = entries[index].start.as_slice();
+                page_start >= &start[..] && page_start < &end[..]
+            })
+            .unwrap();
+        // Split the page within the bucket, and leave the split delta on the
+        // left page, whose right page is linked by the parent and straddles
+        // the end of the bucket.
+        let splits = table.stats().tree.success.split_page;
+        let mut i = N;
+        while table.stats().tree.success.split_page == splits {
+            assert!(i < N * 2);
+            must_put(&table, bucket << 32 | i, 2).await;
+            i += 1;
+        }
+        assert_eq!(
+            table.index_entries().await.unwrap().len(),
+            entries.len() + 1
+        );
+
+        let prefix = (bucket << 32).to_be_bytes();
+        table.drop_prefix(&prefix[..4], 3).await.unwrap();
+        for b in 0..B {
+            for i in 0..N {
+                let key = b << 32 | i;
+                let expect = if b == bucket { None } else { Some(key) };
+                must_get(&table, key, 3, expect).await;
+            }
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn count_range() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        let key = |i: u64| i.to_be_bytes();
+        let count = |start: u64, end: Option<u64>, lsn: u64| {
+            let table = &table;
+            async move {
+                let end = end.map(key);
+                table
+                    .count_range(&key(start), end.as_ref().map(|k| k.as_slice()), lsn)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(count(0, None, 1).await, N);
+        assert_eq!(count(0, None, 0).await, 0);
+
+        // Clean pages are counted without decoding entries.
+        table.set_safe_lsn(1);
+        table.seal().await.unwrap();
+        table.unseal();
+        assert_eq!(count(0, None, 1).await, N);
+        assert_eq!(count(10, Some(100), 1).await, 90);
+        assert_eq!(count(N - 1, Some(N + 1), 1).await, 1);
+        assert_eq!(count(N, None, 1).await, 0);
+        assert_eq!(count(100, Some(100), 1).await, 0);
+
+        // Tombstones fall back to a full count of the affected pages.
+        for i in (0..N).step_by(2) {
+            table.delete(&key(i), 2).await.unwrap();
+        }
+        assert_eq!(count(0, None, 1).await, N);
+        assert_eq!(count(0, None, 2).await, N / 2);
+        assert_eq!(count(10, Some(100), 2).await, 45);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn approx_key_count() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        assert_eq!(table.stats().approx_key_count, N);
+
+        // Overwrites and deletes of absent keys are corrected by consolidation.
+        for i in 0..N / 2 {
+            must_put(&table, i, 2).await;
+        }
+        for i in (0..N + 16).step_by(4) {
+            table.delete(&i.to_be_bytes(), 3).await.unwrap();
+        }
+        table.consolidate_all().await.unwrap();
+        assert_eq!(table.stats().approx_key_count, N - N / 4);
+        table.close().await.unwrap();
+
+        // The count starts from zero after reopening until it is recounted.
+        let table = Table::open(&path, options()).await.unwrap();
+        assert_eq!(table.stats().approx_key_count, 0);
+        assert_eq!(table.recount_keys().await.unwrap(), N - N / 4);
+        assert_eq!(table.stats().approx_key_count, N - N / 4);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn empty_key() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        // Enough versions of the empty key to fill and split pages around it.
+        for lsn in 1..(1 << 6) {
+            table.put(&[], lsn, &lsn.to_be_bytes()).await.unwrap();
+            table.put(&[0], lsn, &[0]).await.unwrap();
+            table.put(&lsn.to_be_bytes(), lsn, &[1]).await.unwrap();
+        }
+        let lsn = 1 << 6;
+        assert_eq!(
+            table.get(&[], lsn).await.unwrap(),
+            Some((lsn - 1).to_be_bytes().to_vec())
+        );
+        assert_eq!(
+            table.get(&[], 1).await.unwrap(),
+            Some(1u64.to_be_bytes().to_vec())
+        );
+        assert_eq!(table.get(&[0], lsn).await.unwrap(), Some(vec![0]));
+        assert_eq!(table.count_range(&[], Some(&[0]), lsn).await.unwrap(), 1);
+
+        // The empty key is the first key of a scan.
+        let guard = table.pin();
+        let mut pages = guard.pages();
+        let mut keys = Vec::new();
+        while let Some(page) = pages.next().await.unwrap() {
+            keys.extend(page.map(|(k, _)| k.to_vec()));
+        }
+        assert_eq!(keys.len(), 2 + (1 << 6) - 1);
+        assert_eq!(keys[0], Vec::<u8>::new());
+        assert_eq!(keys[1], vec![0]);
+        drop(pages);
+        drop(guard);
+
+        table.delete(&[], lsn).await.unwrap();
+        assert_eq!(table.get(&[], lsn).await.unwrap(), None);
+        assert!(table.contains(&[0], lsn).await.unwrap());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn warmup() {
+        let path = tempdir().unwrap();
+        let mut opts = options();
+        opts.page_store.cache_capacity = 1 << 20;
+        opts.page_store.prepopulate_cache_on_flush = false;
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        const N: u64 = 1 << 8;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+
+        let keys = (0..N).map(|i| i.to_be_bytes()).collect::<Vec<_>>();
+        let keys = || keys.iter().map(|k| k.as_slice());
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        let loaded = table.warmup(keys()).await.unwrap();
+        assert!(loaded > 0);
+        // Pages are cached now.
+        assert_eq!(table.warmup(keys()).await.unwrap(), 0);
+        assert_eq!(table.warmup_range(&[], None).await.unwrap(), 0);
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        assert!(table.warmup_range(&[], None).await.unwrap() >= loaded);
+        table.close().await.unwrap();
+
+        // Warming stops once the cache is full.
+        opts.page_store.cache_capacity = 1;
+        let table = Table::open(&path, opts).await.unwrap();
+        assert!(table.warmup_range(&[], None).await.unwrap() < loaded);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn bulk_load_merge() {
+        const N: u64 = 1 << 10;
+        // Keys that are multiples of 4 are in all inputs.
+        let inputs = || {
+            (0..3u8)
+                .map(|rank| {
+                    (0..N)
+                        .filter(move |i| i % 3 == rank as u64 || i % 4 == 0)
+                        .map(move |i| (i.to_be_bytes().to_vec(), vec![rank]))
+                })
+                .collect::<Vec<_>>()
+        };
+        for (rule, winner) in [
+            (DuplicateKeyRule::FirstInput, 0),
+            (DuplicateKeyRule::LastInput, 2),
+        ] {
+            let path = tempdir().unwrap();
+            let table = Table::open(&path, options()).await.unwrap();
+            let loaded = table.bulk_load_merge(inputs(), 1, rule).await.unwrap();
+            assert_eq!(loaded, N);
+            for i in 0..N {
+                let expect = if i % 4 == 0 { winner } else { (i % 3) as u8 };
+                let value = table.get(&i.to_be_bytes(), 1).await.unwrap();
+                assert_eq!(value, Some(vec![expect]));
+            }
+            table.close().await.unwrap();
+        }
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        assert!(matches!(
+            table
+                .bulk_load_merge(inputs(), 1, DuplicateKeyRule::Reject)
+                .await,
+            Err(Error::InvalidArgument)
+        ));
+        let unsorted = vec![vec![(vec![2], vec![]), (vec![1], vec![])].into_iter()];
+        assert!(matches!(
+            table
+                .bulk_load_merge(unsorted, 2, DuplicateKeyRule::Reject)
+                .await,
+            Err(Error::InvalidArgument)
+        ));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_filter() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        let even = |_: &[u8], v: &[u8]| v[7] % 2 == 0;
+        let entries = table
+            .scan_filter(&[], None, ReadOptions::default(), even)
+            .await
+            .unwrap();
+        assert_eq!(entries.len() as u64, N / 2);
+        assert!(entries.iter().all(|(k, v)| k == v && v[7] % 2 == 0));
+
+        let (start, end) = (N / 4, N / 2);
+        let calls = ::std::cell::Cell::new(0);
+        let entries = table
+            .scan_filter(
+                &start.to_be_bytes(),
+                Some(&end.to_be_bytes()),
+                ReadOptions::default(),
+                |k, v| {
+                    calls.set(calls.get() + 1);
+                    even(k, v)
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(calls.get(), end - start);
+        assert_eq!(entries.len() as u64, (end - start) / 2);
+        assert_eq!(entries[0].0, start.to_be_bytes());
+
+        let read_opts = ReadOptions {
+            max_lsn: 0,
+            ..Default::default()
+        };
+        let entries = table.scan_filter(&[], None, read_opts, |_, _| true).await;
+        assert!(entries.unwrap().is_empty());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_bytes_chunked() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        // Each entry has a key and a value of 8 bytes.
+        let chunk_bytes = 16 * 10 + 8;
+        let mut cursor = Some(Vec::new());
+        let mut entries = Vec::new();
+        while let Some(start) = cursor {
+            let chunk = table
+                .scan_bytes_chunked(&start, None, ReadOptions::default(), chunk_bytes)
+                .await
+                .unwrap();
+            assert!(chunk.bytes <= chunk_bytes);
+            if chunk.cursor.is_some() {
+                assert_eq!(chunk.entries.len(), 10);
+            }
+            entries.extend(chunk.entries);
+            cursor = chunk.cursor;
+        }
+        assert_eq!(entries.len() as u64, N);
+        for (i, (k, v)) in entries.iter().enumerate() {
+            assert_eq!(k, &(i as u64).to_be_bytes());
+            assert_eq!(k, v);
+        }
+
+        // An entry larger than the chunk forms a chunk of its own.
+        let (start, end) = (8u64.to_be_bytes(), 10u64.to_be_bytes());
+        let chunk = table
+            .scan_bytes_chunked(&start, Some(&end), ReadOptions::default(), 1)
+            .await
+            .unwrap();
+        assert_eq!(chunk.entries.len(), 1);
+        assert_eq!(chunk.cursor.as_deref(), Some(9u64.to_be_bytes().as_slice()));
+        let chunk = table
+            .scan_bytes_chunked(
+                &chunk.cursor.unwrap(),
+                Some(&end),
+                ReadOptions::default(),
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(chunk.entries.len(), 1);
+        assert_eq!(chunk.cursor, None);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn degenerate_ranges() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        let all = |_: &[u8], _: &[u8]| true;
+        let (lo, hi) = (10u64.to_be_bytes(), 20u64.to_be_bytes());
+        let next = 11u64.to_be_bytes();
+
+        // Empty ranges.
+        assert_eq!(table.count_range(&lo, Some(&lo), 1).await.unwrap(), 0);
+        assert_eq!(table.warmup_range(&lo, Some(&lo)).await.unwrap(), 0);
+        let entries = table
+            .scan_filter(&lo, Some(&lo), ReadOptions::default(), all)
+            .await
+            .unwrap();
+        assert!(entries.is_empty());
+
+        // Inverted ranges.
+        assert!(matches!(
+            table.count_range(&hi, Some(&lo), 1).await,
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            table.warmup_range(&hi, Some(&lo)).await,
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            table
+                .scan_filter(&hi, Some(&lo), ReadOptions::default(), all)
+                .await,
+            Err(Error::InvalidArgument)
+        ));
+        let file = path.path().join("inverted");
+        assert!(matches!(
+            table.scan_to_file(&file, &hi, Some(&lo), 1).await,
+            Err(Error::InvalidArgument)
+        ));
+
+        // Single-key ranges.
+        assert_eq!(table.count_range(&lo, Some(&next), 1).await.unwrap(), 1);
+        let entries = table
+            .scan_filter(&lo, Some(&next), ReadOptions::default(), all)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, lo);
+
+        // The full key space.
+        assert_eq!(table.count_range(&[], None, 1).await.unwrap(), N);
+        let entries = table
+            .scan_filter(&[], None, ReadOptions::default(), all)
+            .await
+            .unwrap();
+        assert_eq!(entries.len() as u64, N);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_to_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("table");
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        let file = dir.path().join("scan");
+        let (start, end) = (N / 4, N / 2);
+        let stats = table
+            .scan_to_file(&file, &start.to_be_bytes(), Some(&end.to_be_bytes()), 1)
+            .await
+            .unwrap();
+        assert_eq!(stats.rows, end - start);
+        assert_eq!(stats.bytes, ::std::fs::metadata(&file).unwrap().len());
+        let stats = table.scan_to_file(&file, &[], None, 1).await.unwrap();
+        assert_eq!(stats.rows, N);
+        table.close().await.unwrap();
+
+        let table = Table::open(dir.path().join("other"), options())
+            .await
+            .unwrap();
+        let content = ::std::fs::read(&file).unwrap();
+        assert_eq!(table.import(content.as_slice()).await.unwrap(), N);
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_segmented() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for lsn in [1, 2] {
+            for i in 0..N {
+                let (key, value) = (i.to_be_bytes(), (i + lsn).to_be_bytes());
+                table.put(&key, lsn, &value).await.unwrap();
+            }
+        }
+
+        let key = |i: u64| i.to_be_bytes().to_vec();
+        let mut stable = ReadOptions::default();
+        stable.max_lsn = 1;
+        stable.fill_cache = false;
+        let segments = [
+            ScanSegment::new(key(N / 8), Some(key(N / 2)), stable),
+            ScanSegment::new(key(N / 2), None, ReadOptions::default()),
+        ];
+        let guard = table.pin();
+        let mut scan = guard.scan_segmented(&segments).unwrap();
+        let mut i = N / 8;
+        while let Some((k, v)) = scan.next().await.unwrap() {
+            let lsn = if i < N / 2 { 1 } else { 2 };
+            assert_eq!(k, key(i));
+            assert_eq!(v, key(i + lsn));
+            i += 1;
+        }
+        assert_eq!(i, N);
+
+        // Segments must be sorted and must not overlap.
+        let mut overlapped = segments.clone();
+        overlapped[0].end = Some(key(N / 2 + 1));
+        assert!(guard.scan_segmented(&overlapped).is_err());
+        overlapped.reverse();
+        assert!(guard.scan_segmented(&overlapped).is_err());
+        drop(scan);
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn deleted_regions() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        assert!(table.deleted_regions(2).await.unwrap().is_empty());
+        for i in N / 4..N * 3 / 4 {
+            table.delete(&i.to_be_bytes(), 2).await.unwrap();
+        }
+
+        let contains = |region: &DeletedRegion, i: u64| {
+            let key = i.to_be_bytes().to_vec();
+            region.start <= key && region.end.as_ref().map_or(true, |end| &key < end)
+        };
+        let regions = table.deleted_regions(2).await.unwrap();
+        assert_eq!(regions.len(), 1);
+        let region = &regions[0];
+        assert!(region.pages > 0);
+        assert!(region.tombstones * 2 > region.keys);
+        assert!(contains(region, N / 2));
+        assert!(!contains(region, 0));
+        assert!(!contains(region, N - 1));
+        // Nothing is deleted before the deletes.
+        assert!(table.deleted_regions(1).await.unwrap().is_empty());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn replication_stream() {
+        async fn drain(stream: &mut raw::ReplicationStream<env::Photon>, follower: &Table) -> u64 {
+            let mut safe_lsn = 0;
+            while let Some(record) = stream.next().await.unwrap() {
+                match record {
+                    ReplicationRecord::Entry { key, lsn, value } => {
+                        assert!(lsn > safe_lsn);
+                        match value {
+                            Some(value) => follower.put(&key, lsn, &value).await.unwrap(),
+                            None => follower.delete(&key, lsn).await.unwrap(),
+                        }
+                    }
+                    ReplicationRecord::SafeLsn(lsn) => safe_lsn = lsn,
+                }
+            }
+            safe_lsn
+        }
+
+        let path = tempdir().unwrap();
+        let leader = Table::open(&path, options()).await.unwrap();
+        let follower_path = tempdir().unwrap();
+        let follower = Table::open(&follower_path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        // Read the versions in many small batches.
+        let mut stream = leader.replication_stream(0).with_batch_bytes(256);
+        for i in 0..N {
+            must_put(&leader, i, 1).await;
+        }
+        // Nothing is shipped before the safe LSN advances.
+        assert_eq!(drain(&mut stream, &follower).await, 0);
+        leader.set_safe_lsn(1);
+        assert_eq!(drain(&mut stream, &follower).await, 1);
+
+        for i in 0..N / 2 {
+            leader.delete(&i.to_be_bytes(), 2).await.unwrap();
+        }
+        for i in N / 2..N {
+            let value = (i + 1).to_be_bytes();
+            leader.put(&i.to_be_bytes(), 3, &value).await.unwrap();
+        }
+        leader.set_safe_lsn(3);
+        // The tombstones are retained for the stream.
+        leader.consolidate_all().await.unwrap();
+        assert_eq!(drain(&mut stream, &follower).await, 3);
+        assert_eq!(stream.shipped_lsn(), 3);
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            let expect = leader.get(&key, 3).await.unwrap();
+            assert_eq!(follower.get(&key, 3).await.unwrap(), expect);
+        }
+
+        // Resume from the last marker with a new stream.
+        drop(stream);
+        let mut stream = leader.replication_stream(3);
+        must_put(&leader, 0, 4).await;
+        leader.set_safe_lsn(4);
+        assert_eq!(drain(&mut stream, &follower).await, 4);
+        must_get(&follower, 0, 4, Some(0)).await;
+        leader.close().await.unwrap();
+        follower.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn stats_counters() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        // Background jobs may look up the cache in between.
+        let cache_hits = table.cache_hit_count();
+        let stats = table.stats();
+        assert_eq!(table.read_count(), stats.tree.success.read);
+        assert_eq!(table.write_count(), stats.tree.success.write);
+        assert!(cache_hits <= stats.store.page_cache.lookup_hit);
+        assert!(table.read_count() >= N);
+        assert!(table.write_count() >= N);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn pause_background() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        table.pause_background().await;
+        assert!(table.stats().background_paused);
+        const N: u64 = 100;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.resume_background();
+        assert!(!table.stats().background_paused);
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+
+        let opts = TableOptions {
+            reject_writes_while_paused: true,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        table.pause_background().await;
+        let buf = N.to_be_bytes();
+        assert!(matches!(table.put(&buf, 2, &buf).await, Err(Error::Paused)));
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.resume_background();
+        must_put(&table, N, 2).await;
+        table.close().await.unwrap();
+    }
+}
@@ -11,15 +11,21 @@
 
 use std::{
     future::Future,
-    ops::Deref,
+    io::{Read, Write},
+    ops::{Deref, RangeBounds},
     path::Path,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use futures::task::noop_waker_ref;
+use futures::{io::AllowStdIo, task::noop_waker_ref};
 
-use crate::{env::Std, raw, PageIter, Result, TableOptions};
+use crate::{
+    env::Std, raw, CandidateInfo, CompactionPlan, ConsolidateStats, DiffReport, DropPrefixStats,
+    DuplicateKeyRule, FileStat, FlushOptions, IndexEntry, KeyStatus, PageIter, PinnedBuffer,
+    ReadOptions, RecoveryProgress, Result, RewriteStats, ScanChunk, ScanFileStats, ScanOptions,
+    TableOptions, WriteOptions,
+};
 
 /// A reference to a latch-free, log-structured table that stores sorted
 /// key-value entries.
@@ -38,6 +44,56 @@ impl Table {
         Ok(Self(table))
     }
 
+    /// Opens a table in the path with the given options, and reports the
+    /// progress of recovery to the callback.
+    ///
+    /// This is a synchronous version of [`raw::Table::open_with_progress`]
+    /// with the [`Std`] environment.
+    pub fn open_with_progress<P, F>(path: P, options: TableOptions, progress: F) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        F: FnMut(RecoveryProgress) + Send,
+    {
+        let table = poll(raw::Table::open_with_progress(Std, path, options, progress))?;
+        Ok(Self(table))
+    }
+
+    /// Opens a read-only view of the table in the path as of the page file
+    /// `generation`.
+    ///
+    /// This is a synchronous version of [`raw::Table::open_at_generation`]
+    /// with the [`Std`] environment.
+    pub fn open_at_generation<P: AsRef<Path>>(
+        path: P,
+        generation: u32,
+        options: TableOptions,
+    ) -> Result<Self> {
+        let table = poll(raw::Table::open_at_generation(
+            Std, path, generation, options,
+        ))?;
+        Ok(Self(table))
+    }
+
+    /// Rewrites the table in `src` to a new table in `dst` with the options.
+    ///
+    /// This is a synchronous version of [`raw::Table::rewrite_with_options`]
+    /// with the [`Std`] environment.
+    pub fn rewrite_with_options<P, Q, F>(
+        src: P,
+        dst: Q,
+        options: TableOptions,
+        progress: F,
+    ) -> Result<RewriteStats>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(&RewriteStats),
+    {
+        poll(raw::Table::rewrite_with_options(
+            Std, src, dst, options, progress,
+        ))
+    }
+
     /// Closes the table if this is the only reference to it.
     ///
     /// This is a synchronous version of [`raw::Table::close`].
@@ -45,11 +101,30 @@ impl Table {
         poll(self.0.close()).map_err(Self)
     }
 
+    /// Stops the background jobs of the table and waits for them to exit.
+    ///
+    /// This is a synchronous version of [`raw::Table::shutdown`].
+    pub fn shutdown(&self) {
+        poll(self.0.shutdown())
+    }
+
+    /// Begins a [`Txn`] that spans multiple operations on the table.
+    pub fn begin_txn(&self) -> Txn<'_> {
+        Txn(self.0.begin_txn())
+    }
+
     /// Returns a [`Guard`] that pins the table for user operations.
     pub fn pin(&self) -> Guard<'_> {
         Guard(self.0.pin())
     }
 
+    /// Takes a snapshot of the table to scan with the read options.
+    ///
+    /// This is a synchronous version of [`raw::Table::scan_snapshot`].
+    pub fn scan_snapshot(&self, options: ReadOptions) -> Snapshot<'_> {
+        Snapshot(poll(self.0.scan_snapshot(options)))
+    }
+
     /// Gets the value corresponding to the key.
     ///
     /// This is a synchronous version of [`raw::Table::get`].
@@ -57,6 +132,85 @@ impl Table {
         poll(self.0.get(key, lsn))
     }
 
+    /// Gets the value corresponding to the key with the given options.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_with_options`].
+    pub fn get_with_options(&self, key: &[u8], options: &ReadOptions) -> Result<Option<Vec<u8>>> {
+        poll(self.0.get_with_options(key, options))
+    }
+
+    /// Gets the value corresponding to the key as an owned [`PinnedBuffer`].
+    ///
+    /// This is a synchronous version of [`raw::Table::get_owned_pinned`].
+    pub fn get_owned_pinned(&self, key: &[u8], lsn: u64) -> Result<Option<PinnedBuffer>> {
+        poll(self.0.get_owned_pinned(key, lsn))
+    }
+
+    /// Gets the value corresponding to the key, and the status of the key.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_with_status`].
+    pub fn get_with_status(&self, key: &[u8], lsn: u64) -> Result<(Option<Vec<u8>>, KeyStatus)> {
+        poll(self.0.get_with_status(key, lsn))
+    }
+
+    /// Gets the value corresponding to the key, and the LSN that the value is
+    /// written at.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_versioned`].
+    pub fn get_versioned(&self, key: &[u8], lsn: u64) -> Result<Option<(Vec<u8>, u64)>> {
+        poll(self.0.get_versioned(key, lsn))
+    }
+
+    /// Returns true if the key has a value at the LSN.
+    ///
+    /// This is a synchronous version of [`raw::Table::contains`].
+    pub fn contains(&self, key: &[u8], lsn: u64) -> Result<bool> {
+        poll(self.0.contains(key, lsn))
+    }
+
+    /// Gets the versions of the key whose LSNs are in the range.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_history`].
+    pub fn get_history<R: RangeBounds<u64>>(
+        &self,
+        key: &[u8],
+        range: R,
+    ) -> Result<Vec<(u64, Option<Vec<u8>>)>> {
+        poll(self.0.get_history(key, range))
+    }
+
+    /// Counts the keys in `[start, end)` that have a value at the LSN.
+    ///
+    /// This is a synchronous version of [`raw::Table::count_range`].
+    pub fn count_range(&self, start: &[u8], end: Option<&[u8]>, lsn: u64) -> Result<u64> {
+        poll(self.0.count_range(start, end, lsn))
+    }
+
+    /// Counts the live keys of the table, and resets the approximate key
+    /// count to it.
+    ///
+    /// This is a synchronous version of [`raw::Table::recount_keys`].
+    pub fn recount_keys(&self) -> Result<u64> {
+        poll(self.0.recount_keys())
+    }
+
+    /// Loads the leaf pages that contain the keys into the page cache.
+    ///
+    /// This is a synchronous version of [`raw::Table::warmup`].
+    pub fn warmup<'k, I>(&self, keys: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = &'k [u8]>,
+    {
+        poll(self.0.warmup(keys))
+    }
+
+    /// Loads the leaf pages of keys in `[start, end)` into the page cache.
+    ///
+    /// This is a synchronous version of [`raw::Table::warmup_range`].
+    pub fn warmup_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<u64> {
+        poll(self.0.warmup_range(start, end))
+    }
+
     /// Puts a key-value entry to the table.
     ///
     /// This is a synchronous version of [`raw::Table::put`].
@@ -64,12 +218,227 @@ impl Table {
         poll(self.0.put(key, lsn, value))
     }
 
+    /// Puts a key-value entry to the table with the given options.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_with_options`].
+    pub fn put_with_options(
+        &self,
+        key: &[u8],
+        lsn: u64,
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> Result<()> {
+        poll(self.0.put_with_options(key, lsn, value, options))
+    }
+
+    /// Puts a key-value entry with a logical timestamp to the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_timestamped`].
+    pub fn put_timestamped(&self, key: &[u8], lsn: u64, value: &[u8], ts: u64) -> Result<()> {
+        poll(self.0.put_timestamped(key, lsn, value, ts))
+    }
+
+    /// Puts a key-value entry to the table, and returns the previous value of
+    /// the key.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_returning_old`].
+    pub fn put_returning_old(&self, key: &[u8], lsn: u64, value: &[u8]) -> Result<Option<Vec<u8>>> {
+        poll(self.0.put_returning_old(key, lsn, value))
+    }
+
     /// Deletes the entry corresponding to the key from the table.
     ///
     /// This is a synchronous version of [`raw::Table::delete`].
     pub fn delete(&self, key: &[u8], lsn: u64) -> Result<()> {
         poll(self.0.delete(key, lsn))
     }
+
+    /// Drops all keys with the prefix.
+    ///
+    /// This is a synchronous version of [`raw::Table::drop_prefix`].
+    pub fn drop_prefix(&self, prefix: &[u8], lsn: u64) -> Result<DropPrefixStats> {
+        poll(self.0.drop_prefix(prefix, lsn))
+    }
+
+    /// Gets the latest value corresponding to the key.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_simple`].
+    pub fn get_simple(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        poll(self.0.get_simple(key))
+    }
+
+    /// Puts a key-value entry to the table with an LSN allocated by the
+    /// table.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_simple`].
+    pub fn put_simple(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        poll(self.0.put_simple(key, value))
+    }
+
+    /// Deletes the entry corresponding to the key from the table with an LSN
+    /// allocated by the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::delete_simple`].
+    pub fn delete_simple(&self, key: &[u8]) -> Result<()> {
+        poll(self.0.delete_simple(key))
+    }
+
+    /// Flush all write buffer data.
+    ///
+    /// This is a synchronous version of [`raw::Table::flush`].
+    pub fn flush(&self, opts: &FlushOptions) {
+        poll(self.0.flush(opts))
+    }
+
+    /// Exports entries visible to the LSN in a portable format.
+    ///
+    /// This is a synchronous version of [`raw::Table::export`].
+    pub fn export<W: Write>(&self, writer: W, lsn: u64) -> Result<u64> {
+        poll(self.0.export(AllowStdIo::new(writer), lsn))
+    }
+
+    /// Writes entries in `[start, end)` visible to the LSN to a file in the
+    /// format of [`Table::export`].
+    ///
+    /// This is a synchronous version of [`raw::Table::scan_to_file`].
+    pub fn scan_to_file<P: AsRef<Path> + Send>(
+        &self,
+        path: P,
+        start: &[u8],
+        end: Option<&[u8]>,
+        lsn: u64,
+    ) -> Result<ScanFileStats> {
+        poll(self.0.scan_to_file(path, start, end, lsn))
+    }
+
+    /// Returns entries in `[start, end)` that satisfy the predicate.
+    ///
+    /// This is a synchronous version of [`raw::Table::scan_filter`].
+    pub fn scan_filter<F>(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        options: ReadOptions,
+        predicate: F,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>>
+    where
+        F: Fn(&[u8], &[u8]) -> bool,
+    {
+        poll(self.0.scan_filter(start, end, options, predicate))
+    }
+
+    /// Returns the next chunk of entries in `[start, end)`, which holds about
+    /// `chunk_bytes` bytes of keys and values.
+    ///
+    /// This is a synchronous version of [`raw::Table::scan_bytes_chunked`].
+    pub fn scan_bytes_chunked(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        options: ReadOptions,
+        chunk_bytes: usize,
+    ) -> Result<ScanChunk> {
+        poll(self.0.scan_bytes_chunked(start, end, options, chunk_bytes))
+    }
+
+    /// Imports entries exported by [`Table::export`].
+    ///
+    /// This is a synchronous version of [`raw::Table::import`].
+    pub fn import<R: Read>(&self, reader: R) -> Result<u64> {
+        poll(self.0.import(AllowStdIo::new(reader)))
+    }
+
+    /// Loads entries merged from multiple sorted inputs with the LSN.
+    ///
+    /// This is a synchronous version of [`raw::Table::bulk_load_merge`].
+    pub fn bulk_load_merge<I>(
+        &self,
+        inputs: Vec<I>,
+        lsn: u64,
+        rule: DuplicateKeyRule,
+    ) -> Result<u64>
+    where
+        I: Iterator<Item = (Vec<u8>, Vec<u8>)>,
+    {
+        poll(self.0.bulk_load_merge(inputs, lsn, rule))
+    }
+
+    /// Returns the leaf pages of the table in the order of keys.
+    ///
+    /// This is a synchronous version of [`raw::Table::index_entries`].
+    pub fn index_entries(&self) -> Result<Vec<IndexEntry>> {
+        poll(self.0.index_entries())
+    }
+
+    /// Returns the page files that can be compacted.
+    ///
+    /// This is a synchronous version of [`raw::Table::compaction_candidates`].
+    pub fn compaction_candidates(&self) -> Vec<CandidateInfo> {
+        poll(self.0.compaction_candidates())
+    }
+
+    /// Returns the statistics of the page files, sorted by file ids.
+    ///
+    /// This is a synchronous version of [`raw::Table::file_stats`].
+    pub fn file_stats(&self) -> Vec<FileStat> {
+        self.0.file_stats()
+    }
+
+    /// Compacts the page file with the given id.
+    ///
+    /// This is a synchronous version of [`raw::Table::compact_file`].
+    pub fn compact_file(&self, file_id: u32) -> Result<()> {
+        poll(self.0.compact_file(file_id))
+    }
+
+    /// Adds a value to the key in the multimap stored in the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_multi`].
+    pub fn put_multi(&self, key: &[u8], value: &[u8], lsn: u64) -> Result<()> {
+        poll(self.0.put_multi(key, value, lsn))
+    }
+
+    /// Gets all values of the key in the multimap stored in the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_multi`].
+    pub fn get_multi(&self, key: &[u8], lsn: u64) -> Result<Vec<Vec<u8>>> {
+        poll(self.0.get_multi(key, lsn))
+    }
+
+    /// Removes a value from the key in the multimap stored in the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::delete_multi`].
+    pub fn delete_multi(&self, key: &[u8], value: &[u8], lsn: u64) -> Result<()> {
+        poll(self.0.delete_multi(key, value, lsn))
+    }
+
+    /// Seals the table for read-only serving.
+    ///
+    /// This is a synchronous version of [`raw::Table::seal`].
+    pub fn seal(&self) -> Result<()> {
+        poll(self.0.seal())
+    }
+
+    /// Pauses the background jobs of the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::pause_background`].
+    pub fn pause_background(&self) {
+        poll(self.0.pause_background())
+    }
+
+    /// Consolidates all pages in the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::consolidate_all`].
+    pub fn consolidate_all(&self) -> Result<ConsolidateStats> {
+        poll(self.0.consolidate_all())
+    }
+
+    /// Estimates the work of consolidating the pages in `[start, end)`.
+    ///
+    /// This is a synchronous version of [`raw::Table::plan_compaction`].
+    pub fn plan_compaction(&self, start: &[u8], end: Option<&[u8]>) -> Result<CompactionPlan> {
+        poll(self.0.plan_compaction(start, end))
+    }
 }
 
 impl Deref for Table {
@@ -95,6 +464,25 @@ impl<'a> Guard<'a> {
     pub fn pages(&self) -> Pages<'_, 'a> {
         Pages(self.0.pages())
     }
+
+    /// Returns an iterator over pages in the table with the given options.
+    pub fn pages_with_options(&self, options: ScanOptions) -> Pages<'_, 'a> {
+        Pages(self.0.pages_with_options(options))
+    }
+
+    /// Returns an iterator over entries in the table.
+    pub fn scan(&self) -> Scan<'_, 'a> {
+        self.scan_with_options(ScanOptions::default())
+    }
+
+    /// Returns an iterator over entries in the table with the given options.
+    pub fn scan_with_options(&self, options: ScanOptions) -> Scan<'_, 'a> {
+        Scan {
+            pages: self.pages_with_options(options),
+            entries: Vec::new().into_iter(),
+            done: false,
+        }
+    }
 }
 
 impl<'a> Deref for Guard<'a> {
@@ -105,6 +493,53 @@ impl<'a> Deref for Guard<'a> {
     }
 }
 
+/// A point-in-time view of a table.
+pub struct Snapshot<'a>(raw::Snapshot<'a, Std>);
+
+impl<'a> Snapshot<'a> {
+    /// Returns an iterator over pages in the snapshot.
+    pub fn pages(&self) -> Pages<'_, 'a> {
+        Pages(self.0.pages())
+    }
+}
+
+impl<'a> Deref for Snapshot<'a> {
+    type Target = raw::Snapshot<'a, Std>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A transaction that spans multiple operations on a table.
+pub struct Txn<'a>(raw::Txn<'a, Std>);
+
+impl<'a> Txn<'a> {
+    /// Gets the latest value corresponding to the key.
+    ///
+    /// This is a synchronous version of [`raw::Txn::get`].
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        poll(self.0.get(key))
+    }
+
+    /// Puts a key-value pair to the transaction.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.0.put(key, value)
+    }
+
+    /// Deletes a key from the transaction.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.0.delete(key)
+    }
+
+    /// Commits the writes of the transaction with the LSN.
+    ///
+    /// This is a synchronous version of [`raw::Txn::commit`].
+    pub fn commit(self, lsn: u64) -> Result<()> {
+        poll(self.0.commit(lsn))
+    }
+}
+
 /// An iterator over pages in a table.
 pub struct Pages<'a, 't>(raw::Pages<'a, 't, Std>);
 
@@ -127,6 +562,51 @@ impl<'a, 't> Deref for Pages<'a, 't> {
     }
 }
 
+/// An iterator over entries in a table.
+///
+/// Unlike [`Pages`], this implements [`Iterator`]. Entries are copied out of
+/// the table page by page, so they don't borrow from the iterator.
+pub struct Scan<'a, 't> {
+    pages: Pages<'a, 't>,
+    entries: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    done: bool,
+}
+
+impl<'a, 't> Iterator for Scan<'a, 't> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            if self.done {
+                return None;
+            }
+            match self.pages.next() {
+                Ok(Some(page)) => {
+                    self.entries = page
+                        .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                        .collect::<Vec<_>>()
+                        .into_iter();
+                }
+                Ok(None) => self.done = true,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// Compares the entries of two tables visible to the LSN key by key.
+///
+/// This is a synchronous version of [`raw::diff_tables`].
+pub fn diff_tables(a: &Table, b: &Table, lsn: u64, max_diffs: Option<usize>) -> Result<DiffReport> {
+    poll(raw::diff_tables(&a.0, &b.0, lsn, max_diffs))
+}
+
 fn poll<F: Future>(mut future: F) -> F::Output {
     let cx = &mut Context::from_waker(noop_waker_ref());
     loop {
@@ -138,3 +618,33 @@ fn poll<F: Future>(mut future: F) -> F::Output {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn std_scan() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, TableOptions::default()).unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            table.put(&buf, i, &buf).unwrap();
+        }
+        table.flush(&FlushOptions::default());
+
+        let guard = table.pin();
+        let entries = guard.scan().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.len(), N as usize);
+        for (i, (k, v)) in entries.into_iter().enumerate() {
+            let buf = (i as u64).to_be_bytes();
+            assert_eq!(k, buf);
+            assert_eq!(v, buf);
+        }
+        drop(guard);
+        table.close().unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::util::sketch::CountMinSketch;
+
+/// The number of counters per row of the sketch.
+const SKETCH_WIDTH: usize = 4096;
+/// The maximum number of hot pages to track.
+const MAX_HOT_PAGES: usize = 64;
+
+/// Tracks the approximate access frequency of sampled leaf pages.
+///
+/// Accesses are counted in a count-min sketch, and the pages with the highest
+/// estimated counts are kept as hot pages. Both are reset periodically so that
+/// they reflect recent accesses.
+pub(super) struct AccessTracker {
+    sampling_rate: f64,
+    reset_interval: Duration,
+    sketch: CountMinSketch,
+    hot_pages: Mutex<Vec<u64>>,
+    last_reset: Mutex<Instant>,
+}
+
+impl AccessTracker {
+    pub(super) fn new(sampling_rate: f64, reset_interval: Duration) -> Self {
+        Self {
+            sampling_rate,
+            reset_interval,
+            sketch: CountMinSketch::new(SKETCH_WIDTH),
+            hot_pages: Mutex::new(Vec::with_capacity(MAX_HOT_PAGES)),
+            last_reset: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Records an access to the page if the access is sampled.
+    pub(super) fn record(&self, page_id: u64) {
+        if !should_sample(self.sampling_rate) {
+            return;
+        }
+
+        // Skip the reset if someone else is checking it.
+        if let Some(mut last_reset) = self.last_reset.try_lock() {
+            if last_reset.elapsed() >= self.reset_interval {
+                self.sketch.clear();
+                self.hot_pages.lock().clear();
+                *last_reset = Instant::now();
+            }
+        }
+
+        self.sketch.add(page_id);
+        // Hot pages are best-effort, skip the update on contention.
+        let Some(mut hot_pages) = self.hot_pages.try_lock() else {
+            return;
+        };
+        if hot_pages.contains(&page_id) {
+            return;
+        }
+        if hot_pages.len() < MAX_HOT_PAGES {
+            hot_pages.push(page_id);
+            return;
+        }
+        let count = self.sketch.estimate(page_id);
+        let (index, min_count) = hot_pages
+            .iter()
+            .map(|&id| self.sketch.estimate(id))
+            .enumerate()
+            .min_by_key(|&(_, count)| count)
+            .expect("hot pages are not empty");
+        if count > min_count {
+            hot_pages[index] = page_id;
+        }
+    }
+
+    /// Returns the hot pages with their approximate access counts, sorted by
+    /// the counts in descending order.
+    pub(super) fn hot_pages(&self) -> Vec<(u64, u64)> {
+        let mut pages = self
+            .hot_pages
+            .lock()
+            .iter()
+            .map(|&id| (id, self.sketch.estimate(id)))
+            .collect::<Vec<_>>();
+        pages.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        pages
+    }
+}
+
+/// Returns true with the probability of the rate.
+pub(super) fn should_sample(rate: f64) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    if rate >= 1.0 {
+        return true;
+    }
+
+    thread_local! {
+        static RNG: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+    }
+    // Xorshift64, see https://www.jstatsoft.org/article/view/v008i14
+    let x = RNG.with(|rng| {
+        let mut x = rng.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        rng.set(x);
+        x
+    });
+    ((x >> 11) as f64 / (1u64 << 53) as f64) < rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_tracker_hot_pages() {
+        let tracker = AccessTracker::new(1.0, Duration::MAX);
+        for id in 0..(MAX_HOT_PAGES as u64 * 2) {
+            for _ in 0..(id % 4) {
+                tracker.record(id);
+            }
+        }
+        for _ in 0..100 {
+            tracker.record(1000);
+        }
+        let hot_pages = tracker.hot_pages();
+        assert!(hot_pages.len() <= MAX_HOT_PAGES);
+        assert_eq!(hot_pages[0].0, 1000);
+        assert!(hot_pages[0].1 >= 100);
+
+        let tracker = AccessTracker::new(0.0, Duration::MAX);
+        tracker.record(1);
+        assert!(tracker.hot_pages().is_empty());
+
+        let tracker = AccessTracker::new(1.0, Duration::ZERO);
+        tracker.record(1);
+        tracker.record(2);
+        assert_eq!(tracker.hot_pages(), vec![(2, 1)]);
+    }
+}
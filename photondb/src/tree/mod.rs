@@ -1,45 +1,163 @@
 use std::{
+    collections::{HashMap, HashSet},
     fmt,
-    sync::atomic::{AtomicU64, Ordering},
-    time::Instant,
+    ops::RangeBounds,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
 };
 
 use log::trace;
+use parking_lot::Mutex;
 
-use crate::{env::Env, page::*, page_store::*};
+use crate::{
+    env::Env,
+    fault::{self, FaultPoint},
+    page::*,
+    page_store::*,
+};
 
 mod page;
-pub use page::PageIter;
 use page::*;
+pub use page::{DeletedRegion, IndexEntry, PageIter};
+
+mod access;
+use access::{should_sample, AccessTracker};
 
 mod stats;
 use stats::AtomicStats;
-pub use stats::TreeStats;
+pub use stats::{CompactionPlan, ConsolidateStats, ScanStats, SizeHistogram, TreeStats};
 
 mod options;
-pub use options::{Options, ReadOptions, WriteOptions};
+pub use options::{
+    DecisionEvent, DecisionKind, DecisionObserver, InnerConsolidation, OpenMode, Options,
+    OptionsPatch, ReadOptions, ScanOptions, SplitGuard, ValueCodec, WriteOptions,
+};
 
 pub(crate) struct Tree {
     options: Options,
     stats: AtomicStats,
+    access: AccessTracker,
     safe_lsn: AtomicU64,
+    /// The LSNs that replication streams have shipped entries up to.
+    ///
+    /// Versions newer than any of them are retained by consolidation even if
+    /// they are not visible to the safe LSN anymore.
+    replication_lsns: Mutex<Vec<Weak<AtomicU64>>>,
+    /// The largest LSN written to the tree, which the window of
+    /// [`Options::min_version_retention`] ends at.
+    latest_lsn: AtomicU64,
+    /// The largest LSN written before the tree was opened, see
+    /// [`Options::reject_lsn_regression`].
+    high_water_lsn: AtomicU64,
+    /// The approximate number of live keys, see [`Tree::approx_key_count`].
+    key_count: AtomicI64,
+    page_chain_length: AtomicUsize,
+    consolidate_keep_recent_deltas: AtomicUsize,
+    max_merge_chain: AtomicUsize,
+    structure_changes: StructureChanges,
+    /// The inner pages whose consolidations are deferred to the background,
+    /// with the keys that their ranges start at, see
+    /// [`Options::inner_consolidation`].
+    deferred_inner_pages: Mutex<HashMap<u64, Vec<u8>>>,
+    sealed: AtomicBool,
+    /// Whether the background jobs are paused, see
+    /// [`Options::reject_writes_while_paused`].
+    paused: AtomicBool,
+    read_only: bool,
 }
 
 impl Tree {
     pub(crate) fn new(options: Options) -> Self {
+        let page_chain_length = AtomicUsize::new(options.page_chain_length);
+        let consolidate_keep_recent_deltas =
+            AtomicUsize::new(options.consolidate_keep_recent_deltas);
+        let max_merge_chain = AtomicUsize::new(options.max_merge_chain);
+        let access = AccessTracker::new(
+            options.access_sampling_rate,
+            options.access_sampling_reset_interval,
+        );
         Self {
             options,
             stats: AtomicStats::default(),
+            access,
             safe_lsn: AtomicU64::new(0),
+            replication_lsns: Mutex::new(Vec::new()),
+            latest_lsn: AtomicU64::new(0),
+            high_water_lsn: AtomicU64::new(0),
+            key_count: AtomicI64::new(0),
+            page_chain_length,
+            consolidate_keep_recent_deltas,
+            max_merge_chain,
+            structure_changes: StructureChanges::default(),
+            deferred_inner_pages: Mutex::default(),
+            sealed: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            read_only: false,
         }
     }
 
+    /// Makes the tree sealed permanently, regardless of [`Self::set_sealed`].
+    pub(crate) fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
     pub(crate) fn begin<E: Env>(&self, guard: Guard<E>) -> TreeTxn<E> {
         TreeTxn::new(self, guard)
     }
 
+    /// Begins a transaction that reads the tree as of now with a frozen guard,
+    /// see [`Guard::freeze`].
+    ///
+    /// The page table is copied while no page is being split or reconciled
+    /// with its parent, and copied again if any of them starts during the
+    /// copy, so that the copied pages form a consistent tree.
+    pub(crate) async fn begin_snapshot<E: Env>(&self, mut guard: Guard<E>) -> TreeTxn<E> {
+        loop {
+            if let Some(seq) = self.structure_changes.stable() {
+                guard.freeze();
+                if self.structure_changes.unchanged_since(seq) {
+                    return TreeTxn::new(self, guard);
+                }
+            }
+            guard.yield_now().await;
+        }
+    }
+
     pub(crate) fn stats(&self) -> TreeStats {
-        self.stats.snapshot()
+        TreeStats {
+            page_size: self.options.page_size,
+            ..self.stats.snapshot()
+        }
+    }
+
+    /// Returns the number of succeeded reads.
+    pub(crate) fn read_count(&self) -> u64 {
+        self.stats.success.read.get()
+    }
+
+    /// Returns the number of succeeded writes.
+    pub(crate) fn write_count(&self) -> u64 {
+        self.stats.success.write.get()
+    }
+
+    /// Returns the durations of page consolidations.
+    pub(crate) fn consolidation_latency(&self) -> LatencyStats {
+        self.stats.consolidate_latency.snapshot()
+    }
+
+    /// Returns the sampled hot leaf pages with their approximate access
+    /// counts.
+    pub(crate) fn hot_pages(&self) -> Vec<(u64, u64)> {
+        self.access.hot_pages()
+    }
+
+    /// Returns true if inner page consolidations are deferred to a background
+    /// job, which runs [`TreeTxn::consolidate_deferred_pages`].
+    pub(crate) fn defers_inner_consolidation(&self) -> bool {
+        self.options.inner_consolidation == InnerConsolidation::Background
     }
 
     pub(crate) fn safe_lsn(&self) -> u64 {
@@ -62,6 +180,207 @@ impl Tree {
             }
         }
     }
+
+    /// Registers a replication stream that has shipped entries up to the LSN.
+    ///
+    /// The stream advances the returned LSN as it goes, and the registration
+    /// is released once the returned value is dropped.
+    pub(crate) fn register_replication(&self, lsn: u64) -> Arc<AtomicU64> {
+        let lsn = Arc::new(AtomicU64::new(lsn));
+        self.replication_lsns.lock().push(Arc::downgrade(&lsn));
+        lsn
+    }
+
+    /// Sets the largest LSN written before the tree was opened.
+    pub(crate) fn set_high_water_lsn(&self, lsn: u64) {
+        self.high_water_lsn.store(lsn, Ordering::Relaxed);
+        self.record_lsn(lsn);
+    }
+
+    /// Checks that the LSN of a write doesn't go below the LSNs written before
+    /// the tree was opened.
+    fn check_lsn_regression(&self, lsn: u64) -> Result<()> {
+        let high_water = self.high_water_lsn.load(Ordering::Relaxed);
+        if lsn >= high_water {
+            return Ok(());
+        }
+        if self.stats.lsn_regressions.get() == 0 {
+            log::warn!("write with LSN {lsn} below the high-water LSN {high_water}");
+        }
+        self.stats.lsn_regressions.inc();
+        if self.options.reject_lsn_regression {
+            return Err(Error::LsnRegression { high_water });
+        }
+        Ok(())
+    }
+
+    /// Records the sizes of the key and the value of a write if the write is
+    /// sampled, see [`Options::size_sampling_rate`].
+    fn record_sizes(&self, key: &Key<'_>, value: &Value<'_>) {
+        if !should_sample(self.options.size_sampling_rate) {
+            return;
+        }
+        self.stats.key_sizes.record(key.raw.len());
+        if !matches!(value, Value::Delete) {
+            self.stats.value_sizes.record(value.len());
+        }
+    }
+
+    /// Records that a write with the LSN has been installed.
+    fn record_lsn(&self, lsn: u64) {
+        self.latest_lsn.fetch_max(lsn, Ordering::Relaxed);
+    }
+
+    /// Returns the smallest LSN in the window of
+    /// [`Options::min_version_retention`], or `u64::MAX` if there is no
+    /// window.
+    fn retention_floor(&self) -> u64 {
+        match self.options.min_version_retention {
+            0 => u64::MAX,
+            span => self.latest_lsn.load(Ordering::Relaxed).saturating_sub(span),
+        }
+    }
+
+    /// Returns the LSN that consolidation can drop versions with, which is
+    /// the safe LSN held back by replication streams and the window of
+    /// [`Options::min_version_retention`].
+    fn retained_lsn(&self) -> u64 {
+        let mut lsn = self.safe_lsn().min(self.retention_floor());
+        self.replication_lsns.lock().retain(|stream| {
+            let Some(stream) = stream.upgrade() else {
+                return false;
+            };
+            lsn = lsn.min(stream.load(Ordering::Acquire));
+            true
+        });
+        lsn
+    }
+
+    /// Returns the approximate number of live keys in the tree.
+    ///
+    /// Writes guess that every put inserts a new key and every delete removes
+    /// an existing one, and leaf consolidations correct the guesses with the
+    /// keys they actually merge.
+    pub(crate) fn approx_key_count(&self) -> u64 {
+        self.key_count.load(Ordering::Relaxed).max(0) as u64
+    }
+
+    pub(crate) fn set_approx_key_count(&self, count: u64) {
+        self.key_count.store(count as i64, Ordering::Relaxed);
+    }
+
+    fn adjust_key_count(&self, delta: i64) {
+        if delta != 0 {
+            self.key_count.fetch_add(delta, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn page_chain_length(&self) -> usize {
+        self.page_chain_length.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_page_chain_length(&self, len: usize) {
+        self.page_chain_length.store(len, Ordering::Relaxed);
+    }
+
+    pub(crate) fn consolidate_keep_recent_deltas(&self) -> usize {
+        self.consolidate_keep_recent_deltas.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_consolidate_keep_recent_deltas(&self, n: usize) {
+        self.consolidate_keep_recent_deltas
+            .store(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn max_merge_chain(&self) -> usize {
+        self.max_merge_chain.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_max_merge_chain(&self, n: usize) {
+        self.max_merge_chain.store(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot_warn_lifetime(&self) -> Duration {
+        self.options.snapshot_warn_lifetime
+    }
+
+    pub(crate) fn is_auto_lsn(&self) -> bool {
+        self.options.auto_lsn
+    }
+
+    pub(crate) fn is_sealed(&self) -> bool {
+        self.read_only || self.sealed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_sealed(&self, sealed: bool) {
+        self.sealed.store(sealed, Ordering::Release);
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+    }
+
+    /// Returns an error if writes are rejected because the background jobs
+    /// are paused.
+    fn check_paused(&self) -> Result<()> {
+        if self.options.reject_writes_while_paused && self.paused.load(Ordering::Acquire) {
+            return Err(Error::Paused);
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the structure modifications of a tree, which change more than one
+/// page table entry together.
+///
+/// Each modification holds a [`StructureChange`] while it updates the page
+/// table, so that a copy of the page table can tell whether it mixes the
+/// entries before and after any modification.
+#[derive(Default)]
+struct StructureChanges {
+    begun: AtomicU64,
+    ended: AtomicU64,
+}
+
+impl StructureChanges {
+    fn begin(&self) -> StructureChange<'_> {
+        self.begun.fetch_add(1, Ordering::SeqCst);
+        StructureChange(self)
+    }
+
+    /// Returns the number of modifications begun so far, or `None` if any of
+    /// them is still in progress.
+    fn stable(&self) -> Option<u64> {
+        let ended = self.ended.load(Ordering::SeqCst);
+        let begun = self.begun.load(Ordering::SeqCst);
+        (begun == ended).then_some(begun)
+    }
+
+    /// Returns true if no modification has begun since [`Self::stable`]
+    /// returned the sequence.
+    fn unchanged_since(&self, seq: u64) -> bool {
+        self.begun.load(Ordering::SeqCst) == seq
+    }
+}
+
+/// A structure modification in progress, which ends when it is dropped.
+struct StructureChange<'a>(&'a StructureChanges);
+
+impl Drop for StructureChange<'_> {
+    fn drop(&mut self) {
+        self.0.ended.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// The status of a key at a given LSN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyStatus {
+    /// The latest visible version of the key is a value.
+    Present,
+    /// The latest visible version of the key is a tombstone.
+    Deleted,
+    /// The key has no visible versions.
+    Absent,
 }
 
 impl fmt::Debug for Tree {
@@ -69,6 +388,13 @@ impl fmt::Debug for Tree {
         f.debug_struct("Tree")
             .field("options", &self.options)
             .field("safe_lsn", &self.safe_lsn())
+            .field("page_chain_length", &self.page_chain_length())
+            .field(
+                "consolidate_keep_recent_deltas",
+                &self.consolidate_keep_recent_deltas(),
+            )
+            .field("max_merge_chain", &self.max_merge_chain())
+            .field("sealed", &self.is_sealed())
             .finish()
     }
 }
@@ -76,14 +402,24 @@ impl fmt::Debug for Tree {
 pub(crate) struct TreeTxn<'a, E: Env> {
     tree: &'a Tree,
     guard: Guard<E>,
+    /// Values decoded by [`Options::value_codec`], which are kept until the
+    /// transaction is dropped.
+    decoded_values: Mutex<Vec<Box<[u8]>>>,
 }
 
 impl<'a, E: Env> TreeTxn<'a, E> {
     fn new(tree: &'a Tree, guard: Guard<E>) -> Self {
-        Self { tree, guard }
+        Self {
+            tree,
+            guard,
+            decoded_values: Mutex::default(),
+        }
     }
 
     /// Initializes the tree if it is not initialized yet.
+    ///
+    /// This is safe to race with other initializations, only one of them
+    /// inserts the root and the others see it.
     pub(crate) async fn init(&self) -> Result<()> {
         let addr = self.guard.page_addr(ROOT_ID);
         if addr != 0 {
@@ -96,47 +432,196 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         let mut txn = self.guard.begin().await;
         let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
         builder.build(&mut new_page);
-        let root_id = txn.insert_page(new_addr);
-        assert_eq!(root_id, ROOT_ID);
-        txn.commit();
+        // Someone else has inserted the root if this fails, and the allocated
+        // page is discarded with the transaction.
+        let _ = txn.insert_page_at(ROOT_ID, new_addr);
 
         Ok(())
     }
 
     /// Gets the value corresponding to the key.
     pub(crate) async fn get(&self, key: Key<'_>) -> Result<Option<&[u8]>> {
+        self.get_with_status(key).await.map(|(value, _)| value)
+    }
+
+    /// Gets the value corresponding to the key, and the status of the key.
+    pub(crate) async fn get_with_status(&self, key: Key<'_>) -> Result<(Option<&[u8]>, KeyStatus)> {
+        let (value, status) = self.lookup(key).await?;
+        Ok((value.map(|(v, _)| v), status))
+    }
+
+    /// Gets the value corresponding to the key, and the LSN that the value is
+    /// written at.
+    pub(crate) async fn get_versioned(&self, key: Key<'_>) -> Result<Option<(&[u8], u64)>> {
+        self.lookup(key).await.map(|(value, _)| value)
+    }
+
+    /// Takes the cached page that holds the bytes read by this transaction,
+    /// see [`Guard::take_cached_page`].
+    ///
+    /// # Safety
+    ///
+    /// Other references to the same cached page read by this transaction must
+    /// not be used after the returned entry is dropped.
+    pub(crate) unsafe fn take_cached_page(
+        &self,
+        bytes: &[u8],
+    ) -> Option<(CacheEntryGuard, std::ops::Range<usize>)> {
+        self.guard.take_cached_page(bytes)
+    }
+
+    /// Gets the value corresponding to the key with its LSN, and the status of
+    /// the key.
+    async fn lookup(&self, key: Key<'_>) -> Result<(Option<(&[u8], u64)>, KeyStatus)> {
+        self.start_op();
         let start_at = Instant::now();
         let (view, _) = self.find_leaf(key.raw).await?;
+        self.tree.access.record(view.id);
         let before_find_value = Instant::now();
-        let value = self.find_value(&key, &view).await?;
+        let (value, status) = self.find_value(&key, &view).await?;
         crate::perf::with(|ctx| ctx.set_find_value(before_find_value.duration_since(start_at)));
 
         let key_size = key.len() as u64;
-        let value_size = value.map(|v| v.len()).unwrap_or_default() as u64;
-        self.tree
-            .stats
-            .success
-            .read_bytes
-            .add(key_size + value_size);
-        crate::perf::with(|ctx| ctx.set_total(start_at.elapsed()));
+        let value_size = value.map(|(v, _)| v.len()).unwrap_or_default() as u64;
+        let stats = &self.tree.stats.success;
+        stats.read_bytes.add(key_size + value_size);
+        stats.read_key_bytes.add(key_size);
+        stats.read_value_bytes.add(value_size);
+        self.finish_op("GET", start_at.elapsed());
+
+        Ok((value, status))
+    }
+
+    /// Returns true if the key has a value visible to the key's LSN.
+    ///
+    /// Unlike [`Self::get`], this doesn't read the value, so it doesn't need
+    /// to read blob pages for large values.
+    pub(crate) async fn contains(&self, key: Key<'_>) -> Result<bool> {
+        self.start_op();
+        let start_at = Instant::now();
+        let (view, _) = self.find_leaf(key.raw).await?;
+        self.tree.access.record(view.id);
+        let entry = self.find_entry(&key, &view).await?;
+        let found = matches!(
+            entry,
+            Some((
+                _,
+                Value::Put(_)
+                    | Value::Indirect(_)
+                    | Value::Encoded(_)
+                    | Value::PutTimestamped { .. }
+            ))
+        );
+        self.tree.stats.success.read_bytes.add(key.len() as u64);
+        self.tree.stats.success.read_key_bytes.add(key.len() as u64);
+        self.finish_op("CONTAINS", start_at.elapsed());
+        Ok(found)
+    }
 
-        Ok(value)
+    /// Loads the leaf pages that contain the keys into the page cache.
+    ///
+    /// Returns the number of pages read from page files, and stops early once
+    /// the cache is full.
+    pub(crate) async fn warmup<'k, I>(&self, keys: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = &'k [u8]>,
+    {
+        let mut loaded = 0;
+        let mut last_id = None;
+        for key in keys {
+            if self.guard.is_cache_full() {
+                break;
+            }
+            let (view, _) = self.find_leaf(key).await?;
+            // Adjacent keys are likely in the same page.
+            if last_id != Some(view.id) {
+                last_id = Some(view.id);
+                loaded += self.load_page_chain(&view).await?;
+            }
+        }
+        Ok(loaded)
     }
 
     /// Writes the key-value pair to the tree.
     pub(crate) async fn write(&self, key: Key<'_>, value: Value<'_>) -> Result<()> {
-        let start_at = Instant::now();
-        let bytes = key.len() + value.len();
+        self.write_with_options(key, value, &WriteOptions::default())
+            .await
+    }
+
+    /// Writes the key-value pair to the tree with the given options.
+    pub(crate) async fn write_with_options(
+        &self,
+        key: Key<'_>,
+        value: Value<'_>,
+        options: &WriteOptions,
+    ) -> Result<()> {
+        self.write_impl(key, value, false, options).await?;
+        Ok(())
+    }
+
+    /// Writes the key-value pair to the tree, and returns the value of the key
+    /// right before the write.
+    ///
+    /// The old value is the one visible to the LSN right below the write's
+    /// LSN, which is read from the same page that the write is applied to.
+    pub(crate) async fn write_returning_old(
+        &self,
+        key: Key<'_>,
+        value: Value<'_>,
+    ) -> Result<Option<Vec<u8>>> {
+        self.write_impl(key, value, true, &WriteOptions::default())
+            .await
+    }
+
+    async fn write_impl(
+        &self,
+        key: Key<'_>,
+        value: Value<'_>,
+        read_old: bool,
+        options: &WriteOptions,
+    ) -> Result<Option<Vec<u8>>> {
+        if self.tree.is_sealed() {
+            return Err(Error::Sealed);
+        }
+        self.tree.check_paused()?;
+        if matches!(&options.page_metadata, Some(m) if m.len() > PAGE_METADATA_MAX_LEN) {
+            return Err(Error::InvalidArgument);
+        }
+        if let Some(max_bytes) = options.max_bytes {
+            let size = (key.raw.len() + value.len()) as u64;
+            if size > max_bytes {
+                return Err(Error::BudgetExceeded { consumed: size });
+            }
+        }
+        self.tree.check_lsn_regression(key.lsn)?;
+        self.start_op();
+        let start_at = self.guard.now();
+        let key_size = key.len() as u64;
+        let value_size = value.len() as u64;
+        let mut retries = 0;
         loop {
-            match self.try_write(key, value).await {
-                Ok(_) => {
-                    self.tree.stats.success.write.inc();
-                    self.tree.stats.success.write_bytes.add(bytes as u64);
-                    crate::perf::with(|ctx| ctx.set_total(start_at.elapsed()));
-                    return Ok(());
+            match self.try_write(key, value, read_old, options).await {
+                Ok(old) => {
+                    self.tree.record_lsn(key.lsn);
+                    self.tree.record_sizes(&key, &value);
+                    let stats = &self.tree.stats.success;
+                    stats.write.inc();
+                    stats.write_bytes.add(key_size + value_size);
+                    stats.write_key_bytes.add(key_size);
+                    stats.write_value_bytes.add(value_size);
+                    let op = if matches!(value, Value::Delete) {
+                        "DELETE"
+                    } else {
+                        "PUT"
+                    };
+                    self.finish_op(op, start_at.elapsed());
+                    return Ok(old);
                 }
-                Err(Error::Again) => {
+                Err(err @ Error::Again) => {
                     self.tree.stats.conflict.write.inc();
+                    retries += 1;
+                    self.check_deadline(start_at, retries, err)?;
+                    self.guard.yield_now().await;
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -144,7 +629,17 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
     }
 
-    async fn try_write(&self, key: Key<'_>, value: Value<'_>) -> Result<()> {
+    async fn try_write(
+        &self,
+        key: Key<'_>,
+        value: Value<'_>,
+        read_old: bool,
+        options: &WriteOptions,
+    ) -> Result<Option<Vec<u8>>> {
+        // Fail fast instead of waiting for the write buffers to be flushed.
+        if self.guard.is_disk_full() {
+            return Err(Error::NoSpace);
+        }
         let before_find_leaf = Instant::now();
         let (mut view, _) = self.find_leaf(key.raw).await?;
         let after_find_leaf = Instant::now();
@@ -154,22 +649,59 @@ impl<'a, E: Env> TreeTxn<'a, E> {
 
         // Try to split the page before every write to avoid starving the split
         // operation due to contentions.
-        if self.should_split_page(&view.page) && self.split_page(view.clone()).await.is_ok() {
-            return Err(Error::Again);
+        if self.should_split_page(&view) {
+            if self.should_presplit_page(&view) {
+                self.tree.stats.presplit_attempted.inc();
+                if matches!(self.split_page(view.clone()).await, Ok(true)) {
+                    return Err(Error::Again);
+                }
+            } else {
+                self.tree.stats.presplit_skipped.inc();
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        self.verify_lsn(&key, &view).await?;
+
+        // The old value must be read from the same page that the delta is
+        // installed on, so it's read again whenever the page changes.
+        let old_key = match key.lsn.checked_sub(1) {
+            Some(lsn) if read_old => Some(Key::new(key.raw, lsn)),
+            _ => None,
+        };
+        let mut old_value = None;
+        if let Some(old_key) = &old_key {
+            old_value = self
+                .find_value(old_key, &view)
+                .await?
+                .0
+                .map(|(v, _)| v.to_vec());
         }
 
+        let no_compression = options.compression == Some(Compression::NONE);
+        let mut txn = self.guard.begin().await;
+        let mut encoded = None;
+        let value = self
+            .prepare_value(&mut txn, value, &mut encoded, no_compression)
+            .await?;
+
         // Build a delta page with the given key-value pair.
+        let key_count_guess = guess_key_count(&value);
         let delta = (key, value);
-        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_item(delta);
-        let mut txn = self.guard.begin().await;
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data)
+            .with_metadata(options.page_metadata.as_deref())
+            .with_item(delta);
         let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
         builder.build(&mut new_page);
+        new_page.set_no_compression(no_compression);
+        txn.record_lsn(key.lsn);
         let after_build_page = Instant::now();
         crate::perf::with(|ctx| {
             ctx.set_write_build_page(after_build_page.duration_since(after_find_leaf))
         });
 
         // Update the corresponding leaf page with the delta.
+        self.guard.yield_now().await;
         loop {
             new_page.set_epoch(view.page.epoch());
             new_page.set_chain_len(view.page.chain_len().saturating_add(1));
@@ -179,11 +711,14 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                     crate::perf::with(|ctx| ctx.add_replace_page(after_build_page.elapsed()));
                     view.addr = new_addr;
                     view.page = new_page.info();
+                    self.tree.adjust_key_count(key_count_guess);
                     break;
                 }
                 Err(None) => return Err(Error::Again),
                 Err(Some((_txn, addr))) => {
-                    // The page has been updated by other transactions.
+                    // The page has been updated by other transactions, which
+                    // may have written the same key with the same LSN.
+                    self.check_raced_writes(&[key], addr, view.addr).await?;
                     // We can keep retrying as long as the page epoch remains
                     // the same. However, this doesn't work for the root
                     // because we split the root without updating its epoch.
@@ -193,6 +728,13 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                             txn = _txn;
                             view.addr = addr;
                             view.page = page;
+                            if let Some(old_key) = &old_key {
+                                old_value = self
+                                    .find_value(old_key, &view)
+                                    .await?
+                                    .0
+                                    .map(|(v, _)| v.to_vec());
+                            }
                             continue;
                         }
                     }
@@ -200,38 +742,70 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 }
             }
         }
+        self.inject_fault(FaultPoint::AfterDeltaInstall);
 
         // Try to consolidate the page if it is too long.
         if self.should_consolidate_page(&view.page) {
             let _ = self.consolidate_and_restructure_page(view).await;
         }
-        Ok(())
-    }
-
-    /// Returns a view to the page.
-    async fn page_view<'g>(&'g self, id: u64, range: Option<Range<'g>>) -> Result<PageView<'g>> {
-        let addr = self.guard.page_addr(id);
-        let page = self.guard.read_page_info(addr)?;
-        Ok(PageView {
-            id,
-            addr,
-            page,
-            range,
-        })
+        Ok(old_value)
     }
 
-    /// Finds the leaf page that may contain the key.
+    /// Validates the reads and applies the writes of a transaction atomically.
     ///
-    /// Returns the leaf page and its parent.
-    async fn find_leaf(&self, key: &[u8]) -> Result<(PageView<'_>, Option<PageView<'_>>)> {
+    /// Each read is a key and the LSN of the value read from it, or `None` if
+    /// the key had no value. Returns [`Error::TxnConflict`] if the latest value
+    /// of any read key is not the one read. The writes must be sorted by keys
+    /// without duplicates, and are installed as one delta page.
+    ///
+    /// All keys must belong to the same leaf page for now, otherwise
+    /// [`Error::InvalidArgument`] is returned.
+    pub(crate) async fn commit(
+        &self,
+        reads: &[(&[u8], Option<u64>)],
+        writes: &[(Key<'_>, Value<'_>)],
+    ) -> Result<()> {
+        if self.tree.is_sealed() && !writes.is_empty() {
+            return Err(Error::Sealed);
+        }
+        if !writes.is_empty() {
+            self.tree.check_paused()?;
+        }
+        let keys = || {
+            reads
+                .iter()
+                .map(|(k, _)| *k)
+                .chain(writes.iter().map(|(k, _)| k.raw))
+        };
+        let (Some(first), Some(last)) = (keys().min(), keys().max()) else {
+            return Ok(());
+        };
+        for (key, _) in writes {
+            self.tree.check_lsn_regression(key.lsn)?;
+        }
+        let start_at = self.guard.now();
+        let mut retries = 0;
         loop {
-            match self.try_find_leaf(key).await {
-                Ok((view, parent)) => {
-                    self.tree.stats.success.read.inc();
-                    return Ok((view, parent));
+            match self.try_commit(first, last, reads, writes).await {
+                Ok(()) => {
+                    let stats = &self.tree.stats.success;
+                    for (key, value) in writes {
+                        self.tree.record_lsn(key.lsn);
+                        self.tree.record_sizes(key, value);
+                        let key_size = key.len() as u64;
+                        let value_size = value.len() as u64;
+                        stats.write.inc();
+                        stats.write_bytes.add(key_size + value_size);
+                        stats.write_key_bytes.add(key_size);
+                        stats.write_value_bytes.add(value_size);
+                    }
+                    return Ok(());
                 }
-                Err(Error::Again) => {
-                    self.tree.stats.conflict.read.inc();
+                Err(err @ Error::Again) => {
+                    self.tree.stats.conflict.write.inc();
+                    retries += 1;
+                    self.check_deadline(start_at, retries, err)?;
+                    self.guard.yield_now().await;
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -239,63 +813,368 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
     }
 
-    async fn try_find_leaf(&self, key: &[u8]) -> Result<(PageView<'_>, Option<PageView<'_>>)> {
-        // The index, range, and parent of the current page, starting from the root.
-        let mut index = ROOT_INDEX;
-        let mut range = ROOT_RANGE;
-        let mut parent = None;
-        loop {
-            let view = self.page_view(index.id, Some(range)).await?;
-            // If the page epoch has changed, the page may not contain the data we expect
-            // anymore. Try to reconcile pending conflicts and restart the operation.
-            //
-            // CAS on the page table entry resolves conflicts for modifications within a
-            // page, but it doesn't prevent us from modifying the wrong logical
-            // page.
-            //
-            // Consider this example where thread 1 tries to insert a key 7.
-            // 1. Thread 1 gets page id 2 from an inner page. Logical page 2 is the
-            //    leaf page that covers key 7.
-            // 2. Thread 2 splits logical page 2, and key 7 now belongs to logical page 3.
-            // 3. Thread 1 gets logical page 2's address.
-            // 4. Thread 1 CAS on the page table to insert key 7 to logical page 2.
-            //
-            // Step 4 breaks the consistency of the tree because key 7 now belongs to
-            // logical page 3. Before we can do any modification to a logical
-            // page, we should check if the logical page's key range is what we
-            // expect (between step 3 and 4). We use epoch to track the key range of a
-            // logical page.
-            if view.page.epoch() != index.epoch {
-                let _ = self.reconcile_page(view, parent).await;
-                return Err(Error::Again);
-            }
-            if view.page.tier().is_leaf() {
-                return Ok((view, parent));
-            }
-            // Find the child page that may contain the key.
-            let (child_index, child_range) = self
-                .find_child(key, &view)
-                .await?
-                .expect("child page must exist");
-            index = child_index;
-            range.start = child_range.start;
-            // If the child has no range end, use the current one instead.
-            if let Some(end) = child_range.end {
-                range.end = Some(end);
+    async fn try_commit(
+        &self,
+        first: &[u8],
+        last: &[u8],
+        reads: &[(&[u8], Option<u64>)],
+        writes: &[(Key<'_>, Value<'_>)],
+    ) -> Result<()> {
+        if !writes.is_empty() && self.guard.is_disk_full() {
+            return Err(Error::NoSpace);
+        }
+        let (mut view, _) = self.find_leaf(first).await?;
+        if let Some(Range { end: Some(end), .. }) = view.range {
+            if last >= end {
+                return Err(Error::InvalidArgument);
             }
-            parent = Some(view);
         }
-    }
+        self.validate_reads(reads, &view).await?;
+        if writes.is_empty() {
+            return Ok(());
+        }
 
-    /// Walks through the page chain and applies the function to each page.
-    ///
-    /// This function returns when it reaches the end of the chain or the
-    /// applied function returns true.
-    async fn walk_page<'g, F>(&'g self, mut addr: u64, mut f: F, hint: CacheOption) -> Result<()>
-    where
-        F: FnMut(u64, PageRef<'g>, Option<CacheToken>) -> bool,
-    {
-        while addr != 0 {
+        if self.should_split_page(&view) && matches!(self.split_page(view.clone()).await, Ok(true))
+        {
+            return Err(Error::Again);
+        }
+
+        #[cfg(debug_assertions)]
+        for (key, _) in writes {
+            self.verify_lsn(key, &view).await?;
+        }
+
+        let mut txn = self.guard.begin().await;
+        let mut encoded = vec![None; writes.len()];
+        let mut items = Vec::with_capacity(writes.len());
+        for ((key, value), encoded) in writes.iter().zip(encoded.iter_mut()) {
+            let value = self.prepare_value(&mut txn, *value, encoded, false).await?;
+            items.push((*key, value));
+        }
+
+        // Build a delta page with all the writes.
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&items);
+        let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+        builder.build(&mut new_page);
+        for (key, _) in &items {
+            txn.record_lsn(key.lsn);
+        }
+        let keys: Vec<_> = items.iter().map(|(key, _)| *key).collect();
+
+        self.guard.yield_now().await;
+        loop {
+            new_page.set_epoch(view.page.epoch());
+            new_page.set_chain_len(view.page.chain_len().saturating_add(1));
+            new_page.set_chain_next(view.addr);
+            match txn.update_page(view.id, view.addr, new_addr) {
+                Ok(_) => {
+                    view.addr = new_addr;
+                    view.page = new_page.info();
+                    self.tree
+                        .adjust_key_count(items.iter().map(|(_, v)| guess_key_count(v)).sum());
+                    break;
+                }
+                Err(None) => return Err(Error::Again),
+                Err(Some((_txn, addr))) => {
+                    // The page has been updated by other transactions, which
+                    // may have written the same keys with the same LSNs.
+                    self.check_raced_writes(&keys, addr, view.addr).await?;
+                    // The reads must be validated again on the new page.
+                    if view.id != ROOT_ID {
+                        let page = self.guard.read_page_info(addr)?;
+                        if page.epoch() == view.page.epoch() {
+                            txn = _txn;
+                            view.addr = addr;
+                            view.page = page;
+                            self.validate_reads(reads, &view).await?;
+                            continue;
+                        }
+                    }
+                    return Err(Error::Again);
+                }
+            }
+        }
+
+        if self.should_consolidate_page(&view.page) {
+            let _ = self.consolidate_and_restructure_page(view).await;
+        }
+        Ok(())
+    }
+
+    /// Returns [`Error::TxnConflict`] if the visible value of any read key in
+    /// the page is not the one read.
+    async fn validate_reads(
+        &self,
+        reads: &[(&[u8], Option<u64>)],
+        view: &PageView<'_>,
+    ) -> Result<()> {
+        for &(raw, lsn) in reads {
+            let latest = match self
+                .find_visible_entry(&Key::new(raw, u64::MAX), view)
+                .await?
+            {
+                Some((_, Value::Delete)) | None => None,
+                Some((k, _)) => Some(k.lsn),
+            };
+            if latest != lsn {
+                return Err(Error::TxnConflict);
+            }
+        }
+        Ok(())
+    }
+
+    /// Prepares the value to be stored in a data page.
+    ///
+    /// Large values are stored in blob pages to keep data pages compact, and
+    /// other values are transformed by [`Options::value_codec`] if any, with
+    /// the transformed value kept in `encoded`.
+    async fn prepare_value<'v>(
+        &self,
+        txn: &mut PageTxn<'_, E>,
+        value: Value<'v>,
+        encoded: &'v mut Option<Vec<u8>>,
+        no_compression: bool,
+    ) -> Result<Value<'v>> {
+        match value {
+            Value::Put(v) if v.len() > self.tree.options.value_inline_threshold => {
+                let builder = BlobPageBuilder::new(v);
+                let (addr, mut page) = txn.alloc_page(builder.size()).await?;
+                builder.build(&mut page);
+                page.set_no_compression(no_compression);
+                Ok(Value::Indirect(addr))
+            }
+            Value::Put(v) => {
+                let codec = self.tree.options.value_codec.as_ref();
+                match codec.and_then(|codec| codec.encode(v)) {
+                    Some(v) => Ok(Value::Encoded(encoded.insert(v))),
+                    None => Ok(value),
+                }
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Resets the perf context for [`Options::slow_op_threshold`] before an
+    /// operation starts.
+    fn start_op(&self) {
+        if self.tree.options.slow_op_threshold.is_some() {
+            crate::perf::reset_perf_ctx();
+        }
+    }
+
+    /// Records the total time of an operation, and logs the operation if it
+    /// is slower than [`Options::slow_op_threshold`].
+    fn finish_op(&self, op: &str, total: Duration) {
+        crate::perf::with(|ctx| {
+            ctx.set_total(total);
+            match self.tree.options.slow_op_threshold {
+                Some(threshold) if total > threshold => match ctx.dominant_phase() {
+                    Some((phase, elapsed)) => {
+                        log::warn!("slow {op}: {phase} {elapsed:?} of {total:?}")
+                    }
+                    None => log::warn!("slow {op}: {total:?}"),
+                },
+                _ => {}
+            }
+        });
+    }
+
+    /// Returns a view to the page.
+    async fn page_view<'g>(&'g self, id: u64, range: Option<Range<'g>>) -> Result<PageView<'g>> {
+        let addr = self.guard.page_addr(id);
+        self.guard.verify_page_id(id, addr)?;
+        let page = self.guard.read_page_info(addr)?;
+        Ok(PageView {
+            id,
+            addr,
+            page,
+            range,
+        })
+    }
+
+    /// Finds the leaf page that may contain the key.
+    ///
+    /// Returns the leaf page and its parent.
+    async fn find_leaf(&self, key: &[u8]) -> Result<(PageView<'_>, Option<PageView<'_>>)> {
+        if self.guard.is_frozen() {
+            let (view, parent, _) = self.find_frozen_leaf(key).await?;
+            return Ok((view, parent));
+        }
+        let start_at = self.guard.now();
+        let mut retries = 0;
+        loop {
+            match self.try_find_leaf(key).await {
+                Ok((view, parent)) => {
+                    self.tree.stats.success.read.inc();
+                    return Ok((view, parent));
+                }
+                Err(err @ Error::Again) => {
+                    self.tree.stats.conflict.read.inc();
+                    retries += 1;
+                    self.check_deadline(start_at, retries, err)?;
+                    self.guard.yield_now().await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns [`Error::Timeout`] with the `last` error if the operation
+    /// started at `start_at` has exceeded the deadline.
+    fn check_deadline(&self, start_at: Instant, retries: usize, last: Error) -> Result<()> {
+        match self.tree.options.operation_deadline {
+            Some(deadline) if self.guard.now().duration_since(start_at) >= deadline => {
+                Err(Error::Timeout {
+                    retries,
+                    last: Box::new(last),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn try_find_leaf(&self, key: &[u8]) -> Result<(PageView<'_>, Option<PageView<'_>>)> {
+        // The index, range, and parent of the current page, starting from the root.
+        let mut index = ROOT_INDEX;
+        let mut range = ROOT_RANGE;
+        let mut parent = None;
+        loop {
+            let view = self.page_view(index.id, Some(range)).await?;
+            // If the page epoch has changed, the page may not contain the data we expect
+            // anymore. Try to reconcile pending conflicts and restart the operation.
+            //
+            // CAS on the page table entry resolves conflicts for modifications within a
+            // page, but it doesn't prevent us from modifying the wrong logical
+            // page.
+            //
+            // Consider this example where thread 1 tries to insert a key 7.
+            // 1. Thread 1 gets page id 2 from an inner page. Logical page 2 is the
+            //    leaf page that covers key 7.
+            // 2. Thread 2 splits logical page 2, and key 7 now belongs to logical page 3.
+            // 3. Thread 1 gets logical page 2's address.
+            // 4. Thread 1 CAS on the page table to insert key 7 to logical page 2.
+            //
+            // Step 4 breaks the consistency of the tree because key 7 now belongs to
+            // logical page 3. Before we can do any modification to a logical
+            // page, we should check if the logical page's key range is what we
+            // expect (between step 3 and 4). We use epoch to track the key range of a
+            // logical page.
+            if view.page.epoch() != index.epoch {
+                let _ = self.reconcile_page(view, parent).await;
+                return Err(Error::Again);
+            }
+            if view.page.tier().is_leaf() {
+                return Ok((view, parent));
+            }
+            #[cfg(debug_assertions)]
+            if self.tree.options.verify_index_ranges {
+                self.verify_index_ranges(&view, &range).await?;
+            }
+            // Find the child page that may contain the key.
+            let Some((child_index, child_range)) = self.find_child(key, &view).await? else {
+                return Err(self.missing_child(key, &view, &range, parent.as_ref()).await);
+            };
+            index = child_index;
+            range.start = child_range.start;
+            // If the child has no range end, use the current one instead.
+            if let Some(end) = child_range.end {
+                range.end = Some(end);
+            }
+            parent = Some(view);
+        }
+    }
+
+    /// Finds the leaf page that may contain the key with a frozen guard.
+    ///
+    /// A frozen guard can't reconcile the splits that the parents don't know
+    /// yet, so it follows the split deltas to the right pages instead.
+    ///
+    /// Returns the leaf page, its parent, and the split of the leaf page that
+    /// the parent doesn't know, if the key is on the left of the split.
+    async fn find_frozen_leaf(
+        &self,
+        key: &[u8],
+    ) -> Result<(PageView<'_>, Option<PageView<'_>>, Option<(&[u8], Index)>)> {
+        let mut index = ROOT_INDEX;
+        let mut range = ROOT_RANGE;
+        let mut parent = None;
+        loop {
+            let mut view = self.page_view(index.id, Some(range)).await?;
+            let mut split = None;
+            while view.page.epoch() != index.epoch {
+                let (split_key, split_index) = self.split_delta(&view).await?;
+                if key < split_key {
+                    range.end = Some(split_key);
+                    view.range = Some(range);
+                    split = Some((split_key, split_index));
+                    break;
+                }
+                index = split_index;
+                range.start = split_key;
+                view = self.page_view(index.id, Some(range)).await?;
+            }
+            if view.page.tier().is_leaf() {
+                return Ok((view, parent, split));
+            }
+            let Some((child_index, child_range)) = self.find_child(key, &view).await? else {
+                return Err(self.missing_child(key, &view, &range, parent.as_ref()).await);
+            };
+            index = child_index;
+            range.start = child_range.start;
+            if let Some(end) = child_range.end {
+                range.end = Some(end);
+            }
+            parent = Some(view);
+        }
+    }
+
+    /// Logs the inner page that has no child covering the key, and returns the
+    /// error for it.
+    ///
+    /// The children of an inner page always cover its range, so this means
+    /// that the page is corrupted.
+    async fn missing_child(
+        &self,
+        key: &[u8],
+        view: &PageView<'_>,
+        range: &Range<'_>,
+        parent: Option<&PageView<'_>>,
+    ) -> Error {
+        let children = self.inner_page_children(view).await.unwrap_or_default();
+        log::error!(
+            "no child covers key {:?} in inner page {} with range {:?}, parent {:?}, children {:?}",
+            key,
+            view.id,
+            range,
+            parent.map(|parent| parent.id),
+            children
+        );
+        Error::Corrupted
+    }
+
+    /// Returns the split delta on top of the page, which is there as long as
+    /// the split is not reconciled with the parent.
+    async fn split_delta<'g>(&'g self, view: &PageView<'g>) -> Result<(&'g [u8], Index)> {
+        let (page, _) = self
+            .guard
+            .read_page(view.addr, CacheOption::default())
+            .await?;
+        if !page.kind().is_split() {
+            return Err(Error::Corrupted);
+        }
+        Ok(split_delta_from_page(page))
+    }
+
+    /// Walks through the page chain and applies the function to each page.
+    ///
+    /// This function returns when it reaches the end of the chain or the
+    /// applied function returns true.
+    async fn walk_page<'g, F>(&'g self, mut addr: u64, mut f: F, hint: CacheOption) -> Result<()>
+    where
+        F: FnMut(u64, PageRef<'g>, Option<CacheToken>) -> bool,
+    {
+        while addr != 0 {
             let (page, cache_token) = self.guard.read_page(addr, hint).await?;
             if f(addr, page, cache_token) {
                 break;
@@ -305,20 +1184,223 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         Ok(())
     }
 
+    /// Walks through the page chain for a point lookup.
+    ///
+    /// If [`Options::cold_cache_delta_pages`] is true, delta pages are read
+    /// with [`CacheOption::REFILL_COLD_WHEN_NOT_FULL`], so that they don't
+    /// evict hot base pages from the cache.
+    async fn walk_page_for_read<'g, F>(&'g self, view: &PageView<'g>, mut f: F) -> Result<()>
+    where
+        F: FnMut(PageRef<'g>) -> bool,
+    {
+        let mut addr = view.addr;
+        // The number of pages left in the chain, including the current one.
+        let mut chain_len = view.page.chain_len();
+        while addr != 0 {
+            let hint = if self.tree.options.cold_cache_delta_pages && chain_len > 1 {
+                CacheOption::REFILL_COLD_WHEN_NOT_FULL
+            } else {
+                CacheOption::default()
+            };
+            let (page, _) = self.guard.read_page(addr, hint).await?;
+            if f(page) {
+                break;
+            }
+            addr = page.chain_next();
+            chain_len = chain_len.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    /// Counts the entries in `[start, end)` of the leaf page that are visible
+    /// to the LSN.
+    ///
+    /// A clean base page is counted with two binary searches. Other pages are
+    /// counted by merging the page chain, without reading blob pages.
+    async fn count_page<'g>(
+        &'g self,
+        view: &PageView<'g>,
+        start: &[u8],
+        end: Option<&[u8]>,
+        read_lsn: u64,
+    ) -> Result<u64> {
+        if view.page.chain_len() == 1 && view.page.chain_next() == 0 {
+            let (page, _) = self
+                .guard
+                .read_page(view.addr, CacheOption::default())
+                .await?;
+            if page.kind().is_data() && page.is_clean() {
+                let page = ValuePageRef::from(page);
+                let rank = |raw: &[u8]| match page.rank(&Key::new(raw, u64::MAX)) {
+                    Ok(i) | Err(i) => i,
+                };
+                let lower = rank(start);
+                let upper = end.map_or(page.len(), rank);
+                return Ok(upper.saturating_sub(lower) as u64);
+            }
+        }
+        let iter = self.iter_page(view).await?;
+        let mut iter = PageIter::new(iter, read_lsn);
+        iter.seek(start);
+        let mut count = 0;
+        while let Some((raw, _)) = iter.next_value() {
+            if end.map_or(false, |end| raw >= end) {
+                break;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the number of keys in the page that have versions visible at
+    /// the LSN, and the number of them whose latest visible versions are
+    /// tombstones.
+    async fn count_tombstones<'g>(
+        &'g self,
+        view: &PageView<'g>,
+        read_lsn: u64,
+    ) -> Result<(u64, u64)> {
+        let iter: MergingPageIter<'g, Key<'g>, Value<'g>> = self.iter_page(view).await?;
+        let mut last_raw = None;
+        let (mut keys, mut tombstones) = (0, 0);
+        for (k, v) in iter {
+            if k.lsn > read_lsn || last_raw == Some(k.raw) {
+                continue;
+            }
+            last_raw = Some(k.raw);
+            keys += 1;
+            if matches!(v, Value::Delete) {
+                tombstones += 1;
+            }
+        }
+        Ok((keys, tombstones))
+    }
+
+    /// Collects the versions in the leaf page whose keys are not smaller than
+    /// `start` and whose LSNs are in `(after, upto]`.
+    ///
+    /// A version is collected with `None` if it is a tombstone.
+    async fn collect_versions<'g>(
+        &'g self,
+        view: &PageView<'g>,
+        start: &[u8],
+        (after, upto): (u64, u64),
+        versions: &mut Vec<(Vec<u8>, u64, Option<Vec<u8>>)>,
+    ) -> Result<()> {
+        let iter: MergingPageIter<'g, Key<'g>, Value<'g>> = self.iter_page(view).await?;
+        for (k, v) in iter {
+            if k.raw < start || k.lsn <= after || k.lsn > upto {
+                continue;
+            }
+            let value = match v {
+                Value::Put(v) | Value::PutTimestamped { bytes: v, .. } => Some(v),
+                Value::Delete => None,
+                Value::Indirect(addr) => Some(self.read_blob(addr).await?),
+                Value::Encoded(v) => Some(self.decode_value(v)?),
+            };
+            versions.push((k.raw.to_vec(), k.lsn, value.map(|v| v.to_vec())));
+        }
+        Ok(())
+    }
+
+    /// Copies all the versions in the leaf page whose keys are not smaller
+    /// than `start`, sorted by keys and then LSNs in ascending order.
+    async fn copy_versions<'g>(
+        &'g self,
+        view: &PageView<'g>,
+        start: &[u8],
+    ) -> Result<Vec<KeyVersion>> {
+        let iter: MergingPageIter<'g, Key<'g>, Value<'g>> = self.iter_page(view).await?;
+        let mut versions: Vec<KeyVersion> = Vec::new();
+        for (k, v) in iter {
+            if k.raw < start {
+                continue;
+            }
+            // The same version may appear in multiple delta pages.
+            if matches!(versions.last(), Some(last) if last.key == k.raw && last.lsn == k.lsn) {
+                continue;
+            }
+            let (value, ts) = match v {
+                Value::Put(v) => (Some(v), None),
+                Value::PutTimestamped { bytes, ts } => (Some(bytes), Some(ts)),
+                Value::Delete => (None, None),
+                Value::Indirect(addr) => (Some(self.read_blob(addr).await?), None),
+                Value::Encoded(v) => (Some(self.decode_value(v)?), None),
+            };
+            versions.push(KeyVersion {
+                key: k.raw.to_vec(),
+                lsn: k.lsn,
+                value: value.map(|v| v.to_vec()),
+                ts,
+            });
+        }
+        // The versions of a key are merged in descending order of LSNs.
+        versions.sort_by(|a, b| (&a.key, a.lsn).cmp(&(&b.key, b.lsn)));
+        Ok(versions)
+    }
+
+    /// Reads the pages on the chain into the page cache.
+    ///
+    /// Returns the number of pages read from page files.
+    async fn load_page_chain(&self, view: &PageView<'_>) -> Result<u64> {
+        let mut loaded = 0;
+        let mut addr = view.addr;
+        while addr != 0 {
+            let (page, from_file) = self.guard.load_page(addr).await?;
+            if from_file {
+                loaded += 1;
+            }
+            addr = page.chain_next();
+        }
+        Ok(loaded)
+    }
+
     /// Creates an iterator over the key-value pairs in the page.
     async fn iter_page<'g, K, V>(&'g self, view: &PageView<'g>) -> Result<MergingPageIter<'g, K, V>>
     where
         K: SortedPageKey,
         V: SortedPageValue,
     {
+        self.iter_page_with_hint(view, CacheOption::default()).await
+    }
+
+    /// Creates an iterator over the key-value pairs in the page, reading the
+    /// pages on the chain with the cache hint.
+    async fn iter_page_with_hint<'g, K, V>(
+        &'g self,
+        view: &PageView<'g>,
+        hint: CacheOption,
+    ) -> Result<MergingPageIter<'g, K, V>>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+    {
+        let (iter, _) = self.iter_page_with_chain_info(view, hint).await?;
+        Ok(iter)
+    }
+
+    /// Creates an iterator over the key-value pairs in the page, and returns
+    /// what is collected from the pages on the chain.
+    async fn iter_page_with_chain_info<'g, K, V>(
+        &'g self,
+        view: &PageView<'g>,
+        hint: CacheOption,
+    ) -> Result<(MergingPageIter<'g, K, V>, ChainInfo<'g>)>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+    {
+        let mut info = ChainInfo::default();
         let mut builder = MergingIterBuilder::with_capacity(view.page.chain_len() as usize);
         let mut range_limit = None;
         self.walk_page(
             view.addr,
             |_, page, _| {
+                info.size += page.size();
                 match page.kind() {
                     PageKind::Data => {
                         builder.add(SortedPageIter::from(page));
+                        info.metadata = info.metadata.or_else(|| page.metadata());
                     }
                     PageKind::Split => {
                         // The split key we first encountered must be the smallest.
@@ -332,41 +1414,74 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                             range_limit = Some(split_key);
                         }
                     }
+                    PageKind::Blob => unreachable!(),
                 }
                 false
             },
-            CacheOption::default(),
+            hint,
         )
         .await?;
-        Ok(MergingPageIter::new(builder.build(), range_limit))
+        let iter = MergingPageIter::new(builder.build(), range_limit);
+        Ok((iter, info))
     }
 
-    /// Finds the value corresponding to the key from the page.
-    async fn find_value<'g>(
+    /// Creates an iterator over the user entries in the leaf page.
+    ///
+    /// Returns the iterator along with the number of bytes read for it,
+    /// including the pages on the chain and the values stored out of them.
+    async fn leaf_iter<'g>(
         &'g self,
-        key: &Key<'_>,
         view: &PageView<'g>,
-    ) -> Result<Option<&'g [u8]>> {
-        let mut value = None;
+        read_lsn: u64,
+        hint: CacheOption,
+    ) -> Result<(PageIter<'g>, usize)> {
+        let (iter, info) = self.iter_page_with_chain_info(view, hint).await?;
+        let mut read_bytes = info.size;
+        let mut iter = PageIter::new(iter, read_lsn).with_metadata(info.metadata);
+        // The iterator can't read pages by itself, so indirect values are
+        // resolved in advance.
+        for value in iter.unresolved_values() {
+            let resolved = match value {
+                Value::Indirect(addr) => self.read_blob(addr).await?,
+                Value::Encoded(v) => self.decode_value(v)?,
+                Value::Put(_) | Value::Delete | Value::PutTimestamped { .. } => unreachable!(),
+            };
+            read_bytes += resolved.len();
+            iter.resolve(value, resolved);
+        }
+        Ok((iter, read_bytes))
+    }
+
+    /// Gets the versions of the key whose LSNs are in the range, in
+    /// descending order of LSNs.
+    ///
+    /// A version is returned with `None` if it is a tombstone.
+    pub(crate) async fn get_history<R: RangeBounds<u64>>(
+        &self,
+        key: &[u8],
+        range: R,
+    ) -> Result<Vec<(u64, Option<&[u8]>)>> {
+        let (view, _) = self.find_leaf(key).await?;
+        let mut entries = Vec::new();
+        let target = Key::new(key, u64::MAX);
         self.walk_page(
             view.addr,
             |_, page, _| {
                 debug_assert!(page.tier().is_leaf());
-                // We only care about data pages here.
                 if page.kind().is_data() {
                     let page = ValuePageRef::from(page);
-                    let index = match page.rank(key) {
+                    let mut index = match page.rank(&target) {
                         Ok(i) => i,
                         Err(i) => i,
                     };
-                    if let Some((k, v)) = page.get(index) {
-                        if k.raw == key.raw {
-                            debug_assert!(k.lsn <= key.lsn);
-                            if let Value::Put(v) = v {
-                                value = Some(v);
-                            }
-                            return true;
+                    while let Some((k, v)) = page.get(index) {
+                        if k.raw != key {
+                            break;
+                        }
+                        if range.contains(&k.lsn) {
+                            entries.push((k.lsn, v));
                         }
+                        index += 1;
                     }
                 }
                 false
@@ -374,7 +1489,187 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             CacheOption::default(),
         )
         .await?;
-        Ok(value)
+
+        // The same version may appear in multiple delta pages.
+        entries.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+        entries.dedup_by_key(|(lsn, _)| *lsn);
+        let mut history = Vec::with_capacity(entries.len());
+        for (lsn, value) in entries {
+            let value = match value {
+                Value::Put(v) | Value::PutTimestamped { bytes: v, .. } => Some(v),
+                Value::Indirect(addr) => Some(self.read_blob(addr).await?),
+                Value::Encoded(v) => Some(self.decode_value(v)?),
+                Value::Delete => None,
+            };
+            history.push((lsn, value));
+        }
+        Ok(history)
+    }
+
+    /// Finds the value corresponding to the key from the page, and the LSN of
+    /// the matched entry.
+    ///
+    /// Also returns whether the search stopped on a value or a tombstone of
+    /// the key, or reached the end of the page chain without a match.
+    async fn find_value<'g>(
+        &'g self,
+        key: &Key<'_>,
+        view: &PageView<'g>,
+    ) -> Result<(Option<(&'g [u8], u64)>, KeyStatus)> {
+        match self.find_visible_entry(key, view).await? {
+            Some((k, Value::Put(v) | Value::PutTimestamped { bytes: v, .. })) => {
+                Ok((Some((v, k.lsn)), KeyStatus::Present))
+            }
+            Some((k, Value::Indirect(addr))) => {
+                let v = self.read_blob(addr).await?;
+                Ok((Some((v, k.lsn)), KeyStatus::Present))
+            }
+            Some((k, Value::Encoded(v))) => {
+                let v = self.decode_value(v)?;
+                Ok((Some((v, k.lsn)), KeyStatus::Present))
+            }
+            Some((_, Value::Delete)) => Ok((None, KeyStatus::Deleted)),
+            None => Ok((None, KeyStatus::Absent)),
+        }
+    }
+
+    /// Finds the entry of the key that holds the value visible to the key's
+    /// LSN.
+    ///
+    /// This is the latest entry, unless it is timestamped, in which case the
+    /// consecutive timestamped entries of the key are resolved as described in
+    /// [`Table::put_timestamped`].
+    ///
+    /// [`Table::put_timestamped`]: crate::raw::Table::put_timestamped
+    async fn find_visible_entry<'g>(
+        &'g self,
+        key: &Key<'_>,
+        view: &PageView<'g>,
+    ) -> Result<Option<(Key<'g>, Value<'g>)>> {
+        let entry = self.find_entry(key, view).await?;
+        let Some((k, v)) = entry else {
+            return Ok(None);
+        };
+        if v.timestamp().is_none() {
+            return Ok(entry);
+        }
+        // The run may span multiple pages on the chain, so it is resolved on
+        // the merged entries of the chain.
+        let mut iter: MergingPageIter<'g, Key<'g>, Value<'g>> = self.iter_page(view).await?;
+        iter.seek(&k);
+        let mut peeked = None;
+        Ok(Some(resolve_timestamped((k, v), &mut iter, &mut peeked)))
+    }
+
+    /// Finds the latest entry of the key that is visible to the key's LSN.
+    async fn find_entry<'g>(
+        &'g self,
+        key: &Key<'_>,
+        view: &PageView<'g>,
+    ) -> Result<Option<(Key<'g>, Value<'g>)>> {
+        let mut entry = None;
+        self.walk_page_for_read(view, |page| {
+            debug_assert!(page.tier().is_leaf());
+            // We only care about data pages here.
+            if page.kind().is_data() {
+                let page = ValuePageRef::from(page);
+                let index = match page.rank(key) {
+                    Ok(i) => i,
+                    Err(i) => i,
+                };
+                if let Some((k, v)) = page.get(index) {
+                    if k.raw == key.raw {
+                        debug_assert!(k.lsn <= key.lsn);
+                        entry = Some((k, v));
+                        return true;
+                    }
+                }
+            }
+            false
+        })
+        .await?;
+        Ok(entry)
+    }
+
+    /// Returns [`Error::LsnConflict`] if a delta installed on the page chain
+    /// from `head` down to `stop` has an entry of one of the keys with the
+    /// same LSN.
+    ///
+    /// Concurrent writes of the same key are applied in the order that they
+    /// win the page updates. A write that loses the update checks only the
+    /// deltas installed by others since it read the page, so of two racing
+    /// writes with the same LSN, the first applied one wins and the other one
+    /// is rejected, without adding any reads to writes that don't race.
+    async fn check_raced_writes(&self, keys: &[Key<'_>], head: u64, stop: u64) -> Result<()> {
+        let mut conflict = None;
+        self.walk_page(
+            head,
+            |addr, page, _| {
+                if addr == stop {
+                    return true;
+                }
+                if page.tier().is_leaf() && page.kind().is_data() {
+                    let page = ValuePageRef::from(page);
+                    conflict = keys.iter().find(|key| page.rank(key).is_ok()).copied();
+                }
+                conflict.is_some()
+            },
+            CacheOption::default(),
+        )
+        .await?;
+        match conflict {
+            Some(key) => Err(Error::LsnConflict { latest: key.lsn }),
+            None => Ok(()),
+        }
+    }
+
+    /// Verifies that the key's LSN is larger than the LSNs of existing entries
+    /// of the same key in the page.
+    ///
+    /// Returns [`Error::LsnConflict`] if the LSN is not larger, since entries
+    /// with the same key must be written with increasing LSNs.
+    #[cfg(debug_assertions)]
+    async fn verify_lsn(&self, key: &Key<'_>, view: &PageView<'_>) -> Result<()> {
+        let latest = Key::new(key.raw, u64::MAX);
+        if let Some((k, _)) = self.find_entry(&latest, view).await? {
+            if k.lsn >= key.lsn {
+                log::error!(
+                    "write key {:?} with LSN {} that is not larger than the latest LSN {}",
+                    key.raw,
+                    key.lsn,
+                    k.lsn
+                );
+                return Err(Error::LsnConflict { latest: k.lsn });
+            }
+        }
+        Ok(())
+    }
+
+    /// Triggers the fault armed at the point, if any.
+    fn inject_fault(&self, point: FaultPoint) {
+        fault::inject(self.tree.options.page_store.fault_injector.as_ref(), point);
+    }
+
+    /// Reads the value of a blob page.
+    async fn read_blob(&self, addr: u64) -> Result<&[u8]> {
+        let (page, _) = self.guard.read_page(addr, CacheOption::default()).await?;
+        Ok(BlobPageRef::from(page).value())
+    }
+
+    /// Decodes a value transformed by [`Options::value_codec`].
+    ///
+    /// The decoded value is kept until the transaction is dropped.
+    fn decode_value(&self, value: &[u8]) -> Result<&[u8]> {
+        // The value is written with a codec that is not configured anymore.
+        let Some(codec) = self.tree.options.value_codec.as_ref() else {
+            return Err(Error::InvalidArgument);
+        };
+        let value = codec.decode(value).into_boxed_slice();
+        let ptr: *const [u8] = &*value;
+        self.decoded_values.lock().push(value);
+        // Safety: the boxed value is never dropped before the transaction, and
+        // moving the box doesn't move the value.
+        Ok(unsafe { &*ptr })
     }
 
     /// Finds the child page that may contain the key from the page.
@@ -419,8 +1714,61 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         Ok(child)
     }
 
+    /// Returns the children of the inner page with their start keys, including
+    /// placeholders and overwritten indexes.
+    async fn inner_page_children<'g>(
+        &'g self,
+        view: &PageView<'g>,
+    ) -> Result<Vec<(&'g [u8], Index)>> {
+        let iter = self.iter_page::<&[u8], Index>(view).await?;
+        Ok(iter.collect())
+    }
+
+    /// Verifies that the children of the inner page tile the range of the
+    /// page, without gaps or overlaps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the children don't tile the range.
+    #[cfg(debug_assertions)]
+    async fn verify_index_ranges(&self, view: &PageView<'_>, range: &Range<'_>) -> Result<()> {
+        let children = self.inner_page_children(view).await?;
+        // The first start key must be the start of the range, so that there is no gap
+        // before it or overlap with the left sibling.
+        let mut valid = children.first().map(|c| c.0) == Some(range.start);
+        let mut covered = false;
+        for (i, &(start, index)) in children.iter().enumerate() {
+            if index != NULL_INDEX {
+                covered = true;
+            }
+            // A placeholder is only visible if no index starts at the same key.
+            if children.get(i + 1).map(|c| c.0) == Some(start) {
+                continue;
+            }
+            let in_range = range.end.map_or(true, |end| start < end);
+            // A placeholder in the range leaves a gap, and an index out of the range
+            // overlaps with the right sibling.
+            if covered != in_range {
+                valid = false;
+            }
+            covered = false;
+        }
+        if !valid {
+            log::error!(
+                "children of inner page {} don't tile its range {:?}: {:?}",
+                view.id,
+                range,
+                children
+            );
+            panic!("inner page ranges must tile the key space");
+        }
+        Ok(())
+    }
+
     // Splits the page into two halves.
-    async fn split_page(&self, view: PageView<'_>) -> Result<()> {
+    //
+    // Returns false if no separator is found to split the page.
+    async fn split_page(&self, view: PageView<'_>) -> Result<bool> {
         // We can only split base data pages.
         if !view.page.kind().is_data() || view.page.chain_next() != 0 {
             return Err(Error::InvalidArgument);
@@ -431,7 +1779,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
     }
 
-    async fn split_page_impl<K, V>(&self, mut view: PageView<'_>) -> Result<()>
+    async fn split_page_impl<K, V>(&self, mut view: PageView<'_>) -> Result<bool>
     where
         K: SortedPageKey,
         V: SortedPageValue,
@@ -446,17 +1794,23 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             .read_page(view.addr, CacheOption::default())
             .await?;
         let page = SortedPageRef::<K, V>::from(page);
-        let Some((split_key, _, right_iter)) = page.into_split_iter() else {
-            return Ok(());
+        let metadata = page.metadata();
+        let Some((split_key, _, right_iter)) = self.split_iter(&view, page) else {
+            return Ok(false);
         };
 
+        // The change must outlive the transaction, which may roll back the right
+        // page when it is dropped.
+        let _change = self.tree.structure_changes.begin();
         let mut txn = self.guard.begin().await;
         // Build and insert the right page.
         let right_id = {
-            let builder =
-                SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(right_iter);
+            let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data)
+                .with_metadata(metadata)
+                .with_iter(right_iter);
             let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
             builder.build(&mut new_page);
+            new_page.set_no_compression(view.page.no_compression());
             txn.insert_page(new_addr)
         };
         // Build a delta page with the right index.
@@ -469,10 +1823,12 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         new_page.set_epoch(view.page.epoch() + 1);
         new_page.set_chain_len(view.page.chain_len().saturating_add(1));
         new_page.set_chain_next(view.addr);
+        self.guard.yield_now().await;
         txn.update_page(view.id, view.addr, new_addr)
             .map(|_| {
                 trace!("split page {:?} with delta {:?}", view, delta);
                 self.tree.stats.success.split_page.inc();
+                self.tree.stats.epoch_bumps.inc();
                 view.addr = new_addr;
                 view.page = new_page.info();
             })
@@ -480,12 +1836,13 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 self.tree.stats.conflict.split_page.inc();
                 Error::Again
             })?;
+        self.inject_fault(FaultPoint::SplitBeforeParentUpdate);
 
         crate::perf::with(|ctx| ctx.add_split_page(start_at.elapsed()));
-        Ok(())
+        Ok(true)
     }
 
-    async fn split_root_impl<K, V>(&self, view: PageView<'_>) -> Result<()>
+    async fn split_root_impl<K, V>(&self, view: PageView<'_>) -> Result<bool>
     where
         K: SortedPageKey,
         V: SortedPageValue,
@@ -499,28 +1856,38 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             .read_page(view.addr, CacheOption::default())
             .await?;
         let page = SortedPageRef::<K, V>::from(page);
-        let Some((split_key, left_iter, right_iter)) = page.into_split_iter() else {
-            return Ok(());
+        let metadata = page.metadata();
+        let Some((split_key, left_iter, right_iter)) = self.split_iter(&view, page) else {
+            return Ok(false);
         };
 
+        let _change = self.tree.structure_changes.begin();
         let mut txn = self.guard.begin().await;
         // Build and insert the left page.
         let left_id = {
-            let builder =
-                SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(left_iter);
+            let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data)
+                .with_metadata(metadata)
+                .with_iter(left_iter);
             let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
             builder.build(&mut new_page);
+            new_page.set_no_compression(view.page.no_compression());
             txn.insert_page(new_addr)
         };
         // Build and insert the right page.
         let right_id = {
-            let builder =
-                SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(right_iter);
+            let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data)
+                .with_metadata(metadata)
+                .with_iter(right_iter);
             let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
             builder.build(&mut new_page);
+            new_page.set_no_compression(view.page.no_compression());
             txn.insert_page(new_addr)
         };
         // Build a delta page with the right index.
+        //
+        // The empty key is the inclusive lower bound of the left page, so a user's
+        // empty key belongs to it. This doesn't collide with user keys since the
+        // empty key is never used as a split separator.
         let delta = [
             ([].as_slice(), Index::new(left_id, 0)),
             (split_key.as_raw(), Index::new(right_id, 0)),
@@ -528,12 +1895,15 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&delta);
         let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
         builder.build(&mut new_page);
+        self.inject_fault(FaultPoint::SplitBeforeRootReplace);
         // Replace and deallocate the original root.
+        self.guard.yield_now().await;
         txn.replace_page(view.id, view.addr, new_addr, &[view.addr])
             .await
             .map(|_| {
                 trace!("split root {:?} with delta {:?}", view, delta);
                 self.tree.stats.success.split_page.inc();
+                true
             })
             .map_err(|_| {
                 self.tree.stats.conflict.split_page.inc();
@@ -541,6 +1911,36 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             })
     }
 
+    /// Finds a separator to split the page.
+    ///
+    /// Separators of leaf pages must be allowed by the split guard if any.
+    #[allow(clippy::type_complexity)]
+    fn split_iter<'p, K, V>(
+        &self,
+        view: &PageView<'_>,
+        page: SortedPageRef<'p, K, V>,
+    ) -> Option<(
+        K,
+        SortedPageRangeIter<'p, K, V>,
+        SortedPageRangeIter<'p, K, V>,
+    )>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+    {
+        let split = match &self.tree.options.split_guard {
+            Some(guard) if view.page.tier().is_leaf() => {
+                page.into_split_iter_with(|key| guard.allows(key))
+            }
+            _ => page.into_split_iter(),
+        };
+        // The empty key is reserved for the lower bound of the leftmost pages.
+        debug_assert!(split
+            .as_ref()
+            .map_or(true, |(sep, ..)| !sep.as_raw().is_empty()));
+        split
+    }
+
     /// Reconciles any conflicts on the page.
     async fn reconcile_page(&self, view: PageView<'_>, parent: Option<PageView<'_>>) -> Result<()> {
         let result = match view.page.kind() {
@@ -552,6 +1952,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                     Err(Error::InvalidArgument)
                 }
             }
+            PageKind::Blob => unreachable!(),
         };
         match result {
             Ok(_) => {
@@ -597,6 +1998,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             vec![(left_key, left_index), (split_key, split_index)]
         };
         let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&delta);
+        let change = self.tree.structure_changes.begin();
         let mut txn = self.guard.begin().await;
         let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
         builder.build(&mut new_page);
@@ -604,6 +2006,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         new_page.set_epoch(parent.page.epoch());
         new_page.set_chain_len(parent.page.chain_len().saturating_add(1));
         new_page.set_chain_next(parent.addr);
+        self.guard.yield_now().await;
         txn.update_page(parent.id, parent.addr, new_addr)
             .map(|_| {
                 trace!("reconcile split page {:?} with delta {:?}", view, delta);
@@ -611,61 +2014,137 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 parent.page = new_page.info();
             })
             .map_err(|_| Error::Again)?;
+        drop(change);
 
         // Try to consolidate the parent page if it is too long.
-        if self.should_consolidate_page(&parent.page) {
+        if self.should_consolidate_page(&parent.page) && !self.defer_consolidation(&parent) {
             let _ = self.consolidate_and_restructure_page(parent).await;
         }
+        // The split delta is not needed anymore once the parent knows the right
+        // page, and consolidation drops it with the entries beyond its split key.
+        if self.tree.options.consolidate_after_split {
+            let _ = self.consolidate_page(view, false).await;
+        }
         Ok(())
     }
 
     /// Consolidates delta pages on the page chain.
-    async fn consolidate_page<'g>(&'g self, view: PageView<'g>) -> Result<PageView<'g>> {
+    ///
+    /// If `partial` is true, leaf pages may only consolidate a prefix of the
+    /// chain, and keep the recent deltas on top of the consolidated page
+    /// according to [`Options::consolidate_keep_recent_deltas`]. Leaf chains
+    /// longer than [`Options::max_merge_chain`] are consolidated in segments.
+    async fn consolidate_page<'g>(
+        &'g self,
+        mut view: PageView<'g>,
+        partial: bool,
+    ) -> Result<PageView<'g>> {
         match view.page.tier() {
             PageTier::Leaf => {
-                let safe_lsn = self.tree.safe_lsn();
-                self.consolidate_page_impl(view, |iter| MergingLeafPageIter::new(iter, safe_lsn))
-                    .await
+                let safe_lsn = self.tree.retained_lsn();
+                loop {
+                    let (new_view, segmented) = self
+                        .consolidate_page_impl(view, partial, |iter, with_base| {
+                            // Tombstones must be kept until they are merged with the base
+                            // page, otherwise the older versions show up again.
+                            MergingLeafPageIter::new(iter, safe_lsn).keep_tombstones(!with_base)
+                        })
+                        .await?;
+                    if !segmented {
+                        return Ok(new_view);
+                    }
+                    view = new_view;
+                }
             }
             PageTier::Inner => {
-                self.consolidate_page_impl(view, MergingInnerPageIter::new)
-                    .await
+                let (view, _) = self
+                    .consolidate_page_impl(view, partial, |iter, _| MergingInnerPageIter::new(iter))
+                    .await?;
+                Ok(view)
             }
         }
     }
 
+    /// Consolidates the page with a consolidation iterator created by `f`,
+    /// which is told whether the base page is merged.
+    ///
+    /// Returns the new view of the page, and whether only a segment of the
+    /// chain is consolidated because of [`Options::max_merge_chain`].
     async fn consolidate_page_impl<'g, F, I, K, V>(
         &'g self,
         mut view: PageView<'g>,
+        partial: bool,
         f: F,
-    ) -> Result<PageView<'g>>
+    ) -> Result<(PageView<'g>, bool)>
     where
-        F: Fn(MergingPageIter<'g, K, V>) -> I,
-        I: RewindableIterator<Item = (K, V)>,
+        F: Fn(MergingPageIter<'g, K, V>, bool) -> I,
+        I: ConsolidationIter<Item = (K, V)>,
         K: SortedPageKey,
         V: SortedPageValue,
     {
         // Collect information for this consolidation.
-        let info = self.collect_consolidation_info(&view).await?;
+        let info = self.collect_consolidation_info(&view, partial).await?;
         let start_at = Instant::now();
-        let iter = f(info.iter);
-        let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(iter);
+        let segmented = info.segmented;
+        let overwritten = info.overwritten;
+        let mut iter = f(info.iter, info.last_page.chain_next() == 0);
+        let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data)
+            .with_metadata(info.metadata)
+            .with_iter(&mut iter);
         let mut txn = self.guard.begin().await;
         let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
         builder.build(&mut new_page);
         new_page.set_epoch(view.page.epoch());
         new_page.set_chain_len(info.last_page.chain_len());
         new_page.set_chain_next(info.last_page.chain_next());
-        // Update the page and deallocate the consolidated delta pages.
-        txn.replace_page(view.id, view.addr, new_addr, &info.page_addrs)
+        new_page.set_no_compression(info.no_compression);
+        new_page.set_clean(iter.is_clean() && info.last_page.chain_next() == 0);
+        // A merged delta page takes over the guesses of the deltas it merges.
+        let mut key_count_delta = info.key_count_delta;
+        if new_page.tier().is_leaf() && new_page.chain_next() != 0 {
+            key_count_delta += guess_page_key_count(PageRef::from(*new_page), None);
+        }
+        // Copy the recent deltas on top of the consolidated page, from the oldest
+        // to the newest.
+        let (mut top_addr, mut top_page) = (new_addr, new_page.info());
+        for page in info.recent_pages.iter().rev() {
+            let (addr, mut buf) = txn.alloc_page(page.size()).await?;
+            buf.copy_from(page);
+            buf.set_chain_len(top_page.chain_len().saturating_add(1));
+            buf.set_chain_next(top_addr);
+            (top_addr, top_page) = (addr, buf.info());
+        }
+        // Update the page and deallocate the consolidated delta pages, as well as
+        // the blob pages that are not referenced anymore.
+        let mut dealloc_addrs = info.page_addrs;
+        dealloc_addrs.extend_from_slice(iter.dropped_blobs());
+        self.guard.yield_now().await;
+        txn.replace_page(view.id, view.addr, top_addr, &dealloc_addrs)
             .await
             .map(|_| {
                 trace!("consolidate page {:?}", view);
                 self.tree.stats.success.consolidate_page.inc();
-                crate::perf::with(|ctx| ctx.add_consolidate_page(start_at.elapsed()));
-                view.addr = new_addr;
-                view.page = new_page.info();
-                view
+                self.tree.adjust_key_count(key_count_delta);
+                // Sample the fill ratio of leaf base pages.
+                if new_page.tier().is_leaf() && new_page.chain_next() == 0 {
+                    let stats = &self.tree.stats;
+                    stats.consolidate_leaf_page.inc();
+                    stats
+                        .consolidate_leaf_page_bytes
+                        .add(new_page.size() as u64);
+                }
+                if segmented {
+                    self.tree.stats.segmented_consolidations.inc();
+                }
+                if overwritten {
+                    self.tree.stats.overwritten_chains.inc();
+                }
+                let elapsed = start_at.elapsed();
+                self.tree.stats.consolidate_latency.record(elapsed);
+                crate::perf::with(|ctx| ctx.add_consolidate_page(elapsed));
+                view.addr = top_addr;
+                view.page = top_page;
+                (view, segmented)
             })
             .map_err(|_| {
                 self.tree.stats.conflict.consolidate_page.inc();
@@ -677,6 +2156,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     async fn collect_consolidation_info<'g, K, V>(
         &'g self,
         view: &PageView<'g>,
+        partial: bool,
     ) -> Result<ConsolidationInfo<'g, K, V>>
     where
         K: SortedPageKey,
@@ -684,8 +2164,22 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     {
         let start_at = Instant::now();
         let chain_len = view.page.chain_len() as usize;
-        let mut builder = MergingIterBuilder::with_capacity(chain_len);
+        let max_recent_pages = if partial && view.page.tier().is_leaf() {
+            self.tree.consolidate_keep_recent_deltas()
+        } else {
+            0
+        };
+        let max_merge_chain = match self.tree.max_merge_chain() {
+            0 => usize::MAX,
+            n if view.page.tier().is_leaf() => n.max(2),
+            _ => usize::MAX,
+        };
+        let mut segmented = false;
+        let mut recent_pages = Vec::new();
+        let mut pages = Vec::with_capacity(chain_len.min(max_merge_chain));
+        let mut chain_size = 0;
         let mut page_size = 0;
+        let mut no_compression_size = 0;
         let mut last_page = view.page.clone();
         let mut page_addrs = Vec::with_capacity(chain_len);
         let mut range_limit = None;
@@ -696,27 +2190,53 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                     PageKind::Data => {
                         // Inner pages can not do partial consolidations because of the
                         // placeholders. This is fine since inner pages
-                        // doesn't consolidate as often as leaf pages.
-                        if page.tier().is_leaf()
-                            && builder.len() >= 2
+                        // doesn't consolidate as often as leaf pages. Pages that keep
+                        // recent deltas are consolidated to the base page instead.
+                        if partial
+                            && page.tier().is_leaf()
+                            && recent_pages.is_empty()
+                            && pages.len() >= 2
                             && page_size < page.size() / 2
                             && range_limit.is_none()
-                            && !self.should_consolidate_page(&page.info())
+                            && page.chain_len() as usize <= self.max_chain_len(&page.info())
+                        {
+                            return true;
+                        }
+                        // Leave the rest of a long chain to the next segment. The split
+                        // delta can't be passed, since it would be dropped from the chain.
+                        if pages.len() + recent_pages.len() >= max_merge_chain
+                            && range_limit.is_none()
                         {
+                            segmented = true;
                             return true;
                         }
                         if let Some(ctoken) = ctoken {
                             ctoken.return_cache_as_cold();
                         }
-                        builder.add(SortedPageIter::from(page));
+                        chain_size += page.size();
+                        // Set aside the leading deltas that may be kept uncompacted.
+                        if recent_pages.len() == page_addrs.len()
+                            && recent_pages.len() < max_recent_pages
+                            && page.chain_next() != 0
+                        {
+                            recent_pages.push(page);
+                            page_addrs.push(addr);
+                            return false;
+                        }
+                        pages.push(page);
                         page_size += page.size();
+                        if page.no_compression() {
+                            no_compression_size += page.size();
+                        }
                     }
                     PageKind::Split => {
+                        chain_size += page.size();
                         if range_limit.is_none() {
                             let (split_key, _) = split_delta_from_page(page);
                             range_limit = Some(split_key);
                         }
                     }
+                    PageKind::Blob => unreachable!(),
                 }
                 last_page = page.info();
                 page_addrs.push(addr);
@@ -725,47 +2245,641 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             CacheOption::REFILL_COLD_WHEN_NOT_FULL,
         )
         .await?;
+        // Keep the recent deltas only if the chain is not flagged to consolidate
+        // again right away, and the consolidated page doesn't need to split,
+        // which only happens to a page without deltas.
+        let max_chain_len = self.tree.page_chain_length().max(1);
+        let mut num_recent_pages = recent_pages
+            .len()
+            .min(max_chain_len.saturating_sub(last_page.chain_len() as usize));
+        if pages.len() < 2 || chain_size > self.max_page_size(view) {
+            num_recent_pages = 0;
+        }
+        let mut overwritten = false;
+        let key_count_delta = if view.page.tier().is_leaf() {
+            let mut deltas = recent_pages[num_recent_pages..].to_vec();
+            deltas.extend_from_slice(&pages);
+            let base = if last_page.chain_next() == 0 {
+                deltas.pop()
+            } else {
+                None
+            };
+            if self.tree.options.disable_consolidation {
+                overwritten = has_overwritten_keys(&deltas, base);
+            }
+            key_count_correction(&deltas, base, range_limit)
+        } else {
+            0
+        };
+        // The consolidated page keeps the metadata of the newest page it merges.
+        let metadata = recent_pages[num_recent_pages..]
+            .iter()
+            .chain(&pages)
+            .find_map(|page| page.metadata());
+        let mut builder = MergingIterBuilder::with_capacity(chain_len.min(max_merge_chain));
+        for page in recent_pages.drain(num_recent_pages..) {
+            builder.add(SortedPageIter::from(page));
+            page_size += page.size();
+            if page.no_compression() {
+                no_compression_size += page.size();
+            }
+        }
+        for page in pages {
+            builder.add(SortedPageIter::from(page));
+        }
         crate::perf::with(|ctx| {
             ctx.add_consolidate_page_size(page_size);
-            ctx.add_consolidate_length(page_addrs.len());
+            ctx.add_consolidate_length(page_addrs.len() - recent_pages.len());
             ctx.add_collect_info(start_at.elapsed());
         });
         let iter = MergingPageIter::new(builder.build(), range_limit);
         Ok(ConsolidationInfo {
             iter,
             last_page,
+            recent_pages,
             page_addrs,
+            // Keep the page uncompressed if it is dominated by such pages.
+            no_compression: no_compression_size * 2 >= page_size.max(1),
+            key_count_delta,
+            metadata,
+            segmented,
+            overwritten,
         })
     }
 
+    /// Detaches the leaf pages whose ranges are within `[start, end)` from the
+    /// tree, and returns the number of detached pages.
+    ///
+    /// The children of a parent that are within the range are replaced by an
+    /// empty leaf page in one update of the parent, and the detached pages
+    /// are deallocated then. Leaf pages that straddle the range boundaries,
+    /// and leaf pages without parents, are left as they are.
+    pub(crate) async fn detach_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<u64> {
+        if self.tree.is_sealed() {
+            return Err(Error::Sealed);
+        }
+        let mut detached = 0;
+        let mut next = start.to_vec();
+        loop {
+            let (_, parent) = self.find_leaf(&next).await?;
+            let Some(parent) = parent else {
+                break;
+            };
+            match self.detach_children(&parent, start, end).await {
+                Ok(num_pages) => detached += num_pages,
+                Err(Error::Again) => {
+                    self.guard.yield_now().await;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+            match parent.range.and_then(|range| range.end) {
+                Some(parent_end) if end.map_or(true, |end| parent_end < end) => {
+                    next = parent_end.to_vec();
+                }
+                _ => break,
+            }
+        }
+        Ok(detached)
+    }
+
+    /// Replaces the children of the inner page within `[start, end)` with an
+    /// empty leaf page, and returns the number of replaced children.
+    async fn detach_children(
+        &self,
+        view: &PageView<'_>,
+        start: &[u8],
+        end: Option<&[u8]>,
+    ) -> Result<u64> {
+        let children: Vec<_> = MergingInnerPageIter::new(self.iter_page(view).await?).collect();
+        let range_end = view.range.and_then(|range| range.end);
+        let child_end = |i: usize| children.get(i + 1).map(|(next, _)| *next).or(range_end);
+        let contains = |i: usize| {
+            children[i].0 >= start
+                && match (child_end(i), end) {
+                    (Some(child_end), Some(end)) => child_end <= end,
+                    (_, None) => true,
+                    (None, Some(_)) => false,
+                }
+        };
+        let Some(first) = (0..children.len()).find(|&i| contains(i)) else {
+            return Ok(0);
+        };
+        let last = (first..children.len())
+            .take_while(|&i| contains(i))
+            .last()
+            .unwrap_or(first);
+        // Don't replace an empty leaf page left by a previous detachment again.
+        if first == last && self.is_empty_leaf(children[first].1).await? {
+            return Ok(0);
+        }
+
+        let mut page_addrs = Vec::with_capacity(view.page.chain_len() as usize);
+        self.walk_page(
+            view.addr,
+            |addr, _, _| {
+                page_addrs.push(addr);
+                false
+            },
+            CacheOption::default(),
+        )
+        .await?;
+        let _change = self.tree.structure_changes.begin();
+        let mut txn = self.guard.begin().await;
+        let iter: ItemIter<(Key, Value)> = None.into();
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(iter);
+        let (leaf_addr, mut leaf_page) = txn.alloc_page(builder.size()).await?;
+        builder.build(&mut leaf_page);
+        let leaf_id = txn.insert_page(leaf_addr);
+        let mut entries = Vec::with_capacity(children.len() + first - last);
+        entries.extend_from_slice(&children[..first]);
+        entries.push((children[first].0, Index::new(leaf_id, 0)));
+        entries.extend_from_slice(&children[last + 1..]);
+        let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&entries);
+        let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+        builder.build(&mut new_page);
+        new_page.set_epoch(view.page.epoch());
+        new_page.set_chain_len(1);
+        new_page.set_chain_next(0);
+        txn.replace_page(view.id, view.addr, new_addr, &page_addrs)
+            .await?;
+        trace!("detach children {}..={} of page {:?}", first, last, view);
+
+        // Writes may still find the detached pages through the old parent, so
+        // the pages are fenced before they are deallocated.
+        let mut ids: Vec<_> = (first..=last)
+            .map(|i| (children[i].1.id, child_end(i).map(<[u8]>::to_vec)))
+            .collect();
+        while let Some((id, end)) = ids.pop() {
+            self.fence_page(id, end.as_deref(), &mut ids).await?;
+        }
+        Ok((last - first + 1) as u64)
+    }
+
+    /// Returns true if the page is a leaf base page without entries.
+    async fn is_empty_leaf(&self, index: Index) -> Result<bool> {
+        let view = self.page_view(index.id, None).await?;
+        if !view.page.tier().is_leaf() || view.page.chain_len() != 1 {
+            return Ok(false);
+        }
+        let (page, _) = self
+            .guard
+            .read_page(view.addr, CacheOption::default())
+            .await?;
+        Ok(ValuePageRef::from(page).len() == 0)
+    }
+
+    /// Replaces a detached page with an empty page of a new epoch, and
+    /// deallocates the pages on its chain, along with the blob pages they
+    /// refer to.
+    ///
+    /// Writes that find the page with the old epoch fail to install their
+    /// deltas and go through the parent again. The empty page is deallocated
+    /// right away as well, and the id of the page is retired, so that it's
+    /// freed once no one can find the page through the old parent anymore. The
+    /// right pages of the splits on the chain are detached too if the
+    /// parent doesn't link them yet, that is, if their split keys are below
+    /// `end`, the end of the page's range in the parent. Such pages are
+    /// pushed to `ids` with the same end.
+    async fn fence_page(
+        &self,
+        id: u64,
+        end: Option<&[u8]>,
+        ids: &mut Vec<(u64, Option<Vec<u8>>)>,
+    ) -> Result<()> {
+        loop {
+            let view = self.page_view(id, None).await?;
+            let mut page_addrs = Vec::with_capacity(view.page.chain_len() as usize);
+            let mut right_ids = Vec::new();
+            self.walk_page(
+                view.addr,
+                |addr, page, _| {
+                    page_addrs.push(addr);
+                    match page.kind() {
+                        PageKind::Data if page.tier().is_leaf() => {
+                            for (_, value) in SortedPageIter::new(ValuePageRef::from(page)) {
+                                if let Value::Indirect(addr) = value {
+                                    page_addrs.push(addr);
+                                }
+                            }
+                        }
+                        PageKind::Split => {
+                            // Split deltas stay on the chain after the parent
+                            // links the right pages, which may be out of the
+                            // range.
+                            let (split_key, index) = split_delta_from_page(page);
+                            if end.map_or(true, |end| split_key < end) {
+                                right_ids.push((index.id, end.map(<[u8]>::to_vec)));
+                            }
+                        }
+                        _ => {}
+                    }
+                    false
+                },
+                CacheOption::REFILL_COLD_WHEN_NOT_FULL,
+            )
+            .await?;
+            // Each page must be deallocated only once.
+            page_addrs.sort_unstable();
+            page_addrs.dedup();
+            let mut txn = self.guard.begin().await;
+            let iter: ItemIter<(Key, Value)> = None.into();
+            let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(iter);
+            let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+            builder.build(&mut new_page);
+            new_page.set_epoch(view.page.epoch() + 1);
+            new_page.set_chain_len(1);
+            new_page.set_chain_next(0);
+            page_addrs.push(new_addr);
+            match txn
+                .replace_page(view.id, view.addr, new_addr, &page_addrs)
+                .await
+            {
+                Ok(()) => {
+                    self.guard.retire_page_id(view.id);
+                    self.tree.stats.epoch_bumps.inc();
+                    ids.extend(right_ids);
+                    return Ok(());
+                }
+                Err(Error::Again) => self.guard.yield_now().await,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Consolidates all pages in the tree, so that every page consists of a
+    /// single base page without delta pages.
+    ///
+    /// Pages that change concurrently during their consolidations are retried.
+    pub(crate) async fn consolidate_all(&self) -> Result<ConsolidateStats> {
+        if self.tree.read_only {
+            return Err(Error::Sealed);
+        }
+        let mut stats = ConsolidateStats::default();
+        loop {
+            match self.try_consolidate_all(&mut stats).await {
+                Err(Error::Again) => {
+                    stats.restarts += 1;
+                    self.guard.yield_now().await;
+                    continue;
+                }
+                result => return result.map(|_| stats),
+            }
+        }
+    }
+
+    async fn try_consolidate_all(&self, stats: &mut ConsolidateStats) -> Result<()> {
+        // The start key and the index of the pages to visit.
+        let mut stack = vec![(ROOT_RANGE.start, ROOT_INDEX)];
+        while let Some((start, index)) = stack.pop() {
+            let mut view = self.page_view(index.id, None).await?;
+            // Reconcile pending splits first, otherwise the consolidation drops the
+            // split delta before the parent knows the new page. Finding the page
+            // reconciles all the pages on the path.
+            if view.page.epoch() != index.epoch {
+                self.find_leaf(start).await?;
+                return Err(Error::Again);
+            }
+            while view.page.chain_len() > 1 {
+                match self.consolidate_page(view, false).await {
+                    Ok(new_view) => {
+                        stats.pages += 1;
+                        stats.bytes += new_view.page.size() as u64;
+                        view = new_view;
+                    }
+                    Err(Error::Again) => {
+                        // The page has changed, try again with the latest version.
+                        self.guard.yield_now().await;
+                        view = self.page_view(index.id, None).await?;
+                        if view.page.epoch() != index.epoch {
+                            self.find_leaf(start).await?;
+                            return Err(Error::Again);
+                        }
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            if view.page.tier().is_inner() {
+                let iter = self.iter_page(&view).await?;
+                stack.extend(MergingInnerPageIter::new(iter));
+            }
+        }
+        Ok(())
+    }
+
+    /// Consolidates the inner pages deferred by [`Self::defer_consolidation`].
+    ///
+    /// Returns the number of pages visited. Pages that change concurrently
+    /// during their consolidations are deferred again.
+    pub(crate) async fn consolidate_deferred_pages(&self) -> usize {
+        let pages = std::mem::take(&mut *self.tree.deferred_inner_pages.lock());
+        let count = pages.len();
+        for (id, start) in pages {
+            match self.consolidate_deferred_page(id, &start).await {
+                Err(Error::Again) => {
+                    // Reconciles of the page may have deferred it again meanwhile.
+                    self.tree
+                        .deferred_inner_pages
+                        .lock()
+                        .entry(id)
+                        .or_insert(start);
+                }
+                // The next reconcile of the page defers it again.
+                Err(err) => trace!("consolidate deferred page {id}: {err:?}"),
+                Ok(()) => {}
+            }
+        }
+        count
+    }
+
+    async fn consolidate_deferred_page(&self, id: u64, start: &[u8]) -> Result<()> {
+        // The page is found from the root, which reconciles the pending splits on
+        // the path, otherwise the consolidation drops the split delta of the page
+        // before its parent knows the right page.
+        let Some(view) = self.try_find_inner_page(id, start).await? else {
+            return Ok(());
+        };
+        // Another consolidation may have caught up with the page.
+        if self.should_consolidate_page(&view.page) {
+            self.consolidate_and_restructure_page(view).await?;
+        }
+        Ok(())
+    }
+
+    /// Finds the inner page with the id on the path to the key.
+    ///
+    /// Returns `None` if the page is not on the path anymore.
+    async fn try_find_inner_page(&self, id: u64, key: &[u8]) -> Result<Option<PageView<'_>>> {
+        let mut index = ROOT_INDEX;
+        let mut range = ROOT_RANGE;
+        let mut parent = None;
+        loop {
+            let view = self.page_view(index.id, Some(range)).await?;
+            if view.page.epoch() != index.epoch {
+                let _ = self.reconcile_page(view, parent).await;
+                return Err(Error::Again);
+            }
+            if view.page.tier().is_leaf() {
+                return Ok(None);
+            }
+            if view.id == id {
+                return Ok(Some(view));
+            }
+            let Some((child_index, child_range)) = self.find_child(key, &view).await? else {
+                return Ok(None);
+            };
+            index = child_index;
+            range.start = child_range.start;
+            if let Some(end) = child_range.end {
+                range.end = Some(end);
+            }
+            parent = Some(view);
+        }
+    }
+
+    /// Estimates the work to consolidate the pages that overlap with
+    /// `[start, end)` like [`Self::consolidate_all`], without consolidating
+    /// them.
+    ///
+    /// The consolidation iterators are run to size the pages that would be
+    /// built, but nothing is written.
+    pub(crate) async fn plan_consolidation(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+    ) -> Result<CompactionPlan> {
+        let mut plan = CompactionPlan::default();
+        let mut stack = vec![(ROOT_RANGE, ROOT_INDEX)];
+        while let Some((range, index)) = stack.pop() {
+            let view = self.page_view(index.id, Some(range)).await?;
+            if view.page.chain_len() > 1 {
+                self.plan_page(&view, &mut plan).await?;
+            }
+            if view.page.tier().is_inner() {
+                let iter = self.iter_page(&view).await?;
+                let children: Vec<_> = MergingInnerPageIter::new(iter).collect();
+                for (i, &(child_start, index)) in children.iter().enumerate() {
+                    let child_end = children.get(i + 1).map(|(next, _)| *next).or(range.end);
+                    if end.map_or(false, |end| child_start >= end)
+                        || child_end.map_or(false, |child_end| child_end <= start)
+                    {
+                        continue;
+                    }
+                    let range = Range {
+                        start: child_start,
+                        end: child_end,
+                    };
+                    stack.push((range, index));
+                }
+            }
+        }
+        let mean = self.tree.consolidation_latency().mean();
+        plan.duration = mean.map(|mean| mean.mul_f64(plan.pages as f64));
+        Ok(plan)
+    }
+
+    /// Adds the work to consolidate the page to the plan.
+    async fn plan_page(&self, view: &PageView<'_>, plan: &mut CompactionPlan) -> Result<()> {
+        let (page_addrs, write_bytes, dropped_blobs) = match view.page.tier() {
+            PageTier::Leaf => {
+                let safe_lsn = self.tree.retained_lsn();
+                let info = self.collect_consolidation_info(view, false).await?;
+                let with_base = info.last_page.chain_next() == 0;
+                let mut iter =
+                    MergingLeafPageIter::new(info.iter, safe_lsn).keep_tombstones(!with_base);
+                let size = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data)
+                    .with_metadata(info.metadata)
+                    .with_iter(&mut iter)
+                    .size();
+                (info.page_addrs, size, iter.dropped_blobs().to_vec())
+            }
+            PageTier::Inner => {
+                let info = self.collect_consolidation_info(view, false).await?;
+                let mut iter = MergingInnerPageIter::new(info.iter);
+                let size = SortedPageBuilder::new(PageTier::Inner, PageKind::Data)
+                    .with_metadata(info.metadata)
+                    .with_iter(&mut iter)
+                    .size();
+                (info.page_addrs, size, Vec::new())
+            }
+        };
+        let mut read_bytes = 0;
+        for addr in page_addrs {
+            read_bytes += self.guard.read_page_info(addr)?.size();
+        }
+        let mut blob_bytes = 0;
+        for &addr in &dropped_blobs {
+            blob_bytes += self.guard.read_page_info(addr)?.size();
+        }
+        plan.pages += 1;
+        plan.read_bytes += read_bytes as u64;
+        plan.write_bytes += write_bytes as u64;
+        plan.reclaimed_bytes += (read_bytes.saturating_sub(write_bytes) + blob_bytes) as u64;
+        plan.dropped_blobs += dropped_blobs.len() as u64;
+        Ok(())
+    }
+
+    /// Returns the leaf pages in the order of keys, by walking inner pages
+    /// only.
+    pub(crate) async fn index_entries(&self) -> Result<Vec<IndexEntry>> {
+        loop {
+            match self.try_index_entries().await {
+                Err(Error::Again) => {
+                    self.guard.yield_now().await;
+                    continue;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_index_entries(&self) -> Result<Vec<IndexEntry>> {
+        let mut entries = Vec::new();
+        // The start key and the index of the pages to visit, in the reverse order of
+        // keys.
+        let mut stack = vec![(ROOT_RANGE.start, ROOT_INDEX)];
+        while let Some((start, index)) = stack.pop() {
+            let view = self.page_view(index.id, None).await?;
+            // Reconcile pending splits first, otherwise the new pages are missing.
+            if view.page.epoch() != index.epoch {
+                self.find_leaf(start).await?;
+                return Err(Error::Again);
+            }
+            if view.page.tier().is_leaf() {
+                let mut approx_size = 0;
+                let mut addr = view.addr;
+                while addr != 0 {
+                    let page = self.guard.read_page_info(addr)?;
+                    approx_size += page.size() as u64;
+                    addr = page.chain_next();
+                }
+                entries.push(IndexEntry {
+                    start: start.to_vec(),
+                    page_id: index.id,
+                    approx_size,
+                });
+            } else {
+                let iter = self.iter_page(&view).await?;
+                let len = stack.len();
+                stack.extend(MergingInnerPageIter::new(iter));
+                stack[len..].reverse();
+            }
+        }
+        Ok(entries)
+    }
+
     /// Consolidates and restructures a page.
     async fn consolidate_and_restructure_page<'g>(&'g self, mut view: PageView<'g>) -> Result<()> {
-        view = self.consolidate_page(view).await?;
+        // Leaf chains are only consolidated to split the pages if consolidation is
+        // disabled, which needs the base pages to be merged.
+        let partial = !self.tree.options.disable_consolidation;
+        view = self.consolidate_page(view, partial).await?;
         // Try to split the page if it is too large.
-        if self.should_split_page(&view.page) {
+        if self.should_split_page(&view) {
             let _ = self.split_page(view).await;
         }
         Ok(())
     }
 
+    // Defers the consolidation of an inner page to the background according to
+    // `Options::inner_consolidation`.
+    //
+    // Returns false if the page should be consolidated inline, which is also the
+    // case if its chain has grown twice as long as it should.
+    fn defer_consolidation(&self, view: &PageView<'_>) -> bool {
+        let Some(range) = view.range else {
+            return false;
+        };
+        if !self.tree.defers_inner_consolidation()
+            || view.page.chain_len() as usize > self.max_chain_len(&view.page) * 2
+        {
+            return false;
+        }
+        // Reconciles of a page that is already deferred don't defer it again, the
+        // deferred consolidation picks up their deltas as well.
+        let mut pages = self.tree.deferred_inner_pages.lock();
+        if !pages.contains_key(&view.id) {
+            pages.insert(view.id, range.start.to_vec());
+            self.tree.stats.deferred_inner_consolidations.inc();
+        }
+        true
+    }
+
     // Returns true if the page should be split.
-    fn should_split_page(&self, page: &PageInfo) -> bool {
-        let mut max_size = self.tree.options.page_size;
-        if page.tier().is_inner() {
+    fn should_split_page(&self, view: &PageView<'_>) -> bool {
+        let threshold = self.max_page_size(view);
+        let split = view.page.size() > threshold && view.page.chain_next() == 0;
+        self.observe_decision(DecisionKind::Split, &view.page, threshold, split);
+        split
+    }
+
+    // Returns true if writes should try to split the page before installing
+    // their deltas.
+    fn should_presplit_page(&self, view: &PageView<'_>) -> bool {
+        let ratio = self.tree.options.presplit_threshold_ratio.max(1.0);
+        view.page.size() as f64 > self.max_page_size(view) as f64 * ratio
+    }
+
+    // Returns the size that the page is split beyond.
+    fn max_page_size(&self, view: &PageView<'_>) -> usize {
+        match self.tree.options.root_split_page_size {
+            Some(size) if view.id == ROOT_ID => size,
             // Adjust the page size for inner pages.
-            max_size /= 2;
+            _ if view.page.tier().is_inner() => self.tree.options.page_size / 2,
+            _ => self.tree.options.page_size,
         }
-        page.size() > max_size && page.chain_next() == 0
     }
 
     // Returns true if the page should be consolidated.
     fn should_consolidate_page(&self, page: &PageInfo) -> bool {
-        let mut max_chain_len = self.tree.options.page_chain_length;
+        let threshold = if page.tier().is_leaf() && self.tree.options.disable_consolidation {
+            self.max_unconsolidated_chain_len(page)
+        } else {
+            self.max_chain_len(page)
+        };
+        let consolidate = page.chain_len() as usize > threshold;
+        self.observe_decision(DecisionKind::Consolidate, page, threshold, consolidate);
+        consolidate
+    }
+
+    // Returns the chain length that the page is consolidated beyond.
+    fn max_chain_len(&self, page: &PageInfo) -> usize {
+        let mut max_chain_len = self.tree.page_chain_length();
         if page.tier().is_inner() {
             // Adjust the chain length for inner pages.
             max_chain_len /= 2;
         }
-        page.chain_len() as usize > max_chain_len.max(1)
+        max_chain_len.max(1)
+    }
+
+    // Returns the chain length that a leaf page is consolidated beyond if
+    // consolidation is disabled.
+    //
+    // The deltas are assumed to be about the size of the newest one, and the
+    // chain is consolidated once they fill half of a page, which is about what a
+    // split leaves in the base page, so that the consolidated page splits.
+    fn max_unconsolidated_chain_len(&self, page: &PageInfo) -> usize {
+        let page_size = self.tree.options.page_size / 2;
+        (page_size / page.size().max(1)).max(self.max_chain_len(page))
+    }
+
+    fn observe_decision(
+        &self,
+        kind: DecisionKind,
+        page: &PageInfo,
+        threshold: usize,
+        outcome: bool,
+    ) {
+        if let Some(observer) = self.tree.options.decision_observer.as_ref() {
+            observer.observe(&DecisionEvent {
+                kind,
+                is_leaf: page.tier().is_leaf(),
+                chain_len: page.chain_len() as usize,
+                page_size: page.size(),
+                threshold,
+                outcome,
+            });
+        }
     }
 }
 
@@ -773,25 +2887,61 @@ impl<'a, E: Env> TreeTxn<'a, E> {
 pub(crate) struct TreeIter<'a, 't: 'a, E: Env> {
     txn: &'a TreeTxn<'t, E>,
     options: ReadOptions,
+    scan_options: ScanOptions,
+    stats: ScanStats,
     inner_iter: Option<MergingInnerPageIter<'a>>,
     inner_next: Option<&'a [u8]>,
+    /// The right page of a split that the parent doesn't know, which a frozen
+    /// guard visits before the next page in the parent.
+    split_next: Option<(&'a [u8], Index)>,
 }
 
-impl<'a, 't: 'a, E: Env> TreeIter<'a, 't, E> {
-    pub(crate) fn new(txn: &'a TreeTxn<'t, E>, options: ReadOptions) -> Self {
-        Self {
-            txn,
-            options,
-            inner_iter: None,
-            inner_next: Some(&[]),
+impl<'a, 't: 'a, E: Env> TreeIter<'a, 't, E> {
+    pub(crate) fn new(
+        txn: &'a TreeTxn<'t, E>,
+        options: ReadOptions,
+        scan_options: ScanOptions,
+    ) -> Self {
+        Self {
+            txn,
+            options,
+            scan_options,
+            stats: ScanStats::default(),
+            inner_iter: None,
+            inner_next: Some(&[]),
+            split_next: None,
+        }
+    }
+
+    /// Starts the scan from the first page that may contain the target,
+    /// skipping entries before it.
+    pub(crate) fn start_at(mut self, target: &'a [u8]) -> Self {
+        self.inner_next = Some(target);
+        self
+    }
+
+    pub(crate) fn stats(&self) -> ScanStats {
+        self.stats.clone()
+    }
+
+    fn restart(&mut self) {
+        self.stats.restarts += 1;
+        if self.stats.restarts > self.scan_options.max_restarts as u64 {
+            // Pin the scan to the safe LSN. Entries visible to it are retained,
+            // and this is a no-op once pinned since the safe LSN only grows.
+            let safe_lsn = self.txn.tree.safe_lsn();
+            self.options.max_lsn = self.options.max_lsn.min(safe_lsn);
         }
     }
 
-    async fn seek(&mut self, target: &[u8]) -> Result<PageIter<'_>> {
-        let (view, parent) = self.txn.find_leaf(target).await?;
-        let iter = self.txn.iter_page(&view).await?;
-        let mut leaf_iter = PageIter::new(iter, self.options.max_lsn);
-        leaf_iter.seek(target);
+    async fn seek(&mut self, target: &'a [u8]) -> Result<PageView<'a>> {
+        let (view, parent) = if self.txn.guard.is_frozen() {
+            let (view, parent, split) = self.txn.find_frozen_leaf(target).await?;
+            self.split_next = split;
+            (view, parent)
+        } else {
+            self.txn.find_leaf(target).await?
+        };
         if let Some(parent) = parent {
             let iter = self.txn.iter_page(&parent).await?;
             let mut iter = MergingInnerPageIter::new(iter);
@@ -804,32 +2954,190 @@ impl<'a, 't: 'a, E: Env> TreeIter<'a, 't, E> {
             self.inner_iter = None;
             self.inner_next = None;
         }
-        Ok(leaf_iter)
+        self.stats.pages += 1;
+        Ok(view)
     }
 
     pub(crate) async fn next_page(&mut self) -> Result<Option<PageIter<'_>>> {
+        let Some((view, start)) = self.next_view().await? else {
+            return Ok(None);
+        };
+        // Pages that are not worth caching don't evict others.
+        let hint = if self.options.fill_cache {
+            CacheOption::default()
+        } else {
+            CacheOption::REFILL_COLD_WHEN_NOT_FULL
+        };
+        let (iter, read_bytes) = self
+            .txn
+            .leaf_iter(&view, self.options.max_lsn, hint)
+            .await?;
+        let mut iter = iter.with_tombstones(self.scan_options.include_tombstones);
+        self.stats.bytes += read_bytes as u64;
+        if let Some(max_bytes) = self.options.max_bytes {
+            if self.stats.bytes > max_bytes {
+                return Err(Error::BudgetExceeded {
+                    consumed: self.stats.bytes,
+                });
+            }
+        }
+        iter.seek(start);
+        Ok(Some(iter))
+    }
+
+    /// Returns the next leaf page, along with a key that is not larger than
+    /// the entries of the page that the scan hasn't returned yet.
+    async fn next_view(&mut self) -> Result<Option<(PageView<'a>, &'a [u8])>> {
         let mut inner_next = self.inner_next.take();
-        if let Some(inner_iter) = self.inner_iter.as_mut() {
-            if let Some((start, index)) = inner_iter.next() {
-                let view = self.txn.page_view(index.id, None).await?;
-                if view.page.epoch() == index.epoch {
-                    let iter = self.txn.iter_page(&view).await?;
-                    self.inner_next = inner_next;
-                    return Ok(Some(PageIter::new(iter, self.options.max_lsn)));
-                } else {
-                    // The page epoch has changed, we need to restart from this.
-                    inner_next = Some(start);
-                }
+        let next = match self.split_next.take() {
+            Some(next) => Some(next),
+            None => self.inner_iter.as_mut().and_then(|iter| iter.next()),
+        };
+        if let Some((start, index)) = next {
+            let view = self.txn.page_view(index.id, None).await?;
+            if view.page.epoch() == index.epoch {
+                self.inner_next = inner_next;
+                self.stats.pages += 1;
+                return Ok(Some((view, start)));
+            } else if self.txn.guard.is_frozen() {
+                // The split will never be reconciled for a frozen guard, so the
+                // left page is returned with entries before the split key, and the
+                // right page is visited next.
+                self.split_next = Some(self.txn.split_delta(&view).await?);
+                self.inner_next = inner_next;
+                self.stats.pages += 1;
+                return Ok(Some((view, start)));
+            } else {
+                // The page epoch has changed, we need to restart from this.
+                inner_next = Some(start);
+                self.restart();
             }
         }
         if let Some(next) = inner_next {
-            let iter = self.seek(next).await?;
-            Ok(Some(iter))
+            let view = self.seek(next).await?;
+            Ok(Some((view, next)))
         } else {
             self.inner_iter = None;
             Ok(None)
         }
     }
+
+    /// Loads the leaf pages before `end` into the page cache.
+    ///
+    /// Returns the number of pages read from page files, and stops early once
+    /// the cache is full.
+    pub(crate) async fn warmup(mut self, end: Option<&[u8]>) -> Result<u64> {
+        let mut loaded = 0;
+        while let Some((view, next)) = self.next_view().await? {
+            if end.map_or(false, |end| next >= end) || self.txn.guard.is_cache_full() {
+                break;
+            }
+            loaded += self.txn.load_page_chain(&view).await?;
+        }
+        Ok(loaded)
+    }
+
+    /// Returns the contiguous ranges of the remaining pages in which most keys
+    /// are deleted.
+    ///
+    /// Tombstones are dropped when pages are consolidated, so these are the
+    /// deletions that are not physically applied yet.
+    pub(crate) async fn deleted_regions(mut self) -> Result<Vec<DeletedRegion>> {
+        let mut regions: Vec<DeletedRegion> = Vec::new();
+        while let Some((view, start)) = self.next_view().await? {
+            let (keys, tombstones) = self
+                .txn
+                .count_tombstones(&view, self.options.max_lsn)
+                .await?;
+            // Only the last region can be open.
+            let open = regions.last_mut().filter(|region| region.end.is_none());
+            if tombstones * 2 > keys {
+                if let Some(region) = open {
+                    region.pages += 1;
+                    region.keys += keys;
+                    region.tombstones += tombstones;
+                } else {
+                    regions.push(DeletedRegion {
+                        start: start.to_vec(),
+                        end: None,
+                        pages: 1,
+                        keys,
+                        tombstones,
+                    });
+                }
+            } else if let Some(region) = open {
+                region.end = Some(start.to_vec());
+            }
+        }
+        Ok(regions)
+    }
+
+    /// Collects the versions of the remaining pages whose LSNs are in `(after,
+    /// upto]`, sorted by LSNs and then keys.
+    ///
+    /// Pages are visited until the keys and values collected reach
+    /// `max_bytes`. Returns the versions and the start key of the next page to
+    /// visit, or `None` if all pages are visited.
+    #[allow(clippy::type_complexity)]
+    pub(crate) async fn versions(
+        mut self,
+        (after, upto): (u64, u64),
+        max_bytes: usize,
+    ) -> Result<(Vec<(Vec<u8>, u64, Option<Vec<u8>>)>, Option<Vec<u8>>)> {
+        let mut versions = Vec::new();
+        let mut bytes = 0;
+        let mut next = None;
+        while let Some((view, start)) = self.next_view().await? {
+            if bytes >= max_bytes {
+                next = Some(start.to_vec());
+                break;
+            }
+            let len = versions.len();
+            self.txn
+                .collect_versions(&view, start, (after, upto), &mut versions)
+                .await?;
+            bytes += versions[len..]
+                .iter()
+                .map(|(key, _, value)| key.len() + value.as_ref().map_or(0, Vec::len))
+                .sum::<usize>();
+        }
+        versions.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        Ok((versions, next))
+    }
+
+    /// Copies all the versions in the next leaf page, see
+    /// [`TreeTxn::copy_versions`].
+    pub(crate) async fn next_versions(&mut self) -> Result<Option<Vec<KeyVersion>>> {
+        let Some((view, start)) = self.next_view().await? else {
+            return Ok(None);
+        };
+        Ok(Some(self.txn.copy_versions(&view, start).await?))
+    }
+
+    /// Counts the visible entries in `[start, end)` of the remaining pages.
+    pub(crate) async fn count(mut self, start: &[u8], end: Option<&[u8]>) -> Result<u64> {
+        let mut count = 0;
+        while let Some((view, next)) = self.next_view().await? {
+            if end.map_or(false, |end| next >= end) {
+                break;
+            }
+            count += self
+                .txn
+                .count_page(&view, start, end, self.options.max_lsn)
+                .await?;
+        }
+        Ok(count)
+    }
+}
+
+/// A version of a key copied out of a leaf page.
+pub(crate) struct KeyVersion {
+    pub(crate) key: Vec<u8>,
+    pub(crate) lsn: u64,
+    /// The value of the version, or `None` for a tombstone.
+    pub(crate) value: Option<Vec<u8>>,
+    /// The timestamp of a timestamped value.
+    pub(crate) ts: Option<u64>,
 }
 
 struct ConsolidationInfo<'a, K, V>
@@ -839,7 +3147,120 @@ where
 {
     iter: MergingPageIter<'a, K, V>,
     last_page: PageInfo,
+    /// The recent deltas to keep on top of the consolidated page, from the
+    /// newest to the oldest.
+    recent_pages: Vec<PageRef<'a>>,
     page_addrs: Vec<u64>,
+    no_compression: bool,
+    /// The correction to the approximate key count for the consolidated
+    /// pages, see [`key_count_correction`].
+    key_count_delta: i64,
+    /// The user metadata for the consolidated page.
+    metadata: Option<&'a [u8]>,
+    /// Whether only a segment of the chain is consolidated.
+    segmented: bool,
+    /// Whether any key in the deltas is written more than once, which is only
+    /// checked if [`Options::disable_consolidation`] is set.
+    overwritten: bool,
+}
+
+/// Returns how a write of the value is guessed to change the number of live
+/// keys.
+fn guess_key_count(value: &Value<'_>) -> i64 {
+    match value {
+        Value::Delete => -1,
+        _ => 1,
+    }
+}
+
+/// Returns the sum of the guesses made for the entries of a leaf delta page,
+/// skipping the keys at or beyond the range limit.
+fn guess_page_key_count(page: PageRef<'_>, range_limit: Option<&[u8]>) -> i64 {
+    SortedPageIter::new(ValuePageRef::from(page))
+        .filter(|(k, _)| range_limit.map_or(true, |limit| k.raw < limit))
+        .map(|(_, v)| guess_key_count(&v))
+        .sum()
+}
+
+/// Returns true if any key in the leaf delta pages is also written by another
+/// delta page or the base page.
+fn has_overwritten_keys<'a>(deltas: &[PageRef<'a>], base: Option<PageRef<'a>>) -> bool {
+    let mut keys = HashSet::<&[u8]>::new();
+    for page in deltas {
+        for (k, _) in SortedPageIter::new(ValuePageRef::from(*page)) {
+            if !keys.insert(k.raw) {
+                return true;
+            }
+        }
+    }
+    let Some(base) = base else {
+        return false;
+    };
+    let base = ValuePageRef::from(base);
+    keys.into_iter().any(|raw| {
+        let index = base
+            .rank(&Key::new(raw, u64::MAX))
+            .unwrap_or_else(|index| index);
+        matches!(base.get(index), Some((k, _)) if k.raw == raw)
+    })
+}
+
+/// Returns how much consolidating the leaf pages corrects the approximate key
+/// count.
+///
+/// If the deltas are merged into the base page, their guesses are replaced
+/// with the actual changes to the live keys of the base page. Otherwise, the
+/// guesses are dropped here and made again for the merged delta page. Keys at
+/// or beyond the range limit have moved to the right page of a split, so they
+/// are left as they are.
+fn key_count_correction<'a>(
+    deltas: &[PageRef<'a>],
+    base: Option<PageRef<'a>>,
+    range_limit: Option<&[u8]>,
+) -> i64 {
+    let guessed = deltas
+        .iter()
+        .map(|page| guess_page_key_count(*page, range_limit))
+        .sum::<i64>();
+    let Some(base) = base else {
+        return -guessed;
+    };
+    // The latest version of every key in the deltas, and whether it is live.
+    let mut latest = HashMap::<&[u8], (u64, bool)>::new();
+    for page in deltas {
+        for (k, v) in SortedPageIter::new(ValuePageRef::from(*page)) {
+            if range_limit.map_or(false, |limit| k.raw >= limit) {
+                continue;
+            }
+            let live = !matches!(v, Value::Delete);
+            let entry = latest.entry(k.raw).or_insert((k.lsn, live));
+            if k.lsn > entry.0 {
+                *entry = (k.lsn, live);
+            }
+        }
+    }
+    let base = ValuePageRef::from(base);
+    let mut actual = 0;
+    for (raw, (_, live)) in latest {
+        let index = base
+            .rank(&Key::new(raw, u64::MAX))
+            .unwrap_or_else(|index| index);
+        let was_live = matches!(
+            base.get(index),
+            Some((k, v)) if k.raw == raw && !matches!(v, Value::Delete)
+        );
+        actual += live as i64 - was_live as i64;
+    }
+    actual - guessed
+}
+
+/// What is collected from the pages on a chain while iterating over it.
+#[derive(Default)]
+struct ChainInfo<'a> {
+    /// The user metadata of the newest page on the chain that carries one.
+    metadata: Option<&'a [u8]>,
+    /// The total size of the pages on the chain.
+    size: usize,
 }
 
 fn split_delta_from_page(page: PageRef<'_>) -> (&[u8], Index) {
@@ -848,3 +3269,904 @@ fn split_delta_from_page(page: PageRef<'_>) -> (&[u8], Index) {
         .get(0)
         .expect("split page delta must exist")
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{
+        env::Env,
+        tests::{must_get, must_put, options},
+        *,
+    };
+
+    #[photonio::test]
+    async fn indirect_values() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            value_inline_threshold: 16,
+            ..options()
+        };
+        let value = |i: u64| -> Vec<u8> {
+            let len = if i % 2 == 0 { 8 } else { 64 };
+            i.to_be_bytes().repeat(len / 8)
+        };
+        const N: u64 = 1 << 10;
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        for lsn in [1, 2] {
+            for i in 0..N {
+                let key = i.to_be_bytes();
+                table.put(&key, lsn, &value(i + lsn)).await.unwrap();
+            }
+        }
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(table.get(&key, 1).await.unwrap(), Some(value(i + 1)));
+            assert_eq!(table.get(&key, 2).await.unwrap(), Some(value(i + 2)));
+        }
+
+        let guard = table.pin();
+        let mut pages = guard.pages();
+        let mut i = 0u64;
+        while let Some(page) = pages.next().await.unwrap() {
+            for (k, v) in page {
+                assert_eq!(k, &i.to_be_bytes());
+                assert_eq!(v, value(i + 2));
+                i += 1;
+            }
+        }
+        assert_eq!(i, N);
+        drop(pages);
+        drop(guard);
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, opts).await.unwrap();
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(table.get(&key, 2).await.unwrap(), Some(value(i + 2)));
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn value_codec() {
+        /// Reverses values of even lengths.
+        struct ReverseCodec;
+
+        impl ValueCodec for ReverseCodec {
+            fn encode(&self, value: &[u8]) -> Option<Vec<u8>> {
+                (value.len() % 2 == 0).then(|| value.iter().rev().copied().collect())
+            }
+
+            fn decode(&self, value: &[u8]) -> Vec<u8> {
+                value.iter().rev().copied().collect()
+            }
+        }
+
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            value_codec: Some(::std::sync::Arc::new(ReverseCodec)),
+            ..options()
+        };
+        let value = |i: u64| -> Vec<u8> {
+            let len = if i % 2 == 0 { 8 } else { 7 };
+            i.to_le_bytes()[..len].to_vec()
+        };
+        const N: u64 = 1 << 10;
+        let table = Table::open(&path, opts.clone()).await.unwrap();
+        for i in 0..N {
+            table.put(&i.to_be_bytes(), 1, &value(i)).await.unwrap();
+        }
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(table.get(&key, 1).await.unwrap(), Some(value(i)));
+            let history = table.get_history(&key, ..).await.unwrap();
+            assert_eq!(history, vec![(1, Some(value(i)))]);
+        }
+
+        let guard = table.pin();
+        let mut pages = guard.pages();
+        let mut i = 0u64;
+        while let Some(page) = pages.next().await.unwrap() {
+            for (k, v) in page {
+                assert_eq!(k, &i.to_be_bytes());
+                assert_eq!(v, value(i));
+                i += 1;
+            }
+        }
+        assert_eq!(i, N);
+        drop(pages);
+        drop(guard);
+        table.close().await.unwrap();
+
+        // Encoded values can't be read without the codec.
+        let table = Table::open(&path, options()).await.unwrap();
+        assert_eq!(
+            table.get(&1u64.to_be_bytes(), 1).await.unwrap(),
+            Some(value(1))
+        );
+        assert!(matches!(
+            table.get(&0u64.to_be_bytes(), 1).await,
+            Err(Error::InvalidArgument)
+        ));
+        table.close().await.unwrap();
+    }
+
+    fn simulate_concurrent_crud(seed: u64) -> TreeStats {
+        let env = env::Sim::new(seed);
+        env.block_on(async {
+            let path = tempdir().unwrap();
+            let table = raw::Table::open(env.clone(), &path, options())
+                .await
+                .unwrap();
+            let mut tasks = Vec::new();
+            for t in 0..4u64 {
+                let table = table.clone();
+                let handle = env.spawn_background(async move {
+                    const N: u64 = 1 << 8;
+                    for i in 0..N {
+                        let buf = (i * 4 + t).to_be_bytes();
+                        table.put(&buf, i, &buf).await.unwrap();
+                        let value = table.get(&buf, i).await.unwrap();
+                        assert_eq!(value, Some(buf.to_vec()));
+                    }
+                });
+                tasks.push(handle);
+            }
+            for task in tasks {
+                task.await;
+            }
+            let stats = table.stats().tree;
+            table.close().await.unwrap();
+            stats
+        })
+    }
+
+    #[test]
+    fn simulated_concurrent_crud() {
+        for seed in [0, 1, 42] {
+            let stats = simulate_concurrent_crud(seed);
+            assert_eq!(
+                format!("{stats:?}"),
+                format!("{:?}", simulate_concurrent_crud(seed))
+            );
+        }
+    }
+
+    #[test]
+    fn concurrent_writes_with_same_lsn() {
+        let env = env::Sim::new(0);
+        env.block_on(async {
+            let path = tempdir().unwrap();
+            let table = raw::Table::open(env.clone(), &path, options())
+                .await
+                .unwrap();
+            let key = 1u64.to_be_bytes();
+            table.put(&key, 1, b"0").await.unwrap();
+            // Both writes are built before either of them is applied.
+            let (a, b) = futures::join!(table.put(&key, 2, b"a"), table.put(&key, 2, b"b"));
+            let (winner, loser) = match (a, b) {
+                (Ok(()), loser) => (b"a", loser),
+                (loser, Ok(())) => (b"b", loser),
+                (a, b) => panic!("both writes failed: {a:?} {b:?}"),
+            };
+            assert!(matches!(loser, Err(Error::LsnConflict { latest: 2 })));
+            let value = table.get(&key, 2).await.unwrap();
+            assert_eq!(value, Some(winner.to_vec()));
+
+            // A transaction races with a write in the same way.
+            let mut txn = table.begin_txn();
+            txn.put(&key, b"c");
+            let (a, b) = futures::join!(txn.commit(3), table.put(&key, 3, b"d"));
+            let (winner, loser) = match (a, b) {
+                (Ok(()), loser) => (b"c", loser),
+                (loser, Ok(())) => (b"d", loser),
+                (a, b) => panic!("both writes failed: {a:?} {b:?}"),
+            };
+            assert!(matches!(loser, Err(Error::LsnConflict { latest: 3 })));
+            let value = table.get(&key, 3).await.unwrap();
+            assert_eq!(value, Some(winner.to_vec()));
+            table.close().await.unwrap();
+        })
+    }
+
+    #[photonio::test]
+    async fn concurrent_init() {
+        use ::std::sync::Arc;
+
+        let path = tempdir().unwrap();
+        let store =
+            page_store::PageStore::open(env::Photon, &path, options().page_store, &mut |_| {})
+                .await
+                .unwrap();
+        let store = Arc::new(store);
+        let tree = Arc::new(tree::Tree::new(options()));
+        let mut tasks = Vec::new();
+        for _ in 0..8 {
+            let store = store.clone();
+            let tree = tree.clone();
+            let handle = photonio::task::spawn(async move {
+                tree.begin(store.guard()).init().await.unwrap();
+            });
+            tasks.push(handle);
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // Only the root page is inserted.
+        let guard = store.guard();
+        assert_ne!(guard.page_addr(1), 0);
+        assert_eq!(guard.page_addr(2), 0);
+        drop(guard);
+        Arc::try_unwrap(store).ok().unwrap().close().await;
+    }
+
+    #[photonio::test]
+    async fn split_guard() {
+        let path = tempdir().unwrap();
+        // Only split at the first key of each prefix.
+        let guard = SplitGuard::new(|key| key[1..] == [0; 8]);
+        let opts = TableOptions {
+            split_guard: Some(guard),
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 4;
+        let key = |prefix: u8, i: u64| [&[prefix][..], &i.to_be_bytes()].concat();
+        for i in 0..N {
+            for prefix in 0..N as u8 {
+                table.put(&key(prefix, i), 1, &[]).await.unwrap();
+            }
+        }
+
+        let guard = table.pin();
+        let mut pages = guard.pages();
+        let mut num_pages = 0;
+        while let Some(page) = pages.next().await.unwrap() {
+            let keys = page.map(|(k, _)| k.to_vec()).collect::<Vec<_>>();
+            assert_eq!(keys.len() as u64 % N, 0);
+            assert!(keys.chunks(N as usize).all(|c| c[0][1..] == [0; 8]));
+            num_pages += 1;
+        }
+        assert!(num_pages > 1);
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn consolidate_after_split() {
+        async fn consolidations(consolidate_after_split: bool) -> u64 {
+            let path = tempdir().unwrap();
+            let opts = TableOptions {
+                consolidate_after_split,
+                ..options()
+            };
+            let table = Table::open(&path, opts).await.unwrap();
+            const N: u64 = 1 << 10;
+            for i in 0..N {
+                must_put(&table, i, 1).await;
+            }
+            for i in 0..N {
+                must_get(&table, i, 1, Some(i)).await;
+            }
+            let stats = table.stats().tree;
+            table.close().await.unwrap();
+            stats.success.consolidate_page
+        }
+
+        // Split pages are consolidated even if their chains are short.
+        assert!(consolidations(true).await > consolidations(false).await);
+    }
+
+    #[photonio::test]
+    async fn inner_consolidation() {
+        async fn deferred(inner_consolidation: InnerConsolidation) -> u64 {
+            let path = tempdir().unwrap();
+            let opts = TableOptions {
+                inner_consolidation,
+                ..options()
+            };
+            let table = Table::open(&path, opts).await.unwrap();
+            const N: u64 = 1 << 12;
+            for i in 0..N {
+                must_put(&table, i, 1).await;
+            }
+            // Let the background job catch up with the deferred pages.
+            env::Env::sleep(&env::Photon, ::std::time::Duration::from_millis(50)).await;
+            for i in 0..N {
+                must_get(&table, i, 1, Some(i)).await;
+            }
+            let stats = table.stats().tree;
+            table.close().await.unwrap();
+            stats.deferred_inner_consolidations
+        }
+
+        assert_eq!(deferred(InnerConsolidation::Inline).await, 0);
+        assert!(deferred(InnerConsolidation::Background).await > 0);
+    }
+
+    #[photonio::test]
+    async fn epoch_bumps() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        let before = table.stats().tree;
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        let stats = table.stats().tree.sub(&before);
+        assert!(stats.epoch_bumps > 0);
+        // Root splits replace the root without changing its range.
+        assert!(stats.epoch_bumps <= stats.success.split_page);
+        let rate = stats.epoch_bump_rate(::std::time::Duration::from_secs(2));
+        assert_eq!(rate, stats.epoch_bumps as f64 / 2.0);
+        assert_eq!(stats.epoch_bump_rate(::std::time::Duration::ZERO), 0.0);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn decision_observer() {
+        use ::std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            decision_observer: Some(DecisionObserver::new(move |event| {
+                observed.lock().unwrap().push(event.clone());
+            })),
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.close().await.unwrap();
+
+        let events = events.lock().unwrap();
+        for kind in [DecisionKind::Split, DecisionKind::Consolidate] {
+            let decisions = events.iter().filter(|e| e.kind == kind);
+            assert!(decisions.clone().any(|e| e.outcome));
+            assert!(decisions.clone().any(|e| !e.outcome));
+        }
+        for event in events.iter() {
+            let value = match event.kind {
+                DecisionKind::Split => event.page_size,
+                DecisionKind::Consolidate => event.chain_len,
+            };
+            if event.outcome {
+                assert!(value > event.threshold);
+            }
+        }
+    }
+
+    #[photonio::test]
+    async fn presplit_threshold_ratio() {
+        async fn presplits(presplit_threshold_ratio: f64) -> (u64, u64) {
+            let path = tempdir().unwrap();
+            let opts = TableOptions {
+                // Pages stay oversized, since splits are always vetoed.
+                split_guard: Some(SplitGuard::new(|_| false)),
+                presplit_threshold_ratio,
+                ..options()
+            };
+            let table = Table::open(&path, opts).await.unwrap();
+            const N: u64 = 1 << 6;
+            for i in 0..N {
+                must_put(&table, i, 1).await;
+            }
+            for i in 0..N {
+                must_get(&table, i, 1, Some(i)).await;
+            }
+            let stats = table.stats().tree;
+            table.close().await.unwrap();
+            (stats.presplit_attempted, stats.presplit_skipped)
+        }
+
+        let (attempted, skipped) = presplits(1.0).await;
+        assert!(attempted > 0);
+        assert_eq!(skipped, 0);
+        let (attempted, skipped) = presplits(f64::INFINITY).await;
+        assert_eq!(attempted, 0);
+        assert!(skipped > 0);
+    }
+
+    #[photonio::test]
+    async fn consolidate_keep_recent_deltas() {
+        async fn consolidations(consolidate_keep_recent_deltas: usize) -> u64 {
+            let path = tempdir().unwrap();
+            let opts = TableOptions {
+                page_size: 1 << 12,
+                consolidate_keep_recent_deltas,
+                ..options()
+            };
+            let table = Table::open(&path, opts).await.unwrap();
+            let mut expect = ::std::collections::HashMap::new();
+            for lsn in 1..=N {
+                let key = (lsn % 4).to_be_bytes();
+                if lsn % 8 == 0 {
+                    table.delete(&key, lsn).await.unwrap();
+                    expect.insert(key, None);
+                } else {
+                    table.put(&key, lsn, &lsn.to_be_bytes()).await.unwrap();
+                    expect.insert(key, Some(lsn.to_be_bytes().to_vec()));
+                }
+                table.set_safe_lsn(lsn / 2);
+                for (key, value) in &expect {
+                    assert_eq!(&table.get(key, lsn).await.unwrap(), value);
+                }
+            }
+            let stats = table.stats().tree;
+            table.close().await.unwrap();
+            stats.success.consolidate_page
+        }
+
+        const N: u64 = 1 << 8;
+        let consolidated = consolidations(0).await;
+        let kept = consolidations(2).await;
+        // Kept deltas fill the chain sooner, but they don't make the page
+        // consolidate on every write.
+        assert!(kept > consolidated);
+        assert!(kept <= N / 2);
+    }
+
+    #[photonio::test]
+    async fn max_merge_chain() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            page_size: 1 << 12,
+            page_chain_length: 16,
+            max_merge_chain: 2,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 8;
+        let mut expect = ::std::collections::HashMap::new();
+        for lsn in 1..=N {
+            let key = (lsn % 8).to_be_bytes();
+            if lsn % 3 == 0 {
+                table.delete(&key, lsn).await.unwrap();
+                expect.insert(key, None);
+            } else {
+                table.put(&key, lsn, &lsn.to_be_bytes()).await.unwrap();
+                expect.insert(key, Some(lsn.to_be_bytes().to_vec()));
+            }
+            table.set_safe_lsn(lsn);
+            for (key, value) in &expect {
+                assert_eq!(&table.get(key, lsn).await.unwrap(), value);
+            }
+        }
+        table.consolidate_all().await.unwrap();
+        for (key, value) in &expect {
+            assert_eq!(&table.get(key, N).await.unwrap(), value);
+        }
+        // Tombstones are kept until the segments reach the base page.
+        assert!(table.stats().tree.segmented_consolidations > 0);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn unsplittable_page() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        // Versions of the same key can't be split into different pages.
+        for lsn in 1..(1 << 8) {
+            table.put(b"key", lsn, &[0; 16]).await.unwrap();
+        }
+        assert_eq!(table.get(b"key", 1).await.unwrap(), Some(vec![0; 16]));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn root_split_page_size() {
+        async fn num_splits(root_split_page_size: Option<usize>) -> u64 {
+            let path = tempdir().unwrap();
+            let opts = TableOptions {
+                root_split_page_size,
+                ..options()
+            };
+            let table = Table::open(&path, opts).await.unwrap();
+            const N: u64 = 1 << 8;
+            for i in 0..N {
+                must_put(&table, i, i).await;
+            }
+            for i in 0..N {
+                must_get(&table, i, i, Some(i)).await;
+            }
+            let splits = table.stats().tree.success.split_page;
+            table.close().await.unwrap();
+            splits
+        }
+
+        assert!(num_splits(None).await > 0);
+        // The root holds all the keys without being split.
+        assert_eq!(num_splits(Some(1 << 20)).await, 0);
+    }
+
+    #[photonio::test]
+    async fn min_version_retention() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            min_version_retention: 5,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        table.set_safe_lsn(u64::MAX);
+        for lsn in 1..=10 {
+            must_put(&table, 0, lsn).await;
+        }
+        table.consolidate_all().await.unwrap();
+
+        // Versions visible to LSNs in [5, 10] are retained.
+        for lsn in 5..=10 {
+            must_get(&table, 0, lsn, Some(0)).await;
+        }
+        must_get(&table, 0, 4, None).await;
+
+        // The window moves with the writes.
+        for lsn in 11..=20 {
+            must_put(&table, 0, lsn).await;
+        }
+        table.consolidate_all().await.unwrap();
+        must_get(&table, 0, 15, Some(0)).await;
+        must_get(&table, 0, 14, None).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn index_entries() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        let entries = table.index_entries().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].start.is_empty());
+
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        let entries = table.index_entries().await.unwrap();
+        assert!(entries.len() > 1);
+        assert!(entries[0].start.is_empty());
+        // The leaf pages cover all keys without overlaps.
+        let mut count = 0;
+        for (i, entry) in entries.iter().enumerate() {
+            assert!(entry.approx_size > 0);
+            let end = entries.get(i + 1).map(|e| e.start.as_slice());
+            if let Some(end) = end {
+                assert!(entry.start.as_slice() < end);
+            }
+            count += table.count_range(&entry.start, end, 1).await.unwrap();
+        }
+        assert_eq!(count, N);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn operation_deadline() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            operation_deadline: Some(::std::time::Duration::ZERO),
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        // Splitting a page restarts the write, which exceeds a zero deadline.
+        const N: u64 = 1 << 10;
+        let mut timeouts = Vec::new();
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            match table.put(&buf, 1, &buf).await {
+                Ok(()) => {}
+                Err(Error::Timeout { retries, last }) => {
+                    assert_eq!(retries, 1);
+                    assert!(matches!(*last, Error::Again));
+                    timeouts.push(i);
+                }
+                Err(err) => panic!("unexpected error: {err:?}"),
+            }
+        }
+        assert!(!timeouts.is_empty());
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, options()).await.unwrap();
+        for i in timeouts {
+            must_get(&table, i, 1, None).await;
+            must_put(&table, i, 1).await;
+        }
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn hot_pages() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            access_sampling_rate: 1.0,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        for _ in 0..10 {
+            must_get(&table, 0, 0, Some(0)).await;
+        }
+        must_get(&table, N - 1, N - 1, Some(N - 1)).await;
+
+        let hot_pages = table.hot_pages();
+        assert!(!hot_pages.is_empty());
+        assert!(hot_pages[0].1 >= 10);
+        assert!(hot_pages.windows(2).all(|w| w[0].1 >= w[1].1));
+        table.close().await.unwrap();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn non_monotonic_lsn() {
+        let path = tempdir().unwrap();
+        let table = std::Table::open(&path, options()).unwrap();
+        table.put(b"key", 2, b"a").unwrap();
+        for lsn in [1, 2] {
+            assert!(matches!(
+                table.put(b"key", lsn, b"b"),
+                Err(Error::LsnConflict { latest: 2 })
+            ));
+        }
+        assert_eq!(table.get(b"key", 2).unwrap(), Some(b"a".to_vec()));
+        table.close().unwrap();
+    }
+
+    #[photonio::test]
+    #[cfg(debug_assertions)]
+    async fn verify_index_ranges() {
+        use ::std::panic::AssertUnwindSafe;
+        use futures::FutureExt;
+        use page::{Index, PageKind, PageTier, SortedPageBuilder};
+
+        let path = tempdir().unwrap();
+        let store =
+            page_store::PageStore::open(env::Photon, &path, options().page_store, &mut |_| {})
+                .await
+                .unwrap();
+        let tree = tree::Tree::new(options());
+        let txn = tree.begin(store.guard());
+        txn.init().await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            txn.write(page::Key::new(&key, i), page::Value::Put(&key))
+                .await
+                .unwrap();
+        }
+        let key = 0u64.to_be_bytes();
+        assert!(txn.get(page::Key::new(&key, N)).await.unwrap().is_some());
+
+        // Install a placeholder on the root that leaves a gap after the keys.
+        let guard = store.guard();
+        let root_addr = guard.page_addr(1);
+        let (root, _) = guard
+            .read_page(root_addr, Default::default())
+            .await
+            .unwrap();
+        assert!(root.tier().is_inner());
+        let delta = [([u8::MAX].as_slice(), Index::new(0, 0))];
+        let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&delta);
+        let mut page_txn = guard.begin().await;
+        let (addr, mut page) = page_txn.alloc_page(builder.size()).await.unwrap();
+        builder.build(&mut page);
+        page.set_epoch(root.epoch());
+        page.set_chain_len(root.chain_len().saturating_add(1));
+        page.set_chain_next(root_addr);
+        assert!(page_txn.update_page(1, root_addr, addr).is_ok());
+        drop(guard);
+
+        let result = AssertUnwindSafe(txn.get(page::Key::new(&key, N)))
+            .catch_unwind()
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn fault_injection() {
+        use ::std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let path = tempdir().unwrap();
+        let injector = FaultInjector::new();
+        let mut opts = options();
+        opts.page_store.fault_injector = Some(injector.clone());
+        let table = std::Table::open(&path, opts.clone()).unwrap();
+        let put = |i: u64| {
+            let key = i.to_be_bytes();
+            catch_unwind(AssertUnwindSafe(|| table.put(&key, 1, &key).unwrap())).is_ok()
+        };
+
+        // The delta is installed before the fault, so the write is visible.
+        injector.arm(FaultPoint::AfterDeltaInstall, FaultAction::Panic);
+        assert!(!put(0));
+        assert!(!injector.is_armed(FaultPoint::AfterDeltaInstall));
+        assert_eq!(table.get(&0u64.to_be_bytes(), 1).unwrap(), Some(vec![0; 8]));
+
+        // Writes are retried after a failed root split or a split whose parent
+        // is not updated yet.
+        const N: u64 = 1 << 10;
+        let mut i = 1;
+        for point in [
+            FaultPoint::SplitBeforeRootReplace,
+            FaultPoint::SplitBeforeParentUpdate,
+        ] {
+            injector.arm(point, FaultAction::Panic);
+            while injector.is_armed(point) {
+                // The split may be triggered before or after the entry is
+                // written.
+                if !put(i) && table.get(&i.to_be_bytes(), 1).unwrap().is_none() {
+                    assert!(put(i));
+                }
+                i += 1;
+            }
+        }
+        for i in i..N {
+            assert!(put(i));
+        }
+        table.close().unwrap();
+
+        let table = std::Table::open(&path, opts).unwrap();
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(table.get(&key, 1).unwrap(), Some(key.to_vec()));
+        }
+        table.close().unwrap();
+    }
+
+    #[photonio::test]
+    async fn key_and_value_bytes() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        table.put(b"key", 1, b"value").await.unwrap();
+        table.get(b"key", 1).await.unwrap();
+        table.get(b"absent", 1).await.unwrap();
+        let stats = table.stats().tree.success;
+        // Keys are accounted with their LSNs.
+        assert_eq!(stats.write_key_bytes, 3 + 8);
+        assert_eq!(stats.write_value_bytes, 5);
+        assert_eq!(
+            stats.write_bytes,
+            stats.write_key_bytes + stats.write_value_bytes
+        );
+        assert_eq!(stats.read_key_bytes, 3 + 8 + 6 + 8);
+        assert_eq!(stats.read_value_bytes, 5);
+        assert_eq!(
+            stats.read_bytes,
+            stats.read_key_bytes + stats.read_value_bytes
+        );
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn cold_cache_delta_pages() {
+        let path = tempdir().unwrap();
+        let mut opts = options();
+        opts.cold_cache_delta_pages = true;
+        opts.page_store.prepopulate_cache_on_flush = false;
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 8;
+        for lsn in 1..4 {
+            for i in 0..N {
+                must_put(&table, i, lsn).await;
+            }
+            table.flush(&FlushOptions::default()).await;
+        }
+        for i in 0..N {
+            must_get(&table, i, 3, Some(i)).await;
+        }
+        let stats = table.stats().store.page_cache;
+        assert!(stats.lookup_hit + stats.lookup_miss > 0);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn disable_consolidation() {
+        const N: u64 = 1 << 10;
+
+        async fn consolidations(disable_consolidation: bool) -> u64 {
+            let path = tempdir().unwrap();
+            let opts = TableOptions {
+                page_size: 4 << 10,
+                disable_consolidation,
+                ..options()
+            };
+            let table = Table::open(&path, opts).await.unwrap();
+            for i in 0..N {
+                must_put(&table, i, 1).await;
+            }
+            for i in 0..N {
+                must_get(&table, i, 1, Some(i)).await;
+            }
+            let stats = table.stats().tree;
+            assert!(stats.success.split_page > 0);
+            assert_eq!(stats.overwritten_chains, 0);
+
+            // Reads still see the latest values of overwritten keys.
+            for i in (0..N).step_by(8) {
+                must_put(&table, i, 2).await;
+            }
+            table.consolidate_all().await.unwrap();
+            for i in 0..N {
+                must_get(&table, i, 2, Some(i)).await;
+            }
+            let overwritten = table.stats().tree.overwritten_chains;
+            assert_eq!(overwritten > 0, disable_consolidation);
+            table.close().await.unwrap();
+            stats.success.consolidate_page
+        }
+
+        assert!(consolidations(true).await < consolidations(false).await);
+    }
+
+    #[photonio::test]
+    async fn size_histograms() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        must_put(&table, 0, 1).await;
+        let stats = table.stats();
+        assert_eq!(stats.key_size_histogram().count(), 0);
+        table.close().await.unwrap();
+
+        let opts = TableOptions {
+            size_sampling_rate: 1.0,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 100;
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            table.put(&key, 2, &vec![0; i as usize]).await.unwrap();
+        }
+        table.delete(&0u64.to_be_bytes(), 3).await.unwrap();
+        let stats = table.stats();
+        let keys = stats.key_size_histogram();
+        assert_eq!(keys.buckets().collect::<Vec<_>>(), [(8..16, N + 1)]);
+        let values = stats.value_size_histogram();
+        assert_eq!(values.count(), N);
+        assert_eq!(values.buckets().next(), Some((0..1, 1)));
+        assert_eq!(values.buckets().last(), Some((64..128, N - 64)));
+        assert_eq!(values.percentile(50.0), 63);
+        assert_eq!(values.percentile(100.0), 127);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn lsn_regression() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, options()).await.unwrap();
+        must_put(&table, 0, 100).await;
+        table.close().await.unwrap();
+
+        // The regression is only counted by default.
+        let table = Table::open(&path, options()).await.unwrap();
+        must_put(&table, 1, 50).await;
+        must_put(&table, 2, 100).await;
+        assert_eq!(table.stats().tree.lsn_regressions, 1);
+        table.close().await.unwrap();
+
+        let opts = TableOptions {
+            reject_lsn_regression: true,
+            ..options()
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        let buf = 3u64.to_be_bytes();
+        let result = table.put(&buf, 99, &buf).await;
+        assert!(matches!(
+            result,
+            Err(Error::LsnRegression { high_water: 100 })
+        ));
+        must_get(&table, 3, u64::MAX, None).await;
+        must_put(&table, 3, 101).await;
+        assert_eq!(table.stats().tree.lsn_regressions, 1);
+        table.close().await.unwrap();
+    }
+}
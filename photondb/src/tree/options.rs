@@ -1,4 +1,6 @@
-use crate::PageStoreOptions;
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{env::IoPriority, Compression, PageStoreOptions};
 
 /// Options to configure a table.
 #[non_exhaustive]
@@ -11,12 +13,302 @@ pub struct Options {
     /// Default: 8KB
     pub page_size: usize,
 
+    /// Approximate size of the root page before it is split, in place of
+    /// `page_size`.
+    ///
+    /// Splitting the root adds a level to the tree. A larger value keeps a
+    /// small table in a single page for longer, while a smaller value makes
+    /// the tree grow taller earlier.
+    ///
+    /// Default: None (the root is split like other pages)
+    pub root_split_page_size: Option<usize>,
+
+    /// A guard to veto splitting leaf pages at some keys.
+    ///
+    /// See [`SplitGuard`] for details.
+    ///
+    /// Default: None (leaf pages are split at the middle)
+    pub split_guard: Option<SplitGuard>,
+
+    /// An observer of the decisions on whether to split or consolidate pages.
+    ///
+    /// See [`DecisionObserver`] for details.
+    ///
+    /// Default: None
+    pub decision_observer: Option<DecisionObserver>,
+
+    /// A codec to transform values before they are stored, like a per-value
+    /// compression.
+    ///
+    /// See [`ValueCodec`] for details.
+    ///
+    /// Default: None
+    pub value_codec: Option<Arc<dyn ValueCodec>>,
+
     /// Approximate number of delta pages chained per page before it is
     /// consolidated.
     ///
     /// Default: 4
     pub page_chain_length: usize,
 
+    /// The number of recent delta pages to leave on top of the new base page
+    /// when a leaf page is consolidated by writes.
+    ///
+    /// Fully consolidating a write-hot page makes the next writes start a new
+    /// chain right away, which is consolidated again soon. If this is not
+    /// zero, the most recent deltas are kept uncompacted on top of the
+    /// consolidated base page instead, so the page is consolidated again after
+    /// `page_chain_length - consolidate_keep_recent_deltas` more writes. The
+    /// deltas are kept only if the chain stays within
+    /// [`Self::page_chain_length`] and the page doesn't need to split.
+    ///
+    /// Default: 0 (pages are fully consolidated)
+    pub consolidate_keep_recent_deltas: usize,
+
+    /// The maximum number of leaf pages on a chain to merge at a time when
+    /// the chain is consolidated.
+    ///
+    /// A page chain can grow very long if its consolidations keep failing,
+    /// and merging all of it at once takes memory in proportion to its
+    /// length. If this is not zero, longer chains are consolidated in
+    /// segments instead: the newest pages are merged into a delta page first,
+    /// which is then merged with the next pages, until the base page is
+    /// reached. Values below 2 are treated as 2.
+    ///
+    /// Default: 0 (chains are merged at once)
+    pub max_merge_chain: usize,
+
+    /// Consolidates a page as soon as its split is reconciled with the parent.
+    ///
+    /// A split delta stays on the chain of the left page until the page is
+    /// consolidated, and reads have to walk past it until then. If this is
+    /// true, the left page is consolidated right after the parent knows the
+    /// right page, which trims the base page to the left range and drops the
+    /// split delta, at the cost of rewriting the base page.
+    ///
+    /// Default: false
+    pub consolidate_after_split: bool,
+
+    /// When inner pages are consolidated once their chains grow too long.
+    ///
+    /// Inner pages grow a delta whenever a split of a child is reconciled,
+    /// which happens inline on the path of the operation that finds the
+    /// split. Inner pages are always consolidated as a whole, so such a
+    /// consolidation is heavier than that of a leaf page, and shows up as a
+    /// latency spike of the unlucky read or write. With
+    /// [`InnerConsolidation::Background`], the consolidations are deferred to
+    /// a background job instead. A page whose chain grows twice as long as
+    /// it should while the job lags behind is still consolidated inline.
+    ///
+    /// Default: [`InnerConsolidation::Inline`]
+    pub inner_consolidation: InnerConsolidation,
+
+    /// How large a page must grow, relative to the size it is split at,
+    /// before writes try to split it first.
+    ///
+    /// Writes try to split an oversized page before installing their deltas,
+    /// so that the split is not starved by contentions on a write-hot page. A
+    /// ratio above 1.0 skips this for pages that have just crossed the split
+    /// size, which saves the split attempt on every write to them. Such pages
+    /// are still split when they are consolidated, and writes take over once
+    /// the pages grow beyond the ratio. Ratios below 1.0 are treated as 1.0.
+    ///
+    /// Default: 1.0
+    pub presplit_threshold_ratio: f64,
+
+    /// Rejects writes with LSNs that go backwards across reopens.
+    ///
+    /// The largest LSN written to the table is recorded on every flush, and
+    /// becomes the high-water mark when the table is opened again. A write
+    /// with a smaller LSN after that usually means that the source of LSNs has
+    /// regressed, like an external clock stepped back or a persisted counter
+    /// reset, which breaks the order of versions silently. Such writes are
+    /// counted by [`TreeStats::lsn_regressions`] and logged with a warning
+    /// for the first one. If this is true, they are rejected with
+    /// [`Error::LsnRegression`] as well. Applications that replay their own
+    /// logs into the table after a restart should leave this off.
+    ///
+    /// Default: false
+    ///
+    /// [`TreeStats::lsn_regressions`]: crate::TreeStats::lsn_regressions
+    /// [`Error::LsnRegression`]: crate::Error::LsnRegression
+    pub reject_lsn_regression: bool,
+
+    /// Rejects writes while the background jobs are paused.
+    ///
+    /// Writes keep filling the write buffers while flushes are paused by
+    /// [`Table::pause_background`], and stall once all buffers are full until
+    /// the jobs are resumed. If this is true, they are rejected with
+    /// [`Error::Paused`] instead, so that the pause doesn't block writers.
+    ///
+    /// Default: false
+    ///
+    /// [`Table::pause_background`]: crate::raw::Table::pause_background
+    /// [`Error::Paused`]: crate::Error::Paused
+    pub reject_writes_while_paused: bool,
+
+    /// The span of LSNs to retain the versions of, regardless of the safe
+    /// LSN.
+    ///
+    /// Consolidations drop the versions that are not visible to
+    /// [`Table::safe_lsn`] anymore. If this is not zero, the versions visible
+    /// to any LSN within this span below the largest LSN written to the table
+    /// are retained as well, even if the safe LSN has advanced past them. This
+    /// keeps a rolling window of recent versions for time-travel reads without
+    /// holding back the safe LSN. With [`Self::auto_lsn`], the span is the
+    /// number of recent writes.
+    ///
+    /// The window costs space in proportion to the writes within it, since
+    /// every overwritten or deleted version in the window is kept until it
+    /// falls out, and pages with such versions are consolidated again without
+    /// shrinking. The window starts from the largest LSN recorded by flushes
+    /// when the table is opened.
+    ///
+    /// Default: 0 (no versions are retained beyond the safe LSN)
+    ///
+    /// [`Table::safe_lsn`]: crate::raw::Table::safe_lsn
+    pub min_version_retention: u64,
+
+    /// Caches delta pages as cold when they are read by point lookups.
+    ///
+    /// Delta pages are consolidated into the base page soon, so caching them as
+    /// recently used may evict the working set. If this is true, point lookups
+    /// only cache delta pages when the cache is not full, and leave them to be
+    /// evicted first, while base pages are cached as usual.
+    ///
+    /// Default: false
+    pub cold_cache_delta_pages: bool,
+
+    /// Leaves leaf pages unconsolidated until they are large enough to split.
+    ///
+    /// Every write appends a delta page to its leaf page, and writes
+    /// consolidate the chain once it grows beyond
+    /// [`Self::page_chain_length`]. For append-only workloads that never
+    /// overwrite keys, these consolidations have no old versions to drop,
+    /// and only rewrite the base page again and again. If this is true,
+    /// writes leave the deltas on the chain until they are estimated to fill
+    /// half of a page, and then consolidate the whole chain so that the page
+    /// splits. Reads walk the longer chains as usual, and remain correct if
+    /// keys are overwritten anyway, which is counted by
+    /// [`TreeStats::overwritten_chains`]. Inner pages are not affected.
+    ///
+    /// Default: false
+    ///
+    /// [`TreeStats::overwritten_chains`]: crate::TreeStats::overwritten_chains
+    pub disable_consolidation: bool,
+
+    /// Values larger than this are stored in separate blob pages, leaving only
+    /// a fixed-size reference in data pages.
+    ///
+    /// This keeps data pages compact when small and large values are mixed,
+    /// at the cost of an extra read to return a large value.
+    ///
+    /// Default: usize::MAX (all values are stored inline)
+    pub value_inline_threshold: usize,
+
+    /// The fraction of reads that record the accessed leaf page for
+    /// [`Table::hot_pages`].
+    ///
+    /// A value of zero disables the sampling.
+    ///
+    /// Default: 0.0
+    ///
+    /// [`Table::hot_pages`]: crate::raw::Table::hot_pages
+    pub access_sampling_rate: f64,
+
+    /// The interval to reset the sampled access counts.
+    ///
+    /// Default: 60s
+    pub access_sampling_reset_interval: Duration,
+
+    /// The fraction of writes that record the sizes of their keys and values
+    /// for [`TableStats::key_size_histogram`] and
+    /// [`TableStats::value_size_histogram`].
+    ///
+    /// The distributions help to size [`Self::page_size`] and
+    /// [`Self::value_inline_threshold`]. A value of zero disables the
+    /// sampling.
+    ///
+    /// Default: 0.0
+    ///
+    /// [`TableStats::key_size_histogram`]: crate::TableStats::key_size_histogram
+    /// [`TableStats::value_size_histogram`]: crate::TableStats::value_size_histogram
+    pub size_sampling_rate: f64,
+
+    /// How to open the table depending on whether it exists in the path.
+    ///
+    /// Default: [`OpenMode::CreateIfMissing`]
+    pub open_mode: OpenMode,
+
+    /// Whether to take over the lock of the table if the process that holds
+    /// it doesn't exist anymore.
+    ///
+    /// The lock of a crashed process is released by the OS, but it may still
+    /// be held by child processes that inherited it. Otherwise, opening the
+    /// table fails with [`Error::AlreadyLocked`].
+    ///
+    /// Default: false
+    ///
+    /// [`Error::AlreadyLocked`]: crate::Error::AlreadyLocked
+    pub ignore_stale_lock: bool,
+
+    /// The maximum time an operation keeps retrying on conflicts.
+    ///
+    /// If an operation is still conflicting with others after this duration,
+    /// it fails with [`Error::Timeout`] instead of retrying forever.
+    ///
+    /// Default: None (retry until the operation succeeds)
+    ///
+    /// [`Error::Timeout`]: crate::Error::Timeout
+    pub operation_deadline: Option<Duration>,
+
+    /// Logs a warning if a snapshot is held longer than this.
+    ///
+    /// A snapshot keeps the write buffers and the page files that it can read
+    /// from being released, see [`Table::scan_snapshot`].
+    ///
+    /// Default: 60s
+    ///
+    /// [`Table::scan_snapshot`]: crate::raw::Table::scan_snapshot
+    pub snapshot_warn_lifetime: Duration,
+
+    /// Logs the operations that take longer than this, along with the phase
+    /// of [`PerfCtx`] that takes the most time.
+    ///
+    /// The perf context of the current thread is reset at the start of each
+    /// operation if this is set.
+    ///
+    /// Default: None
+    ///
+    /// [`PerfCtx`]: crate::perf::PerfCtx
+    pub slow_op_threshold: Option<Duration>,
+
+    /// Whether to allocate LSNs from an internal counter for
+    /// [`Table::put_simple`] and [`Table::delete_simple`], for users who don't
+    /// need multiple versions of a key.
+    ///
+    /// The counter is recorded in the manifest on every flush, so it never
+    /// goes backwards across reopens. [`Table::get_simple`] always reads the
+    /// latest values. Mixing these with the methods that take LSNs on the same
+    /// table is not supported.
+    ///
+    /// Default: false
+    ///
+    /// [`Table::put_simple`]: crate::raw::Table::put_simple
+    /// [`Table::delete_simple`]: crate::raw::Table::delete_simple
+    /// [`Table::get_simple`]: crate::raw::Table::get_simple
+    pub auto_lsn: bool,
+
+    /// Whether to verify that the children of an inner page tile the range of
+    /// the page, without gaps or overlaps, whenever a lookup passes through
+    /// the page.
+    ///
+    /// A violation panics with the separators of the page. This only takes
+    /// effect in debug builds, and it makes lookups much slower.
+    ///
+    /// Default: false
+    pub verify_index_ranges: bool,
+
     /// Options for the underlying page store.
     pub page_store: PageStoreOptions,
 }
@@ -25,12 +317,232 @@ impl Default for Options {
     fn default() -> Self {
         Self {
             page_size: 8 << 10,
+            root_split_page_size: None,
+            split_guard: None,
+            decision_observer: None,
+            value_codec: None,
             page_chain_length: 4,
+            consolidate_keep_recent_deltas: 0,
+            max_merge_chain: 0,
+            consolidate_after_split: false,
+            inner_consolidation: InnerConsolidation::Inline,
+            presplit_threshold_ratio: 1.0,
+            reject_lsn_regression: false,
+            reject_writes_while_paused: false,
+            min_version_retention: 0,
+            cold_cache_delta_pages: false,
+            disable_consolidation: false,
+            value_inline_threshold: usize::MAX,
+            access_sampling_rate: 0.0,
+            access_sampling_reset_interval: Duration::from_secs(60),
+            size_sampling_rate: 0.0,
+            open_mode: OpenMode::CreateIfMissing,
+            ignore_stale_lock: false,
+            operation_deadline: None,
+            snapshot_warn_lifetime: Duration::from_secs(60),
+            slow_op_threshold: None,
+            auto_lsn: false,
+            verify_index_ranges: false,
             page_store: PageStoreOptions::default(),
         }
     }
 }
 
+/// A callback that decides whether a leaf page can be split at a key.
+///
+/// When a leaf page is split, candidate split keys are passed to the callback
+/// from the middle of the page outward, and the page is split at the first key
+/// that the callback returns true for. Entries before the split key stay in
+/// the left page, and the others move to the right page. If the callback
+/// rejects all candidates, the split is deferred, and the page keeps growing
+/// until a later split finds an allowed key.
+///
+/// For example, rejecting keys that don't start a new prefix keeps entries of
+/// the same prefix in the same page, unless the page holds only one prefix.
+#[derive(Clone)]
+pub struct SplitGuard(Arc<SplitFn>);
+
+type SplitFn = dyn Fn(&[u8]) -> bool + Send + Sync;
+
+impl SplitGuard {
+    /// Creates a guard with the callback.
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Returns true if the page can be split at the key.
+    pub(crate) fn allows(&self, key: &[u8]) -> bool {
+        (self.0)(key)
+    }
+}
+
+impl fmt::Debug for SplitGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SplitGuard").finish()
+    }
+}
+
+/// The kinds of decisions observed by a [`DecisionObserver`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecisionKind {
+    /// Whether a page should be split, which compares the page size with
+    /// [`Options::page_size`].
+    Split,
+    /// Whether a page should be consolidated, which compares the chain length
+    /// with [`Options::page_chain_length`].
+    Consolidate,
+}
+
+/// The inputs and the outcome of a decision on a page.
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct DecisionEvent {
+    /// The kind of the decision.
+    pub kind: DecisionKind,
+    /// Whether the page is a leaf page.
+    pub is_leaf: bool,
+    /// The number of pages on the chain of the page.
+    pub chain_len: usize,
+    /// The size of the page.
+    pub page_size: usize,
+    /// The value that the page size or the chain length must exceed, adjusted
+    /// for the tier of the page.
+    pub threshold: usize,
+    /// Whether the page should be split or consolidated.
+    pub outcome: bool,
+}
+
+/// A callback invoked with every decision on whether to split or consolidate
+/// a page.
+///
+/// Decisions are made on the hot paths of reads and writes, so the callback
+/// should be cheap, like updating a histogram of how close pages are to the
+/// thresholds.
+#[derive(Clone)]
+pub struct DecisionObserver(Arc<ObserveFn>);
+
+type ObserveFn = dyn Fn(&DecisionEvent) + Send + Sync;
+
+impl DecisionObserver {
+    /// Creates an observer with the callback.
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&DecisionEvent) + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    pub(crate) fn observe(&self, event: &DecisionEvent) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for DecisionObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DecisionObserver").finish()
+    }
+}
+
+/// Transforms values at the record level, like a per-value compression.
+///
+/// Each value stored in a data page is passed to [`Self::encode`] when it is
+/// written, and the value is stored as is if the codec declines to transform
+/// it, like an incompressible value. A transformed value is marked in the
+/// record, and it is passed to [`Self::decode`] when it is read. Values stored
+/// in blob pages, see [`Options::value_inline_threshold`], are not
+/// transformed.
+///
+/// Values transformed by a codec can only be read with the same codec, and
+/// reading them without a codec fails with [`Error::InvalidArgument`].
+///
+/// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
+pub trait ValueCodec: Send + Sync {
+    /// Returns the transformed value, or `None` to store the value as is.
+    fn encode(&self, value: &[u8]) -> Option<Vec<u8>>;
+
+    /// Returns the value that is transformed to `value` by [`Self::encode`].
+    fn decode(&self, value: &[u8]) -> Vec<u8>;
+}
+
+impl fmt::Debug for dyn ValueCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueCodec").finish_non_exhaustive()
+    }
+}
+
+/// Modes to open a table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Opens the table if it exists, or creates a new one otherwise.
+    CreateIfMissing,
+    /// Creates a new table, fails with [`Error::AlreadyExists`] if the table
+    /// exists.
+    ///
+    /// [`Error::AlreadyExists`]: crate::Error::AlreadyExists
+    CreateNew,
+    /// Opens an existing table, fails with [`Error::NotFound`] if the table
+    /// does not exist.
+    ///
+    /// [`Error::NotFound`]: crate::Error::NotFound
+    OpenExisting,
+}
+
+/// Modes to consolidate inner pages, see [`Options::inner_consolidation`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InnerConsolidation {
+    /// Consolidates inner pages on the path of the operation that grows them.
+    #[default]
+    Inline,
+    /// Consolidates inner pages in a background job.
+    Background,
+}
+
+/// A set of changes to apply to the options of a live table.
+///
+/// Fields set to `None` are left unchanged. The following fields can be
+/// changed while the table is running:
+///
+/// - `page_chain_length`, `consolidate_keep_recent_deltas` and
+///   `max_merge_chain`: take effect on subsequent consolidations.
+/// - `cache_capacity`: resizes the page cache, evicting unreferenced pages if
+///   the cache is shrunk.
+/// - `compression_on_flush`: takes effect on subsequent flushes.
+/// - `compression_on_cold_compact`: takes effect on subsequent compactions.
+///
+/// Other fields are fixed once the table is opened, setting them results in
+/// an error. There is no option to limit the rate of background IO. The
+/// reclaim thresholds, such as
+/// [`PageStoreOptions::max_space_amplification_percent`], are not included
+/// because the reclaim job plans a whole round of work from them when the
+/// round starts.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default)]
+pub struct OptionsPatch {
+    /// See [`Options::page_size`]. This can not be changed on a live table.
+    pub page_size: Option<usize>,
+
+    /// See [`Options::page_chain_length`].
+    pub page_chain_length: Option<usize>,
+
+    /// See [`Options::consolidate_keep_recent_deltas`].
+    pub consolidate_keep_recent_deltas: Option<usize>,
+
+    /// See [`Options::max_merge_chain`].
+    pub max_merge_chain: Option<usize>,
+
+    /// See [`PageStoreOptions::cache_capacity`].
+    pub cache_capacity: Option<usize>,
+
+    /// See [`PageStoreOptions::compression_on_flush`].
+    pub compression_on_flush: Option<Compression>,
+
+    /// See [`PageStoreOptions::compression_on_cold_compact`].
+    pub compression_on_cold_compact: Option<Compression>,
+}
+
 /// Options to configure the behavior of reads.
 #[non_exhaustive]
 #[derive(Clone, Debug)]
@@ -44,8 +556,39 @@ pub struct ReadOptions {
 
     /// Whether to fill pages load from store to the page cache.
     ///
+    /// For scans, if false, the leaf pages read from files are only cached as
+    /// cold ones while the cache has room, so that they don't evict hot pages.
+    ///
     /// Default: true
     pub fill_cache: bool,
+
+    /// The maximum number of bytes that a scan can read.
+    ///
+    /// The bytes of the leaf pages, including their delta pages and the
+    /// values stored out of them, are added up as the scan reads them. Once
+    /// they exceed the budget, the scan fails with [`Error::BudgetExceeded`].
+    ///
+    /// Default: None
+    ///
+    /// [`Error::BudgetExceeded`]: crate::Error::BudgetExceeded
+    pub max_bytes: Option<u64>,
+
+    /// The priority of the reads of page files for this read.
+    ///
+    /// Reads with [`IoPriority::High`] hold back the IO of background flushes
+    /// and compactions while they are in flight, which protects the tail
+    /// latency of critical reads at the cost of background work. This applies
+    /// to [`Table::get_with_options`], [`Table::scan_filter`], and
+    /// [`Table::scan_snapshot`]. It's only a hint for the [`Env`] of the
+    /// table, which may ignore it.
+    ///
+    /// Default: [`IoPriority::Normal`]
+    ///
+    /// [`Table::get_with_options`]: crate::raw::Table::get_with_options
+    /// [`Table::scan_filter`]: crate::raw::Table::scan_filter
+    /// [`Table::scan_snapshot`]: crate::raw::Table::scan_snapshot
+    /// [`Env`]: crate::env::Env
+    pub priority: IoPriority,
 }
 
 impl Default for ReadOptions {
@@ -53,11 +596,104 @@ impl Default for ReadOptions {
         Self {
             max_lsn: u64::MAX,
             fill_cache: true,
+            max_bytes: None,
+            priority: IoPriority::Normal,
         }
     }
 }
 
-/// Options to configure the behavior of writes.
+/// Options to configure the behavior of scans.
 #[non_exhaustive]
 #[derive(Clone, Debug)]
-pub struct WriteOptions {}
+pub struct ScanOptions {
+    /// The maximum number of restarts before a scan pins its read LSN.
+    ///
+    /// A scan restarts from the root when the next page it's going to visit
+    /// is split concurrently. Once the number of restarts exceeds this value,
+    /// the scan lowers its maximum visible LSN to the safe LSN of the table,
+    /// so that the rest of the scan reads a fixed snapshot instead of chasing
+    /// concurrent writes. Entries written after the safe LSN are not returned
+    /// after that.
+    ///
+    /// Default: `usize::MAX`
+    pub max_restarts: usize,
+
+    /// Whether scans return the keys whose latest visible versions are
+    /// tombstones.
+    ///
+    /// Such keys are returned by [`PageIter::next_entry`] with `None` values,
+    /// so that deletes can be propagated, like when diffing two scans. The
+    /// versions of a key are still collapsed to the latest one visible to the
+    /// read LSN. The [`Iterator`] implementation of [`PageIter`] skips them
+    /// regardless of this option.
+    ///
+    /// Tombstones visible to the safe LSN may be dropped once they are
+    /// consolidated into base pages, after which their keys are not returned
+    /// at all.
+    ///
+    /// Default: false
+    ///
+    /// [`PageIter`]: crate::PageIter
+    /// [`PageIter::next_entry`]: crate::PageIter::next_entry
+    pub include_tombstones: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_restarts: usize::MAX,
+            include_tombstones: false,
+        }
+    }
+}
+
+/// Options to configure the behavior of writes.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    /// The compression preferred for the pages that hold this write.
+    ///
+    /// This is a hint for data that is known to compress poorly. Only
+    /// [`Compression::NONE`] is supported for now, which stores the pages of
+    /// this write uncompressed regardless of
+    /// [`PageStoreOptions::compression_on_flush`]. A page consolidated from
+    /// such pages stays uncompressed if they make up at least half of it.
+    /// Other values are ignored.
+    ///
+    /// Default: None
+    pub compression: Option<Compression>,
+
+    /// The user metadata to stamp into the delta page that holds this write,
+    /// like a schema version or the partition that the data comes from.
+    ///
+    /// The metadata is stored in the page and survives flushes.
+    /// Consolidations and splits keep the metadata of the newest page they
+    /// rewrite. It can be read back with [`PageIter::page_metadata`] when
+    /// walking the pages. The metadata must not be longer than 32 bytes,
+    /// otherwise the write fails with [`Error::InvalidArgument`].
+    ///
+    /// Default: None
+    ///
+    /// [`PageIter::page_metadata`]: crate::PageIter::page_metadata
+    /// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
+    pub page_metadata: Option<Vec<u8>>,
+
+    /// The maximum number of bytes that a write can take.
+    ///
+    /// A write whose key and value take more bytes fails with
+    /// [`Error::BudgetExceeded`] without being applied.
+    ///
+    /// Default: None
+    ///
+    /// [`Error::BudgetExceeded`]: crate::Error::BudgetExceeded
+    pub max_bytes: Option<u64>,
+
+    /// The priority of the reads of page files for this write, like the
+    /// reads to find the page to write to and to consolidate it.
+    ///
+    /// The write itself goes to a write buffer in memory, which is flushed in
+    /// the background. See [`ReadOptions::priority`] for more details.
+    ///
+    /// Default: [`IoPriority::Normal`]
+    pub priority: IoPriority,
+}
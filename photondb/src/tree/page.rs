@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::{page::*, page_store::*};
 
 /// The root id is fixed to the minimal id in the page store.
@@ -15,11 +17,46 @@ pub(super) struct PageView<'a> {
     pub(super) range: Option<Range<'a>>,
 }
 
+/// A leaf page found by walking the inner pages of a tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The separator key that the range of the leaf page starts at.
+    pub start: Vec<u8>,
+    /// The id of the leaf page.
+    pub page_id: u64,
+    /// The approximate size of the leaf page in bytes, including its delta
+    /// pages, which is taken from page metadata without reading the page.
+    pub approx_size: u64,
+}
+
+/// A contiguous range of leaf pages in which most keys are deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeletedRegion {
+    /// The separator key that the range starts at.
+    pub start: Vec<u8>,
+    /// The separator key that the range ends before, or `None` if the range
+    /// extends to the end of the table.
+    pub end: Option<Vec<u8>>,
+    /// The number of leaf pages in the range.
+    pub pages: u64,
+    /// The number of keys in the range.
+    pub keys: u64,
+    /// The number of keys in the range whose latest versions are tombstones.
+    pub tombstones: u64,
+}
+
 /// An iterator over user entries in a page.
 pub struct PageIter<'a> {
     iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
     read_lsn: u64,
     last_raw: Option<&'a [u8]>,
+    include_tombstones: bool,
+    /// The item read ahead when resolving timestamped values.
+    peeked: Option<(Key<'a>, Value<'a>)>,
+    blobs: HashMap<u64, &'a [u8]>,
+    /// Decoded values keyed by the addresses of the encoded values.
+    decoded: HashMap<usize, &'a [u8]>,
+    metadata: Option<&'a [u8]>,
 }
 
 impl<'a> PageIter<'a> {
@@ -28,21 +65,98 @@ impl<'a> PageIter<'a> {
             iter,
             read_lsn,
             last_raw: None,
+            include_tombstones: false,
+            peeked: None,
+            blobs: HashMap::new(),
+            decoded: HashMap::new(),
+            metadata: None,
         }
     }
 
+    pub(super) fn with_metadata(mut self, metadata: Option<&'a [u8]>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub(super) fn with_tombstones(mut self, include_tombstones: bool) -> Self {
+        self.include_tombstones = include_tombstones;
+        self
+    }
+
+    /// Returns the user metadata of the newest page on the chain that carries
+    /// one, see [`WriteOptions::page_metadata`].
+    ///
+    /// [`WriteOptions::page_metadata`]: crate::WriteOptions::page_metadata
+    pub fn page_metadata(&self) -> Option<&'a [u8]> {
+        self.metadata
+    }
+
     /// Positions the iterator at the first item that is at or after `target`.
     pub fn seek(&mut self, target: &[u8]) {
         self.iter.seek(&Key::new(target, self.read_lsn));
         self.last_raw = None;
+        self.peeked = None;
     }
-}
 
-impl<'a> Iterator for PageIter<'a> {
-    type Item = (&'a [u8], &'a [u8]);
+    /// Returns the indirect and encoded values visible to the iterator, which
+    /// must be resolved before they are returned.
+    ///
+    /// The iterator is positioned at the first item afterwards.
+    pub(super) fn unresolved_values(&mut self) -> Vec<Value<'a>> {
+        let mut values = Vec::new();
+        while let Some((_, v)) = self.next_value() {
+            if matches!(v, Value::Indirect(_) | Value::Encoded(_)) {
+                values.push(v);
+            }
+        }
+        self.iter.rewind();
+        self.last_raw = None;
+        self.peeked = None;
+        values
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        for (k, v) in &mut self.iter {
+    /// Resolves an indirect or encoded value to `resolved`.
+    pub(super) fn resolve(&mut self, value: Value<'a>, resolved: &'a [u8]) {
+        match value {
+            Value::Indirect(addr) => {
+                self.blobs.insert(addr, resolved);
+            }
+            Value::Encoded(v) => {
+                self.decoded.insert(v.as_ptr() as usize, resolved);
+            }
+            Value::Put(_) | Value::Delete | Value::PutTimestamped { .. } => unreachable!(),
+        }
+    }
+
+    /// Returns the next entry, or the next key with `None` if its latest
+    /// visible version is a tombstone and [`ScanOptions::include_tombstones`]
+    /// is set.
+    ///
+    /// [`ScanOptions::include_tombstones`]: crate::ScanOptions::include_tombstones
+    pub fn next_entry(&mut self) -> Option<(&'a [u8], Option<&'a [u8]>)> {
+        while let Some((k, v)) = self.next_latest() {
+            match v {
+                Value::Delete if self.include_tombstones => return Some((k, None)),
+                Value::Delete => {}
+                v => return Some((k, Some(self.value(v)))),
+            }
+        }
+        None
+    }
+
+    /// Returns the next visible value, which is never a delete.
+    pub(super) fn next_value(&mut self) -> Option<(&'a [u8], Value<'a>)> {
+        while let Some((k, v)) = self.next_latest() {
+            if !matches!(v, Value::Delete) {
+                return Some((k, v));
+            }
+        }
+        None
+    }
+
+    /// Returns the latest visible version of the next key.
+    fn next_latest(&mut self) -> Option<(&'a [u8], Value<'a>)> {
+        while let Some((k, v)) = self.peeked.take().or_else(|| self.iter.next()) {
             if k.lsn > self.read_lsn {
                 continue;
             }
@@ -52,12 +166,68 @@ impl<'a> Iterator for PageIter<'a> {
                 }
             }
             self.last_raw = Some(k.raw);
-            if let Value::Put(value) = v {
-                return Some((k.raw, value));
-            }
+            let (_, v) = resolve_timestamped((k, v), &mut self.iter, &mut self.peeked);
+            return Some((k.raw, v));
         }
         None
     }
+
+    /// Returns the bytes of a value that is not a delete.
+    fn value(&self, v: Value<'a>) -> &'a [u8] {
+        match v {
+            Value::Put(value) | Value::PutTimestamped { bytes: value, .. } => value,
+            Value::Indirect(addr) => self.blobs.get(&addr).expect("unresolved indirect value"),
+            Value::Encoded(v) => self
+                .decoded
+                .get(&(v.as_ptr() as usize))
+                .expect("unresolved encoded value"),
+            Value::Delete => unreachable!(),
+        }
+    }
+}
+
+/// Resolves the value of a key from its latest visible version.
+///
+/// If the version is timestamped, it competes with the consecutive older
+/// timestamped versions of the same key that follow it in `iter`, and the one
+/// with the largest timestamp wins. Ties go to the newer version. The first
+/// item that doesn't belong to the run is left in `peeked`.
+pub(super) fn resolve_timestamped<'a, I>(
+    latest: (Key<'a>, Value<'a>),
+    iter: &mut I,
+    peeked: &mut Option<(Key<'a>, Value<'a>)>,
+) -> (Key<'a>, Value<'a>)
+where
+    I: Iterator<Item = (Key<'a>, Value<'a>)>,
+{
+    let Some(mut max_ts) = latest.1.timestamp() else {
+        return latest;
+    };
+    let mut winner = latest;
+    for (k, v) in iter {
+        match v.timestamp() {
+            Some(ts) if k.raw == latest.0.raw => {
+                if ts > max_ts {
+                    max_ts = ts;
+                    winner = (k, v);
+                }
+            }
+            _ => {
+                *peeked = Some((k, v));
+                break;
+            }
+        }
+    }
+    winner
+}
+
+impl<'a> Iterator for PageIter<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, v) = self.next_value()?;
+        Some((k, self.value(v)))
+    }
 }
 
 pub(super) struct MergingPageIter<'a, K, V>
@@ -130,12 +300,31 @@ where
     }
 }
 
+/// An iterator that produces the items of a consolidated page.
+pub(super) trait ConsolidationIter: RewindableIterator {
+    /// Returns the addresses of blob pages that are no longer referenced by
+    /// the items produced since the last rewind.
+    fn dropped_blobs(&self) -> &[u64];
+
+    /// Returns true if the items produced since the last rewind are puts of
+    /// distinct keys that are visible to all reads.
+    fn is_clean(&self) -> bool;
+}
+
 /// An iterator that merges multiple leaf delta pages for consolidation.
 pub(super) struct MergingLeafPageIter<'a> {
     iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
     safe_lsn: u64,
     last_raw: Option<&'a [u8]>,
     skip_same_raw: bool,
+    keep_tombstones: bool,
+    /// The item read ahead when resolving timestamped values.
+    peeked: Option<(Key<'a>, Value<'a>)>,
+    /// Whether the next version of the same raw must be kept, because it ends
+    /// a run of timestamped versions that older pages may continue.
+    keep_barrier: bool,
+    dropped_blobs: Vec<u64>,
+    clean: bool,
 }
 
 impl<'a> MergingLeafPageIter<'a> {
@@ -145,7 +334,45 @@ impl<'a> MergingLeafPageIter<'a> {
             safe_lsn,
             last_raw: None,
             skip_same_raw: false,
+            keep_tombstones: false,
+            peeked: None,
+            keep_barrier: false,
+            dropped_blobs: Vec::new(),
+            clean: true,
+        }
+    }
+
+    /// Keeps the tombstones that are visible to the safe LSN, which is
+    /// required if the base page is not merged.
+    pub(super) fn keep_tombstones(mut self, keep: bool) -> Self {
+        self.keep_tombstones = keep;
+        self
+    }
+
+    /// Returns the item, and marks the iterator unclean if the item is not
+    /// visible to all reads.
+    ///
+    /// Older versions and tombstones are only produced if there is a newer
+    /// version that is invisible to the safe LSN, so the LSN is enough here.
+    fn output(&mut self, item: (Key<'a>, Value<'a>)) -> Option<(Key<'a>, Value<'a>)> {
+        if item.0.lsn > self.safe_lsn {
+            self.clean = false;
         }
+        Some(item)
+    }
+
+    /// Returns the winner of the timestamped versions that start with the
+    /// oldest version visible to the safe LSN.
+    ///
+    /// The versions older than the run are skipped, but the one right after
+    /// the run is kept if tombstones are kept, since it shadows the versions in
+    /// the base page that would otherwise join the run.
+    fn output_resolved(&mut self, item: (Key<'a>, Value<'a>)) -> Option<(Key<'a>, Value<'a>)> {
+        let winner = resolve_timestamped(item, &mut self.iter, &mut self.peeked);
+        if self.keep_tombstones && item.1.timestamp().is_some() {
+            self.keep_barrier = matches!(self.peeked, Some((k, _)) if k.raw == item.0.raw);
+        }
+        self.output(winner)
     }
 }
 
@@ -153,22 +380,30 @@ impl<'a> Iterator for MergingLeafPageIter<'a> {
     type Item = (Key<'a>, Value<'a>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        for (k, v) in &mut self.iter {
+        while let Some((k, v)) = self.peeked.take().or_else(|| self.iter.next()) {
             if let Some(last) = self.last_raw {
                 if k.raw == last {
+                    if self.keep_barrier {
+                        self.keep_barrier = false;
+                        self.clean = false;
+                        return Some((k, v));
+                    }
                     // Skip versions of the same raw.
                     if self.skip_same_raw {
+                        if let Value::Indirect(addr) = v {
+                            self.dropped_blobs.push(addr);
+                        }
                         continue;
                     }
                     // Output versions that are visible to the safe LSN.
                     if k.lsn > self.safe_lsn {
-                        return Some((k, v));
+                        return self.output((k, v));
                     }
                     // This is the oldest version visible to the safe LSN.
                     self.skip_same_raw = true;
                     match v {
-                        Value::Put(_) => return Some((k, v)),
-                        Value::Delete => continue,
+                        Value::Delete if !self.keep_tombstones => continue,
+                        _ => return self.output_resolved((k, v)),
                     }
                 }
             }
@@ -178,10 +413,11 @@ impl<'a> Iterator for MergingLeafPageIter<'a> {
             match v {
                 // If the latest version is a delete and all older versions are not visible to the
                 // safe LSN, we can skip all of them.
-                Value::Delete if k.lsn <= self.safe_lsn => {
+                Value::Delete if k.lsn <= self.safe_lsn && !self.keep_tombstones => {
                     continue;
                 }
-                _ => return Some((k, v)),
+                _ if k.lsn <= self.safe_lsn => return self.output_resolved((k, v)),
+                _ => return self.output((k, v)),
             }
         }
         None
@@ -193,6 +429,20 @@ impl<'a> RewindableIterator for MergingLeafPageIter<'a> {
         self.iter.rewind();
         self.last_raw = None;
         self.skip_same_raw = false;
+        self.peeked = None;
+        self.keep_barrier = false;
+        self.dropped_blobs.clear();
+        self.clean = true;
+    }
+}
+
+impl<'a> ConsolidationIter for MergingLeafPageIter<'a> {
+    fn dropped_blobs(&self) -> &[u64] {
+        &self.dropped_blobs
+    }
+
+    fn is_clean(&self) -> bool {
+        self.clean
     }
 }
 
@@ -200,6 +450,8 @@ impl<'a> SeekableIterator<Key<'_>> for MergingLeafPageIter<'a> {
     fn seek(&mut self, target: &Key<'_>) -> bool {
         self.last_raw = None;
         self.skip_same_raw = false;
+        self.peeked = None;
+        self.keep_barrier = false;
         self.iter.seek(target)
     }
 }
@@ -248,6 +500,16 @@ impl<'a> RewindableIterator for MergingInnerPageIter<'a> {
     }
 }
 
+impl<'a> ConsolidationIter for MergingInnerPageIter<'a> {
+    fn dropped_blobs(&self) -> &[u64] {
+        &[]
+    }
+
+    fn is_clean(&self) -> bool {
+        false
+    }
+}
+
 impl<'a> SeekableIterator<[u8]> for MergingInnerPageIter<'a> {
     fn seek(&mut self, target: &[u8]) -> bool {
         self.last_raw = None;
@@ -372,6 +634,11 @@ mod tests {
             for (a, b) in (&mut iter).zip(expect) {
                 assert_eq!(a, b);
             }
+            // Only the latest versions are left once all of them are visible.
+            iter.by_ref().for_each(drop);
+            assert_eq!(iter.is_clean(), lsn >= 3);
+            iter.rewind();
+            assert!(iter.is_clean());
         }
 
         {
@@ -401,6 +668,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn timestamped_values() {
+        let data = vec![
+            (
+                Key::new(&[1], 4),
+                Value::PutTimestamped { bytes: &[4], ts: 2 },
+            ),
+            (
+                Key::new(&[1], 3),
+                Value::PutTimestamped { bytes: &[3], ts: 5 },
+            ),
+            (Key::new(&[1], 2), Value::Put(&[2])),
+            (
+                Key::new(&[1], 1),
+                Value::PutTimestamped { bytes: &[1], ts: 9 },
+            ),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+
+        // The largest timestamp wins until a plain put.
+        let lsn_expect = [(1, [1]), (2, [2]), (3, [3]), (4, [3])];
+        for (lsn, expect) in lsn_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let mut iter = PageIter::new(merging_iter, lsn);
+            assert_eq!(iter.next(), Some(([1].as_slice(), expect.as_slice())));
+            assert_eq!(iter.next(), None);
+        }
+
+        let lsn_expect = [
+            (3, false, vec![data[0], data[1]]),
+            (4, false, vec![data[1]]),
+            // The plain put is kept to shadow the base page.
+            (4, true, vec![data[1], data[2]]),
+        ];
+        for (lsn, keep_tombstones, expect) in lsn_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let iter = MergingLeafPageIter::new(merging_iter, lsn).keep_tombstones(keep_tombstones);
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+    }
+
+    #[test]
+    fn merging_leaf_page_iter_dropped_blobs() {
+        let data = vec![
+            (Key::new(&[1], 3), Value::Indirect(3)),
+            (Key::new(&[1], 2), Value::Put(&[2])),
+            (Key::new(&[1], 1), Value::Indirect(1)),
+            (Key::new(&[3], 2), Value::Delete),
+            (Key::new(&[3], 1), Value::Indirect(2)),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+
+        let lsn_expect: [(u64, &[u64]); 3] = [(0, &[]), (1, &[]), (2, &[1, 2])];
+        for (lsn, expect) in lsn_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let mut iter = MergingLeafPageIter::new(merging_iter, lsn);
+            for _ in 0..2 {
+                iter.rewind();
+                for _ in &mut iter {}
+                assert_eq!(iter.dropped_blobs(), expect);
+            }
+        }
+    }
+
     #[test]
     fn merging_inner_page_iter() {
         let data1 = [
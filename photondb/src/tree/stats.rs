@@ -1,6 +1,6 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range, time::Duration};
 
-use crate::util::atomic::Counter;
+use crate::{page_store::AtomicLatency, util::atomic::Counter};
 
 /// Statistics of a tree.
 #[derive(Clone, Debug, Default)]
@@ -9,6 +9,58 @@ pub struct TreeStats {
     pub success: TxnStats,
     /// Statistics of conflicted transactions.
     pub conflict: TxnStats,
+    /// The number of leaf pages consolidated into a single base page.
+    pub consolidate_leaf_page: u64,
+    /// The total size of the base pages built by leaf page consolidations.
+    pub consolidate_leaf_page_bytes: u64,
+    /// The number of times writes tried to split oversized pages before
+    /// installing their deltas.
+    pub presplit_attempted: u64,
+    /// The number of times writes skipped splitting oversized pages below
+    /// [`Options::presplit_threshold_ratio`].
+    ///
+    /// [`Options::presplit_threshold_ratio`]: crate::TableOptions::presplit_threshold_ratio
+    pub presplit_skipped: u64,
+    /// The number of times a long chain of leaf pages was consolidated in
+    /// segments, as bounded by [`Options::max_merge_chain`].
+    ///
+    /// [`Options::max_merge_chain`]: crate::TableOptions::max_merge_chain
+    pub segmented_consolidations: u64,
+    /// The number of leaf pages consolidated with keys written more than once
+    /// on their chains while [`Options::disable_consolidation`] is set, which
+    /// the option doesn't expect.
+    ///
+    /// [`Options::disable_consolidation`]: crate::TableOptions::disable_consolidation
+    pub overwritten_chains: u64,
+    /// The number of writes with LSNs smaller than the largest LSN written
+    /// before the table was opened, see [`Options::reject_lsn_regression`].
+    ///
+    /// [`Options::reject_lsn_regression`]: crate::TableOptions::reject_lsn_regression
+    pub lsn_regressions: u64,
+    /// The number of inner page consolidations deferred to the background,
+    /// see [`Options::inner_consolidation`].
+    ///
+    /// [`Options::inner_consolidation`]: crate::TableOptions::inner_consolidation
+    pub deferred_inner_consolidations: u64,
+    /// The number of page epoch increments, each of which changes the range
+    /// of a page, like a split.
+    ///
+    /// Operations that find the epoch of a page changed under them retry
+    /// from the root, so a high rate of this indicates structural
+    /// contention, see [`Self::epoch_bump_rate`].
+    pub epoch_bumps: u64,
+    /// The sizes of the keys of the sampled writes, see
+    /// [`Options::size_sampling_rate`].
+    ///
+    /// [`Options::size_sampling_rate`]: crate::TableOptions::size_sampling_rate
+    pub key_sizes: SizeHistogram,
+    /// The sizes of the values of the sampled puts, see
+    /// [`Options::size_sampling_rate`].
+    ///
+    /// [`Options::size_sampling_rate`]: crate::TableOptions::size_sampling_rate
+    pub value_sizes: SizeHistogram,
+    /// The configured page size of the tree.
+    pub page_size: usize,
 }
 
 impl TreeStats {
@@ -17,6 +69,54 @@ impl TreeStats {
         Self {
             success: self.success.sub(&o.success),
             conflict: self.conflict.sub(&o.conflict),
+            consolidate_leaf_page: self
+                .consolidate_leaf_page
+                .wrapping_sub(o.consolidate_leaf_page),
+            consolidate_leaf_page_bytes: self
+                .consolidate_leaf_page_bytes
+                .wrapping_sub(o.consolidate_leaf_page_bytes),
+            presplit_attempted: self.presplit_attempted.wrapping_sub(o.presplit_attempted),
+            presplit_skipped: self.presplit_skipped.wrapping_sub(o.presplit_skipped),
+            segmented_consolidations: self
+                .segmented_consolidations
+                .wrapping_sub(o.segmented_consolidations),
+            overwritten_chains: self.overwritten_chains.wrapping_sub(o.overwritten_chains),
+            lsn_regressions: self.lsn_regressions.wrapping_sub(o.lsn_regressions),
+            deferred_inner_consolidations: self
+                .deferred_inner_consolidations
+                .wrapping_sub(o.deferred_inner_consolidations),
+            epoch_bumps: self.epoch_bumps.wrapping_sub(o.epoch_bumps),
+            key_sizes: self.key_sizes.sub(&o.key_sizes),
+            value_sizes: self.value_sizes.sub(&o.value_sizes),
+            page_size: self.page_size,
+        }
+    }
+
+    /// Returns the average ratio of used bytes to the page size of the leaf
+    /// base pages.
+    ///
+    /// This is sampled from leaf page consolidations, so it reflects the pages
+    /// consolidated recently rather than all pages in the tree. Returns zero if
+    /// no leaf page is consolidated.
+    pub fn avg_leaf_fill_ratio(&self) -> f64 {
+        let capacity = self.consolidate_leaf_page as f64 * self.page_size as f64;
+        if capacity == 0.0 {
+            0.0
+        } else {
+            self.consolidate_leaf_page_bytes as f64 / capacity
+        }
+    }
+
+    /// Returns the number of epoch bumps per second over `elapsed`.
+    ///
+    /// To get a recent rate, call this on the difference of two stats taken
+    /// `elapsed` apart, see [`Self::sub`]. Returns zero if `elapsed` is zero.
+    pub fn epoch_bump_rate(&self, elapsed: Duration) -> f64 {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.epoch_bumps as f64 / secs
         }
     }
 }
@@ -31,14 +131,64 @@ impl Display for TreeStats {
                 reconcile_page: {}, \
                 consolidate_page: {}, \
                 read_bytes: {}, \
-                write_bytes: {}",
+                read_key_bytes: {}, \
+                read_value_bytes: {}, \
+                write_bytes: {}, \
+                write_key_bytes: {}, \
+                write_value_bytes: {}",
             self.success.read,
             self.success.write,
             self.success.split_page,
             self.success.reconcile_page,
             self.success.consolidate_page,
             self.success.read_bytes,
-            self.success.write_bytes
+            self.success.read_key_bytes,
+            self.success.read_value_bytes,
+            self.success.write_bytes,
+            self.success.write_key_bytes,
+            self.success.write_value_bytes
+        )?;
+        writeln!(
+            f,
+            "TreeStats_leaf: consolidate_leaf_page: {}, \
+                consolidate_leaf_page_bytes: {}, \
+                avg_leaf_fill_ratio: {:.2}",
+            self.consolidate_leaf_page,
+            self.consolidate_leaf_page_bytes,
+            self.avg_leaf_fill_ratio()
+        )?;
+        writeln!(
+            f,
+            "TreeStats_presplit: attempted: {}, skipped: {}",
+            self.presplit_attempted, self.presplit_skipped
+        )?;
+        writeln!(
+            f,
+            "TreeStats_segmented_consolidations: {}",
+            self.segmented_consolidations
+        )?;
+        writeln!(
+            f,
+            "TreeStats_overwritten_chains: {}",
+            self.overwritten_chains
+        )?;
+        writeln!(f, "TreeStats_lsn_regressions: {}", self.lsn_regressions)?;
+        writeln!(
+            f,
+            "TreeStats_deferred_inner_consolidations: {}",
+            self.deferred_inner_consolidations
+        )?;
+        writeln!(f, "TreeStats_epoch_bumps: {}", self.epoch_bumps)?;
+        writeln!(
+            f,
+            "TreeStats_sizes: key_p50: {}, \
+                key_p99: {}, \
+                value_p50: {}, \
+                value_p99: {}",
+            self.key_sizes.percentile(50.0),
+            self.key_sizes.percentile(99.0),
+            self.value_sizes.percentile(50.0),
+            self.value_sizes.percentile(99.0)
         )?;
         writeln!(
             f,
@@ -56,10 +206,143 @@ impl Display for TreeStats {
     }
 }
 
+/// The number of buckets of a [`SizeHistogram`], one for empty sizes and one
+/// for each power of two.
+const SIZE_BUCKETS: usize = u64::BITS as usize + 1;
+
+/// A histogram of sizes in buckets of powers of two.
+///
+/// Bucket 0 counts the empty sizes, and bucket `i` counts the sizes in
+/// `[2^(i-1), 2^i)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeHistogram {
+    buckets: [u64; SIZE_BUCKETS],
+}
+
+impl SizeHistogram {
+    /// Returns the number of sampled sizes.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Returns the ranges of sizes of the non-empty buckets, with the number
+    /// of sampled sizes in them, from the smallest sizes to the largest.
+    pub fn buckets(&self) -> impl Iterator<Item = (Range<u64>, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| (bucket_range(i), count))
+    }
+
+    /// Returns the upper bound of the bucket that the percentile of the
+    /// sampled sizes falls in, or zero if nothing is sampled.
+    ///
+    /// The percentile is in `[0.0, 100.0]`.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let count = self.count();
+        let rank = (count as f64 * percentile.clamp(0.0, 100.0) / 100.0).ceil() as u64;
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if n > 0 && seen >= rank {
+                return bucket_range(i).end - 1;
+            }
+        }
+        0
+    }
+
+    /// Sub other histogram to produce a new histogram.
+    pub fn sub(&self, o: &SizeHistogram) -> SizeHistogram {
+        let mut buckets = self.buckets;
+        for (n, o) in buckets.iter_mut().zip(o.buckets) {
+            *n = n.wrapping_sub(o);
+        }
+        SizeHistogram { buckets }
+    }
+}
+
+impl Default for SizeHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; SIZE_BUCKETS],
+        }
+    }
+}
+
+fn bucket_range(i: usize) -> Range<u64> {
+    match i {
+        0 => 0..1,
+        _ => 1 << (i - 1)..1u64.checked_shl(i as u32).unwrap_or(u64::MAX),
+    }
+}
+
+/// Statistics of a scan.
+#[derive(Clone, Debug, Default)]
+pub struct ScanStats {
+    /// The number of pages returned by the scan.
+    pub pages: u64,
+    /// The number of bytes read by the scan, including the delta pages of
+    /// the leaf pages and the values stored out of them.
+    pub bytes: u64,
+    /// The number of times the scan restarted from the root because of
+    /// concurrent splits.
+    pub restarts: u64,
+}
+
+/// Statistics of a full-tree consolidation.
+#[derive(Clone, Debug, Default)]
+pub struct ConsolidateStats {
+    /// The number of pages consolidated.
+    pub pages: u64,
+    /// The total size of the pages built by the consolidations.
+    pub bytes: u64,
+    /// The number of times the consolidation restarted from the root because
+    /// of concurrent splits.
+    pub restarts: u64,
+}
+
+/// An estimate of the work to consolidate pages, see
+/// [`Table::plan_compaction`].
+///
+/// [`Table::plan_compaction`]: crate::raw::Table::plan_compaction
+#[derive(Clone, Debug, Default)]
+pub struct CompactionPlan {
+    /// The number of pages that would be consolidated.
+    pub pages: u64,
+    /// The total size of the pages that would be merged.
+    pub read_bytes: u64,
+    /// The total size of the pages that would be built.
+    pub write_bytes: u64,
+    /// The size that would be freed by dropping obsolete versions and
+    /// tombstones, including the blob pages that they refer to.
+    ///
+    /// The rest of [`Self::read_bytes`] is rewritten as it is.
+    pub reclaimed_bytes: u64,
+    /// The number of blob pages that would be freed.
+    pub dropped_blobs: u64,
+    /// The estimated duration, based on the average duration of the
+    /// consolidations since the table was opened, or `None` if there has been
+    /// none.
+    pub duration: Option<Duration>,
+}
+
 #[derive(Default)]
 pub(super) struct AtomicStats {
     pub(super) success: AtomicTxnStats,
     pub(super) conflict: AtomicTxnStats,
+    pub(super) consolidate_leaf_page: Counter,
+    pub(super) consolidate_leaf_page_bytes: Counter,
+    pub(super) presplit_attempted: Counter,
+    pub(super) presplit_skipped: Counter,
+    pub(super) segmented_consolidations: Counter,
+    pub(super) overwritten_chains: Counter,
+    pub(super) lsn_regressions: Counter,
+    pub(super) deferred_inner_consolidations: Counter,
+    pub(super) epoch_bumps: Counter,
+    pub(super) key_sizes: AtomicSizeHistogram,
+    pub(super) value_sizes: AtomicSizeHistogram,
+    pub(super) consolidate_latency: AtomicLatency,
 }
 
 impl AtomicStats {
@@ -67,6 +350,43 @@ impl AtomicStats {
         TreeStats {
             success: self.success.snapshot(),
             conflict: self.conflict.snapshot(),
+            consolidate_leaf_page: self.consolidate_leaf_page.get(),
+            consolidate_leaf_page_bytes: self.consolidate_leaf_page_bytes.get(),
+            presplit_attempted: self.presplit_attempted.get(),
+            presplit_skipped: self.presplit_skipped.get(),
+            segmented_consolidations: self.segmented_consolidations.get(),
+            overwritten_chains: self.overwritten_chains.get(),
+            lsn_regressions: self.lsn_regressions.get(),
+            deferred_inner_consolidations: self.deferred_inner_consolidations.get(),
+            epoch_bumps: self.epoch_bumps.get(),
+            key_sizes: self.key_sizes.snapshot(),
+            value_sizes: self.value_sizes.snapshot(),
+            page_size: 0,
+        }
+    }
+}
+
+pub(super) struct AtomicSizeHistogram {
+    buckets: [Counter; SIZE_BUCKETS],
+}
+
+impl AtomicSizeHistogram {
+    pub(super) fn record(&self, size: usize) {
+        let bucket = (u64::BITS - (size as u64).leading_zeros()) as usize;
+        self.buckets[bucket].inc();
+    }
+
+    fn snapshot(&self) -> SizeHistogram {
+        SizeHistogram {
+            buckets: std::array::from_fn(|i| self.buckets[i].get()),
+        }
+    }
+}
+
+impl Default for AtomicSizeHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| Counter::default()),
         }
     }
 }
@@ -81,7 +401,11 @@ pub struct TxnStats {
     pub consolidate_page: u64,
     pub rewrite_page: u64,
     pub read_bytes: u64,
+    pub read_key_bytes: u64,
+    pub read_value_bytes: u64,
     pub write_bytes: u64,
+    pub write_key_bytes: u64,
+    pub write_value_bytes: u64,
 }
 
 #[derive(Default)]
@@ -89,7 +413,11 @@ pub(super) struct AtomicTxnStats {
     pub(super) read: Counter,
     pub(super) write: Counter,
     pub(super) read_bytes: Counter,
+    pub(super) read_key_bytes: Counter,
+    pub(super) read_value_bytes: Counter,
     pub(super) write_bytes: Counter,
+    pub(super) write_key_bytes: Counter,
+    pub(super) write_value_bytes: Counter,
     pub(super) split_page: Counter,
     pub(super) reconcile_page: Counter,
     pub(super) consolidate_page: Counter,
@@ -102,7 +430,11 @@ impl AtomicTxnStats {
             read: self.read.get(),
             write: self.write.get(),
             read_bytes: self.read_bytes.get(),
+            read_key_bytes: self.read_key_bytes.get(),
+            read_value_bytes: self.read_value_bytes.get(),
             write_bytes: self.write_bytes.get(),
+            write_key_bytes: self.write_key_bytes.get(),
+            write_value_bytes: self.write_value_bytes.get(),
             split_page: self.split_page.get(),
             reconcile_page: self.reconcile_page.get(),
             consolidate_page: self.consolidate_page.get(),
@@ -117,7 +449,11 @@ impl TxnStats {
             read: self.read.wrapping_sub(o.read),
             write: self.write.wrapping_sub(o.write),
             read_bytes: self.read_bytes.wrapping_sub(o.read_bytes),
+            read_key_bytes: self.read_key_bytes.wrapping_sub(o.read_key_bytes),
+            read_value_bytes: self.read_value_bytes.wrapping_sub(o.read_value_bytes),
             write_bytes: self.write_bytes.wrapping_sub(o.write_bytes),
+            write_key_bytes: self.write_key_bytes.wrapping_sub(o.write_key_bytes),
+            write_value_bytes: self.write_value_bytes.wrapping_sub(o.write_value_bytes),
             split_page: self.split_page.wrapping_sub(o.split_page),
             reconcile_page: self.reconcile_page.wrapping_sub(o.reconcile_page),
             consolidate_page: self.consolidate_page.wrapping_sub(o.consolidate_page),
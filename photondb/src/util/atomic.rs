@@ -20,6 +20,10 @@ impl Counter {
     pub(crate) fn add(&self, n: u64) -> u64 {
         self.0.fetch_add(n, Ordering::Relaxed)
     }
+
+    pub(crate) fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed)
+    }
 }
 
 impl Default for Counter {
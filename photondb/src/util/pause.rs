@@ -0,0 +1,138 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+/// Pauses and resumes background jobs.
+///
+/// Jobs wrap each unit of work in [`Self::enter`], which waits while the
+/// switch is paused. [`Self::quiesced`] waits for the work that has entered
+/// before the pause to finish.
+#[derive(Clone, Default)]
+pub(crate) struct PauseSwitch {
+    core: Arc<Mutex<Core>>,
+}
+
+#[derive(Default)]
+struct Core {
+    paused: bool,
+    /// The number of units of work in progress.
+    active: usize,
+    wakers: Vec<Waker>,
+}
+
+impl Core {
+    fn register(&mut self, waker: &Waker) {
+        if !self.wakers.iter().any(|w| w.will_wake(waker)) {
+            self.wakers.push(waker.clone());
+        }
+    }
+
+    fn wake_all(&mut self) {
+        for waker in std::mem::take(&mut self.wakers) {
+            waker.wake();
+        }
+    }
+}
+
+impl PauseSwitch {
+    pub(crate) fn is_paused(&self) -> bool {
+        self.core.lock().unwrap().paused
+    }
+
+    pub(crate) fn pause(&self) {
+        self.core.lock().unwrap().paused = true;
+    }
+
+    pub(crate) fn resume(&self) {
+        let mut core = self.core.lock().unwrap();
+        core.paused = false;
+        core.wake_all();
+    }
+
+    /// Waits until the switch is not paused, and returns a guard of the unit
+    /// of work that follows.
+    pub(crate) fn enter(&self) -> Enter<'_> {
+        Enter { switch: self }
+    }
+
+    /// Waits until no unit of work is in progress.
+    pub(crate) fn quiesced(&self) -> Quiesced<'_> {
+        Quiesced { switch: self }
+    }
+}
+
+pub(crate) struct Enter<'a> {
+    switch: &'a PauseSwitch,
+}
+
+impl<'a> Future for Enter<'a> {
+    type Output = Active;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut core = self.switch.core.lock().unwrap();
+        if core.paused {
+            core.register(cx.waker());
+            return Poll::Pending;
+        }
+        core.active += 1;
+        Poll::Ready(Active {
+            core: self.switch.core.clone(),
+        })
+    }
+}
+
+pub(crate) struct Quiesced<'a> {
+    switch: &'a PauseSwitch,
+}
+
+impl<'a> Future for Quiesced<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut core = self.switch.core.lock().unwrap();
+        if core.active == 0 {
+            return Poll::Ready(());
+        }
+        core.register(cx.waker());
+        Poll::Pending
+    }
+}
+
+/// A unit of work in progress, which ends when this is dropped.
+pub(crate) struct Active {
+    core: Arc<Mutex<Core>>,
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        let mut core = self.core.lock().unwrap();
+        core.active -= 1;
+        if core.active == 0 {
+            core.wake_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, FutureExt};
+
+    use super::*;
+
+    #[test]
+    fn pause_switch() {
+        let switch = PauseSwitch::default();
+        let active = block_on(switch.enter());
+        switch.pause();
+        assert!(switch.is_paused());
+        assert!(switch.enter().now_or_never().is_none());
+        assert!(switch.quiesced().now_or_never().is_none());
+        drop(active);
+        assert!(switch.quiesced().now_or_never().is_some());
+        switch.resume();
+        assert!(switch.enter().now_or_never().is_some());
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A thread-safe count-min sketch that estimates the frequency of keys.
+///
+/// The estimation never underestimates the frequency, but it may overestimate
+/// it due to hash collisions.
+pub(crate) struct CountMinSketch {
+    width: usize,
+    counters: Vec<AtomicU64>,
+}
+
+impl CountMinSketch {
+    const DEPTH: usize = 4;
+
+    /// Creates a sketch with `width` counters per row.
+    pub(crate) fn new(width: usize) -> Self {
+        assert!(width > 0);
+        let counters = (0..width * Self::DEPTH)
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        Self { width, counters }
+    }
+
+    /// Increases the frequency of the key by one.
+    pub(crate) fn add(&self, key: u64) {
+        for row in 0..Self::DEPTH {
+            self.counters[self.index(row, key)].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the estimated frequency of the key.
+    pub(crate) fn estimate(&self, key: u64) -> u64 {
+        (0..Self::DEPTH)
+            .map(|row| self.counters[self.index(row, key)].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Resets the frequency of all keys to zero.
+    pub(crate) fn clear(&self) {
+        for counter in &self.counters {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn index(&self, row: usize, key: u64) -> usize {
+        let hash = mix(key ^ (row as u64).wrapping_mul(0x9e3779b97f4a7c15));
+        row * self.width + (hash % self.width as u64) as usize
+    }
+}
+
+// The finalizer of SplitMix64, see https://prng.di.unimi.it/splitmix64.c
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_min_sketch() {
+        let sketch = CountMinSketch::new(64);
+        for key in 0..128u64 {
+            for _ in 0..key {
+                sketch.add(key);
+            }
+        }
+        for key in 0..128u64 {
+            assert!(sketch.estimate(key) >= key);
+        }
+        sketch.clear();
+        for key in 0..128u64 {
+            assert_eq!(sketch.estimate(key), 0);
+        }
+    }
+}